@@ -0,0 +1,113 @@
+//! Command-line client built on `poe_api_process`.
+//!
+//! ```text
+//! cargo run --example poe --features cli -- chat -b Claude-3.7-Sonnet "hi"
+//! cargo run --example poe --features cli -- models
+//! cargo run --example poe --features cli -- upload file.pdf
+//! ```
+//!
+//! The access key is read from `POE_ACCESS_KEY`.
+
+use poe_api_process::{ChatMessage, ChatRequest, PoeClient};
+use std::env;
+use std::error::Error;
+use std::process::ExitCode;
+
+const POE_BASE_URL: &str = "https://api.poe.com";
+const POE_FILE_UPLOAD_URL: &str = "https://www.quora.com/poe_api/file_upload_3RD_PARTY_POST";
+const DEFAULT_BOT: &str = "Claude-3.7-Sonnet";
+
+const USAGE: &str = "\
+Usage:
+  poe chat [-b <bot>] <message>...   Stream a reply to stdout
+  poe models                         List available models
+  poe upload <file> [mime-type]      Upload a local file and print its URL";
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some((command, args)) = args.split_first() else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+
+    let Ok(access_key) = env::var("POE_ACCESS_KEY") else {
+        eprintln!("POE_ACCESS_KEY is not set");
+        return ExitCode::FAILURE;
+    };
+
+    let result = match command.as_str() {
+        "chat" => chat(&access_key, args).await,
+        "models" => models(&access_key).await,
+        "upload" => upload(&access_key, args).await,
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn client(bot_name: &str, access_key: &str) -> PoeClient {
+    PoeClient::new(bot_name, access_key, POE_BASE_URL, POE_FILE_UPLOAD_URL)
+}
+
+async fn chat(access_key: &str, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut bot_name = DEFAULT_BOT;
+    let mut words = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-b" | "--bot" => {
+                bot_name = args.next().ok_or("-b requires a bot name")?;
+            }
+            _ => words.push(arg.as_str()),
+        }
+    }
+    if words.is_empty() {
+        return Err("chat requires a message".into());
+    }
+
+    let request = ChatRequest {
+        query: vec![ChatMessage {
+            role: "user".to_string(),
+            content: words.join(" "),
+            content_type: "text/markdown".to_string(),
+            attachments: None,
+        }],
+        ..Default::default()
+    };
+
+    client(bot_name, access_key)
+        .pipe_text_to(request, &mut tokio::io::stdout())
+        .await?;
+    println!();
+    Ok(())
+}
+
+async fn models(access_key: &str) -> Result<(), Box<dyn Error>> {
+    let models = client("", access_key).get_v1_model_list().await?;
+    for model in models.data {
+        println!("{}\t{}", model.id, model.owned_by);
+    }
+    Ok(())
+}
+
+async fn upload(access_key: &str, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let Some(file) = args.first() else {
+        return Err("upload requires a file path".into());
+    };
+
+    let response = client("", access_key)
+        .upload_local_file(file, args.get(1).map(String::as_str))
+        .await?;
+    println!("{}", response.attachment_url);
+    Ok(())
+}
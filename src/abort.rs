@@ -0,0 +1,45 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::Notify;
+
+#[derive(Debug, Default)]
+struct AbortState {
+    aborted: AtomicBool,
+    notify: Notify,
+}
+
+/// Cancellation token for [`PoeClient::stream_request_with_cancel`](crate::PoeClient::stream_request_with_cancel).
+///
+/// Clones share the same state, so one clone can be handed to the stream
+/// and another kept by whoever decides to stop it (a UI button, a timeout).
+#[derive(Debug, Clone, Default)]
+pub struct AbortHandle {
+    state: Arc<AbortState>,
+}
+
+impl AbortHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Abort every stream using this handle; later streams fail immediately
+    pub fn abort(&self) {
+        self.state.aborted.store(true, Ordering::SeqCst);
+        self.state.notify.notify_waiters();
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.state.aborted.load(Ordering::SeqCst)
+    }
+
+    /// Resolve once [`AbortHandle::abort`] has been called
+    pub async fn aborted(&self) {
+        loop {
+            let notified = self.state.notify.notified();
+            if self.is_aborted() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
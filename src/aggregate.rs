@@ -0,0 +1,162 @@
+use crate::error::{PoeError, PoeErrorWithPartial};
+use crate::types::{
+    ChatEventType, ChatResponse, ChatResponseData, ChatToolCall, DoneInfo, FileData, MetaData,
+    Usage,
+};
+
+/// Response assembled from the events of a chat stream
+#[derive(Debug, Clone, Default)]
+pub struct CompleteResponse {
+    /// Final text, with `replace_response` events applied
+    pub text: String,
+    pub tool_calls: Vec<ChatToolCall>,
+    pub files: Vec<FileData>,
+    /// Payload of the `done` event, if it carried one
+    pub done: Option<DoneInfo>,
+    /// Rendering hints from the last `meta` event
+    pub meta: Option<MetaData>,
+    /// Follow-ups suggested by Poe, in arrival order
+    pub suggested_replies: Vec<String>,
+    /// Usage reported during the response, later reports overriding earlier ones
+    pub usage: Option<Usage>,
+    /// Whether the `done` event was received
+    pub completed: bool,
+}
+
+impl CompleteResponse {
+    /// `text` with inline file references resolved against `files`, see
+    /// [`resolve_inline_refs`]
+    pub fn text_with_files(&self) -> String {
+        resolve_inline_refs(&self.text, &self.files)
+    }
+}
+
+/// Rewrite `[label][inline_ref]` markers naming a returned file into inline
+/// Markdown: an image (`![label](url)`) for `image/*` files, a link
+/// (`[label](url)`) otherwise.
+///
+/// An empty label is replaced by the file name; markers naming no file are
+/// left as they are.
+pub fn resolve_inline_refs(text: &str, files: &[FileData]) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('[') {
+        let (before, candidate) = rest.split_at(start);
+        let file = reference_link(candidate).and_then(|(label, reference, len)| {
+            files
+                .iter()
+                .find(|file| !file.inline_ref.is_empty() && file.inline_ref == reference)
+                .map(|file| (label, file, len))
+        });
+        let Some((label, file, len)) = file else {
+            output.push_str(before);
+            output.push('[');
+            rest = &candidate[1..];
+            continue;
+        };
+
+        // 是否為圖片依檔案類型決定，而非 bot 是否寫了 `!`
+        output.push_str(before.strip_suffix('!').unwrap_or(before));
+        if file.content_type.starts_with("image/") {
+            output.push('!');
+        }
+        let label = if label.is_empty() { &file.name } else { label };
+        if file.url.contains([' ', '(', ')']) {
+            output.push_str(&format!("[{}](<{}>)", label, file.url));
+        } else {
+            output.push_str(&format!("[{}]({})", label, file.url));
+        }
+        rest = &candidate[len..];
+    }
+    output.push_str(rest);
+    output
+}
+
+// 解析開頭的 `[label][reference]`，回傳標籤、參照與總長度（皆不可跨行或巢狀）
+fn reference_link(text: &str) -> Option<(&str, &str, usize)> {
+    let bracket = |part: &str| {
+        let end = part.find([']', '[', '\n'])?;
+        (part.as_bytes()[end] == b']').then_some(end)
+    };
+    let label_end = bracket(&text[1..])? + 1;
+    let reference_part = text[label_end + 1..].strip_prefix('[')?;
+    let reference_end = bracket(reference_part)?;
+    Some((
+        &text[1..label_end],
+        &reference_part[..reference_end],
+        label_end + 2 + reference_end + 1,
+    ))
+}
+
+/// Folds chat stream events into a [`CompleteResponse`], keeping what was
+/// received so far available when the stream fails.
+#[derive(Debug, Default)]
+pub struct ResponseAggregator {
+    response: CompleteResponse,
+}
+
+impl ResponseAggregator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply one event; `error` events are returned as `PoeError::BotError`
+    pub fn push(&mut self, event: ChatResponse) -> Result<(), PoeError> {
+        match (event.event, event.data) {
+            (ChatEventType::Text, Some(ChatResponseData::Text { text })) => {
+                self.response.text.push_str(&text)
+            }
+            (ChatEventType::ReplaceResponse, Some(ChatResponseData::Text { text })) => {
+                self.response.text = text
+            }
+            (ChatEventType::Json, Some(ChatResponseData::ToolCalls(tool_calls))) => {
+                self.response.tool_calls.extend(tool_calls)
+            }
+            (ChatEventType::File, Some(ChatResponseData::File(file))) => {
+                self.response.files.push(file)
+            }
+            (ChatEventType::Meta, Some(ChatResponseData::Meta(meta))) => {
+                self.response.meta = Some(meta)
+            }
+            (ChatEventType::SuggestedReply, Some(ChatResponseData::SuggestedReply { text })) => {
+                self.response.suggested_replies.push(text)
+            }
+            (ChatEventType::Usage, Some(ChatResponseData::Usage(usage))) => {
+                self.response.usage.get_or_insert_with(Usage::default).merge(usage)
+            }
+            (ChatEventType::Done, data) => {
+                self.response.completed = true;
+                if let Some(ChatResponseData::Done(info)) = data {
+                    self.response.done = Some(info);
+                }
+            }
+            (ChatEventType::Error, Some(ChatResponseData::Error { text, .. })) => {
+                return Err(PoeError::BotError(text));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Check whether the `done` event has been applied
+    pub fn is_done(&self) -> bool {
+        self.response.completed
+    }
+
+    /// Response accumulated so far
+    pub fn response(&self) -> &CompleteResponse {
+        &self.response
+    }
+
+    pub fn into_response(self) -> CompleteResponse {
+        self.response
+    }
+
+    /// Pair `error` with the response accumulated so far
+    pub fn into_partial(self, error: PoeError) -> PoeErrorWithPartial {
+        PoeErrorWithPartial {
+            error,
+            partial: self.response,
+        }
+    }
+}
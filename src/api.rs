@@ -0,0 +1,67 @@
+use crate::client::PoeClient;
+use crate::error::PoeError;
+#[cfg(feature = "upload")]
+use crate::types::FileUploadResponse;
+use crate::types::{ChatRequest, ChatResponse, ModelResponse};
+use async_trait::async_trait;
+use futures_util::Stream;
+use std::pin::Pin;
+
+/// Stream of decoded events returned by [`PoeApi::stream_request`]
+pub type ChatStream<'a> = Pin<Box<dyn Stream<Item = Result<ChatResponse, PoeError>> + Send + 'a>>;
+
+/// Object-safe view of the client's core surface (chat streaming, uploads
+/// and model listing).
+///
+/// Depend on `Arc<dyn PoeApi>` instead of `PoeClient` to inject
+/// [`MockPoeClient`](crate::MockPoeClient) in tests or wrap the client in
+/// decorators such as caching or logging.
+#[async_trait]
+pub trait PoeApi: Send + Sync {
+    /// Send a chat request and stream the decoded events
+    async fn stream_request<'a>(&'a self, request: ChatRequest)
+    -> Result<ChatStream<'a>, PoeError>;
+
+    /// Upload a local file
+    #[cfg(feature = "upload")]
+    async fn upload_local_file(
+        &self,
+        file_path: &str,
+        mime_type: Option<&str>,
+    ) -> Result<FileUploadResponse, PoeError>;
+
+    /// Upload a file by URL
+    #[cfg(feature = "upload")]
+    async fn upload_remote_file(&self, download_url: &str) -> Result<FileUploadResponse, PoeError>;
+
+    /// List the models available to the access key
+    async fn get_v1_model_list(&self) -> Result<ModelResponse, PoeError>;
+}
+
+#[async_trait]
+impl PoeApi for PoeClient {
+    async fn stream_request<'a>(
+        &'a self,
+        request: ChatRequest,
+    ) -> Result<ChatStream<'a>, PoeError> {
+        PoeClient::stream_request(self, request).await
+    }
+
+    #[cfg(feature = "upload")]
+    async fn upload_local_file(
+        &self,
+        file_path: &str,
+        mime_type: Option<&str>,
+    ) -> Result<FileUploadResponse, PoeError> {
+        PoeClient::upload_local_file(self, file_path, mime_type).await
+    }
+
+    #[cfg(feature = "upload")]
+    async fn upload_remote_file(&self, download_url: &str) -> Result<FileUploadResponse, PoeError> {
+        PoeClient::upload_remote_file(self, download_url).await
+    }
+
+    async fn get_v1_model_list(&self) -> Result<ModelResponse, PoeError> {
+        PoeClient::get_v1_model_list(self).await
+    }
+}
@@ -0,0 +1,174 @@
+use crate::client::PoeClient;
+use crate::error::PoeError;
+#[cfg(feature = "trace")]
+use crate::logging::TARGET_UPLOAD;
+use crate::types::ChatMessage;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+#[cfg(feature = "trace")]
+use tracing::debug;
+
+const DEFAULT_INLINE_LIMIT: usize = 32 * 1024;
+
+/// Extracts plain text from a file's bytes
+pub trait TextExtractor: Send + Sync {
+    fn extract(&self, bytes: &[u8]) -> Result<String, PoeError>;
+}
+
+impl<F> TextExtractor for F
+where
+    F: Fn(&[u8]) -> Result<String, PoeError> + Send + Sync,
+{
+    fn extract(&self, bytes: &[u8]) -> Result<String, PoeError> {
+        self(bytes)
+    }
+}
+
+fn utf8_text(bytes: &[u8]) -> Result<String, PoeError> {
+    Ok(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// What `attach_local_files` did with a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpansionAction {
+    /// Extracted text was appended to the message content
+    Inlined { path: String, chars: usize },
+    /// The file was uploaded and added as an attachment
+    Attached { path: String, url: String },
+}
+
+/// Rules for expanding local files into a message, mirroring Poe's own
+/// attachment expansion for third-party bots that lack it.
+///
+/// Files with a registered extractor (txt, md, csv, tsv, json and log by
+/// default) are inlined as text up to `inline_limit` characters; larger or
+/// unsupported files are uploaded as attachments. Register an extractor for
+/// `pdf` (or other formats) with [`AttachmentExpansion::with_extractor`].
+#[derive(Clone)]
+pub struct AttachmentExpansion {
+    inline_limit: usize,
+    extractors: HashMap<String, Arc<dyn TextExtractor>>,
+}
+
+impl Default for AttachmentExpansion {
+    fn default() -> Self {
+        let mut extractors: HashMap<String, Arc<dyn TextExtractor>> = HashMap::new();
+        for extension in ["txt", "md", "markdown", "csv", "tsv", "json", "log"] {
+            extractors.insert(extension.to_string(), Arc::new(utf8_text));
+        }
+
+        Self {
+            inline_limit: DEFAULT_INLINE_LIMIT,
+            extractors,
+        }
+    }
+}
+
+impl AttachmentExpansion {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inline extracted text up to `chars` characters (default 32768)
+    pub fn with_inline_limit(mut self, chars: usize) -> Self {
+        self.inline_limit = chars;
+        self
+    }
+
+    /// Extract text from files with `extension` (case-insensitive)
+    pub fn with_extractor(
+        mut self,
+        extension: &str,
+        extractor: impl TextExtractor + 'static,
+    ) -> Self {
+        self.extractors
+            .insert(extension.to_lowercase(), Arc::new(extractor));
+        self
+    }
+
+    fn extractor_for(&self, path: &Path) -> Option<&Arc<dyn TextExtractor>> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        self.extractors.get(&extension)
+    }
+}
+
+impl std::fmt::Debug for AttachmentExpansion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut extensions: Vec<&String> = self.extractors.keys().collect();
+        extensions.sort();
+        f.debug_struct("AttachmentExpansion")
+            .field("inline_limit", &self.inline_limit)
+            .field("extractors", &extensions)
+            .finish()
+    }
+}
+
+impl PoeClient {
+    /// 將本地檔案展開至訊息：可擷取文字且未超過上限者直接內嵌，其餘上傳為附件
+    pub async fn attach_local_files(
+        &self,
+        message: &mut ChatMessage,
+        paths: &[&str],
+        expansion: &AttachmentExpansion,
+    ) -> Result<Vec<ExpansionAction>, PoeError> {
+        let mut actions = Vec::with_capacity(paths.len());
+
+        for &file_path in paths {
+            let path = Path::new(file_path);
+            if !path.exists() {
+                return Err(PoeError::FileNotFound(file_path.to_string()));
+            }
+
+            if let Some(extractor) = expansion.extractor_for(path) {
+                let text = extractor.extract(&tokio::fs::read(path).await?)?;
+                let chars = text.chars().count();
+                if chars <= expansion.inline_limit {
+                    let name = path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .unwrap_or(file_path);
+                    message.content.push_str(&format!(
+                        "\n\n--- {} ---\n{}\n--- end of {} ---",
+                        name,
+                        text.trim_end(),
+                        name
+                    ));
+
+                    #[cfg(feature = "trace")]
+                    debug!(target: TARGET_UPLOAD, "已內嵌檔案文字: {} | {} 字元", file_path, chars);
+                    actions.push(ExpansionAction::Inlined {
+                        path: file_path.to_string(),
+                        chars,
+                    });
+                    continue;
+                }
+
+                #[cfg(feature = "trace")]
+                debug!(
+                    target: TARGET_UPLOAD,
+                    "檔案文字超過內嵌上限 ({} > {})，改為上傳: {}",
+                    chars, expansion.inline_limit, file_path
+                );
+            }
+
+            let uploaded = self.upload_local_file(file_path, None).await?;
+            #[cfg(feature = "trace")]
+            debug!(
+                target: TARGET_UPLOAD,
+                "已上傳檔案作為附件: {} -> {}",
+                file_path, uploaded.attachment_url
+            );
+            message
+                .attachments
+                .get_or_insert_with(Vec::new)
+                .push(uploaded.to_attachment());
+            actions.push(ExpansionAction::Attached {
+                path: file_path.to_string(),
+                url: uploaded.attachment_url,
+            });
+        }
+
+        Ok(actions)
+    }
+}
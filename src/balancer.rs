@@ -0,0 +1,269 @@
+use crate::api::{ChatStream, PoeApi};
+use crate::circuit::CircuitBreaker;
+use crate::client::PoeClient;
+use crate::error::PoeError;
+use crate::metrics::BotLoadStats;
+#[cfg(feature = "upload")]
+use crate::types::FileUploadResponse;
+use crate::types::{ChatEventType, ChatRequest, ChatResponse, ModelResponse};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How a [`BotPool`] picks the bot for the next request
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BalanceStrategy {
+    /// Take turns
+    #[default]
+    RoundRobin,
+    /// Pick the bot with the fewest open streams
+    LeastInFlight,
+    /// Pick the bot with the lowest average time to first event; bots not
+    /// measured yet are tried first
+    LatencyAware,
+}
+
+// 新樣本在延遲移動平均中的權重
+const LATENCY_WEIGHT: f64 = 0.2;
+
+#[derive(Debug, Default)]
+struct MemberStats {
+    in_flight: AtomicUsize,
+    requests: AtomicU64,
+    failures: AtomicU64,
+    latency: Mutex<Option<Duration>>,
+}
+
+impl MemberStats {
+    fn latency(&self) -> Option<Duration> {
+        *self.latency.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn record_latency(&self, sample: Duration) {
+        let mut latency = self.latency.lock().unwrap_or_else(|e| e.into_inner());
+        *latency = Some(match *latency {
+            Some(average) => average.mul_f64(1.0 - LATENCY_WEIGHT) + sample.mul_f64(LATENCY_WEIGHT),
+            None => sample,
+        });
+    }
+
+    fn record_failure(&self) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// 串流存活期間計入 in_flight
+struct InFlightGuard(Arc<MemberStats>);
+
+impl InFlightGuard {
+    fn new(stats: &Arc<MemberStats>) -> Self {
+        stats.in_flight.fetch_add(1, Ordering::Relaxed);
+        stats.requests.fetch_add(1, Ordering::Relaxed);
+        Self(stats.clone())
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+struct PoolMember {
+    client: PoeClient,
+    stats: Arc<MemberStats>,
+}
+
+/// Spreads chat requests across bots serving the same model.
+///
+/// Bots whose circuit is open in the pool's [`CircuitBreaker`] are skipped
+/// until their cool-down ends (all bots are candidates when every circuit is
+/// open). Per-bot load is available from [`BotPool::stats`]. Every bot shares
+/// the template's settings and connection pool.
+pub struct BotPool {
+    members: Vec<PoolMember>,
+    strategy: BalanceStrategy,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    next: AtomicUsize,
+}
+
+impl BotPool {
+    /// Balance over `bots` (the template's own bot when empty)
+    pub fn new<I, S>(template: PoeClient, bots: I, strategy: BalanceStrategy) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut clients: Vec<PoeClient> = bots
+            .into_iter()
+            .map(|bot| template.clone().with_bot(bot.as_ref()))
+            .collect();
+        if clients.is_empty() {
+            clients.push(template);
+        }
+        Self {
+            members: clients
+                .into_iter()
+                .map(|client| PoolMember {
+                    client,
+                    stats: Arc::default(),
+                })
+                .collect(),
+            strategy,
+            circuit_breaker: None,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Track failures per bot and route around bots whose circuit is open
+    pub fn with_circuit_breaker(mut self, breaker: Arc<CircuitBreaker>) -> Self {
+        for member in &mut self.members {
+            member.client = member.client.clone().with_circuit_breaker(breaker.clone());
+        }
+        self.circuit_breaker = Some(breaker);
+        self
+    }
+
+    pub fn strategy(&self) -> BalanceStrategy {
+        self.strategy
+    }
+
+    /// Get the bot names in the pool
+    pub fn bots(&self) -> impl Iterator<Item = &str> {
+        self.members
+            .iter()
+            .map(|member| member.client.get_bot_name())
+    }
+
+    /// Get the current load of every bot, in pool order
+    pub fn stats(&self) -> Vec<BotLoadStats> {
+        self.members
+            .iter()
+            .map(|member| BotLoadStats {
+                bot_name: member.client.get_bot_name().to_string(),
+                in_flight: member.stats.in_flight.load(Ordering::Relaxed),
+                requests: member.stats.requests.load(Ordering::Relaxed),
+                failures: member.stats.failures.load(Ordering::Relaxed),
+                latency: member.stats.latency(),
+            })
+            .collect()
+    }
+
+    fn pick(&self) -> &PoolMember {
+        let mut candidates: Vec<&PoolMember> = self
+            .members
+            .iter()
+            .filter(|member| {
+                self.circuit_breaker
+                    .as_ref()
+                    .is_none_or(|breaker| breaker.is_available(member.client.get_bot_name()))
+            })
+            .collect();
+        if candidates.is_empty() {
+            candidates = self.members.iter().collect();
+        }
+
+        let turn = self.next.fetch_add(1, Ordering::Relaxed);
+        match self.strategy {
+            BalanceStrategy::RoundRobin => candidates[turn % candidates.len()],
+            // 負載相同時輪流選擇，避免總是落在第一個 bot
+            BalanceStrategy::LeastInFlight => candidates
+                .iter()
+                .cycle()
+                .skip(turn % candidates.len())
+                .take(candidates.len())
+                .min_by_key(|member| member.stats.in_flight.load(Ordering::Relaxed))
+                .copied()
+                .expect("a bot pool has at least one bot"),
+            BalanceStrategy::LatencyAware => candidates
+                .iter()
+                .cycle()
+                .skip(turn % candidates.len())
+                .take(candidates.len())
+                .min_by_key(|member| {
+                    (
+                        member.stats.latency().unwrap_or_default(),
+                        member.stats.in_flight.load(Ordering::Relaxed),
+                    )
+                })
+                .copied()
+                .expect("a bot pool has at least one bot"),
+        }
+    }
+
+    /// Send a chat request to the bot chosen by the strategy, returning that
+    /// bot together with its stream
+    pub async fn stream_request_with_bot<'a>(
+        &'a self,
+        request: ChatRequest,
+    ) -> Result<(&'a str, ChatStream<'a>), PoeError> {
+        let member = self.pick();
+        let guard = InFlightGuard::new(&member.stats);
+        let started = Instant::now();
+
+        let stream = match member.client.stream_request(request).await {
+            Ok(stream) => stream,
+            Err(error) => {
+                member.stats.record_failure();
+                return Err(error);
+            }
+        };
+
+        let mut first = true;
+        let stream = stream.map(move |item| {
+            let stats = &guard.0;
+            if first {
+                first = false;
+                stats.record_latency(started.elapsed());
+            }
+            if matches!(
+                item,
+                Err(_)
+                    | Ok(ChatResponse {
+                        event: ChatEventType::Error,
+                        ..
+                    })
+            ) {
+                stats.record_failure();
+            }
+            item
+        });
+        Ok((member.client.get_bot_name(), Box::pin(stream)))
+    }
+}
+
+#[async_trait]
+impl PoeApi for BotPool {
+    async fn stream_request<'a>(
+        &'a self,
+        request: ChatRequest,
+    ) -> Result<ChatStream<'a>, PoeError> {
+        Ok(self.stream_request_with_bot(request).await?.1)
+    }
+
+    #[cfg(feature = "upload")]
+    async fn upload_local_file(
+        &self,
+        file_path: &str,
+        mime_type: Option<&str>,
+    ) -> Result<FileUploadResponse, PoeError> {
+        self.members[0]
+            .client
+            .upload_local_file(file_path, mime_type)
+            .await
+    }
+
+    #[cfg(feature = "upload")]
+    async fn upload_remote_file(&self, download_url: &str) -> Result<FileUploadResponse, PoeError> {
+        self.members[0]
+            .client
+            .upload_remote_file(download_url)
+            .await
+    }
+
+    async fn get_v1_model_list(&self) -> Result<ModelResponse, PoeError> {
+        self.members[0].client.get_v1_model_list().await
+    }
+}
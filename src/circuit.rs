@@ -0,0 +1,123 @@
+use crate::error::PoeError;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Observable state of a bot's circuit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests flow normally
+    Closed,
+    /// Requests fail fast with `PoeError::CircuitOpen` until the cool-down ends
+    Open,
+    /// One probe request is in flight; its outcome closes or re-opens the circuit
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Circuit {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    // 探測請求被取消時不會回報結果，超過冷卻時間即視為失效
+    HalfOpen { since: Instant },
+}
+
+/// Per-bot circuit breaker: after `failure_threshold` consecutive failures a
+/// bot's circuit opens for `cool_down`, then a single probe decides whether
+/// it closes again. A probe that never reports back (e.g. its future was
+/// dropped) expires after another `cool_down`, letting a new probe through.
+///
+/// Share one breaker between clients of different bots with `Arc`.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cool_down: Duration,
+    circuits: Mutex<HashMap<String, Circuit>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cool_down: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cool_down,
+            circuits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get the current state of `bot_name`'s circuit
+    pub fn state(&self, bot_name: &str) -> CircuitState {
+        let circuits = self.circuits.lock().unwrap_or_else(|e| e.into_inner());
+        match circuits.get(bot_name) {
+            None | Some(Circuit::Closed { .. }) => CircuitState::Closed,
+            Some(Circuit::Open { .. }) => CircuitState::Open,
+            Some(Circuit::HalfOpen { .. }) => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Check whether `check` would let a request to `bot_name` through,
+    /// without changing the circuit
+    pub fn is_available(&self, bot_name: &str) -> bool {
+        let circuits = self.circuits.lock().unwrap_or_else(|e| e.into_inner());
+        match circuits.get(bot_name) {
+            None | Some(Circuit::Closed { .. }) => true,
+            Some(Circuit::Open { until }) => Instant::now() >= *until,
+            Some(Circuit::HalfOpen { since }) => self.probe_expired(*since),
+        }
+    }
+
+    /// Check whether a request to `bot_name` may be sent, moving an expired
+    /// open circuit to half-open for a single probe
+    pub fn check(&self, bot_name: &str) -> Result<(), PoeError> {
+        let mut circuits = self.circuits.lock().unwrap_or_else(|e| e.into_inner());
+        match circuits.get(bot_name) {
+            None | Some(Circuit::Closed { .. }) => Ok(()),
+            Some(Circuit::Open { until }) if Instant::now() >= *until => {
+                let probe = Circuit::HalfOpen {
+                    since: Instant::now(),
+                };
+                circuits.insert(bot_name.to_string(), probe);
+                Ok(())
+            }
+            Some(Circuit::HalfOpen { since }) if self.probe_expired(*since) => {
+                let probe = Circuit::HalfOpen {
+                    since: Instant::now(),
+                };
+                circuits.insert(bot_name.to_string(), probe);
+                Ok(())
+            }
+            Some(Circuit::Open { .. }) | Some(Circuit::HalfOpen { .. }) => {
+                Err(PoeError::CircuitOpen(bot_name.to_string()))
+            }
+        }
+    }
+
+    /// Record a successful request, closing the circuit
+    pub fn record_success(&self, bot_name: &str) {
+        let mut circuits = self.circuits.lock().unwrap_or_else(|e| e.into_inner());
+        circuits.remove(bot_name);
+    }
+
+    /// Record a failed request, opening the circuit once the threshold is reached
+    pub fn record_failure(&self, bot_name: &str) {
+        let mut circuits = self.circuits.lock().unwrap_or_else(|e| e.into_inner());
+        let failures = match circuits.get(bot_name) {
+            Some(Circuit::Closed { failures }) => failures + 1,
+            None => 1,
+            // A failed probe re-opens the circuit immediately
+            Some(Circuit::HalfOpen { .. }) | Some(Circuit::Open { .. }) => self.failure_threshold,
+        };
+
+        let circuit = if failures >= self.failure_threshold {
+            Circuit::Open {
+                until: Instant::now() + self.cool_down,
+            }
+        } else {
+            Circuit::Closed { failures }
+        };
+        circuits.insert(bot_name.to_string(), circuit);
+    }
+
+    fn probe_expired(&self, since: Instant) -> bool {
+        Instant::now() >= since + self.cool_down
+    }
+}
@@ -1,33 +1,167 @@
-use crate::error::PoeError;
+use crate::abort::AbortHandle;
+use crate::aggregate::CompleteResponse;
+use crate::circuit::CircuitBreaker;
+use crate::error::{PoeError, PoeErrorWithPartial};
 use crate::types::*;
 use crate::logging::*;
+use crate::metrics::{StreamGauges, StreamStats};
+use crate::retry::{RetryBudget, RetryBudgetStats, RetryPolicy, is_transient};
+use crate::signing::{RequestSigner, SigningRequest};
+use crate::sse::{SseFrame, SseParser};
+use crate::stream_ext::{ChatStreamExt, ReplaceStrategy};
+use crate::runtime::{Timer, default_timer, timeout};
+#[cfg(not(feature = "reqwest"))]
+use crate::transport::MissingTransport;
+#[cfg(feature = "reqwest")]
+use crate::transport::ReqwestTransport;
+use crate::transport::{HttpRequest, HttpResponse, HttpTransport};
+use crate::util::to_hex;
 use futures_util::Stream;
-use futures_util::StreamExt;
-use futures_util::future::join_all;
+use futures_util::future::{Either, select};
+use futures_util::{StreamExt, TryStreamExt};
+#[cfg(feature = "reqwest")]
 use reqwest::Client;
-use reqwest::header::{COOKIE, HeaderMap, HeaderValue};
+#[cfg(feature = "cookies")]
+use reqwest::cookie::Jar;
 use serde_json::Value;
-use std::path::Path;
-use std::pin::Pin;
-use tokio_util::io::ReaderStream;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::pin::{Pin, pin};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 #[cfg(feature = "trace")]
 use tracing::{debug, warn};
-
-const POE_GQL_URL: &str = "https://poe.com/api/gql_POST";
-const POE_GQL_MODEL_HASH: &str = "b24b2f2f6da147b3345eec1a433ed17b6e1332df97dea47622868f41078a40cc";
-const POE_GQL_MODEL_REVISION: &str = "e2acc7025b43e08e88164ba8105273f37fbeaa26";
-
+use url::Url;
+
+mod builder;
+#[cfg(feature = "gql")]
+mod gql;
+#[cfg(feature = "upload")]
+mod multipart;
+mod probe;
+#[cfg(feature = "upload")]
+mod upload;
+
+pub use builder::PoeClientBuilder;
+pub use probe::BotCapabilities;
+#[cfg(feature = "gql")]
+pub use gql::get_model_list;
+#[cfg(feature = "upload")]
+pub(crate) use multipart::MultipartForm;
+#[cfg(feature = "image")]
+pub(crate) use upload::record_checksum;
+
+const POE_DEFAULT_MAX_EVENT_SIZE: usize = 16 * 1024 * 1024;
+const POE_API_BASE_URL: &str = "https://api.poe.com";
+const POE_FILE_UPLOAD_URL: &str = "https://www.quora.com/poe_api/file_upload_3RD_PARTY_POST";
+#[cfg(feature = "upload")]
+const POE_FILE_ATTACHMENT_URL: &str =
+    "https://www.quora.com/poe_api/file_attachment_3RD_PARTY_POST";
+
+// 建構時解析的端點；PoeClient::new 不會失敗，錯誤留到發送請求時回報
+type Endpoint = Result<Url, url::ParseError>;
+
+#[cfg(feature = "upload")]
+type MimeMismatchHandler = Arc<dyn Fn(&MimeMismatch) + Send + Sync>;
+
+/// Poe API client.
+///
+/// Configuration lives behind an `Arc`, so cloning a client (e.g. for every
+/// `upload_files_batch` task) is a pointer copy and shares the connection pool.
 #[derive(Clone)]
 pub struct PoeClient {
-    client: Client,
+    inner: Arc<ClientInner>,
+}
+
+// Shared client configuration
+#[derive(Clone)]
+struct ClientInner {
     bot_name: String,
     access_key: String,
-    poe_base_url: String,
-    poe_file_upload_url: String,
+    poe_base_url: Endpoint,
+    poe_file_upload_url: Endpoint,
     logging_config: LoggingConfig,
+    #[cfg(feature = "gql")]
+    language_code: Option<String>,
+    #[cfg(feature = "gql")]
+    accept_language: Option<String>,
+    #[cfg(feature = "gql")]
+    web_session: Option<PoeWebSession>,
+    #[cfg(feature = "gql")]
+    model_list_policy: ModelListPolicy,
+    strict_roles: bool,
+    keep_alive_events: bool,
+    tool_call_completion: ToolCallCompletion,
+    #[cfg(feature = "upload")]
+    mime_mismatch_handler: Option<MimeMismatchHandler>,
+    #[cfg(feature = "upload")]
+    attachment_ttl: Option<Duration>,
+    #[cfg(feature = "upload")]
+    poe_file_attachment_url: Endpoint,
+    // 序列化前套用的角色對應 (from, to)
+    role_map: Vec<(String, String)>,
+    signer: Option<Arc<dyn RequestSigner>>,
+    transport: Arc<dyn HttpTransport>,
+    timer: Arc<dyn Timer>,
+    retry_budget: Option<Arc<RetryBudget>>,
+    retry_policy: Option<RetryPolicy>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    max_event_size: usize,
+    stream_gauges: Arc<StreamGauges>,
+    // probe_capabilities 的結果，clone 之間共享
+    capability_cache: Arc<Mutex<HashMap<String, BotCapabilities>>>,
+    #[cfg(feature = "gql")]
+    gql_url: Endpoint,
+    #[cfg(feature = "gql")]
+    gql_hash_sources: Vec<GraphqlHashSource>,
+    // 最近一次成功的 hash，clone 之間共享
+    #[cfg(feature = "gql")]
+    gql_model_hash: Arc<Mutex<String>>,
+    #[cfg(feature = "cookies")]
+    cookie_jar: Arc<Jar>,
+}
+
+/// Parse an endpoint URL, dropping trailing slashes from its path.
+///
+/// URLs that cannot take a path (such as `mailto:`) are rejected.
+pub(crate) fn parse_endpoint(url: &str) -> Result<Url, url::ParseError> {
+    let mut url = Url::parse(url)?;
+    if url.cannot_be_a_base() {
+        return Err(url::ParseError::RelativeUrlWithCannotBeABaseBase);
+    }
+    while url.path().len() > 1 && url.path().ends_with('/') {
+        url.path_segments_mut()
+            .expect("checked above")
+            .pop_if_empty();
+    }
+    Ok(url)
+}
+
+// 在端點路徑後追加片段，片段中的 `/`、`?` 等字元會被編碼
+fn join_endpoint(endpoint: &Endpoint, segments: &[&str]) -> Result<Url, PoeError> {
+    let mut url = endpoint.clone()?;
+    url.path_segments_mut()
+        .map_err(|_| url::ParseError::RelativeUrlWithCannotBeABaseBase)?
+        .pop_if_empty()
+        .extend(segments);
+    Ok(url)
+}
+
+fn endpoint_str(endpoint: &Endpoint) -> &str {
+    match endpoint {
+        // 根路徑序列化時必定帶 `/`
+        Ok(url) => url.as_str().strip_suffix('/').unwrap_or(url.as_str()),
+        Err(_) => "",
+    }
 }
 
 impl PoeClient {
+    /// Create a client for `bot_name`.
+    ///
+    /// Invalid URLs are reported as [`PoeError::InvalidUrl`] by the first
+    /// request using them; [`PoeClient::builder`] reports them up front.
     pub fn new(
         bot_name: &str,
         access_key: &str,
@@ -35,45 +169,255 @@ impl PoeClient {
         poe_file_upload_url: &str,
     ) -> Self {
         #[cfg(feature = "trace")]
-        debug!("Creating new PoeClient instance, bot_name: {}", bot_name);
-
-        // Handle trailing slashes in URLs
-        let normalized_base_url = if poe_base_url.ends_with('/') {
-            poe_base_url.trim_end_matches('/').to_string()
-        } else {
-            poe_base_url.to_string()
-        };
-
-        let normalized_file_upload_url = if poe_file_upload_url.ends_with('/') {
-            poe_file_upload_url.trim_end_matches('/').to_string()
-        } else {
-            poe_file_upload_url.to_string()
-        };
+        debug!(target: TARGET_CLIENT, "Creating new PoeClient instance, bot_name: {}", bot_name);
 
         Self {
-            client: Client::new(),
-            bot_name: bot_name.to_string(),
-            access_key: access_key.to_string(),
-            poe_base_url: normalized_base_url,
-            poe_file_upload_url: normalized_file_upload_url,
-            logging_config: LoggingConfig::default(),
+            inner: Arc::new(ClientInner {
+                bot_name: bot_name.to_string(),
+                access_key: access_key.to_string(),
+                poe_base_url: parse_endpoint(poe_base_url),
+                poe_file_upload_url: parse_endpoint(poe_file_upload_url),
+                logging_config: LoggingConfig::default(),
+                #[cfg(feature = "gql")]
+                language_code: None,
+                #[cfg(feature = "gql")]
+                accept_language: None,
+                #[cfg(feature = "gql")]
+                web_session: None,
+                #[cfg(feature = "gql")]
+                model_list_policy: ModelListPolicy::default(),
+                strict_roles: false,
+                keep_alive_events: false,
+                tool_call_completion: ToolCallCompletion::default(),
+                #[cfg(feature = "upload")]
+                mime_mismatch_handler: None,
+                #[cfg(feature = "upload")]
+                attachment_ttl: None,
+                #[cfg(feature = "upload")]
+                poe_file_attachment_url: parse_endpoint(POE_FILE_ATTACHMENT_URL),
+                role_map: Vec::new(),
+                signer: None,
+                #[cfg(feature = "reqwest")]
+                transport: Arc::new(ReqwestTransport::new(Client::new())),
+                #[cfg(not(feature = "reqwest"))]
+                transport: Arc::new(MissingTransport),
+                timer: default_timer(),
+                retry_budget: None,
+                retry_policy: None,
+                circuit_breaker: None,
+                max_event_size: POE_DEFAULT_MAX_EVENT_SIZE,
+                stream_gauges: Arc::new(StreamGauges::default()),
+                capability_cache: Arc::new(Mutex::new(HashMap::new())),
+                #[cfg(feature = "gql")]
+                gql_url: parse_endpoint(gql::POE_GQL_URL),
+                #[cfg(feature = "gql")]
+                gql_hash_sources: vec![GraphqlHashSource::Env],
+                #[cfg(feature = "gql")]
+                gql_model_hash: Arc::new(Mutex::new(gql::POE_GQL_MODEL_HASH.to_string())),
+                #[cfg(feature = "cookies")]
+                cookie_jar: Arc::new(Jar::default()),
+            }),
         }
     }
 
     /// Configure logging settings
     pub fn with_logging_config(mut self, config: LoggingConfig) -> Self {
-        self.logging_config = config;
+        Arc::make_mut(&mut self.inner).logging_config = config;
         self
     }
 
     /// Get current logging configuration
     pub fn get_logging_config(&self) -> &LoggingConfig {
-        &self.logging_config
+        &self.inner.logging_config
     }
 
     /// Update logging configuration
     pub fn set_logging_config(&mut self, config: LoggingConfig) {
-        self.logging_config = config;
+        Arc::make_mut(&mut self.inner).logging_config = config;
+    }
+
+    /// Send requests to another bot, keeping the rest of the configuration.
+    ///
+    /// Call it on a clone to get a second client sharing the connection pool.
+    pub fn with_bot(mut self, bot_name: &str) -> Self {
+        Arc::make_mut(&mut self.inner).bot_name = bot_name.to_string();
+        self
+    }
+
+    /// Authenticate with another access key, e.g. after rotating it
+    pub fn with_access_key(mut self, access_key: &str) -> Self {
+        Arc::make_mut(&mut self.inner).access_key = access_key.to_string();
+        self
+    }
+
+    /// Get the bot that chat requests are sent to
+    pub fn get_bot_name(&self) -> &str {
+        &self.inner.bot_name
+    }
+
+    /// Get the normalized Poe API base URL, without a trailing slash (empty
+    /// when the configured URL is invalid)
+    pub fn get_base_url(&self) -> &str {
+        endpoint_str(&self.inner.poe_base_url)
+    }
+
+    /// Get the normalized file upload URL, without a trailing slash (empty
+    /// when the configured URL is invalid)
+    pub fn get_file_upload_url(&self) -> &str {
+        endpoint_str(&self.inner.poe_file_upload_url)
+    }
+
+    /// Enable strict mode, rejecting requests whose role sequence Poe would refuse
+    pub fn with_strict_roles(mut self, strict: bool) -> Self {
+        Arc::make_mut(&mut self.inner).strict_roles = strict;
+        self
+    }
+
+    /// Surface server pings as `ChatEventType::Ping` / `ChatResponseData::KeepAlive`
+    /// events (off by default), e.g. for proxies keeping their own clients alive
+    pub fn with_keep_alive_events(mut self, enabled: bool) -> Self {
+        Arc::make_mut(&mut self.inner).keep_alive_events = enabled;
+        self
+    }
+
+    /// Set when streamed tool calls count as complete for bots that never
+    /// send `finish_reason: "tool_calls"`
+    pub fn with_tool_call_completion(mut self, completion: ToolCallCompletion) -> Self {
+        Arc::make_mut(&mut self.inner).tool_call_completion = completion;
+        self
+    }
+
+    /// Rename message role `from` to `to` when sending, for bots expecting
+    /// another convention (e.g. `"bot"` -> `"assistant"`)
+    pub fn with_role_mapping(mut self, from: &str, to: &str) -> Self {
+        let role_map = &mut Arc::make_mut(&mut self.inner).role_map;
+        role_map.retain(|(existing, _)| existing != from);
+        role_map.push((from.to_string(), to.to_string()));
+        self
+    }
+
+    /// Sign every outgoing chat, upload and model list request with `signer`
+    pub fn with_request_signer(mut self, signer: impl RequestSigner + 'static) -> Self {
+        Arc::make_mut(&mut self.inner).signer = Some(Arc::new(signer));
+        self
+    }
+
+    /// Abort streams with `PoeError::EventTooLarge` when a single SSE line or the data of one event exceeds `bytes` (default 16 MiB)
+    pub fn with_max_event_size(mut self, bytes: usize) -> Self {
+        Arc::make_mut(&mut self.inner).max_event_size = bytes;
+        self
+    }
+
+    /// Cap retries (e.g. GraphQL hash refreshes) across all calls of this client and its clones
+    pub fn with_retry_budget(mut self, budget: RetryBudget) -> Self {
+        Arc::make_mut(&mut self.inner).retry_budget = Some(Arc::new(budget));
+        self
+    }
+
+    /// Retry chat requests that fail with a transient error (transport error,
+    /// 429 or 5xx) before the stream starts, and `error` events with
+    /// `allow_retry: true` for requests without a `RetryableErrorPolicy`,
+    /// waiting with exponential backoff in between.
+    ///
+    /// Retries also draw from the retry budget, if one is configured.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        Arc::make_mut(&mut self.inner).retry_policy = Some(policy);
+        self
+    }
+
+    /// Get the retry budget balance and exhaustion count, if a budget is configured
+    pub fn retry_budget_stats(&self) -> Option<RetryBudgetStats> {
+        self.inner.retry_budget.as_ref().map(|budget| budget.stats())
+    }
+
+    /// Fail fast with `PoeError::CircuitOpen` while this bot keeps failing (breaker may be shared across bots)
+    pub fn with_circuit_breaker(mut self, breaker: Arc<CircuitBreaker>) -> Self {
+        Arc::make_mut(&mut self.inner).circuit_breaker = Some(breaker);
+        self
+    }
+
+    /// Check whether the retry budget allows one more retry (always true without a budget)
+    pub(crate) fn allow_retry(&self) -> bool {
+        self.inner
+            .retry_budget
+            .as_ref()
+            .is_none_or(|budget| budget.try_withdraw())
+    }
+
+    /// Get the current stream gauges (active streams and buffered bytes), shared across clones
+    pub fn stream_stats(&self) -> StreamStats {
+        self.inner.stream_gauges.snapshot()
+    }
+
+    /// Start building a client with custom HTTP settings (timeouts, headers,
+    /// user agent) or a shared `reqwest::Client`
+    pub fn builder() -> PoeClientBuilder {
+        PoeClientBuilder::default()
+    }
+
+    /// Use a preconfigured HTTP client (proxy, timeouts, TLS) for every request
+    #[cfg(feature = "reqwest")]
+    pub fn with_http_client(self, client: Client) -> Self {
+        self.with_transport(ReqwestTransport::new(client))
+    }
+
+    /// Send every request (chat, file uploads, model lists and GraphQL)
+    /// through a custom transport.
+    ///
+    /// Without the `reqwest` feature this is required before sending anything.
+    pub fn with_transport(mut self, transport: impl HttpTransport + 'static) -> Self {
+        Arc::make_mut(&mut self.inner).transport = Arc::new(transport);
+        self
+    }
+
+    /// Use another timer for retry back-off and tool call quiescence windows,
+    /// e.g. one driven by the async runtime of the application
+    pub fn with_timer(mut self, timer: impl Timer + 'static) -> Self {
+        Arc::make_mut(&mut self.inner).timer = Arc::new(timer);
+        self
+    }
+
+    /// Send a request through the configured transport, adding signature headers
+    async fn send_signed(
+        &self,
+        method: &str,
+        url: &str,
+        mut headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    ) -> Result<HttpResponse, PoeError> {
+        headers.extend(self.signature_headers(method, url, &body)?);
+
+        if let Some(budget) = &self.inner.retry_budget {
+            budget.deposit();
+        }
+
+        self.inner
+            .transport
+            .send(HttpRequest {
+                method: method.to_string(),
+                url: url.to_string(),
+                headers,
+                body,
+            })
+            .await
+    }
+
+    /// Compute the signature headers for a request (empty without a signer)
+    fn signature_headers(
+        &self,
+        method: &str,
+        url: &str,
+        body: &[u8],
+    ) -> Result<Vec<(String, String)>, PoeError> {
+        match &self.inner.signer {
+            Some(signer) => signer.sign(&SigningRequest {
+                method,
+                url,
+                body,
+                timestamp: LoggingHelper::get_timestamp(),
+            }),
+            None => Ok(Vec::new()),
+        }
     }
 
     /// Helper method to create and log ChatResponse
@@ -92,34 +436,72 @@ impl PoeClient {
         ChatResponse { event, data }
     }
 
-    pub async fn stream_request(
-        &self,
-        #[cfg(feature = "xml")] mut request: ChatRequest,
-        #[cfg(not(feature = "xml"))] request: ChatRequest,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatResponse, PoeError>> + Send + '_>>, PoeError> {
-        #[cfg(feature = "trace")]
-        debug!("Starting stream request, bot_name: {}", self.bot_name);
+    /// Build the outbound request exactly as `stream_request` would send it, without sending it.
+    ///
+    /// Useful to inspect the XML prompt injection or for golden-file tests;
+    /// credentials in the returned headers are masked.
+    pub fn prepare_request(&self, request: ChatRequest) -> Result<PreparedRequest, PoeError> {
+        let request = self.prepare_chat_request(request)?;
+        let url = join_endpoint(&self.inner.poe_base_url, &["bot", &self.inner.bot_name])?.to_string();
+        let body = serde_json::to_vec(&request)?;
+
+        let mut headers = vec![
+            ("Authorization".to_string(), "Bearer ***MASKED***".to_string()),
+            ("Content-Type".to_string(), "application/json".to_string()),
+        ];
+        headers.extend(self.signature_headers("POST", &url, &body)?);
+
+        Ok(PreparedRequest {
+            url,
+            headers,
+            body: serde_json::from_slice(&body)?,
+        })
+    }
 
-        // Log the incoming chat request
-        #[cfg(feature = "trace")]
-        LoggingHelper::log_chat_request(&request);
+    /// Validate the request and apply role mapping and feature-dependent conversions (XML tools) before sending
+    fn prepare_chat_request(&self, mut request: ChatRequest) -> Result<ChatRequest, PoeError> {
+        if self.inner.strict_roles {
+            request.validate_roles()?;
+        }
+
+        for message in &mut request.query {
+            // 未知的 content_type 仍照送，僅提示可能的拼寫錯誤
+            #[cfg(feature = "trace")]
+            if !message.parsed_content_type().is_known() {
+                warn!(
+                    target: TARGET_CLIENT,
+                    "Unexpected content_type {:?} on {} message; Poe renders text/markdown and text/plain",
+                    message.content_type,
+                    message.role
+                );
+            }
+            if let Some((_, to)) = self
+                .inner
+                .role_map
+                .iter()
+                .find(|(from, _)| *from == message.role)
+            {
+                message.role = to.clone();
+            }
+        }
 
         // When xml feature is enabled, automatically convert tools to XML format
-        #[cfg(feature = "xml")]
+        #[cfg(feature = "xml-prompt")]
         {
             if request.tools.is_some() {
                 #[cfg(feature = "trace")]
-                debug!("XML feature enabled, automatically converting tools to XML format");
+                debug!(target: TARGET_XML, "XML feature enabled, automatically converting tools to XML format");
 
                 // Use xml module methods
                 request.append_tools_as_xml();
                 request.tools = None; // Clear original tool definitions
+                request.tool_choice = None; // Already reflected in the XML prompt
             }
 
             // If there are tool results, also convert to XML format and clear original data
             if request.tool_results.is_some() {
                 #[cfg(feature = "trace")]
-                debug!("XML feature enabled, automatically converting tool results to XML format");
+                debug!(target: TARGET_XML, "XML feature enabled, automatically converting tool results to XML format");
 
                 // Convert tool results to XML format and append to message end
                 request.append_tool_results_as_xml();
@@ -130,9 +512,200 @@ impl PoeClient {
             }
         }
 
-        let url = format!("{}/bot/{}", self.poe_base_url, self.bot_name);
+        Ok(request)
+    }
+
+    /// Send a chat request and stream the decoded events, handling retryable
+    /// `error` events according to `request.retryable_error_policy`
+    pub async fn stream_request(
+        &self,
+        request: ChatRequest,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatResponse, PoeError>> + Send + '_>>, PoeError> {
+        let is_retryable = |item: &Result<ChatResponse, PoeError>| {
+            matches!(
+                item,
+                Ok(ChatResponse {
+                    event: ChatEventType::Error,
+                    data: Some(ChatResponseData::Error { allow_retry: true, .. }),
+                })
+            )
+        };
+
+        // 未指定策略的請求沿用客戶端的重試設定
+        let policy = match (request.retryable_error_policy, &self.inner.retry_policy) {
+            (Some(policy), _) => policy,
+            (None, Some(retry_policy)) => RetryableErrorPolicy::Retry {
+                max_retries: retry_policy.max_retries,
+            },
+            (None, None) => RetryableErrorPolicy::YieldAndContinue,
+        };
+        // 客戶端未設定重試策略時，以預設的退避時間重試
+        let backoff = self.inner.retry_policy.unwrap_or_default();
+
+        match policy {
+            RetryableErrorPolicy::YieldAndContinue => self.stream_request_retrying(request).await,
+            RetryableErrorPolicy::Terminate => {
+                let stream = self.stream_request_retrying(request).await?;
+                Ok(Box::pin(stream.scan(false, move |stopped, item| {
+                    if *stopped {
+                        return futures_util::future::ready(None);
+                    }
+                    *stopped = is_retryable(&item);
+                    futures_util::future::ready(Some(item))
+                })))
+            }
+            RetryableErrorPolicy::Retry { max_retries } => {
+                let stream = self.stream_request_retrying(request.clone()).await?;
+                // (目前串流, 剩餘重試次數, 是否已輸出文字, 是否已輸出無法撤回的檔案或工具呼叫)
+                let state = (Some(stream), max_retries, false, false);
+                Ok(Box::pin(futures_util::stream::unfold(
+                    state,
+                    move |(mut stream, mut retries_left, mut emitted_text, mut emitted_final)| {
+                        let request = request.clone();
+                        async move {
+                            loop {
+                                let item = stream.as_mut()?.next().await?;
+                                if is_retryable(&item)
+                                    && !emitted_final
+                                    && retries_left > 0
+                                    && self.allow_retry()
+                                {
+                                    retries_left -= 1;
+                                    #[cfg(feature = "trace")]
+                                    warn!(target: TARGET_CLIENT, "收到可重試的錯誤事件，重新發送請求，剩餘 {} 次", retries_left);
+                                    self.inner.timer.sleep(backoff.delay(max_retries - retries_left - 1)).await;
+                                    match self.stream_request_retrying(request.clone()).await {
+                                        Ok(retried) => {
+                                            stream = Some(retried);
+                                            if emitted_text {
+                                                emitted_text = false;
+                                                let reset = Ok(ChatResponse {
+                                                    event: ChatEventType::ReplaceResponse,
+                                                    data: Some(ChatResponseData::Text {
+                                                        text: String::new(),
+                                                    }),
+                                                });
+                                                let state = (stream, retries_left, emitted_text, emitted_final);
+                                                return Some((reset, state));
+                                            }
+                                            continue;
+                                        }
+                                        Err(e) => {
+                                            let state = (None, retries_left, emitted_text, emitted_final);
+                                            return Some((Err(e), state));
+                                        }
+                                    }
+                                }
+
+                                emitted_text |= matches!(
+                                    item,
+                                    Ok(ChatResponse {
+                                        event: ChatEventType::Text | ChatEventType::ReplaceResponse,
+                                        ..
+                                    })
+                                );
+                                emitted_final |= matches!(
+                                    item,
+                                    Ok(ChatResponse {
+                                        data: Some(ChatResponseData::File(_) | ChatResponseData::ToolCalls(_)),
+                                        ..
+                                    })
+                                );
+                                let state = (stream, retries_left, emitted_text, emitted_final);
+                                return Some((item, state));
+                            }
+                        }
+                    },
+                )))
+            }
+        }
+    }
+
+    /// Send the request, retrying transient failures according to the retry policy
+    async fn stream_request_retrying(
+        &self,
+        request: ChatRequest,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatResponse, PoeError>> + Send + '_>>, PoeError> {
+        let Some(policy) = self.inner.retry_policy else {
+            return self.stream_request_once(request).await;
+        };
+
+        let mut attempt = 0;
+        loop {
+            match self.stream_request_once(request.clone()).await {
+                Err(error)
+                    if attempt < policy.max_retries && is_transient(&error) && self.allow_retry() =>
+                {
+                    // 429 優先依 Retry-After 等待，但不超過最大延遲
+                    let delay = match error.retry_after() {
+                        Some(wait) if error.status() == Some(429) => wait.min(policy.max_delay),
+                        _ => policy.delay(attempt),
+                    };
+                    #[cfg(feature = "trace")]
+                    warn!(target: TARGET_CLIENT, "請求失敗: {}，{:?} 後重試", error, delay);
+                    self.inner.timer.sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn stream_request_once(
+        &self,
+        request: ChatRequest,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatResponse, PoeError>> + Send + '_>>, PoeError> {
+        #[cfg(feature = "trace")]
+        debug!(target: TARGET_CLIENT, "Starting stream request, bot_name: {}", self.inner.bot_name);
+
+        #[cfg(feature = "upload")]
+        let request = {
+            let mut request = request;
+            self.refresh_stale_attachments(&mut request).await?;
+            request
+        };
+
+        // Log the incoming chat request
+        #[cfg(feature = "trace")]
+        LoggingHelper::log_chat_request(&request);
+
+        let request = self.prepare_chat_request(request)?;
+        let body = serde_json::to_vec(&request)?;
+        self.stream_body(body, request.tools.unwrap_or_default()).await
+    }
+
+    /// Send a hand-built JSON body to the bot endpoint and stream the decoded
+    /// events, for protocol fields [`ChatRequest`] does not model yet.
+    ///
+    /// The body is sent as is: no role mapping, validation, attachment
+    /// refresh or retries. Tools listed under `"tools"` are still used to
+    /// detect XML tool calls.
+    pub async fn stream_request_raw_json(
+        &self,
+        body: Value,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatResponse, PoeError>> + Send + '_>>, PoeError> {
+        #[cfg(feature = "trace")]
+        debug!(target: TARGET_CLIENT, "Starting raw JSON stream request, bot_name: {}", self.inner.bot_name);
+
+        let available_tools = body
+            .get("tools")
+            .and_then(|tools| serde_json::from_value(tools.clone()).ok())
+            .unwrap_or_default();
+        self.stream_body(serde_json::to_vec(&body)?, available_tools).await
+    }
+
+    // 發送已序列化的請求主體並解碼回應串流
+    async fn stream_body(
+        &self,
+        body: Vec<u8>,
+        available_tools: Vec<ChatTool>,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatResponse, PoeError>> + Send + '_>>, PoeError> {
+        #[cfg(not(feature = "xml-parse"))]
+        let _ = available_tools;
+
+        let url = join_endpoint(&self.inner.poe_base_url, &["bot", &self.inner.bot_name])?.to_string();
         #[cfg(feature = "trace")]
-        debug!("Sending request to URL: {}", url);
+        debug!(target: TARGET_CLIENT, "Sending request to URL: {}", url);
 
         // Prepare request logging
         #[cfg(feature = "trace")]
@@ -140,7 +713,7 @@ impl PoeClient {
         #[cfg(not(feature = "trace"))]
         let _request_start_time = LoggingHelper::get_timestamp();
         #[cfg(feature = "trace")]
-        let request_body = serde_json::to_string(&request).unwrap_or_else(|_| "Failed to serialize".to_string());
+        let request_body = String::from_utf8_lossy(&body).into_owned();
         
         #[cfg(feature = "trace")]
         {
@@ -155,22 +728,38 @@ impl PoeClient {
                 body: Some(request_body.clone()),
                 body_size: Some(request_body.len()),
             };
-            LoggingHelper::log_request(&request_log, &self.logging_config);
+            LoggingHelper::log_request(&request_log, &self.inner.logging_config);
         }
 
         #[cfg(feature = "trace")]
         debug!(
+            target: TARGET_CLIENT,
             "🔍 Complete request body sent: {}",
-            serde_json::to_string_pretty(&request).unwrap_or_else(|_| "Failed to serialize".to_string())
+            serde_json::from_slice::<serde_json::Value>(&body)
+                .and_then(|body| serde_json::to_string_pretty(&body))
+                .unwrap_or_else(|_| "Failed to serialize".to_string())
         );
 
-        let response = self
-            .client
-            .post(&url)
-            .header("Authorization", format!("Bearer {}", self.access_key))
-            .json(&request)
-            .send()
-            .await?;
+        let headers = vec![
+            ("Authorization".to_string(), format!("Bearer {}", self.inner.access_key)),
+            ("Content-Type".to_string(), "application/json".to_string()),
+        ];
+        if let Some(breaker) = &self.inner.circuit_breaker {
+            breaker.check(&self.inner.bot_name)?;
+        }
+
+        let response = self.send_signed("POST", &url, headers, body).await;
+
+        // 傳輸錯誤、5xx 及 429 視為 bot 故障，其餘回應視為 bot 可用
+        if let Some(breaker) = &self.inner.circuit_breaker {
+            match &response {
+                Ok(response) if response.status < 500 && response.status != 429 => {
+                    breaker.record_success(&self.inner.bot_name)
+                }
+                _ => breaker.record_failure(&self.inner.bot_name),
+            }
+        }
+        let response = response?;
 
         #[cfg(feature = "trace")]
         let response_start_time = LoggingHelper::get_timestamp();
@@ -179,359 +768,255 @@ impl PoeClient {
         #[cfg(feature = "trace")]
         let duration_ms = response_start_time - request_start_time;
 
-        if !response.status().is_success() {
-            let status = response.status();
+        if !response.is_success() {
+            #[cfg(feature = "trace")]
+            let status = response.status;
             #[cfg(feature = "trace")]
-            warn!("API request failed, status code: {}", status);
+            warn!(target: TARGET_CLIENT, "API request failed, status code: {}", status);
             
             // Log error response
             #[cfg(feature = "trace")]
             {
                 let response_log = ResponseLog {
                     timestamp: response_start_time,
-                    status_code: status.as_u16(),
+                    status_code: status,
                     headers: None,
                     body: None,
                     body_size: None,
                     duration_ms: Some(duration_ms),
                 };
-                LoggingHelper::log_response(&response_log, &self.logging_config);
+                LoggingHelper::log_response(&response_log, &self.inner.logging_config);
             }
             
-            return Err(PoeError::BotError(format!("API response status code: {}", status)));
+            return Err(status_error(response, &url).await);
         }
 
         #[cfg(feature = "trace")]
-        debug!("Successfully received stream response");
+        debug!(target: TARGET_CLIENT, "Successfully received stream response");
 
         // Log successful response
         #[cfg(feature = "trace")]
         {
             let response_log = ResponseLog {
                 timestamp: response_start_time,
-                status_code: response.status().as_u16(),
+                status_code: response.status,
                 headers: None,
                 body: Some("Streaming response".to_string()),
                 body_size: None,
                 duration_ms: Some(duration_ms),
             };
-            LoggingHelper::log_response(&response_log, &self.logging_config);
+            LoggingHelper::log_response(&response_log, &self.inner.logging_config);
         }
 
-        let mut static_buffer = String::new();
-        let mut current_event: Option<ChatEventType> = None;
-        let mut is_collecting_data = false;
+        let mut sse_parser = SseParser::new().with_max_line_length(self.inner.max_event_size);
         // 用於累積 tool_calls 的狀態
         let mut accumulated_tool_calls: Vec<PartialToolCall> = Vec::new();
         let mut tool_calls_complete = false;
 
         // XML 工具調用緩衝和檢測狀態
-        #[cfg(feature = "xml")]
+        #[cfg(feature = "xml-parse")]
         let mut xml_text_buffer = String::new();
-        #[cfg(feature = "xml")]
+        #[cfg(feature = "xml-parse")]
         let mut xml_detection_active = false;
-        #[cfg(feature = "xml")]
-        let available_tools = request.tools.clone().unwrap_or_default();
-
-        let stream = response
-            .bytes_stream()
-            .map(move |result| {
-                result.map_err(PoeError::from).map(|chunk| {
-                    let chunk_str = String::from_utf8_lossy(&chunk);
+
+        // 原始串流塊的序號，用於排查代理緩衝造成的事件邊界問題
+        #[cfg(feature = "trace-wire")]
+        let mut wire_sequence: u64 = 0;
+        #[cfg(feature = "trace-wire")]
+        let wire_logging_config = self.inner.logging_config.clone();
+
+        let keep_alive_events = self.inner.keep_alive_events;
+        let tool_call_completion = self.inner.tool_call_completion;
+        let mut stream_guard = self.inner.stream_gauges.track();
+
+        let stream = body_events(
+            response.body,
+            tool_call_completion.quiescence,
+            self.inner.timer.clone(),
+        )
+            .map(move |body_event| {
+                let (result, at_end) = match body_event {
+                    BodyEvent::Chunk(result) => (result, false),
+                    // 未收到 finish_reason 時，於閒置時送出已累積完整的工具調用
+                    BodyEvent::Idle => return Ok(Self::flush_tool_calls(&mut accumulated_tool_calls)),
+                    BodyEvent::End => (Ok(bytes::Bytes::new()), true),
+                };
+                result.map(|chunk| {
                     #[cfg(feature = "trace")]
-                    debug!("處理串流塊，大小: {} 字節", chunk.len());
+                    debug!(target: TARGET_SSE, "處理串流塊，大小: {} 字節", chunk.len());
+                    #[cfg(feature = "trace-wire")]
+                    if !at_end {
+                        LoggingHelper::log_wire_chunk(wire_sequence, &chunk, &wire_logging_config);
+                        wire_sequence += 1;
+                    }
 
                     let mut events = Vec::new();
-                    // 將新的塊添加到靜態緩衝區
-                    static_buffer.push_str(&chunk_str);
-
-                    // 尋找完整的消息
-                    while let Some(newline_pos) = static_buffer.find('\n') {
-                        let line = static_buffer[..newline_pos].trim().to_string();
-                        static_buffer = static_buffer[newline_pos + 1..].to_string();
-
-                        if line.is_empty() {
-                            // 重置當前事件狀態，準備處理下一個事件
-                            current_event = None;
-                            is_collecting_data = false;
-                            continue;
-                        }
-
-                        if line == ": ping" {
-                            #[cfg(feature = "trace")]
-                            debug!("收到 ping 訊號");
-                            continue;
+                    // 取出本塊中所有完整的 SSE 事件；行過長時先處理之前的事件再回報錯誤
+                    sse_parser.push_bytes(&chunk);
+                    let mut frames = Vec::new();
+                    let mut overflow = None;
+                    loop {
+                        match sse_parser.next_frame() {
+                            Ok(Some(frame)) => frames.push(frame),
+                            Ok(None) => break,
+                            Err(e) => {
+                                overflow = Some(e);
+                                break;
+                            }
                         }
+                    }
+                    // 串流結束時送出伺服器未以空行結束的最後一個事件
+                    if at_end && overflow.is_none() {
+                        frames.extend(sse_parser.finish().map(SseFrame::Event));
+                    }
 
-                        if line.starts_with("event: ") {
-                            let event_name = line.trim_start_matches("event: ").trim();
-                            #[cfg(feature = "trace")]
-                            debug!("解析事件類型: {}", event_name);
-
-                            let event_type = match event_name {
-                                "text" => ChatEventType::Text,
-                                "replace_response" => ChatEventType::ReplaceResponse,
-                                "json" => ChatEventType::Json,
-                                "file" => ChatEventType::File,
-                                "done" => ChatEventType::Done,
-                                "error" => ChatEventType::Error,
-                                _ => {
+                    for frame in frames {
+                        let sse_event = match frame {
+                            SseFrame::Event(sse_event) => sse_event,
+                            SseFrame::Comment(comment) => {
+                                if comment.trim() == "ping" {
                                     #[cfg(feature = "trace")]
-                                    warn!("收到未知事件類型: {}", event_name);
-                                    continue;
+                                    debug!(target: TARGET_SSE, "收到 ping 訊號");
+                                    if keep_alive_events {
+                                        events.push(Ok(ChatResponse {
+                                            event: ChatEventType::Ping,
+                                            data: Some(ChatResponseData::KeepAlive),
+                                        }));
+                                    }
                                 }
-                            };
-
-                            current_event = Some(event_type);
-                            is_collecting_data = false;
-                            continue;
-                        }
+                                continue;
+                            }
+                        };
 
-                        if line.starts_with("data: ") {
-                            let data = line.trim_start_matches("data: ").trim();
-                            #[cfg(feature = "trace")]
-                            debug!(
-                                "收到事件數據: {}",
-                                if data.len() > 100 { &data[..100] } else { data }
-                            );
-
-                            if let Some(ref event_type) = current_event {
-                                match event_type {
-                                    ChatEventType::Text | ChatEventType::ReplaceResponse => {
-                                        if let Ok(json) = serde_json::from_str::<Value>(data) {
-                                            if let Some(text) = json.get("text").and_then(Value::as_str) {
-                                                #[cfg(feature = "trace")]
-                                                debug!("解析到文本數據，長度: {}", text.len());
-
-                                                // XML 工具調用檢測和緩衝邏輯
-                                                #[cfg(feature = "xml")]
-                                                {
-                                                    // 基於實際工具定義的智能檢測
-                                                    let should_start_xml_detection = !xml_detection_active && (
-                                                        text.contains("<tool_call>") ||
-                                                        text.contains("<invoke") ||
-                                                        // 檢查是否包含任何已定義的工具名稱標籤
-                                                        available_tools.iter().any(|tool|
-                                                            text.contains(&format!("<{}>", tool.function.name))
-                                                        )
-                                                    );
-                                                    if should_start_xml_detection {
-                                                        xml_detection_active = true;
-                                                        xml_text_buffer.clear();
-                                                        #[cfg(feature = "trace")]
-                                                        debug!("檢測到已定義工具的 XML 調用，開始 XML 緩衝 | 清空緩衝區重新開始");
-                                                    }
-                                                    if xml_detection_active {
-                                                        xml_text_buffer.push_str(text);
-                                                        #[cfg(feature = "trace")]
-                                                        debug!("XML 模式：文本已添加到緩衝區 | 長度: {}", xml_text_buffer.len());
-                                                        // 檢查是否有完整的工具調用
-                                                        let message = ChatMessage {
-                                                            role: "assistant".to_string(),
-                                                            content: xml_text_buffer.clone(),
-                                                            attachments: None,
-                                                            content_type: "text/plain".to_string(),
-                                                        };
-                                                        // 使用工具定義來檢測和解析
-                                                        if message.contains_xml_tool_calls_with_tools(&available_tools) {
-                                                            let tool_calls = message.extract_xml_tool_calls_with_tools(&available_tools);
-                                                            if !tool_calls.is_empty() {
-                                                                #[cfg(feature = "trace")]
-                                                                debug!("檢測到完整的 XML 工具調用，轉換為標準格式，數量: {}", tool_calls.len());
-                                                                // 發送工具調用事件
-                                                                events.push(Ok(self.create_and_log_response(
-                                                                    ChatEventType::Json,
-                                                                    Some(ChatResponseData::ToolCalls(tool_calls)),
-                                                                )));
-                                                                // 移除 XML 部分並發送剩餘文本
-                                                                let clean_text = Self::remove_xml_tool_calls(&xml_text_buffer);
-                                                                if !clean_text.trim().is_empty() {
-                                                                    events.push(Ok(ChatResponse {
-                                                                        event: event_type.clone(),
-                                                                        data: Some(ChatResponseData::Text {
-                                                                            text: clean_text,
-                                                                        }),
-                                                                    }));
-                                                                }
-                                                                // 重置 XML 緩衝狀態
-                                                                xml_text_buffer.clear();
-                                                                xml_detection_active = false;
-                                                            } else {
-                                                                // 沒有完整的工具調用，繼續緩衝
-                                                                #[cfg(feature = "trace")]
-                                                                debug!("XML 工具調用尚未完整，繼續緩衝");
-                                                            }
-                                                        } else {
-                                                            // 檢查是否應該釋放緩衝區
-                                                            let should_release = xml_text_buffer.contains('\n') &&
-                                                                 xml_text_buffer.len() > 200 &&
-                                                                 !available_tools.iter().any(|tool|
-                                                                     xml_text_buffer.contains(&format!("<{}>", tool.function.name)) ||
-                                                                     xml_text_buffer.contains(&format!("</{}>", tool.function.name))
-                                                                 ) &&
-                                                                 !xml_text_buffer.contains("<tool_call>") &&
-                                                                 !xml_text_buffer.contains("<invoke");
-                                                            if should_release {
-                                                                #[cfg(feature = "trace")]
-                                                                debug!("XML 緩衝區過大或不包含工具調用，發送為普通文本");
-                                                                // 發送緩衝的文本
-                                                                events.push(Ok(ChatResponse {
-                                                                    event: event_type.clone(),
-                                                                    data: Some(ChatResponseData::Text {
-                                                                        text: xml_text_buffer.clone(),
-                                                                    }),
-                                                                }));
-                                                                // 重置緩衝狀態
-                                                                xml_text_buffer.clear();
-                                                                xml_detection_active = false;
-                                                            } else {
-                                                                // 繼續緩衝
-                                                                #[cfg(feature = "trace")]
-                                                                debug!("繼續緩衝 XML 文本，當前長度: {}", xml_text_buffer.len());
-                                                            }
-                                                        }
-                                                    } else {
-                                                        // 沒有檢測到 XML，直接發送文本
-                                                        events.push(Ok(ChatResponse {
-                                                            event: event_type.clone(),
-                                                            data: Some(ChatResponseData::Text {
-                                                                text: text.to_string(),
-                                                            }),
-                                                        }));
-                                                    }
-                                                }
+                        let event_name = sse_event.event.as_deref().unwrap_or("message");
+                        #[cfg(feature = "trace")]
+                        debug!(target: TARGET_SSE, "解析事件類型: {}", event_name);
+
+                        let event_type = match event_name {
+                            "text" => ChatEventType::Text,
+                            "replace_response" => ChatEventType::ReplaceResponse,
+                            "json" => ChatEventType::Json,
+                            "file" => ChatEventType::File,
+                            "done" => ChatEventType::Done,
+                            "error" => ChatEventType::Error,
+                            "meta" => ChatEventType::Meta,
+                            "suggested_reply" => ChatEventType::SuggestedReply,
+                            // 本 crate 轉發串流時產生的 usage 事件
+                            "usage" => ChatEventType::Usage,
+                            "ping" => {
+                                #[cfg(feature = "trace")]
+                                debug!(target: TARGET_SSE, "收到 ping 訊號");
+                                // 忽略 ping 事件隨附的 data
+                                if keep_alive_events {
+                                    events.push(Ok(ChatResponse {
+                                        event: ChatEventType::Ping,
+                                        data: Some(ChatResponseData::KeepAlive),
+                                    }));
+                                }
+                                continue;
+                            }
+                            _ => {
+                                #[cfg(feature = "trace")]
+                                debug!(target: TARGET_SSE, "收到未知事件類型，原樣轉交: {}", event_name);
+                                ChatEventType::Unknown(event_name.to_string())
+                            }
+                        };
 
-                                                #[cfg(not(feature = "xml"))]
-                                                {
-                                                    events.push(Ok(ChatResponse {
-                                                        event: event_type.clone(),
-                                                        data: Some(ChatResponseData::Text {
-                                                            text: text.to_string(),
-                                                        }),
-                                                    }));
-                                                }
-                                            }
-                                        } else {
-                                            #[cfg(feature = "trace")]
-                                            debug!("JSON 解析失敗，可能是不完整的數據，等待更多數據");
-                                            is_collecting_data = true;
-                                        }
-                                    }
-                                    ChatEventType::File => {
-                                        if let Ok(file_data) = serde_json::from_str::<FileData>(data) {
-                                            #[cfg(feature = "trace")]
-                                            debug!("解析到文件數據: {}", file_data.name);
-                                            events.push(Ok(ChatResponse {
-                                                event: ChatEventType::File,
-                                                data: Some(ChatResponseData::File(file_data)),
-                                            }));
-                                        } else {
-                                            #[cfg(feature = "trace")]
-                                            debug!("文件數據 JSON 解析失敗，可能是不完整的數據，等待更多數據");
-                                            is_collecting_data = true;
-                                        }
+                        let data = sse_event.data.as_str();
+                        #[cfg(feature = "trace")]
+                        debug!(
+                            target: TARGET_SSE,
+                            "收到事件數據: {}",
+                            data.chars().take(100).collect::<String>()
+                        );
+
+                        let event_type = &event_type;
+                        match event_type {
+                            // ping 事件已於上方處理
+                            ChatEventType::Ping => {}
+                            ChatEventType::Meta => {
+                                if let Ok(meta) = serde_json::from_str::<MetaData>(data) {
+                                    // bot 宣告未知的 content_type 時提示，內容仍照原樣轉交
+                                    #[cfg(feature = "trace")]
+                                    if let Some(content_type) = meta.parsed_content_type()
+                                        && !content_type.is_known()
+                                    {
+                                        warn!(target: TARGET_SSE, "bot 宣告了未知的 content_type: {}", content_type);
                                     }
-                                    ChatEventType::Json => {
-                                        if let Ok(json) = serde_json::from_str::<Value>(data) {
-                                            #[cfg(feature = "trace")]
-                                            debug!("解析到 JSON 事件數據");
-                                            // 檢查是否有 finish_reason: "tool_calls"，表示工具調用完成
-                                            let finish_reason = json
-                                                .get("choices")
-                                                .and_then(|choices| choices.get(0))
-                                                .and_then(|choice| choice.get("finish_reason"))
-                                                .and_then(Value::as_str);
-
-                                            if finish_reason == Some("tool_calls") {
-                                                #[cfg(feature = "trace")]
-                                                debug!("檢測到工具調用完成標誌");
-                                                tool_calls_complete = true;
-                                            }
-
-                                            // 檢查是否包含 tool_calls delta
-                                            let tool_calls_delta = json
-                                                .get("choices")
-                                                .and_then(|choices| choices.get(0))
-                                                .and_then(|choice| choice.get("delta"))
-                                                .and_then(|delta| delta.get("tool_calls"));
+                                    events.push(Ok(ChatResponse {
+                                        event: ChatEventType::Meta,
+                                        data: Some(ChatResponseData::Meta(meta)),
+                                    }));
+                                } else {
+                                    #[cfg(feature = "trace")]
+                                    warn!(target: TARGET_SSE, "meta 事件 JSON 解析失敗，略過: {}", data);
+                                }
+                            }
+                            ChatEventType::SuggestedReply => {
+                                if let Some(text) = serde_json::from_str::<Value>(data)
+                                    .ok()
+                                    .and_then(|json| json.get("text")?.as_str().map(str::to_string))
+                                {
+                                    events.push(Ok(ChatResponse {
+                                        event: ChatEventType::SuggestedReply,
+                                        data: Some(ChatResponseData::SuggestedReply { text }),
+                                    }));
+                                } else {
+                                    #[cfg(feature = "trace")]
+                                    warn!(target: TARGET_SSE, "建議回覆事件 JSON 解析失敗，略過: {}", data);
+                                }
+                            }
+                            ChatEventType::Usage => {
+                                if let Some(usage) = serde_json::from_str::<Value>(data)
+                                    .ok()
+                                    .and_then(|json| Usage::from_payload(&json))
+                                {
+                                    events.push(Ok(ChatResponse {
+                                        event: ChatEventType::Usage,
+                                        data: Some(ChatResponseData::Usage(usage)),
+                                    }));
+                                }
+                            }
+                            ChatEventType::Unknown(_) => {
+                                // 非 JSON 的 data 以字串保留
+                                let raw = serde_json::from_str::<Value>(data)
+                                    .unwrap_or_else(|_| Value::String(data.to_string()));
+                                events.push(Ok(ChatResponse {
+                                    event: event_type.clone(),
+                                    data: Some(ChatResponseData::Raw(raw)),
+                                }));
+                            }
+                            ChatEventType::Text | ChatEventType::ReplaceResponse => {
+                                if let Ok(json) = serde_json::from_str::<Value>(data) {
+                                    if let Some(text) = json.get("text").and_then(Value::as_str) {
+                                        #[cfg(feature = "trace")]
+                                        debug!(target: TARGET_SSE, "解析到文本數據，長度: {}", text.len());
 
-                                            if let Some(tool_calls_array) = tool_calls_delta {
+                                        // XML 工具調用檢測和緩衝邏輯
+                                        #[cfg(feature = "xml-parse")]
+                                        {
+                                            // 基於實際工具定義的智能檢測
+                                            let should_start_xml_detection = !xml_detection_active && (
+                                                text.contains("<tool_call>") ||
+                                                text.contains("<invoke") ||
+                                                // 檢查是否包含任何已定義的工具名稱標籤
+                                                available_tools.iter().any(|tool|
+                                                    text.contains(&format!("<{}>", tool.function.name))
+                                                )
+                                            );
+                                            if should_start_xml_detection {
+                                                xml_detection_active = true;
+                                                xml_text_buffer.clear();
                                                 #[cfg(feature = "trace")]
-                                                debug!("檢測到工具調用 delta");
-                                                // 處理每個工具調用的 delta
-                                                if let Some(tool_calls) = tool_calls_array.as_array() {
-                                                    for tool_call_delta in tool_calls {
-                                                        let index = tool_call_delta
-                                                            .get("index")
-                                                            .and_then(Value::as_u64)
-                                                            .unwrap_or(0)
-                                                            as usize;
-
-                                                        // 確保 accumulated_tool_calls 有足夠的元素
-                                                        while accumulated_tool_calls.len() <= index {
-                                                            accumulated_tool_calls.push(PartialToolCall::default());
-                                                        }
-
-                                                        // 更新 id 和 type
-                                                        if let Some(id) = tool_call_delta
-                                                            .get("id")
-                                                            .and_then(Value::as_str)
-                                                        {
-                                                            accumulated_tool_calls[index].id = id.to_string();
-                                                        }
-
-                                                        if let Some(type_str) = tool_call_delta
-                                                            .get("type")
-                                                            .and_then(Value::as_str)
-                                                        {
-                                                            accumulated_tool_calls[index].r#type = type_str.to_string();
-                                                        }
-
-                                                        // 更新 function 相關欄位
-                                                        if let Some(function) = tool_call_delta.get("function") {
-                                                            if let Some(name) = function
-                                                                .get("name")
-                                                                .and_then(Value::as_str)
-                                                            {
-                                                                accumulated_tool_calls[index].function_name = name.to_string();
-                                                            }
-
-                                                            if let Some(args) = function
-                                                                .get("arguments")
-                                                                .and_then(Value::as_str)
-                                                            {
-                                                                accumulated_tool_calls[index].function_arguments.push_str(args);
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            } else if !tool_calls_complete {
-                                                // 如果沒有 tool_calls delta 且工具調用尚未完成，
-                                                // 則按一般 JSON 處理
-                                                events.push(Ok(ChatResponse {
-                                                    event: ChatEventType::Json,
-                                                    data: Some(ChatResponseData::Text {
-                                                        text: data.to_string(),
-                                                    }),
-                                                }));
+                                                debug!(target: TARGET_XML, "檢測到已定義工具的 XML 調用，開始 XML 緩衝 | 清空緩衝區重新開始");
                                             }
-                                        } else {
-                                            #[cfg(feature = "trace")]
-                                            debug!("JSON 事件解析失敗，可能是不完整的數據");
-                                            is_collecting_data = true;
-                                        }
-                                    }
-                                    ChatEventType::Done => {
-                                        #[cfg(feature = "trace")]
-                                        debug!("收到完成事件");
-                                        // 處理任何剩餘的 XML 緩衝內容
-                                        #[cfg(feature = "xml")]
-                                        {
-                                            if xml_detection_active && !xml_text_buffer.trim().is_empty() {
+                                            if xml_detection_active {
+                                                xml_text_buffer.push_str(text);
                                                 #[cfg(feature = "trace")]
-                                                debug!("處理剩餘的 XML 緩衝內容，長度: {}", xml_text_buffer.len());
+                                                debug!(target: TARGET_XML, "XML 模式：文本已添加到緩衝區 | 長度: {}", xml_text_buffer.len());
+                                                // 檢查是否有完整的工具調用
                                                 let message = ChatMessage {
-                                                    role: "assistant".to_string(),
+                                                    role: ROLE_ASSISTANT.to_string(),
                                                     content: xml_text_buffer.clone(),
                                                     attachments: None,
                                                     content_type: "text/plain".to_string(),
@@ -541,269 +1026,270 @@ impl PoeClient {
                                                     let tool_calls = message.extract_xml_tool_calls_with_tools(&available_tools);
                                                     if !tool_calls.is_empty() {
                                                         #[cfg(feature = "trace")]
-                                                        debug!("在完成事件中檢測到 XML 工具調用，數量: {}", tool_calls.len());
+                                                        debug!(target: TARGET_XML, "檢測到完整的 XML 工具調用，轉換為標準格式，數量: {}", tool_calls.len());
                                                         // 發送工具調用事件
-                                                        events.push(Ok(ChatResponse {
-                                                            event: ChatEventType::Json,
-                                                            data: Some(ChatResponseData::ToolCalls(tool_calls)),
-                                                        }));
-                                                        // 發送清理後的文本（如果有）
+                                                        events.push(Ok(self.create_and_log_response(
+                                                            ChatEventType::Json,
+                                                            Some(ChatResponseData::ToolCalls(tool_calls)),
+                                                        )));
+                                                        // 移除 XML 部分並發送剩餘文本
                                                         let clean_text = Self::remove_xml_tool_calls(&xml_text_buffer);
                                                         if !clean_text.trim().is_empty() {
                                                             events.push(Ok(ChatResponse {
-                                                                event: ChatEventType::Text,
+                                                                event: event_type.clone(),
                                                                 data: Some(ChatResponseData::Text {
                                                                     text: clean_text,
                                                                 }),
                                                             }));
                                                         }
+                                                        // 重置 XML 緩衝狀態
+                                                        xml_text_buffer.clear();
+                                                        xml_detection_active = false;
                                                     } else {
-                                                        // 發送為普通文本
+                                                        // 沒有完整的工具調用，繼續緩衝
+                                                        #[cfg(feature = "trace")]
+                                                        debug!(target: TARGET_XML, "XML 工具調用尚未完整，繼續緩衝");
+                                                    }
+                                                } else {
+                                                    // 檢查是否應該釋放緩衝區
+                                                    let should_release = xml_text_buffer.contains('\n') &&
+                                                         xml_text_buffer.len() > 200 &&
+                                                         !available_tools.iter().any(|tool|
+                                                             xml_text_buffer.contains(&format!("<{}>", tool.function.name)) ||
+                                                             xml_text_buffer.contains(&format!("</{}>", tool.function.name))
+                                                         ) &&
+                                                         !xml_text_buffer.contains("<tool_call>") &&
+                                                         !xml_text_buffer.contains("<invoke");
+                                                    if should_release {
+                                                        #[cfg(feature = "trace")]
+                                                        debug!(target: TARGET_XML, "XML 緩衝區過大或不包含工具調用，發送為普通文本");
+                                                        // 發送緩衝的文本
                                                         events.push(Ok(ChatResponse {
-                                                            event: ChatEventType::Text,
+                                                            event: event_type.clone(),
                                                             data: Some(ChatResponseData::Text {
                                                                 text: xml_text_buffer.clone(),
                                                             }),
                                                         }));
+                                                        // 重置緩衝狀態
+                                                        xml_text_buffer.clear();
+                                                        xml_detection_active = false;
+                                                    } else {
+                                                        // 繼續緩衝
+                                                        #[cfg(feature = "trace")]
+                                                        debug!(target: TARGET_XML, "繼續緩衝 XML 文本，當前長度: {}", xml_text_buffer.len());
                                                     }
-                                                } else {
-                                                    // 發送為普通文本
-                                                    events.push(Ok(ChatResponse {
-                                                        event: ChatEventType::Text,
-                                                        data: Some(ChatResponseData::Text {
-                                                            text: xml_text_buffer.clone(),
-                                                        }),
-                                                    }));
                                                 }
-                                                // 清理緩衝狀態
-                                                xml_text_buffer.clear();
-                                                xml_detection_active = false;
-                                            }
-                                        }
-                                        events.push(Ok(ChatResponse {
-                                            event: ChatEventType::Done,
-                                            data: Some(ChatResponseData::Empty),
-                                        }));
-                                        current_event = None;
-                                    }
-                                    ChatEventType::Error => {
-                                        if let Ok(json) = serde_json::from_str::<Value>(data) {
-                                            let text = json
-                                                .get("text")
-                                                .and_then(Value::as_str)
-                                                .unwrap_or("未知錯誤");
-                                            let allow_retry = json
-                                                .get("allow_retry")
-                                                .and_then(Value::as_bool)
-                                                .unwrap_or(false);
-
-                                            #[cfg(feature = "trace")]
-                                            warn!("收到錯誤事件: {}, 可重試: {}", text, allow_retry);
-
-                                            events.push(Ok(ChatResponse {
-                                                event: ChatEventType::Error,
-                                                data: Some(ChatResponseData::Error {
-                                                    text: text.to_string(),
-                                                    allow_retry,
-                                                }),
-                                            }));
-                                        } else {
-                                            #[cfg(feature = "trace")]
-                                            warn!("無法解析錯誤事件數據: {}", data);
-                                        }
-                                        current_event = None;
-                                    }
-                                }
-                            } else {
-                                #[cfg(feature = "trace")]
-                                debug!("收到數據但沒有當前事件類型");
-                            }
-                        } else if is_collecting_data {
-                            // 嘗試解析累積的 JSON
-                            #[cfg(feature = "trace")]
-                            debug!("嘗試解析未完整的 JSON 數據: {}", line);
-
-                            if let Some(ref event_type) = current_event {
-                                match event_type {
-                                    ChatEventType::Text | ChatEventType::ReplaceResponse => {
-                                        if let Ok(json) = serde_json::from_str::<Value>(&line) {
-                                            if let Some(text) = json.get("text").and_then(Value::as_str) {
-                                                #[cfg(feature = "trace")]
-                                                debug!("成功解析到累積的 JSON 文本，長度: {}", text.len());
-
+                                            } else {
+                                                // 沒有檢測到 XML，直接發送文本
                                                 events.push(Ok(ChatResponse {
                                                     event: event_type.clone(),
                                                     data: Some(ChatResponseData::Text {
                                                         text: text.to_string(),
                                                     }),
                                                 }));
-                                                is_collecting_data = false;
-                                                current_event = None;
                                             }
                                         }
-                                    }
-                                    ChatEventType::File => {
-                                        if let Ok(file_data) = serde_json::from_str::<FileData>(&line) {
-                                            #[cfg(feature = "trace")]
-                                            debug!("成功解析到累積的文件數據: {}", file_data.name);
 
+                                        #[cfg(not(feature = "xml-parse"))]
+                                        {
                                             events.push(Ok(ChatResponse {
-                                                event: ChatEventType::File,
-                                                data: Some(ChatResponseData::File(file_data)),
+                                                event: event_type.clone(),
+                                                data: Some(ChatResponseData::Text {
+                                                    text: text.to_string(),
+                                                }),
                                             }));
-                                            is_collecting_data = false;
-                                            current_event = None;
                                         }
                                     }
-                                    ChatEventType::Json => {
-                                        if let Ok(json) = serde_json::from_str::<Value>(&line) {
-                                            #[cfg(feature = "trace")]
-                                            debug!("成功解析到累積的 JSON 事件數據");
-
-                                            // 檢查是否有 finish_reason: "tool_calls"
-                                            let finish_reason = json
-                                                .get("choices")
-                                                .and_then(|choices| choices.get(0))
-                                                .and_then(|choice| choice.get("finish_reason"))
-                                                .and_then(Value::as_str);
-
-                                            if finish_reason == Some("tool_calls") {
-                                                #[cfg(feature = "trace")]
-                                                debug!("檢測到工具調用完成標誌");
-                                                tool_calls_complete = true;
-                                            }
-
-                                            // 檢查是否包含 tool_calls delta
-                                            let tool_calls_delta = json
-                                                .get("choices")
-                                                .and_then(|choices| choices.get(0))
-                                                .and_then(|choice| choice.get("delta"))
-                                                .and_then(|delta| delta.get("tool_calls"));
+                                } else {
+                                    #[cfg(feature = "trace")]
+                                    warn!(target: TARGET_SSE, "文本事件 JSON 解析失敗，略過: {}", data);
+                                }
+                            }
+                            ChatEventType::File => {
+                                if let Ok(file_data) = serde_json::from_str::<FileData>(data) {
+                                    #[cfg(feature = "trace")]
+                                    debug!(target: TARGET_SSE, "解析到文件數據: {}", file_data.name);
+                                    events.push(Ok(ChatResponse {
+                                        event: ChatEventType::File,
+                                        data: Some(ChatResponseData::File(file_data)),
+                                    }));
+                                } else {
+                                    #[cfg(feature = "trace")]
+                                    warn!(target: TARGET_SSE, "文件事件 JSON 解析失敗，略過: {}", data);
+                                }
+                            }
+                            ChatEventType::Json => {
+                                if let Ok(json) = serde_json::from_str::<Value>(data) {
+                                    #[cfg(feature = "trace")]
+                                    debug!(target: TARGET_SSE, "解析到 JSON 事件數據");
+                                    // OpenAI 格式的 usage 欄位另以 Usage 事件送出
+                                    if let Some(usage) = json.get("usage").and_then(Usage::from_payload) {
+                                        events.push(Ok(ChatResponse {
+                                            event: ChatEventType::Usage,
+                                            data: Some(ChatResponseData::Usage(usage)),
+                                        }));
+                                    }
+                                    // 累積所有 choice 的 tool_calls delta，任一 choice 回報
+                                    // finish_reason: "tool_calls" 即視為完成
+                                    let (has_tool_call_deltas, finished) =
+                                        Self::accumulate_tool_call_deltas(&json, &mut accumulated_tool_calls);
+                                    if finished {
+                                        #[cfg(feature = "trace")]
+                                        debug!(target: TARGET_SSE, "檢測到工具調用完成標誌");
+                                        tool_calls_complete = true;
+                                    }
 
-                                            if let Some(tool_calls_array) = tool_calls_delta {
+                                    if !has_tool_call_deltas && !tool_calls_complete {
+                                        // 如果沒有 tool_calls delta 且工具調用尚未完成，
+                                        // 則按一般 JSON 處理
+                                        events.push(Ok(ChatResponse {
+                                            event: ChatEventType::Json,
+                                            data: Some(ChatResponseData::Text {
+                                                text: data.to_string(),
+                                            }),
+                                        }));
+                                    }
+                                } else {
+                                    #[cfg(feature = "trace")]
+                                    warn!(target: TARGET_SSE, "JSON 事件解析失敗，略過: {}", data);
+                                }
+                            }
+                            ChatEventType::Done => {
+                                #[cfg(feature = "trace")]
+                                debug!(target: TARGET_SSE, "收到完成事件");
+                                // 處理任何剩餘的 XML 緩衝內容
+                                #[cfg(feature = "xml-parse")]
+                                {
+                                    if xml_detection_active && !xml_text_buffer.trim().is_empty() {
+                                        #[cfg(feature = "trace")]
+                                        debug!(target: TARGET_XML, "處理剩餘的 XML 緩衝內容，長度: {}", xml_text_buffer.len());
+                                        let message = ChatMessage {
+                                            role: ROLE_ASSISTANT.to_string(),
+                                            content: xml_text_buffer.clone(),
+                                            attachments: None,
+                                            content_type: "text/plain".to_string(),
+                                        };
+                                        // 使用工具定義來檢測和解析
+                                        if message.contains_xml_tool_calls_with_tools(&available_tools) {
+                                            let tool_calls = message.extract_xml_tool_calls_with_tools(&available_tools);
+                                            if !tool_calls.is_empty() {
                                                 #[cfg(feature = "trace")]
-                                                debug!("檢測到工具調用 delta");
-
-                                                // 處理每個工具調用的 delta
-                                                if let Some(tool_calls) = tool_calls_array.as_array() {
-                                                    for tool_call_delta in tool_calls {
-                                                        let index = tool_call_delta
-                                                            .get("index")
-                                                            .and_then(Value::as_u64)
-                                                            .unwrap_or(0)
-                                                            as usize;
-
-                                                        // 確保 accumulated_tool_calls 有足夠的元素
-                                                        while accumulated_tool_calls.len() <= index {
-                                                            accumulated_tool_calls.push(PartialToolCall::default());
-                                                        }
-
-                                                        // 更新 id 和 type
-                                                        if let Some(id) = tool_call_delta
-                                                            .get("id")
-                                                            .and_then(Value::as_str)
-                                                        {
-                                                            accumulated_tool_calls[index].id = id.to_string();
-                                                        }
-
-                                                        if let Some(type_str) = tool_call_delta
-                                                            .get("type")
-                                                            .and_then(Value::as_str)
-                                                        {
-                                                            accumulated_tool_calls[index].r#type = type_str.to_string();
-                                                        }
-
-                                                        // 更新 function 相關欄位
-                                                        if let Some(function) = tool_call_delta.get("function") {
-                                                            if let Some(name) = function
-                                                                .get("name")
-                                                                .and_then(Value::as_str)
-                                                            {
-                                                                accumulated_tool_calls[index].function_name = name.to_string();
-                                                            }
-
-                                                            if let Some(args) = function
-                                                                .get("arguments")
-                                                                .and_then(Value::as_str)
-                                                            {
-                                                                accumulated_tool_calls[index].function_arguments.push_str(args);
-                                                            }
-                                                        }
-                                                    }
-                                                }
-
-                                                // 如果工具調用完成，則創建並發送 ChatResponse
-                                                if tool_calls_complete && !accumulated_tool_calls.is_empty() {
-                                                    let complete_tool_calls = accumulated_tool_calls
-                                                        .iter()
-                                                        .filter(|tc| {
-                                                            !tc.id.is_empty() && !tc.function_name.is_empty()
-                                                        })
-                                                        .map(|tc| ChatToolCall {
-                                                            id: tc.id.clone(),
-                                                            r#type: tc.r#type.clone(),
-                                                            function: FunctionCall {
-                                                                name: tc.function_name.clone(),
-                                                                arguments: tc.function_arguments.clone(),
-                                                            },
-                                                        })
-                                                        .collect::<Vec<ChatToolCall>>();
-
-                                                    if !complete_tool_calls.is_empty() {
-                                                        #[cfg(feature = "trace")]
-                                                        debug!("發送完整的工具調用，數量: {}", complete_tool_calls.len());
-
-                                                        events.push(Ok(ChatResponse {
-                                                            event: ChatEventType::Json,
-                                                            data: Some(ChatResponseData::ToolCalls(complete_tool_calls)),
-                                                        }));
-
-                                                        // 重置累積狀態
-                                                        accumulated_tool_calls.clear();
-                                                        tool_calls_complete = false;
-                                                    }
+                                                debug!(target: TARGET_XML, "在完成事件中檢測到 XML 工具調用，數量: {}", tool_calls.len());
+                                                // 發送工具調用事件
+                                                events.push(Ok(ChatResponse {
+                                                    event: ChatEventType::Json,
+                                                    data: Some(ChatResponseData::ToolCalls(tool_calls)),
+                                                }));
+                                                // 發送清理後的文本（如果有）
+                                                let clean_text = Self::remove_xml_tool_calls(&xml_text_buffer);
+                                                if !clean_text.trim().is_empty() {
+                                                    events.push(Ok(ChatResponse {
+                                                        event: ChatEventType::Text,
+                                                        data: Some(ChatResponseData::Text {
+                                                            text: clean_text,
+                                                        }),
+                                                    }));
                                                 }
                                             } else {
-                                                // 如果沒有 tool_calls delta，則按一般 JSON 處理
+                                                // 發送為普通文本
                                                 events.push(Ok(ChatResponse {
-                                                    event: ChatEventType::Json,
+                                                    event: ChatEventType::Text,
                                                     data: Some(ChatResponseData::Text {
-                                                        text: line.to_string(),
+                                                        text: xml_text_buffer.clone(),
                                                     }),
                                                 }));
                                             }
-
-                                            is_collecting_data = false;
-                                            current_event = None;
+                                        } else {
+                                            // 發送為普通文本
+                                            events.push(Ok(ChatResponse {
+                                                event: ChatEventType::Text,
+                                                data: Some(ChatResponseData::Text {
+                                                    text: xml_text_buffer.clone(),
+                                                }),
+                                            }));
                                         }
+                                        // 清理緩衝狀態
+                                        xml_text_buffer.clear();
+                                        xml_detection_active = false;
                                     }
-                                    ChatEventType::Done | ChatEventType::Error => {
-                                        // 這些事件類型不應該有累積的數據
-                                        is_collecting_data = false;
+                                }
+                                // 同一塊中已完成的工具調用須在完成事件之前發送；
+                                // 未收到 finish_reason 的累積調用也一併送出，標記為推斷完成
+                                if tool_calls_complete {
+                                    let complete_tool_calls =
+                                        Self::drain_complete_tool_calls(&mut accumulated_tool_calls);
+                                    if !complete_tool_calls.is_empty() {
+                                        events.push(Ok(ChatResponse {
+                                            event: ChatEventType::Json,
+                                            data: Some(ChatResponseData::ToolCalls(complete_tool_calls)),
+                                        }));
+                                        tool_calls_complete = false;
                                     }
+                                } else {
+                                    events.extend(Self::flush_tool_calls(&mut accumulated_tool_calls));
+                                }
+                                if let Some(usage) = serde_json::from_str::<Value>(data)
+                                    .ok()
+                                    .and_then(|json| Usage::from_payload(&json))
+                                {
+                                    events.push(Ok(ChatResponse {
+                                        event: ChatEventType::Usage,
+                                        data: Some(ChatResponseData::Usage(usage)),
+                                    }));
+                                }
+                                // 完成事件若帶有內容（訊息 ID、token 數等）則保留
+                                let done_data = match DoneInfo::from_payload(data) {
+                                    Some(info) => ChatResponseData::Done(info),
+                                    None => ChatResponseData::Empty,
+                                };
+                                events.push(Ok(ChatResponse {
+                                    event: ChatEventType::Done,
+                                    data: Some(done_data),
+                                }));
+                            }
+                            ChatEventType::Error => {
+                                if let Ok(json) = serde_json::from_str::<Value>(data) {
+                                    let text = json
+                                        .get("text")
+                                        .and_then(Value::as_str)
+                                        .unwrap_or("未知錯誤");
+                                    let allow_retry = json
+                                        .get("allow_retry")
+                                        .and_then(Value::as_bool)
+                                        .unwrap_or(false);
+
+                                    #[cfg(feature = "trace")]
+                                    warn!(target: TARGET_SSE, "收到錯誤事件: {}, 可重試: {}", text, allow_retry);
+
+                                    events.push(Ok(ChatResponse {
+                                        event: ChatEventType::Error,
+                                        data: Some(ChatResponseData::Error {
+                                            text: text.to_string(),
+                                            allow_retry,
+                                        }),
+                                    }));
+                                } else {
+                                    #[cfg(feature = "trace")]
+                                    warn!(target: TARGET_SSE, "無法解析錯誤事件數據: {}", data);
                                 }
                             }
                         }
                     }
 
+                    if let Some(e) = overflow {
+                        #[cfg(feature = "trace")]
+                        warn!(target: TARGET_SSE, "{}", e);
+                        events.push(Err(e));
+                        return events;
+                    }
+
                     // 在處理完 chunk 中的所有行之後，檢查是否需要發送最終的 tool_calls 事件
-                    if tool_calls_complete && !accumulated_tool_calls.is_empty() {
-                        let complete_tool_calls = accumulated_tool_calls
-                            .iter()
-                            .filter(|tc| !tc.id.is_empty() && !tc.function_name.is_empty())
-                            .map(|tc| ChatToolCall {
-                                id: tc.id.clone(),
-                                r#type: tc.r#type.clone(),
-                                function: FunctionCall {
-                                    name: tc.function_name.clone(),
-                                    arguments: tc.function_arguments.clone(),
-                                },
-                            })
-                            .collect::<Vec<ChatToolCall>>();
+                    if tool_calls_complete {
+                        let complete_tool_calls = Self::drain_complete_tool_calls(&mut accumulated_tool_calls);
 
                         if !complete_tool_calls.is_empty() {
                             #[cfg(feature = "trace")]
-                            debug!("發送最終的完整工具調用，數量: {}", complete_tool_calls.len());
+                            debug!(target: TARGET_SSE, "發送最終的完整工具調用，數量: {}", complete_tool_calls.len());
 
                             events.push(Ok(ChatResponse {
                                 event: ChatEventType::Json,
@@ -811,11 +1297,26 @@ impl PoeClient {
                             }));
 
                             // 重置狀態
-                            accumulated_tool_calls.clear();
                             tool_calls_complete = false;
                         }
                     }
 
+                    // 未收到 finish_reason 時，於串流結束時送出已累積完整的工具調用
+                    if at_end && tool_call_completion.on_stream_end {
+                        events.extend(Self::flush_tool_calls(&mut accumulated_tool_calls));
+                    }
+
+                    // 回報此串流目前佔用的緩衝區大小
+                    #[cfg(feature = "xml-parse")]
+                    let buffered_bytes = sse_parser.buffered_len() + xml_text_buffer.len();
+                    #[cfg(not(feature = "xml-parse"))]
+                    let buffered_bytes = sse_parser.buffered_len();
+                    let tool_call_bytes = accumulated_tool_calls
+                        .iter()
+                        .map(|tc| tc.id.len() + tc.function_name.len() + tc.function_arguments.len())
+                        .sum();
+                    stream_guard.update(buffered_bytes, tool_call_bytes);
+
                     events
                 })
             })
@@ -824,15 +1325,323 @@ impl PoeClient {
                     Ok(events) => events,
                     Err(e) => {
                         #[cfg(feature = "trace")]
-                        warn!("串流處理錯誤: {}", e);
+                        warn!(target: TARGET_SSE, "串流處理錯誤: {}", e);
                         vec![Err(e)]
                     }
                 })
             });
 
+        // 行過長時中止串流，不再讀取剩餘的回應內容
+        let stream = stream.scan(false, |aborted, item| {
+            if *aborted {
+                return futures_util::future::ready(None);
+            }
+            *aborted = matches!(item, Err(PoeError::EventTooLarge { .. }));
+            futures_util::future::ready(Some(item))
+        });
+
+        // 若回應在沒有 done/error 事件的情況下結束，補發 StreamTruncated 讓呼叫端能辨識截斷
+        let terminated = Arc::new(AtomicBool::new(false));
+        let seen_terminal = terminated.clone();
+        let stream = stream
+            .inspect(move |item| {
+                if matches!(
+                    item,
+                    Err(_)
+                        | Ok(ChatResponse {
+                            event: ChatEventType::Done | ChatEventType::Error,
+                            ..
+                        })
+                ) {
+                    seen_terminal.store(true, Ordering::Relaxed);
+                }
+            })
+            .chain(
+                futures_util::stream::once(async move { terminated.load(Ordering::Relaxed) })
+                    .filter_map(|terminated| async move {
+                        if terminated {
+                            None
+                        } else {
+                            #[cfg(feature = "trace")]
+                            warn!(target: TARGET_SSE, "串流在收到 done 事件前結束");
+                            Some(Err(PoeError::StreamTruncated))
+                        }
+                    }),
+            );
+
         Ok(Box::pin(stream))
     }
 
+    /// Accumulate the tool call deltas of every choice in an OpenAI-style chunk,
+    /// returning whether it carried any and whether a choice finished with `tool_calls`
+    fn accumulate_tool_call_deltas(
+        json: &Value,
+        accumulated_tool_calls: &mut Vec<PartialToolCall>,
+    ) -> (bool, bool) {
+        let mut has_deltas = false;
+        let mut finished = false;
+        let choices = json.get("choices").and_then(Value::as_array);
+
+        for (position, choice) in choices.into_iter().flatten().enumerate() {
+            let choice_index = choice
+                .get("index")
+                .and_then(Value::as_u64)
+                .map_or(position, |index| index as usize);
+            finished |= choice.get("finish_reason").and_then(Value::as_str) == Some("tool_calls");
+
+            let Some(deltas) = choice.get("delta").and_then(|delta| delta.get("tool_calls")) else {
+                continue;
+            };
+            has_deltas = true;
+
+            for tool_call_delta in deltas.as_array().into_iter().flatten() {
+                let index = tool_call_delta
+                    .get("index")
+                    .and_then(Value::as_u64)
+                    .unwrap_or(0) as usize;
+                let position = accumulated_tool_calls
+                    .iter()
+                    .position(|tc| tc.choice_index == choice_index && tc.index == index)
+                    .unwrap_or_else(|| {
+                        accumulated_tool_calls.push(PartialToolCall {
+                            choice_index,
+                            index,
+                            ..Default::default()
+                        });
+                        accumulated_tool_calls.len() - 1
+                    });
+                let tool_call = &mut accumulated_tool_calls[position];
+
+                // 同一調用的每個 delta 常重複 id、type 與名稱，相同時不重新配置
+                if let Some(id) = tool_call_delta.get("id").and_then(Value::as_str)
+                    && tool_call.id != id
+                {
+                    tool_call.id = id.to_string();
+                }
+                if let Some(type_str) = tool_call_delta.get("type").and_then(Value::as_str)
+                    && tool_call.r#type != type_str
+                {
+                    tool_call.r#type = type_str.to_string();
+                }
+                if let Some(function) = tool_call_delta.get("function") {
+                    if let Some(name) = function.get("name").and_then(Value::as_str)
+                        && tool_call.function_name != name
+                    {
+                        tool_call.function_name = name.to_string();
+                    }
+                    if let Some(arguments) = function.get("arguments").and_then(Value::as_str) {
+                        tool_call.function_arguments.push_str(arguments);
+                    }
+                }
+            }
+        }
+
+        (has_deltas, finished)
+    }
+
+    /// Emit the complete accumulated tool calls, if any, as a `json` event
+    /// with every call flagged as inferred
+    fn flush_tool_calls(
+        accumulated_tool_calls: &mut Vec<PartialToolCall>,
+    ) -> Vec<Result<ChatResponse, PoeError>> {
+        let mut complete_tool_calls = Self::drain_complete_tool_calls(accumulated_tool_calls);
+        if complete_tool_calls.is_empty() {
+            return Vec::new();
+        }
+        for tool_call in &mut complete_tool_calls {
+            tool_call.inferred = true;
+        }
+
+        #[cfg(feature = "trace")]
+        debug!(target: TARGET_SSE, "未收到 finish_reason，送出已累積的工具調用，數量: {}", complete_tool_calls.len());
+        vec![Ok(ChatResponse {
+            event: ChatEventType::Json,
+            data: Some(ChatResponseData::ToolCalls(complete_tool_calls)),
+        })]
+    }
+
+    /// Take the fully accumulated tool calls (with id and name), clearing the accumulator
+    fn drain_complete_tool_calls(accumulated_tool_calls: &mut Vec<PartialToolCall>) -> Vec<ChatToolCall> {
+        let is_complete = |tc: &PartialToolCall| !tc.id.is_empty() && !tc.function_name.is_empty();
+        if !accumulated_tool_calls.iter().any(is_complete) {
+            return Vec::new();
+        }
+
+        // 依 choice 與調用順序排列，直接移出累積的字串，避免逐欄位複製
+        accumulated_tool_calls.sort_by_key(|tc| (tc.choice_index, tc.index));
+        accumulated_tool_calls
+            .drain(..)
+            .filter(is_complete)
+            .map(|tc| ChatToolCall {
+                id: tc.id,
+                r#type: tc.r#type,
+                function: FunctionCall {
+                    name: tc.function_name,
+                    arguments: tc.function_arguments,
+                },
+                inferred: false,
+                choice_index: tc.choice_index,
+            })
+            .collect()
+    }
+
+    /// Send a chat request and wait for the whole response: final text, tool
+    /// calls, files and `done` payload.
+    ///
+    /// An `error` event fails the call with `PoeError::BotError`; the error
+    /// keeps whatever was received before the failure in `partial`.
+    pub async fn send_request(
+        &self,
+        request: ChatRequest,
+    ) -> Result<CompleteResponse, PoeErrorWithPartial> {
+        self.stream_request(request).await?.collect_response().await
+    }
+
+    /// Ask a one-shot question and return the bot's final text
+    pub async fn ask(&self, prompt: &str) -> Result<String, PoeError> {
+        let request = ChatRequest::builder().user_message(prompt).build()?;
+        Ok(self.send_request(request).await?.text)
+    }
+
+    /// Relay a user's rating of a bot message, e.g. a thumbs up/down from a
+    /// chat UI
+    pub async fn report_feedback(
+        &self,
+        message_id: &str,
+        conversation_id: &str,
+        feedback_type: FeedbackType,
+    ) -> Result<(), PoeError> {
+        self.send_feedback(ReportFeedbackRequest::new(
+            message_id,
+            conversation_id,
+            feedback_type,
+        ))
+        .await
+    }
+
+    /// Send a `report_feedback` request to the bot, e.g. one carrying a user ID
+    pub async fn send_feedback(&self, request: ReportFeedbackRequest) -> Result<(), PoeError> {
+        #[cfg(feature = "trace")]
+        debug!(
+            target: TARGET_CLIENT,
+            "回報訊息 {} 的回饋: {:?}", request.message_id, request.feedback_type
+        );
+
+        let url = join_endpoint(&self.inner.poe_base_url, &["bot", &self.inner.bot_name])?.to_string();
+        let headers = vec![
+            ("Authorization".to_string(), format!("Bearer {}", self.inner.access_key)),
+            ("Content-Type".to_string(), "application/json".to_string()),
+        ];
+        let response = self
+            .send_signed("POST", &url, headers, serde_json::to_vec(&request)?)
+            .await?;
+
+        if !response.is_success() {
+            let error = status_error(response, &url).await;
+            #[cfg(feature = "trace")]
+            warn!(target: TARGET_CLIENT, "回報回饋失敗: {}", error);
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    /// Stream a chat request that stops as soon as `abort` is triggered.
+    ///
+    /// Aborting drops the underlying HTTP response, closing the connection
+    /// instead of waiting for the bot to finish; the stream then yields
+    /// `PoeError::Aborted` once and ends.
+    pub async fn stream_request_with_cancel(
+        &self,
+        request: ChatRequest,
+        abort: AbortHandle,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatResponse, PoeError>> + Send + '_>>, PoeError> {
+        // select 先輪詢左側，中止優先於剛好同時完成的請求
+        let stream = match select(pin!(abort.aborted()), pin!(self.stream_request(request))).await {
+            Either::Left(_) => return Err(PoeError::Aborted),
+            Either::Right((stream, _)) => stream?,
+        };
+
+        Ok(Box::pin(futures_util::stream::unfold(
+            Some(stream),
+            move |stream| {
+                let abort = abort.clone();
+                async move {
+                    let mut stream = stream?;
+                    match select(pin!(abort.aborted()), stream.next()).await {
+                        // 丟棄內部串流即釋放 HTTP 連線
+                        Either::Left(_) => Some((Err(PoeError::Aborted), None)),
+                        Either::Right((item, _)) => item.map(|item| (item, Some(stream))),
+                    }
+                }
+            },
+        )))
+    }
+
+    /// 將文字增量直接串流寫入 `writer`（stdout、檔案、socket），
+    /// `replace_response` 以 [`ReplaceStrategy::CarriageReturn`] 改寫，回傳最終文字
+    pub async fn pipe_text_to<W>(
+        &self,
+        request: ChatRequest,
+        writer: &mut W,
+    ) -> Result<String, PoeError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        self.pipe_text_to_with(request, writer, ReplaceStrategy::default())
+            .await
+    }
+
+    /// 與 `pipe_text_to` 相同，但可指定 `replace_response` 的處理方式
+    pub async fn pipe_text_to_with<W>(
+        &self,
+        request: ChatRequest,
+        writer: &mut W,
+        strategy: ReplaceStrategy,
+    ) -> Result<String, PoeError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut stream = self.stream_request(request).await?;
+        let mut text = String::new();
+
+        while let Some(response) = stream.next().await {
+            let response = response?;
+            let chunk = match (&response.event, response.data) {
+                (ChatEventType::Text, Some(ChatResponseData::Text { text: delta })) => {
+                    text.push_str(&delta);
+                    delta
+                }
+                (ChatEventType::ReplaceResponse, Some(ChatResponseData::Text { text: full })) => {
+                    let Some(prefix) = strategy.prefix() else {
+                        continue;
+                    };
+                    let chunk = format!("{}{}", prefix, full);
+                    text = full;
+                    chunk
+                }
+                (ChatEventType::Error, Some(ChatResponseData::Error { text: message, .. })) => {
+                    return Err(PoeError::BotError(message));
+                }
+                (ChatEventType::Done, _) => break,
+                _ => continue,
+            };
+
+            writer
+                .write_all(chunk.as_bytes())
+                .await
+                .map_err(PoeError::OutputWriteFailed)?;
+            writer.flush().await.map_err(PoeError::OutputWriteFailed)?;
+        }
+
+        Ok(text)
+    }
+
+    /// Stream a request to completion and return its final text
+    pub(crate) async fn collect_text(&self, request: ChatRequest) -> Result<String, PoeError> {
+        let response = self.stream_request(request).await?.collect_response().await?;
+        Ok(response.text)
+    }
+
     pub async fn send_tool_results(
         &self,
         original_request: ChatRequest,
@@ -840,16 +1649,16 @@ impl PoeClient {
         tool_results: Vec<ChatToolResult>,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatResponse, PoeError>> + Send + '_>>, PoeError> {
         #[cfg(feature = "trace")]
-        debug!("發送工具調用結果，bot_name: {}", self.bot_name);
+        debug!(target: TARGET_CLIENT, "發送工具調用結果，bot_name: {}", self.inner.bot_name);
 
         // 創建包含工具結果的新請求
         let mut request = original_request;
 
         // 當啟用 xml feature 時，將工具結果以 XML 格式附加到訊息末尾
-        #[cfg(feature = "xml")]
+        #[cfg(feature = "xml-prompt")]
         {
             #[cfg(feature = "trace")]
-            debug!("檢測到 xml feature 啟用，將工具結果轉換為 XML 格式並附加到訊息末尾");
+            debug!(target: TARGET_XML, "檢測到 xml feature 啟用，將工具結果轉換為 XML 格式並附加到訊息末尾");
 
             // 先設置工具調用和結果，以便 XML 轉換方法可以訪問
             request.tool_calls = Some(tool_calls);
@@ -864,6 +1673,7 @@ impl PoeClient {
 
             #[cfg(feature = "trace")]
             debug!(
+                target: TARGET_XML,
                 "🔧 工具結果 XML 轉換完成，檢查訊息內容: {}",
                 request
                     .query
@@ -875,7 +1685,7 @@ impl PoeClient {
         }
 
         // 當未啟用 xml feature 時，使用原有的 JSON API 方式
-        #[cfg(not(feature = "xml"))]
+        #[cfg(not(feature = "xml-prompt"))]
         {
             request.tool_calls = Some(tool_calls);
             request.tool_results = Some(tool_results);
@@ -883,6 +1693,7 @@ impl PoeClient {
 
         #[cfg(feature = "trace")]
         debug!(
+            target: TARGET_CLIENT,
             "發送工具結果請求結構: {}",
             serde_json::to_string_pretty(&request).unwrap_or_else(|_| "無法序列化請求".to_string())
         );
@@ -891,322 +1702,91 @@ impl PoeClient {
         self.stream_request(request).await
     }
 
-    /// 上傳本地檔案
-    pub async fn upload_local_file(
+    /// Download a file (e.g. an attachment URL), verifying its SHA-256 when
+    /// `expected_sha256` is given (such as `FileUploadResponse::sha256`)
+    pub async fn download_file(
         &self,
-        file_path: &str,
-        mime_type: Option<&str>,
-    ) -> Result<FileUploadResponse, PoeError> {
-        #[cfg(feature = "trace")]
-        debug!(
-            "開始上傳本地檔案: {} | MIME 類型: {:?}",
-            file_path, mime_type
-        );
-        // 檢查檔案是否存在
-        let path = Path::new(file_path);
-        if !path.exists() {
-            #[cfg(feature = "trace")]
-            warn!("檔案不存在: {}", file_path);
-            return Err(PoeError::FileNotFound(file_path.to_string()));
-        }
-
-        // 簡化 MIME 類型處理：如果有提供 mime_type 就使用，否則使用預設值
-        let content_type = mime_type.unwrap_or("application/octet-stream").to_string();
-
+        url: &str,
+        expected_sha256: Option<&str>,
+    ) -> Result<Vec<u8>, PoeError> {
         #[cfg(feature = "trace")]
-        debug!("使用 MIME 類型: {}", content_type);
+        debug!(target: TARGET_CLIENT, "開始下載檔案: {}", url);
 
-        // 建立 multipart 表單
-        let file = tokio::fs::File::open(path).await.map_err(|e| {
+        url::Url::parse(url)?;
+        let response = self.send_signed("GET", url, Vec::new(), Vec::new()).await?;
+        if !response.is_success() {
+            let error = status_error(response, url).await;
             #[cfg(feature = "trace")]
-            warn!("無法開啟檔案: {}", e);
-            PoeError::FileReadError(e)
-        })?;
-
-        let file_part =
-            reqwest::multipart::Part::stream(reqwest::Body::wrap_stream(ReaderStream::new(file)))
-                .file_name(
-                    path.file_name()
-                        .and_then(|name| name.to_str())
-                        .unwrap_or("file")
-                        .to_string(),
-                )
-                .mime_str(&content_type)
-                .map_err(|e| {
-                    #[cfg(feature = "trace")]
-                    warn!("設置 MIME 類型失敗: {}", e);
-                    PoeError::FileUploadFailed(format!("設置 MIME 類型失敗: {}", e))
-                })?;
-
-        let form = reqwest::multipart::Form::new().part("file", file_part);
-
-        // 發送請求
-        self.send_upload_request(form).await
-    }
-
-    /// 上傳遠端檔案 (通過URL)
-    pub async fn upload_remote_file(
-        &self,
-        download_url: &str,
-    ) -> Result<FileUploadResponse, PoeError> {
-        #[cfg(feature = "trace")]
-        debug!("開始上傳遠端檔案: {}", download_url);
-
-        // 檢查URL格式
-        url::Url::parse(download_url)?;
-
-        // 建立 multipart 表單
-        let form = reqwest::multipart::Form::new().text("download_url", download_url.to_string());
-
-        // 發送請求
-        self.send_upload_request(form).await
-    }
-
-    /// 批量上傳檔案 (接受混合的本地和遠端檔案)
-    pub async fn upload_files_batch(
-        &self,
-        files: Vec<FileUploadRequest>,
-    ) -> Result<Vec<FileUploadResponse>, PoeError> {
-        #[cfg(feature = "trace")]
-        debug!("開始批量上傳檔案，數量: {}", files.len());
-
-        if files.is_empty() {
-            return Ok(Vec::new());
-        }
-
-        // 為每個檔案創建上傳任務
-        let mut upload_tasks = Vec::with_capacity(files.len());
-
-        for file_request in files {
-            let task = match file_request {
-                FileUploadRequest::LocalFile { file, mime_type } => {
-                    let client = self.clone();
-                    let file_path = file.clone();
-                    tokio::spawn(async move {
-                        client
-                            .upload_local_file(&file_path, mime_type.as_deref())
-                            .await
-                    })
-                }
-                FileUploadRequest::RemoteFile { download_url } => {
-                    let client = self.clone();
-                    let url = download_url.clone();
-                    tokio::spawn(async move { client.upload_remote_file(&url).await })
-                }
-            };
-            upload_tasks.push(task);
-        }
-
-        // 等待所有上傳任務完成
-        let results = join_all(upload_tasks).await;
-
-        // 收集結果
-        let mut upload_responses = Vec::with_capacity(results.len());
-
-        for task_result in results.into_iter() {
-            match task_result {
-                Ok(upload_result) => match upload_result {
-                    Ok(response) => {
-                        #[cfg(feature = "trace")]
-                        debug!("檔案上傳成功: {}", response.attachment_url);
-                        upload_responses.push(response);
-                    }
-                    Err(e) => {
-                        #[cfg(feature = "trace")]
-                        warn!("檔案上傳失敗: {}", e);
-                        return Err(e);
-                    }
-                },
-                Err(e) => {
-                    #[cfg(feature = "trace")]
-                    warn!("檔案上傳任務失敗: {}", e);
-                    return Err(PoeError::FileUploadFailed(format!("上傳任務失敗: {}", e)));
-                }
-            }
+            warn!(target: TARGET_CLIENT, "下載檔案失敗: {}", error);
+            return Err(error);
         }
 
-        #[cfg(feature = "trace")]
-        debug!("批量上傳全部成功，共 {} 個檔案", upload_responses.len());
-
-        Ok(upload_responses)
-    }
-
-    /// Send file upload request (internal method)
-    async fn send_upload_request(
-        &self,
-        form: reqwest::multipart::Form,
-    ) -> Result<FileUploadResponse, PoeError> {
-        #[cfg(feature = "trace")]
-        debug!("Sending file upload request to {}", self.poe_file_upload_url);
+        let bytes = response
+            .body
+            .try_fold(Vec::new(), |mut bytes, chunk| {
+                bytes.extend_from_slice(&chunk);
+                futures_util::future::ready(Ok(bytes))
+            })
+            .await?;
 
-        // Prepare request logging
-        #[cfg(feature = "trace")]
-        let request_start_time = LoggingHelper::get_timestamp();
-        #[cfg(not(feature = "trace"))]
-        let _request_start_time = LoggingHelper::get_timestamp();
-        
-        #[cfg(feature = "trace")]
+        let actual = to_hex(&Sha256::digest(&bytes));
+        if let Some(expected) = expected_sha256
+            && !expected.eq_ignore_ascii_case(&actual)
         {
-            let request_log = RequestLog {
-                timestamp: request_start_time,
-                method: "POST".to_string(),
-                url: self.poe_file_upload_url.clone(),
-                headers: Some(vec![
-                    ("Authorization".to_string(), "Bearer ***MASKED***".to_string()),
-                    ("Content-Type".to_string(), "multipart/form-data".to_string()),
-                ]),
-                body: Some("Multipart form data".to_string()),
-                body_size: None,
-            };
-            LoggingHelper::log_request(&request_log, &self.logging_config);
-        }
-
-        let response = self
-            .client
-            .post(&self.poe_file_upload_url)
-            .header("Authorization", format!("Bearer {}", self.access_key))
-            .multipart(form)
-            .send()
-            .await
-            .map_err(|e| {
-                #[cfg(feature = "trace")]
-                warn!("File upload request failed: {}", e);
-                PoeError::RequestFailed(e)
-            })?;
-
-        #[cfg(feature = "trace")]
-        let response_start_time = LoggingHelper::get_timestamp();
-        #[cfg(not(feature = "trace"))]
-        let _response_start_time = LoggingHelper::get_timestamp();
-        #[cfg(feature = "trace")]
-        let duration_ms = response_start_time - request_start_time;
-
-            let status = response.status();
-        
-        if !status.is_success() {
-            let text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unable to read response content".to_string());
-
-            #[cfg(feature = "trace")]
-            warn!("File upload API response error - status code: {}, content: {}", status, text);
-
-            // Log error response
-            #[cfg(feature = "trace")]
-            {
-                let response_log = ResponseLog {
-                    timestamp: response_start_time,
-                    status_code: status.as_u16(),
-                    headers: None,
-                    body: Some(text.clone()),
-                    body_size: Some(text.len()),
-                    duration_ms: Some(duration_ms),
-                };
-                LoggingHelper::log_response(&response_log, &self.logging_config);
-            }
-
-            return Err(PoeError::FileUploadFailed(format!(
-                "Upload failed - status code: {}, content: {}",
-                status, text
-            )));
-        }
-
-        #[cfg(feature = "trace")]
-        debug!("Successfully received file upload response");
-
-        let response_text = response.text().await.map_err(|e| {
             #[cfg(feature = "trace")]
-            warn!("Failed to read file upload response content: {}", e);
-            PoeError::RequestFailed(e)
-        })?;
-
-        // Log successful response
-        #[cfg(feature = "trace")]
-        {
-            let response_log = ResponseLog {
-                timestamp: response_start_time,
-                status_code: status.as_u16(),
-                headers: None,
-                body: Some(response_text.clone()),
-                body_size: Some(response_text.len()),
-                duration_ms: Some(duration_ms),
-            };
-            LoggingHelper::log_response(&response_log, &self.logging_config);
+            warn!(target: TARGET_CLIENT, "下載檔案的 SHA-256 不符: {} | 預期: {} | 實際: {}", url, expected, actual);
+            return Err(PoeError::ChecksumMismatch {
+                file: url.to_string(),
+                expected: expected.to_ascii_lowercase(),
+                actual,
+            });
         }
 
-        #[cfg(feature = "trace")]
-        debug!("檔案上傳回應內容: {}", response_text);
-
-        let upload_response: FileUploadResponse =
-            serde_json::from_str(&response_text).map_err(|e| {
-                #[cfg(feature = "trace")]
-                warn!("解析檔案上傳回應失敗: {}", e);
-                PoeError::JsonParseFailed(e)
-            })?;
-
-        #[cfg(feature = "trace")]
-        debug!("檔案上傳成功，附件URL: {}", upload_response.attachment_url);
-
-        Ok(upload_response)
+        Ok(bytes)
     }
 
     /// 獲取 v1/models API 的模型列表 (需要 access_key)
     pub async fn get_v1_model_list(&self) -> Result<ModelResponse, PoeError> {
         #[cfg(feature = "trace")]
-        debug!("開始獲取 v1/models 模型列表");
+        debug!(target: TARGET_CLIENT, "開始獲取 v1/models 模型列表");
 
-        let url = format!("{}/v1/models", self.poe_base_url);
+        let url = join_endpoint(&self.inner.poe_base_url, &["v1", "models"])?.to_string();
         #[cfg(feature = "trace")]
-        debug!("發送 v1/models 請求至 URL: {}", url);
+        debug!(target: TARGET_CLIENT, "發送 v1/models 請求至 URL: {}", url);
 
+        let headers = vec![
+            ("Authorization".to_string(), format!("Bearer {}", self.inner.access_key)),
+            ("Content-Type".to_string(), "application/json".to_string()),
+        ];
         let response = self
-            .client
-            .get(&url)
-            .header("Authorization", format!("Bearer {}", self.access_key))
-            .header("Content-Type", "application/json")
-            .send()
+            .send_signed("GET", &url, headers, Vec::new())
             .await
-            .map_err(|e| {
+            .inspect_err(|_e| {
                 #[cfg(feature = "trace")]
-                warn!("發送 v1/models 請求失敗: {}", e);
-                PoeError::RequestFailed(e)
+                warn!(target: TARGET_CLIENT, "發送 v1/models 請求失敗: {}", _e);
             })?;
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "無法讀取回應內容".to_string());
-
+        if !response.is_success() {
+            let error = status_error(response, &url).await;
             #[cfg(feature = "trace")]
-            warn!(
-                "v1/models API 回應錯誤 - 狀態碼: {}, 內容: {}",
-                status, text
-            );
-
-            return Err(PoeError::BotError(format!(
-                "v1/models API 回應錯誤 - 狀態碼: {}, 內容: {}",
-                status, text
-            )));
+            warn!(target: TARGET_CLIENT, "v1/models API 回應錯誤: {}", error);
+            return Err(error);
         }
 
         #[cfg(feature = "trace")]
-        debug!("成功接收到 v1/models 回應");
+        debug!(target: TARGET_CLIENT, "成功接收到 v1/models 回應");
 
-        let response_text = response.text().await.map_err(|e| {
+        let response_text = response.text().await.inspect_err(|_e| {
             #[cfg(feature = "trace")]
-            warn!("讀取 v1/models 回應內容失敗: {}", e);
-            PoeError::RequestFailed(e)
+            warn!(target: TARGET_CLIENT, "讀取 v1/models 回應內容失敗: {}", _e);
         })?;
 
         #[cfg(feature = "trace")]
-        debug!("v1/models 回應內容: {}", response_text);
+        debug!(target: TARGET_CLIENT, "v1/models 回應內容: {}", response_text);
 
         let json_data: Value = serde_json::from_str(&response_text).map_err(|e| {
             #[cfg(feature = "trace")]
-            warn!("解析 v1/models 回應失敗: {}", e);
+            warn!(target: TARGET_CLIENT, "解析 v1/models 回應失敗: {}", e);
             PoeError::JsonParseFailed(e)
         })?;
 
@@ -1214,7 +1794,7 @@ impl PoeClient {
 
         if let Some(data_array) = json_data.get("data").and_then(Value::as_array) {
             #[cfg(feature = "trace")]
-            debug!("找到 {} 個模型", data_array.len());
+            debug!(target: TARGET_CLIENT, "找到 {} 個模型", data_array.len());
 
             for model_data in data_array {
                 if let (Some(id), Some(object), Some(created), Some(owned_by)) = (
@@ -1228,12 +1808,15 @@ impl PoeClient {
                         object: object.to_string(),
                         created,
                         owned_by: owned_by.to_string(),
+                        architecture: model_data
+                            .get("architecture")
+                            .and_then(|architecture| serde_json::from_value(architecture.clone()).ok()),
                     });
                 }
             }
         } else {
             #[cfg(feature = "trace")]
-            warn!("無法從 v1/models 回應中取得模型列表");
+            warn!(target: TARGET_CLIENT, "無法從 v1/models 回應中取得模型列表");
             return Err(PoeError::BotError(
                 "無法從 v1/models 回應中取得模型列表".to_string(),
             ));
@@ -1241,22 +1824,30 @@ impl PoeClient {
 
         if model_list.is_empty() {
             #[cfg(feature = "trace")]
-            warn!("取得的模型列表為空");
+            warn!(target: TARGET_CLIENT, "取得的模型列表為空");
             return Err(PoeError::BotError("取得的模型列表為空".to_string()));
         }
 
         #[cfg(feature = "trace")]
-        debug!("成功解析 {} 個模型", model_list.len());
+        debug!(target: TARGET_CLIENT, "成功解析 {} 個模型", model_list.len());
 
         Ok(ModelResponse { data: model_list })
     }
 
+    /// 獲取 v1/models API 的模型列表，並依 `filter` 篩選
+    pub async fn get_v1_model_list_filtered(
+        &self,
+        filter: &ModelFilter,
+    ) -> Result<ModelResponse, PoeError> {
+        Ok(self.get_v1_model_list().await?.filtered(filter))
+    }
+
     /// 從文本中移除 XML 工具調用部分
-    #[cfg(feature = "xml")]
+    #[cfg(feature = "xml-parse")]
     pub fn remove_xml_tool_calls(text: &str) -> String {
         // 創建一個臨時的 ChatMessage 來檢測工具調用
         let message = ChatMessage {
-            role: "assistant".to_string(),
+            role: ROLE_ASSISTANT.to_string(),
             content: text.to_string(),
             attachments: None,
             content_type: "text/plain".to_string(),
@@ -1320,148 +1911,45 @@ impl PoeClient {
     }
 }
 
-pub async fn get_model_list(language_code: Option<&str>) -> Result<ModelResponse, PoeError> {
-    #[cfg(feature = "trace")]
-    debug!("開始獲取模型列表，語言代碼: {:?}", language_code);
-
-    let client = Client::builder()
-        .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
-        .build()
-        .map_err(|e| {
-            #[cfg(feature = "trace")]
-            warn!("建立 HTTP 客戶端失敗: {}", e);
-            PoeError::BotError(e.to_string())
-        })?;
-
-    let payload = serde_json::json!({
-        "queryName": "ExploreBotsListPaginationQuery",
-        "variables": {
-            "categoryName": "defaultCategory",
-            "count": 150
-        },
-        "extensions": {
-            "hash": POE_GQL_MODEL_HASH
-        }
-    });
-
-    #[cfg(feature = "trace")]
-    debug!("準備 GraphQL 請求載荷，使用 hash: {}", POE_GQL_MODEL_HASH);
-
-    let mut headers = HeaderMap::new();
-    headers.insert("Content-Type", HeaderValue::from_static("application/json"));
-    headers.insert("Accept", HeaderValue::from_static("*/*"));
-    headers.insert(
-        "Accept-Language",
-        HeaderValue::from_static("zh-TW,zh;q=0.9,en-US;q=0.8,en;q=0.7"),
-    );
-    headers.insert("Origin", HeaderValue::from_static("https://poe.com"));
-    headers.insert("Referer", HeaderValue::from_static("https://poe.com"));
-    headers.insert("Sec-Fetch-Dest", HeaderValue::from_static("empty"));
-    headers.insert("Sec-Fetch-Mode", HeaderValue::from_static("cors"));
-    headers.insert("Sec-Fetch-Site", HeaderValue::from_static("same-origin"));
-    headers.insert(
-        "poe-revision",
-        HeaderValue::from_static(POE_GQL_MODEL_REVISION),
-    );
-    headers.insert("poegraphql", HeaderValue::from_static("1"));
-
-    if let Some(code) = language_code {
-        let cookie_value = format!("Poe-Language-Code={}; p-b=1", code);
-        #[cfg(feature = "trace")]
-        debug!("設置語言 Cookie: {}", cookie_value);
-
-        headers.insert(
-            COOKIE,
-            HeaderValue::from_str(&cookie_value).map_err(|e| {
-                #[cfg(feature = "trace")]
-                warn!("設置 Cookie 失敗: {}", e);
-                PoeError::BotError(e.to_string())
-            })?,
-        );
-    }
-
-    #[cfg(feature = "trace")]
-    debug!("發送 GraphQL 請求至 {}", POE_GQL_URL);
-
-    let response = client
-        .post(POE_GQL_URL)
-        .headers(headers)
-        .json(&payload)
-        .send()
-        .await
-        .map_err(|e| {
-            #[cfg(feature = "trace")]
-            warn!("發送 GraphQL 請求失敗: {}", e);
-            PoeError::RequestFailed(e)
-        })?;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let text = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "無法讀取回應內容".to_string());
-
-        #[cfg(feature = "trace")]
-        warn!("GraphQL API 回應錯誤 - 狀態碼: {}, 內容: {}", status, text);
-
-        return Err(PoeError::BotError(format!(
-            "API 回應錯誤 - 狀態碼: {}, 內容: {}",
-            status, text
-        )));
-    }
-
-    #[cfg(feature = "trace")]
-    debug!("成功接收到 GraphQL 回應");
-
-    let json_value = response.text().await.map_err(|e| {
-        #[cfg(feature = "trace")]
-        warn!("讀取 GraphQL 回應內容失敗: {}", e);
-        PoeError::RequestFailed(e)
-    })?;
-
-    let data: Value = serde_json::from_str(&json_value).map_err(|e| {
-        #[cfg(feature = "trace")]
-        warn!("解析 GraphQL 回應 JSON 失敗: {}", e);
-        PoeError::JsonParseFailed(e)
-    })?;
-
-    let mut model_list = Vec::with_capacity(150);
+enum BodyEvent<T> {
+    Chunk(T),
+    /// No data arrived within the quiescence window
+    Idle,
+    End,
+}
 
-    if let Some(edges) = data["data"]["exploreBotsConnection"]["edges"].as_array() {
-        #[cfg(feature = "trace")]
-        debug!("找到 {} 個模型節點", edges.len());
+/// 讀取非 2xx 回應的內容並轉為 `PoeError::ApiStatus`
+pub(crate) async fn status_error(mut response: HttpResponse, endpoint: &str) -> PoeError {
+    let status = response.status;
+    let headers = std::mem::take(&mut response.headers);
+    let body = response.text().await.unwrap_or_default();
+    PoeError::api_status(endpoint, status, &headers, &body)
+}
 
-        for edge in edges {
-            if let Some(handle) = edge["node"]["handle"].as_str() {
-                #[cfg(feature = "trace")]
-                debug!("解析模型 ID: {}", handle);
-
-                model_list.push(ModelInfo {
-                    id: handle.to_string(),
-                    object: "model".to_string(),
-                    created: 0,
-                    owned_by: "poe".to_string(),
-                });
-            } else {
-                #[cfg(feature = "trace")]
-                debug!("模型節點中找不到 handle 欄位");
+// 包裝回應主體：結束時多送一個 End，設定閒置時間時於無資料期間送出 Idle
+fn body_events<S>(
+    body: S,
+    idle: Option<Duration>,
+    timer: Arc<dyn Timer>,
+) -> impl Stream<Item = BodyEvent<S::Item>>
+where
+    S: Stream + Unpin,
+{
+    futures_util::stream::unfold(Some(body), move |body| {
+        let timer = timer.clone();
+        async move {
+            let mut body = body?;
+            let next = match idle {
+                Some(window) => match timeout(timer.as_ref(), window, body.next()).await {
+                    Some(next) => next,
+                    None => return Some((BodyEvent::Idle, Some(body))),
+                },
+                None => body.next().await,
+            };
+            match next {
+                Some(item) => Some((BodyEvent::Chunk(item), Some(body))),
+                None => Some((BodyEvent::End, None)),
             }
         }
-    } else {
-        #[cfg(feature = "trace")]
-        warn!("無法從回應中取得模型列表節點");
-        return Err(PoeError::BotError("無法從回應中取得模型列表".to_string()));
-    }
-
-    if model_list.is_empty() {
-        #[cfg(feature = "trace")]
-        warn!("取得的模型列表為空");
-        return Err(PoeError::BotError("取得的模型列表為空".to_string()));
-    }
-
-    #[cfg(feature = "trace")]
-    debug!("成功解析 {} 個模型", model_list.len());
-
-    Ok(ModelResponse { data: model_list })
+    })
 }
@@ -0,0 +1,165 @@
+use super::{POE_API_BASE_URL, POE_FILE_UPLOAD_URL, PoeClient, parse_endpoint};
+use crate::error::PoeError;
+#[cfg(feature = "reqwest")]
+use reqwest::Client;
+#[cfg(feature = "reqwest")]
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+#[cfg(feature = "reqwest")]
+use std::time::Duration;
+
+/// Builder for a [`PoeClient`] with custom HTTP settings, created by
+/// [`PoeClient::builder`].
+///
+/// The URLs default to the public Poe endpoints. Timeouts, default headers
+/// and the user agent configure a new `reqwest::Client`; they are ignored
+/// when a prebuilt client is passed to `PoeClientBuilder::http_client`. These
+/// HTTP settings need the `reqwest` feature; with another transport, pass it
+/// to [`PoeClient::with_transport`] after building.
+#[derive(Debug, Clone)]
+pub struct PoeClientBuilder {
+    bot_name: String,
+    access_key: String,
+    base_url: String,
+    file_upload_url: String,
+    #[cfg(feature = "reqwest")]
+    timeout: Option<Duration>,
+    #[cfg(feature = "reqwest")]
+    connect_timeout: Option<Duration>,
+    #[cfg(feature = "reqwest")]
+    default_headers: Vec<(String, String)>,
+    #[cfg(feature = "reqwest")]
+    user_agent: Option<String>,
+    #[cfg(feature = "reqwest")]
+    http_client: Option<Client>,
+}
+
+impl Default for PoeClientBuilder {
+    fn default() -> Self {
+        Self {
+            bot_name: String::new(),
+            access_key: String::new(),
+            base_url: POE_API_BASE_URL.to_string(),
+            file_upload_url: POE_FILE_UPLOAD_URL.to_string(),
+            #[cfg(feature = "reqwest")]
+            timeout: None,
+            #[cfg(feature = "reqwest")]
+            connect_timeout: None,
+            #[cfg(feature = "reqwest")]
+            default_headers: Vec::new(),
+            #[cfg(feature = "reqwest")]
+            user_agent: None,
+            #[cfg(feature = "reqwest")]
+            http_client: None,
+        }
+    }
+}
+
+impl PoeClientBuilder {
+    pub fn bot(mut self, bot_name: &str) -> Self {
+        self.bot_name = bot_name.to_string();
+        self
+    }
+
+    pub fn access_key(mut self, access_key: &str) -> Self {
+        self.access_key = access_key.to_string();
+        self
+    }
+
+    pub fn base_url(mut self, url: &str) -> Self {
+        self.base_url = url.to_string();
+        self
+    }
+
+    pub fn file_upload_url(mut self, url: &str) -> Self {
+        self.file_upload_url = url.to_string();
+        self
+    }
+
+    /// Limit each request, including reading a streamed response, to `timeout`
+    #[cfg(feature = "reqwest")]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    #[cfg(feature = "reqwest")]
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Send a header with every request
+    #[cfg(feature = "reqwest")]
+    pub fn default_header(mut self, name: &str, value: &str) -> Self {
+        self.default_headers
+            .push((name.to_string(), value.to_string()));
+        self
+    }
+
+    #[cfg(feature = "reqwest")]
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Use a prebuilt client, e.g. one with a proxy whose connection pool is
+    /// shared by clients for many bots
+    #[cfg(feature = "reqwest")]
+    pub fn http_client(mut self, client: Client) -> Self {
+        self.http_client = Some(client);
+        self
+    }
+
+    /// Build the client, failing on invalid URLs ([`PoeError::InvalidUrl`]),
+    /// headers or HTTP settings
+    pub fn build(self) -> Result<PoeClient, PoeError> {
+        parse_endpoint(&self.base_url)?;
+        parse_endpoint(&self.file_upload_url)?;
+
+        let client = PoeClient::new(
+            &self.bot_name,
+            &self.access_key,
+            &self.base_url,
+            &self.file_upload_url,
+        );
+        #[cfg(feature = "reqwest")]
+        let client = client.with_http_client(self.build_http_client()?);
+        Ok(client)
+    }
+
+    // 未提供現成 client 時，依設定建立新的 reqwest::Client
+    #[cfg(feature = "reqwest")]
+    fn build_http_client(&self) -> Result<Client, PoeError> {
+        let http_client = match &self.http_client {
+            Some(client) => client.clone(),
+            None => {
+                let mut headers = HeaderMap::new();
+                for (name, value) in &self.default_headers {
+                    let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| {
+                        PoeError::TransportError(format!("Invalid header name {}: {}", name, e))
+                    })?;
+                    let value = HeaderValue::from_str(value).map_err(|e| {
+                        PoeError::TransportError(format!(
+                            "Invalid value for header {}: {}",
+                            name, e
+                        ))
+                    })?;
+                    headers.append(name, value);
+                }
+
+                let mut builder = Client::builder().default_headers(headers);
+                if let Some(timeout) = self.timeout {
+                    builder = builder.timeout(timeout);
+                }
+                if let Some(timeout) = self.connect_timeout {
+                    builder = builder.connect_timeout(timeout);
+                }
+                if let Some(user_agent) = &self.user_agent {
+                    builder = builder.user_agent(user_agent);
+                }
+                builder.build()?
+            }
+        };
+        Ok(http_client)
+    }
+}
@@ -0,0 +1,483 @@
+use super::{POE_API_BASE_URL, POE_FILE_UPLOAD_URL, PoeClient, status_error};
+use crate::error::PoeError;
+#[cfg(feature = "trace")]
+use crate::logging::TARGET_GQL;
+use crate::transport::HttpRequest;
+use crate::types::*;
+#[cfg(feature = "cookies")]
+use reqwest::cookie::{CookieStore, Jar};
+#[cfg(feature = "cookies")]
+use reqwest::header::HeaderValue;
+use serde_json::Value;
+use std::sync::{Arc, OnceLock};
+#[cfg(feature = "trace")]
+use tracing::{debug, warn};
+
+pub(super) const POE_GQL_URL: &str = "https://poe.com/api/gql_POST";
+const POE_GQL_MODEL_QUERY: &str = "ExploreBotsListPaginationQuery";
+pub(super) const POE_GQL_MODEL_HASH: &str =
+    "b24b2f2f6da147b3345eec1a433ed17b6e1332df97dea47622868f41078a40cc";
+const POE_GQL_MODEL_HASH_ENV: &str = "POE_GQL_MODEL_HASH";
+const POE_GQL_MODEL_REVISION: &str = "e2acc7025b43e08e88164ba8105273f37fbeaa26";
+const POE_GQL_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+const POE_DEFAULT_ACCEPT_LANGUAGE: &str = "zh-TW,zh;q=0.9,en-US;q=0.8,en;q=0.7";
+
+impl PoeClient {
+    /// Configure the locale used for poe.com GraphQL calls (`Accept-Language` and `Poe-Language-Code` cookie)
+    pub fn with_language_code(mut self, language_code: &str) -> Self {
+        Arc::make_mut(&mut self.inner).language_code = Some(language_code.to_string());
+        self
+    }
+
+    /// Override the `Accept-Language` header sent on poe.com GraphQL calls
+    pub fn with_accept_language(mut self, accept_language: &str) -> Self {
+        Arc::make_mut(&mut self.inner).accept_language = Some(accept_language.to_string());
+        self
+    }
+
+    /// Get the configured client locale
+    pub fn get_language_code(&self) -> Option<&str> {
+        self.inner.language_code.as_deref()
+    }
+
+    /// Attach a logged-in poe.com web session, used only by GraphQL calls
+    pub fn with_web_session(mut self, session: PoeWebSession) -> Self {
+        Arc::make_mut(&mut self.inner).web_session = Some(session);
+        self
+    }
+
+    /// Check whether a poe.com web session is configured
+    pub fn has_web_session(&self) -> bool {
+        self.inner.web_session.is_some()
+    }
+
+    /// Configure which model list source `get_models` uses and whether it falls back
+    pub fn with_model_list_policy(mut self, policy: ModelListPolicy) -> Self {
+        Arc::make_mut(&mut self.inner).model_list_policy = policy;
+        self
+    }
+
+    /// Override the poe.com GraphQL endpoint (e.g. to route through a proxy);
+    /// an invalid URL fails GraphQL requests with [`PoeError::InvalidUrl`]
+    pub fn with_graphql_url(mut self, url: &str) -> Self {
+        Arc::make_mut(&mut self.inner).gql_url = super::parse_endpoint(url);
+        self
+    }
+
+    /// Configure where to look for a new persisted query hash when the current one expires,
+    /// tried in order (default: the env override)
+    pub fn with_graphql_hash_sources(mut self, sources: Vec<GraphqlHashSource>) -> Self {
+        Arc::make_mut(&mut self.inner).gql_hash_sources = sources;
+        self
+    }
+
+    /// Use `jar` to persist cookies set by poe.com GraphQL responses (e.g. shared between clients)
+    #[cfg(feature = "cookies")]
+    pub fn with_cookie_jar(mut self, jar: Arc<Jar>) -> Self {
+        Arc::make_mut(&mut self.inner).cookie_jar = jar;
+        self
+    }
+
+    /// Get the cookie jar used for poe.com GraphQL calls
+    #[cfg(feature = "cookies")]
+    pub fn cookie_jar(&self) -> Arc<Jar> {
+        self.inner.cookie_jar.clone()
+    }
+
+    /// Build the `Cookie` value for a GraphQL call
+    pub(crate) fn gql_cookie(&self, language_code: Option<&str>) -> Option<String> {
+        let mut cookies = Vec::new();
+
+        if let Some(code) = language_code {
+            cookies.push(format!("Poe-Language-Code={}", code));
+        }
+
+        match &self.inner.web_session {
+            Some(session) => cookies.push(session.cookie_value()),
+            None if language_code.is_some() => cookies.push("p-b=1".to_string()),
+            None => {}
+        }
+
+        // 補上 cookie jar 中保存、且未被明確設定覆蓋的 cookie
+        #[cfg(feature = "cookies")]
+        if let Ok(url) = &self.inner.gql_url
+            && let Some(stored) = self.inner.cookie_jar.cookies(url)
+            && let Ok(stored) = stored.to_str()
+        {
+            let explicit = cookies.join("; ");
+            let explicit_names: Vec<&str> = explicit
+                .split("; ")
+                .filter_map(|cookie| cookie.split('=').next())
+                .collect();
+            let stored: Vec<String> = stored
+                .split("; ")
+                .filter(|cookie| {
+                    let name = cookie.split('=').next().unwrap_or_default();
+                    !explicit_names.contains(&name)
+                })
+                .map(str::to_string)
+                .collect();
+            cookies.extend(stored);
+        }
+
+        if cookies.is_empty() {
+            None
+        } else {
+            Some(cookies.join("; "))
+        }
+    }
+
+    /// Build the `Accept-Language` value for a GraphQL call
+    pub(crate) fn accept_language_header(&self, language_code: Option<&str>) -> String {
+        if let Some(accept_language) = &self.inner.accept_language {
+            return accept_language.clone();
+        }
+
+        match language_code {
+            Some(code) => {
+                let primary = code.split('-').next().unwrap_or(code);
+                let mut values = vec![code.to_string()];
+                if primary != code {
+                    values.push(format!("{};q=0.9", primary));
+                }
+                if primary != "en" {
+                    values.push("en;q=0.8".to_string());
+                }
+                values.join(",")
+            }
+            None => POE_DEFAULT_ACCEPT_LANGUAGE.to_string(),
+        }
+    }
+
+    /// 獲取 poe.com GraphQL 探索列表中的模型 (不需要 access_key)
+    ///
+    /// 若 persisted query hash 已失效，會依 `with_graphql_hash_sources` 設定的來源
+    /// 嘗試取得新 hash 並重試，成功的 hash 會被記住供後續呼叫使用
+    #[cfg_attr(feature = "trace", tracing::instrument(skip(self), fields(bot_name = %self.inner.bot_name)))]
+    pub async fn get_model_list(
+        &self,
+        language_code: Option<&str>,
+    ) -> Result<ModelResponse, PoeError> {
+        #[cfg(feature = "trace")]
+        debug!(target: TARGET_GQL, "開始獲取模型列表，語言代碼: {:?}", language_code);
+
+        let language_code = language_code.or(self.inner.language_code.as_deref());
+        let current_hash = self.current_gql_model_hash();
+
+        let error = match self.query_model_list(language_code, &current_hash).await {
+            Err(e) if e.is_graphql_hash_expired() => e,
+            result => return result,
+        };
+
+        #[cfg(feature = "trace")]
+        warn!(target: TARGET_GQL, "GraphQL hash 已失效，嘗試刷新: {}", error);
+
+        let mut tried = vec![current_hash];
+        for hash in self.gql_model_hash_candidates().await {
+            if tried.contains(&hash) {
+                continue;
+            }
+
+            if !self.allow_retry() {
+                #[cfg(feature = "trace")]
+                warn!(target: TARGET_GQL, "重試預算已用盡，不再嘗試刷新 GraphQL hash");
+                break;
+            }
+
+            #[cfg(feature = "trace")]
+            debug!(target: TARGET_GQL, "使用候選 hash 重試: {}", hash);
+
+            match self.query_model_list(language_code, &hash).await {
+                Ok(models) => {
+                    *self
+                        .inner
+                        .gql_model_hash
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner()) = hash;
+                    return Ok(models);
+                }
+                Err(e) if e.is_graphql_hash_expired() => tried.push(hash),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(error)
+    }
+
+    /// 目前使用的 GraphQL 模型列表 hash
+    fn current_gql_model_hash(&self) -> String {
+        self.inner
+            .gql_model_hash
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// 依設定的來源順序收集候選 hash，無法取得的來源會被略過
+    async fn gql_model_hash_candidates(&self) -> Vec<String> {
+        let mut candidates = Vec::new();
+
+        for source in &self.inner.gql_hash_sources {
+            match source {
+                GraphqlHashSource::Env => {
+                    if let Ok(hash) = std::env::var(POE_GQL_MODEL_HASH_ENV)
+                        && !hash.trim().is_empty()
+                    {
+                        candidates.push(hash.trim().to_string());
+                    }
+                }
+                GraphqlHashSource::Remote(url) => match self.fetch_remote_gql_hashes(url).await {
+                    Ok(hashes) => candidates.extend(hashes),
+                    Err(_e) => {
+                        #[cfg(feature = "trace")]
+                        warn!(target: TARGET_GQL, "從 {} 取得 GraphQL hash 失敗: {}", url, _e);
+                    }
+                },
+            }
+        }
+
+        candidates
+    }
+
+    /// 從遠端 JSON 文件讀取模型列表查詢的 hash
+    async fn fetch_remote_gql_hashes(&self, url: &str) -> Result<Vec<String>, PoeError> {
+        let response = self
+            .inner
+            .transport
+            .send(HttpRequest {
+                method: "GET".to_string(),
+                url: url.to_string(),
+                headers: Vec::new(),
+                body: Vec::new(),
+            })
+            .await?;
+        if !response.is_success() {
+            return Err(status_error(response, url).await);
+        }
+        let body: Value = serde_json::from_str(&response.text().await?)?;
+
+        Ok(match &body[POE_GQL_MODEL_QUERY] {
+            Value::String(hash) => vec![hash.clone()],
+            Value::Array(hashes) => hashes
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect(),
+            _ => Vec::new(),
+        })
+    }
+
+    /// 使用指定的 persisted query hash 發送一次模型列表查詢
+    async fn query_model_list(
+        &self,
+        language_code: Option<&str>,
+        hash: &str,
+    ) -> Result<ModelResponse, PoeError> {
+        let payload = serde_json::json!({
+            "queryName": POE_GQL_MODEL_QUERY,
+            "variables": {
+                "categoryName": "defaultCategory",
+                "count": 150
+            },
+            "extensions": {
+                "hash": hash
+            }
+        });
+
+        #[cfg(feature = "trace")]
+        debug!(target: TARGET_GQL, "準備 GraphQL 請求載荷，使用 hash: {}", hash);
+
+        let mut headers: Vec<(String, String)> = [
+            ("content-type", "application/json"),
+            ("accept", "*/*"),
+            ("user-agent", POE_GQL_USER_AGENT),
+            ("origin", "https://poe.com"),
+            ("referer", "https://poe.com"),
+            ("sec-fetch-dest", "empty"),
+            ("sec-fetch-mode", "cors"),
+            ("sec-fetch-site", "same-origin"),
+            ("poe-revision", POE_GQL_MODEL_REVISION),
+            ("poegraphql", "1"),
+        ]
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        let accept_language = header("accept-language", self.accept_language_header(language_code))
+            .inspect_err(|_e| {
+                #[cfg(feature = "trace")]
+                warn!(target: TARGET_GQL, "設置 Accept-Language 失敗: {}", _e);
+            })?;
+        headers.push(accept_language);
+
+        if let Some(cookie_value) = self.gql_cookie(language_code) {
+            // Cookie 可能包含登入憑證，僅記錄語言代碼
+            #[cfg(feature = "trace")]
+            debug!(
+                target: TARGET_GQL,
+                "設置 Cookie，語言代碼: {:?}，使用登入 session: {}",
+                language_code,
+                self.inner.web_session.is_some()
+            );
+
+            headers.push(header("cookie", cookie_value).inspect_err(|_e| {
+                #[cfg(feature = "trace")]
+                warn!(target: TARGET_GQL, "設置 Cookie 失敗: {}", _e);
+            })?);
+        }
+
+        let gql_url = self.inner.gql_url.clone()?;
+
+        #[cfg(feature = "trace")]
+        debug!(target: TARGET_GQL, "發送 GraphQL 請求至 {}", gql_url);
+
+        let body = serde_json::to_vec(&payload)?;
+        let mut response = self
+            .send_signed("POST", gql_url.as_str(), headers, body)
+            .await
+            .inspect_err(|_e| {
+                #[cfg(feature = "trace")]
+                warn!(target: TARGET_GQL, "發送 GraphQL 請求失敗: {}", _e);
+            })?;
+
+        #[cfg(feature = "cookies")]
+        {
+            let set_cookies: Vec<HeaderValue> = response
+                .header_values("set-cookie")
+                .filter_map(|value| HeaderValue::from_str(value).ok())
+                .collect();
+            self.inner
+                .cookie_jar
+                .set_cookies(&mut set_cookies.iter(), &gql_url);
+        }
+
+        if !response.is_success() {
+            let status = response.status;
+            let headers = std::mem::take(&mut response.headers);
+            let text = response.text().await.unwrap_or_default();
+
+            #[cfg(feature = "trace")]
+            warn!(target: TARGET_GQL, "GraphQL API 回應錯誤 - 狀態碼: {}, 內容: {}", status, text);
+
+            let errors = serde_json::from_str::<Value>(&text)
+                .map(|body| GraphqlError::from_response(&body))
+                .unwrap_or_default();
+            if !errors.is_empty() {
+                return Err(PoeError::GraphqlError {
+                    query_name: POE_GQL_MODEL_QUERY.to_string(),
+                    errors,
+                });
+            }
+
+            return Err(PoeError::api_status(gql_url.as_str(), status, &headers, &text));
+        }
+
+        #[cfg(feature = "trace")]
+        debug!(target: TARGET_GQL, "成功接收到 GraphQL 回應");
+
+        let json_value = response.text().await.inspect_err(|_e| {
+            #[cfg(feature = "trace")]
+            warn!(target: TARGET_GQL, "讀取 GraphQL 回應內容失敗: {}", _e);
+        })?;
+
+        let data: Value = serde_json::from_str(&json_value).map_err(|e| {
+            #[cfg(feature = "trace")]
+            warn!(target: TARGET_GQL, "解析 GraphQL 回應 JSON 失敗: {}", e);
+            PoeError::JsonParseFailed(e)
+        })?;
+
+        let mut model_list = Vec::with_capacity(150);
+
+        if let Some(edges) = data["data"]["exploreBotsConnection"]["edges"].as_array() {
+            #[cfg(feature = "trace")]
+            debug!(target: TARGET_GQL, "找到 {} 個模型節點", edges.len());
+
+            for edge in edges {
+                if let Some(handle) = edge["node"]["handle"].as_str() {
+                    #[cfg(feature = "trace")]
+                    debug!(target: TARGET_GQL, "解析模型 ID: {}", handle);
+
+                    model_list.push(ModelInfo {
+                        id: handle.to_string(),
+                        object: "model".to_string(),
+                        created: 0,
+                        owned_by: "poe".to_string(),
+                        architecture: None,
+                    });
+                } else {
+                    #[cfg(feature = "trace")]
+                    debug!(target: TARGET_GQL, "模型節點中找不到 handle 欄位");
+                }
+            }
+        } else {
+            let errors = GraphqlError::from_response(&data);
+            if !errors.is_empty() {
+                #[cfg(feature = "trace")]
+                warn!(target: TARGET_GQL, "GraphQL 回應包含 {} 個錯誤", errors.len());
+                return Err(PoeError::GraphqlError {
+                    query_name: POE_GQL_MODEL_QUERY.to_string(),
+                    errors,
+                });
+            }
+
+            #[cfg(feature = "trace")]
+            warn!(target: TARGET_GQL, "無法從回應中取得模型列表節點");
+            return Err(PoeError::BotError("無法從回應中取得模型列表".to_string()));
+        }
+
+        if model_list.is_empty() {
+            #[cfg(feature = "trace")]
+            warn!(target: TARGET_GQL, "取得的模型列表為空");
+            return Err(PoeError::BotError("取得的模型列表為空".to_string()));
+        }
+
+        #[cfg(feature = "trace")]
+        debug!(target: TARGET_GQL, "成功解析 {} 個模型", model_list.len());
+
+        Ok(ModelResponse { data: model_list })
+    }
+
+    /// 依照 ModelListPolicy 獲取模型列表，主要來源失敗時自動切換至備用來源
+    ///
+    /// 兩個來源都失敗時返回備用來源的錯誤
+    pub async fn get_models(&self) -> Result<ModelResponse, PoeError> {
+        match self.inner.model_list_policy {
+            ModelListPolicy::V1Only => self.get_v1_model_list().await,
+            ModelListPolicy::GraphqlOnly => self.get_model_list(None).await,
+            ModelListPolicy::V1ThenGraphql => match self.get_v1_model_list().await {
+                Ok(models) => Ok(models),
+                Err(_e) => {
+                    #[cfg(feature = "trace")]
+                    warn!(target: TARGET_GQL, "v1/models 獲取失敗，改用 GraphQL 模型列表: {}", _e);
+                    self.get_model_list(None).await
+                }
+            },
+            ModelListPolicy::GraphqlThenV1 => match self.get_model_list(None).await {
+                Ok(models) => Ok(models),
+                Err(_e) => {
+                    #[cfg(feature = "trace")]
+                    warn!(target: TARGET_GQL, "GraphQL 模型列表獲取失敗，改用 v1/models: {}", _e);
+                    self.get_v1_model_list().await
+                }
+            },
+        }
+    }
+}
+
+/// 使用預設客戶端獲取模型列表 (不需要 access_key)
+pub async fn get_model_list(language_code: Option<&str>) -> Result<ModelResponse, PoeError> {
+    // 共用同一個客戶端，讓重複呼叫重用連線池及刷新後的 hash
+    static DEFAULT_CLIENT: OnceLock<PoeClient> = OnceLock::new();
+
+    DEFAULT_CLIENT
+        .get_or_init(|| PoeClient::new("", "", POE_API_BASE_URL, POE_FILE_UPLOAD_URL))
+        .get_model_list(language_code)
+        .await
+}
+
+// 標頭值不可含控制字元（如 CR/LF），規則與 HTTP 函式庫一致
+fn header(name: &str, value: String) -> Result<(String, String), PoeError> {
+    if value.bytes().any(|byte| (byte < 0x20 && byte != b'\t') || byte == 0x7f) {
+        return Err(PoeError::BotError(format!("invalid value for header {}", name)));
+    }
+    Ok((name.to_string(), value))
+}
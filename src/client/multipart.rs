@@ -0,0 +1,87 @@
+use crate::error::PoeError;
+use crate::util::generate_id;
+
+/// `multipart/form-data` body of an upload request, encoded in memory so any
+/// [`HttpTransport`](crate::transport::HttpTransport) can send it
+pub(crate) struct MultipartForm {
+    boundary: String,
+    body: Vec<u8>,
+}
+
+impl MultipartForm {
+    pub(crate) fn new() -> Self {
+        Self {
+            boundary: generate_id("poe-form-boundary"),
+            body: Vec::new(),
+        }
+    }
+
+    pub(crate) fn text(mut self, name: &str, value: &str) -> Self {
+        self.part_header(&format!("form-data; name=\"{}\"", escape(name)), None);
+        self.body.extend_from_slice(value.as_bytes());
+        self.body.extend_from_slice(b"\r\n");
+        self
+    }
+
+    /// Add a file field, rejecting content types that are not `type/subtype`
+    pub(crate) fn file(
+        mut self,
+        name: &str,
+        file_name: &str,
+        content_type: &str,
+        bytes: &[u8],
+    ) -> Result<Self, PoeError> {
+        let valid = content_type
+            .split_once('/')
+            .is_some_and(|(kind, subtype)| !kind.trim().is_empty() && !subtype.trim().is_empty())
+            && !content_type.chars().any(char::is_control);
+        if !valid {
+            return Err(PoeError::FileUploadFailed(format!(
+                "設置 MIME 類型失敗: 無效的 MIME 類型 '{}'",
+                content_type
+            )));
+        }
+
+        self.part_header(
+            &format!(
+                "form-data; name=\"{}\"; filename=\"{}\"",
+                escape(name),
+                escape(file_name)
+            ),
+            Some(content_type),
+        );
+        self.body.extend_from_slice(bytes);
+        self.body.extend_from_slice(b"\r\n");
+        Ok(self)
+    }
+
+    /// Close the body, returning its `Content-Type` header value and bytes
+    pub(crate) fn finish(mut self) -> (String, Vec<u8>) {
+        self.body
+            .extend_from_slice(format!("--{}--\r\n", self.boundary).as_bytes());
+        (
+            format!("multipart/form-data; boundary={}", self.boundary),
+            self.body,
+        )
+    }
+
+    fn part_header(&mut self, disposition: &str, content_type: Option<&str>) {
+        let mut header = format!(
+            "--{}\r\nContent-Disposition: {}\r\n",
+            self.boundary, disposition
+        );
+        if let Some(content_type) = content_type {
+            header.push_str(&format!("Content-Type: {}\r\n", content_type));
+        }
+        header.push_str("\r\n");
+        self.body.extend_from_slice(header.as_bytes());
+    }
+}
+
+// 依 HTML 表單編碼規則跳脫引號與換行，避免破壞 Content-Disposition
+fn escape(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
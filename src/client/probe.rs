@@ -0,0 +1,156 @@
+use super::PoeClient;
+use crate::aggregate::CompleteResponse;
+use crate::error::{PoeError, PoeErrorWithPartial};
+use crate::types::*;
+
+const PROBE_TOOL_NAME: &str = "report_probe_result";
+const PROBE_LONG_OUTPUT_COUNT: usize = 1000;
+
+/// Capabilities of a bot, as observed by [`PoeClient::probe_capabilities`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BotCapabilities {
+    /// The bot answered a forced tool call with a tool call
+    pub supports_tools: bool,
+    /// Image input listed in the v1/models entry, `None` when the bot is not listed
+    pub supports_images: Option<bool>,
+    /// The bot replied with bare, parseable JSON when asked to
+    pub json_mode: bool,
+    /// Characters returned when asked for a long answer; a lower bound on the
+    /// output limit when the answer was complete
+    pub output_length: usize,
+    /// Whether the long answer was complete, i.e. `output_length` was not cut off
+    pub output_complete: bool,
+}
+
+impl PoeClient {
+    /// Run a small battery of probe prompts against `bot` (tool calling, JSON
+    /// replies, a long answer) and look up its image support in the model list.
+    ///
+    /// Results are cached per bot and shared across clones of this client;
+    /// each uncached call sends three chat requests billed to the access key.
+    /// A probe the bot answers with an `error` event counts as unsupported.
+    pub async fn probe_capabilities(&self, bot: &str) -> Result<BotCapabilities, PoeError> {
+        if let Some(capabilities) = self.cached_capabilities(bot) {
+            return Ok(capabilities);
+        }
+
+        let client = self.clone().with_bot(bot);
+        let tool_probe = ChatRequest {
+            tools: Some(vec![
+                ChatTool::function(PROBE_TOOL_NAME)
+                    .description("Report the result of the capability probe")
+                    .param("status", ParamType::String, true)
+                    .enum_values(["ok"])
+                    .build(),
+            ]),
+            tool_choice: Some(ToolChoice::Function(PROBE_TOOL_NAME.to_string())),
+            ..probe_request(&format!(
+                "Call the {} tool with status \"ok\". Do not reply with text.",
+                PROBE_TOOL_NAME
+            ))
+        };
+        let supports_tools = probe(&client, tool_probe)
+            .await?
+            .is_some_and(|response| !response.tool_calls.is_empty());
+
+        let json_mode = probe(
+            &client,
+            probe_request(
+                "Reply with exactly this JSON object and nothing else, no code fences: {\"ok\": true}",
+            ),
+        )
+        .await?
+        .is_some_and(|response| {
+            serde_json::from_str::<serde_json::Value>(response.text.trim())
+                .is_ok_and(|value| value["ok"] == true)
+        });
+
+        let long_output = probe(
+            &client,
+            probe_request(&format!(
+                "Write every whole number from 1 to {} in order, separated by single spaces, with no other text.",
+                PROBE_LONG_OUTPUT_COUNT
+            )),
+        )
+        .await?
+        .unwrap_or_default();
+        let output_complete = long_output
+            .text
+            .split_whitespace()
+            .last()
+            .is_some_and(|last| last.trim_end_matches('.') == PROBE_LONG_OUTPUT_COUNT.to_string());
+
+        // 模型列表無法取得時只是無法判斷圖片支援，不影響其他結果
+        let supports_images = self.get_v1_model_list().await.ok().and_then(|models| {
+            models.find(bot).map(|model| {
+                model.architecture.as_ref().is_some_and(|architecture| {
+                    architecture
+                        .input_modalities
+                        .iter()
+                        .any(|modality| modality == "image")
+                })
+            })
+        });
+
+        let capabilities = BotCapabilities {
+            supports_tools,
+            supports_images,
+            json_mode,
+            output_length: long_output.text.chars().count(),
+            output_complete,
+        };
+        self.inner
+            .capability_cache
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(bot.to_string(), capabilities.clone());
+        Ok(capabilities)
+    }
+
+    /// Get the cached probe result of `bot`, if it was probed before
+    pub fn cached_capabilities(&self, bot: &str) -> Option<BotCapabilities> {
+        self.inner
+            .capability_cache
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(bot)
+            .cloned()
+    }
+
+    /// Forget every probe result, e.g. after a bot was updated
+    pub fn clear_capability_cache(&self) {
+        self.inner
+            .capability_cache
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
+
+fn probe_request(prompt: &str) -> ChatRequest {
+    ChatRequest {
+        query: vec![ChatMessage {
+            role: ROLE_USER.to_string(),
+            content: prompt.to_string(),
+            content_type: "text/markdown".to_string(),
+            attachments: None,
+        }],
+        temperature: Some(0.0),
+        ..Default::default()
+    }
+}
+
+/// Send a probe, returning `None` when the bot rejected it with an `error` event
+async fn probe(
+    client: &PoeClient,
+    request: ChatRequest,
+) -> Result<Option<CompleteResponse>, PoeError> {
+    match client.send_request(request).await {
+        Ok(response) => Ok(Some(response)),
+        Err(PoeErrorWithPartial {
+            error: PoeError::BotError(_),
+            ..
+        }) => Ok(None),
+        Err(error) => Err(error.error),
+    }
+}
@@ -0,0 +1,519 @@
+use super::{MultipartForm, PoeClient};
+use crate::error::PoeError;
+use crate::logging::*;
+use crate::types::*;
+use crate::util::{sniff_mime, to_hex};
+use futures_util::future::join_all;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+#[cfg(feature = "trace")]
+use tracing::{debug, warn};
+
+// 附件在到期前多久視為過期並重新上傳
+const ATTACHMENT_REFRESH_MARGIN_SECS: u64 = 60;
+
+impl PoeClient {
+    /// Call `handler` when the server reports a different MIME type for an
+    /// uploaded file than the declared (or sniffed) one
+    pub fn with_mime_mismatch_handler(
+        mut self,
+        handler: impl Fn(&MimeMismatch) + Send + Sync + 'static,
+    ) -> Self {
+        Arc::make_mut(&mut self.inner).mime_mismatch_handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// Assume uploaded attachment URLs expire after `ttl` when the server
+    /// does not report an expiry; stale attachments are re-uploaded before sending
+    pub fn with_attachment_ttl(mut self, ttl: Duration) -> Self {
+        Arc::make_mut(&mut self.inner).attachment_ttl = Some(ttl);
+        self
+    }
+
+    /// Send attachments to a different endpoint than Poe's, e.g. a proxy
+    /// (see [`PoeClient::post_message_attachment`])
+    pub fn with_file_attachment_url(mut self, url: &str) -> Self {
+        Arc::make_mut(&mut self.inner).poe_file_attachment_url = super::parse_endpoint(url);
+        self
+    }
+
+    /// 上傳本地檔案
+    pub async fn upload_local_file(
+        &self,
+        file_path: &str,
+        mime_type: Option<&str>,
+    ) -> Result<FileUploadResponse, PoeError> {
+        #[cfg(feature = "trace")]
+        debug!(
+            target: TARGET_UPLOAD,
+            "開始上傳本地檔案: {} | MIME 類型: {:?}",
+            file_path, mime_type
+        );
+        // 檢查檔案是否存在
+        let path = Path::new(file_path);
+        if !path.exists() {
+            #[cfg(feature = "trace")]
+            warn!(target: TARGET_UPLOAD, "檔案不存在: {}", file_path);
+            return Err(PoeError::FileNotFound(file_path.to_string()));
+        }
+
+        // 簡化 MIME 類型處理：如果有提供 mime_type 就使用，否則使用預設值
+        let content_type = mime_type.unwrap_or("application/octet-stream").to_string();
+
+        #[cfg(feature = "trace")]
+        debug!(target: TARGET_UPLOAD, "使用 MIME 類型: {}", content_type);
+
+        // 讀取檔案並計算 SHA-256，供上傳後比對與完整性紀錄
+        let (file_name, bytes) = read_local_file(path).await?;
+        let digest = to_hex(&Sha256::digest(&bytes));
+
+        // 建立 multipart 表單
+        let form = MultipartForm::new().file("file", &file_name, &content_type, &bytes)?;
+
+        // 發送請求
+        let response = self.send_upload_request(form).await?;
+        let response = record_checksum(file_path, response, digest)?;
+        self.check_upload_mime(file_path, mime_type, &response)
+            .await;
+        Ok(self.track_upload(
+            response,
+            FileUploadRequest::LocalFile {
+                file: file_path.to_string(),
+                mime_type: mime_type.map(str::to_string),
+            },
+        ))
+    }
+
+    /// 比對伺服器回報的 MIME 類型與本地宣告（或偵測）的類型，不一致時發出警告
+    async fn check_upload_mime(
+        &self,
+        file_path: &str,
+        declared: Option<&str>,
+        response: &FileUploadResponse,
+    ) {
+        let Some(reported) = response.mime_type.as_deref() else {
+            return;
+        };
+        let expected = match declared {
+            Some(declared) => declared.to_string(),
+            None => {
+                let mut head = Vec::with_capacity(16);
+                if let Ok(file) = tokio::fs::File::open(file_path).await {
+                    let _ = file.take(16).read_to_end(&mut head).await;
+                }
+                match sniff_mime(Path::new(file_path), &head) {
+                    Some(sniffed) => sniffed.to_string(),
+                    None => return,
+                }
+            }
+        };
+
+        let essence = |mime: &str| {
+            mime.split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_lowercase()
+        };
+        if essence(&expected) == essence(reported) {
+            return;
+        }
+
+        let mismatch = MimeMismatch {
+            file: file_path.to_string(),
+            expected,
+            reported: reported.to_string(),
+        };
+        #[cfg(feature = "trace")]
+        warn!(
+            target: TARGET_UPLOAD,
+            "上傳檔案的 MIME 類型不一致: {} | 本地: {} | 伺服器: {}",
+            mismatch.file, mismatch.expected, mismatch.reported
+        );
+        if let Some(handler) = &self.inner.mime_mismatch_handler {
+            handler(&mismatch);
+        }
+    }
+
+    /// 上傳遠端檔案 (通過URL)
+    pub async fn upload_remote_file(
+        &self,
+        download_url: &str,
+    ) -> Result<FileUploadResponse, PoeError> {
+        #[cfg(feature = "trace")]
+        debug!(target: TARGET_UPLOAD, "開始上傳遠端檔案: {}", download_url);
+
+        // 檢查URL格式
+        url::Url::parse(download_url)?;
+
+        // 建立 multipart 表單
+        let form = MultipartForm::new().text("download_url", download_url);
+
+        // 發送請求
+        let response = self.send_upload_request(form).await?;
+        Ok(self.track_upload(
+            response,
+            FileUploadRequest::RemoteFile {
+                download_url: download_url.to_string(),
+            },
+        ))
+    }
+
+    /// 記錄上傳來源，並在伺服器未提供時依 TTL 推算到期時間
+    pub(crate) fn track_upload(
+        &self,
+        mut response: FileUploadResponse,
+        source: FileUploadRequest,
+    ) -> FileUploadResponse {
+        if response.expires_at.is_none()
+            && let Some(ttl) = self.inner.attachment_ttl
+        {
+            response.expires_at = Some(LoggingHelper::get_timestamp() / 1000 + ttl.as_secs());
+        }
+        response.source = Some(source);
+        response
+    }
+
+    /// Re-upload `attachment` from its source if its URL has expired or
+    /// expires within a minute, returning whether it was refreshed
+    pub async fn refresh_attachment(&self, attachment: &mut Attachment) -> Result<bool, PoeError> {
+        let now = LoggingHelper::get_timestamp() / 1000;
+        if !attachment.is_stale(now, ATTACHMENT_REFRESH_MARGIN_SECS) {
+            return Ok(false);
+        }
+        let Some(source) = attachment.source.clone() else {
+            #[cfg(feature = "trace")]
+            warn!(target: TARGET_UPLOAD, "附件已過期但沒有來源可重新上傳: {}", attachment.url);
+            return Ok(false);
+        };
+
+        #[cfg(feature = "trace")]
+        debug!(target: TARGET_UPLOAD, "附件即將過期，重新上傳: {}", attachment.url);
+        let response = match &source {
+            FileUploadRequest::LocalFile { file, mime_type } => {
+                self.upload_local_file(file, mime_type.as_deref()).await?
+            }
+            FileUploadRequest::RemoteFile { download_url } => {
+                self.upload_remote_file(download_url).await?
+            }
+        };
+        *attachment = response.to_attachment();
+        Ok(true)
+    }
+
+    /// 送出前重新上傳請求中過期的附件
+    pub(super) async fn refresh_stale_attachments(
+        &self,
+        request: &mut ChatRequest,
+    ) -> Result<(), PoeError> {
+        for message in &mut request.query {
+            for attachment in message.attachments.iter_mut().flatten() {
+                self.refresh_attachment(attachment).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 批量上傳檔案 (接受混合的本地和遠端檔案)
+    pub async fn upload_files_batch(
+        &self,
+        files: Vec<FileUploadRequest>,
+    ) -> Result<Vec<FileUploadResponse>, PoeError> {
+        #[cfg(feature = "trace")]
+        debug!(target: TARGET_UPLOAD, "開始批量上傳檔案，數量: {}", files.len());
+
+        if files.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // 為每個檔案創建上傳任務
+        let mut upload_tasks = Vec::with_capacity(files.len());
+
+        for file_request in files {
+            let task = match file_request {
+                FileUploadRequest::LocalFile { file, mime_type } => {
+                    let client = self.clone();
+                    let file_path = file.clone();
+                    tokio::spawn(async move {
+                        client
+                            .upload_local_file(&file_path, mime_type.as_deref())
+                            .await
+                    })
+                }
+                FileUploadRequest::RemoteFile { download_url } => {
+                    let client = self.clone();
+                    let url = download_url.clone();
+                    tokio::spawn(async move { client.upload_remote_file(&url).await })
+                }
+            };
+            upload_tasks.push(task);
+        }
+
+        // 等待所有上傳任務完成
+        let results = join_all(upload_tasks).await;
+
+        // 收集結果
+        let mut upload_responses = Vec::with_capacity(results.len());
+
+        for task_result in results.into_iter() {
+            match task_result {
+                Ok(upload_result) => match upload_result {
+                    Ok(response) => {
+                        #[cfg(feature = "trace")]
+                        debug!(target: TARGET_UPLOAD, "檔案上傳成功: {}", response.attachment_url);
+                        upload_responses.push(response);
+                    }
+                    Err(e) => {
+                        #[cfg(feature = "trace")]
+                        warn!(target: TARGET_UPLOAD, "檔案上傳失敗: {}", e);
+                        return Err(e);
+                    }
+                },
+                Err(e) => {
+                    #[cfg(feature = "trace")]
+                    warn!(target: TARGET_UPLOAD, "檔案上傳任務失敗: {}", e);
+                    return Err(PoeError::FileUploadFailed(format!("上傳任務失敗: {}", e)));
+                }
+            }
+        }
+
+        #[cfg(feature = "trace")]
+        debug!(target: TARGET_UPLOAD, "批量上傳全部成功，共 {} 個檔案", upload_responses.len());
+
+        Ok(upload_responses)
+    }
+
+    /// Attach a file to a bot reply that is being streamed, for server bots
+    /// answering Poe.
+    ///
+    /// `message_id` is the ID Poe sent with the query being answered. Inline
+    /// attachments can be shown in the reply's markdown through the returned
+    /// `inline_ref`; others are listed below the reply.
+    pub async fn post_message_attachment(
+        &self,
+        message_id: &str,
+        file: FileUploadRequest,
+        is_inline: bool,
+    ) -> Result<MessageAttachmentResponse, PoeError> {
+        #[cfg(feature = "trace")]
+        debug!(target: TARGET_UPLOAD, "為訊息 {} 附加檔案: {:?}", message_id, file);
+
+        let form = MultipartForm::new()
+            .text("message_id", message_id)
+            .text("is_inline", &is_inline.to_string());
+        let form = match file {
+            FileUploadRequest::LocalFile { file, mime_type } => {
+                let path = Path::new(&file);
+                if !path.exists() {
+                    return Err(PoeError::FileNotFound(file));
+                }
+                let content_type = mime_type.as_deref().unwrap_or("application/octet-stream");
+                let (file_name, bytes) = read_local_file(path).await?;
+                form.file("file", &file_name, content_type, &bytes)?
+            }
+            FileUploadRequest::RemoteFile { download_url } => {
+                url::Url::parse(&download_url)?;
+                form.text("download_url", &download_url)
+            }
+        };
+
+        // 附件端點沿用 Poe server bot 協定，Authorization 直接帶 access key
+        self.send_multipart(
+            self.inner.poe_file_attachment_url.clone()?,
+            self.inner.access_key.clone(),
+            form,
+        )
+        .await
+    }
+
+    /// Send file upload request (internal method)
+    pub(crate) async fn send_upload_request(
+        &self,
+        form: MultipartForm,
+    ) -> Result<FileUploadResponse, PoeError> {
+        let upload_response: FileUploadResponse = self
+            .send_multipart(
+                self.inner.poe_file_upload_url.clone()?,
+                format!("Bearer {}", self.inner.access_key),
+                form,
+            )
+            .await?;
+
+        #[cfg(feature = "trace")]
+        debug!(target: TARGET_UPLOAD, "檔案上傳成功，附件URL: {}", upload_response.attachment_url);
+
+        Ok(upload_response)
+    }
+
+    // 送出 multipart 表單並解析 JSON 回應，供上傳與訊息附件共用
+    async fn send_multipart<T: serde::de::DeserializeOwned>(
+        &self,
+        upload_url: url::Url,
+        authorization: String,
+        form: MultipartForm,
+    ) -> Result<T, PoeError> {
+        #[cfg(feature = "trace")]
+        debug!(
+            target: TARGET_UPLOAD,
+            "Sending file upload request to {}",
+            upload_url
+        );
+
+        // Prepare request logging
+        #[cfg(feature = "trace")]
+        let request_start_time = LoggingHelper::get_timestamp();
+        #[cfg(not(feature = "trace"))]
+        let _request_start_time = LoggingHelper::get_timestamp();
+
+        #[cfg(feature = "trace")]
+        {
+            let request_log = RequestLog {
+                timestamp: request_start_time,
+                method: "POST".to_string(),
+                url: upload_url.to_string(),
+                headers: Some(vec![
+                    (
+                        "Authorization".to_string(),
+                        "Bearer ***MASKED***".to_string(),
+                    ),
+                    (
+                        "Content-Type".to_string(),
+                        "multipart/form-data".to_string(),
+                    ),
+                ]),
+                body: Some("Multipart form data".to_string()),
+                body_size: None,
+            };
+            LoggingHelper::log_request(&request_log, &self.inner.logging_config);
+        }
+
+        let (content_type, body) = form.finish();
+        let headers = vec![
+            ("Authorization".to_string(), authorization),
+            ("Content-Type".to_string(), content_type),
+        ];
+        let mut response = self
+            .send_signed("POST", upload_url.as_str(), headers, body)
+            .await
+            .inspect_err(|_e| {
+                #[cfg(feature = "trace")]
+                warn!(target: TARGET_UPLOAD, "File upload request failed: {}", _e);
+            })?;
+
+        #[cfg(feature = "trace")]
+        let response_start_time = LoggingHelper::get_timestamp();
+        #[cfg(not(feature = "trace"))]
+        let _response_start_time = LoggingHelper::get_timestamp();
+        #[cfg(feature = "trace")]
+        let duration_ms = response_start_time - request_start_time;
+
+        let status = response.status;
+
+        if !response.is_success() {
+            let headers = std::mem::take(&mut response.headers);
+            let text = response.text().await.unwrap_or_default();
+
+            #[cfg(feature = "trace")]
+            warn!(
+                target: TARGET_UPLOAD,
+                "File upload API response error - status code: {}, content: {}",
+                status, text
+            );
+
+            // Log error response
+            #[cfg(feature = "trace")]
+            {
+                let response_log = ResponseLog {
+                    timestamp: response_start_time,
+                    status_code: status,
+                    headers: None,
+                    body: Some(text.clone()),
+                    body_size: Some(text.len()),
+                    duration_ms: Some(duration_ms),
+                };
+                LoggingHelper::log_response(&response_log, &self.inner.logging_config);
+            }
+
+            return Err(PoeError::api_status(
+                upload_url.as_str(),
+                status,
+                &headers,
+                &text,
+            ));
+        }
+
+        #[cfg(feature = "trace")]
+        debug!(target: TARGET_UPLOAD, "Successfully received file upload response");
+
+        let response_text = response.text().await.inspect_err(|_e| {
+            #[cfg(feature = "trace")]
+            warn!(target: TARGET_UPLOAD, "Failed to read file upload response content: {}", _e);
+        })?;
+
+        // Log successful response
+        #[cfg(feature = "trace")]
+        {
+            let response_log = ResponseLog {
+                timestamp: response_start_time,
+                status_code: status,
+                headers: None,
+                body: Some(response_text.clone()),
+                body_size: Some(response_text.len()),
+                duration_ms: Some(duration_ms),
+            };
+            LoggingHelper::log_response(&response_log, &self.inner.logging_config);
+        }
+
+        #[cfg(feature = "trace")]
+        debug!(target: TARGET_UPLOAD, "檔案上傳回應內容: {}", response_text);
+
+        serde_json::from_str(&response_text).map_err(|e| {
+            #[cfg(feature = "trace")]
+            warn!(target: TARGET_UPLOAD, "解析檔案上傳回應失敗: {}", e);
+            PoeError::JsonParseFailed(e)
+        })
+    }
+}
+
+/// 讀取本地檔案作為 multipart 的檔案欄位，回傳檔名與內容
+async fn read_local_file(path: &Path) -> Result<(String, Vec<u8>), PoeError> {
+    let bytes = tokio::fs::read(path).await.map_err(|e| {
+        #[cfg(feature = "trace")]
+        warn!(target: TARGET_UPLOAD, "無法讀取檔案: {}", e);
+        PoeError::FileReadError(e)
+    })?;
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("file")
+        .to_string();
+    Ok((file_name, bytes))
+}
+
+/// 將本地計算的 SHA-256 記錄於上傳回應，伺服器回報的值不一致時回傳錯誤
+pub(crate) fn record_checksum(
+    file: &str,
+    mut response: FileUploadResponse,
+    digest: String,
+) -> Result<FileUploadResponse, PoeError> {
+    if let Some(reported) = &response.sha256
+        && !reported.eq_ignore_ascii_case(&digest)
+    {
+        #[cfg(feature = "trace")]
+        warn!(
+            target: TARGET_UPLOAD,
+            "上傳檔案的 SHA-256 不符: {} | 本地: {} | 伺服器: {}",
+            file, digest, reported
+        );
+        return Err(PoeError::ChecksumMismatch {
+            file: file.to_string(),
+            expected: digest,
+            actual: reported.to_ascii_lowercase(),
+        });
+    }
+    response.sha256 = Some(digest);
+    Ok(response)
+}
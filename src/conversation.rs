@@ -0,0 +1,354 @@
+use crate::client::PoeClient;
+use crate::error::PoeError;
+use crate::types::*;
+use crate::util::generate_id;
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use std::pin::Pin;
+
+/// Per-call overrides for `Conversation::regenerate_last`
+#[derive(Debug, Clone, Default)]
+pub struct RegenerateOptions {
+    pub temperature: Option<f32>,
+    pub seed: Option<u64>,
+}
+
+/// Who added an attachment to a conversation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AttachmentOrigin {
+    /// Sent by the user with a message
+    User,
+    /// Received from the bot as a `file` event
+    Bot,
+}
+
+/// An attachment recorded by a [`Conversation`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConversationAttachment {
+    pub url: String,
+    pub content_type: Option<String>,
+    /// File name, known for bot files only
+    pub name: Option<String>,
+    pub origin: AttachmentOrigin,
+    /// Index in `Conversation::messages` of the turn that sent or produced it
+    pub turn: usize,
+}
+
+/// Tool calls a bot made while producing one turn, with their results
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolExchange {
+    pub tool_calls: Vec<ChatToolCall>,
+    pub tool_results: Vec<ChatToolResult>,
+    /// Index in `Conversation::messages` of the bot turn the calls led to
+    pub turn: usize,
+}
+
+/// Multi-turn conversation state: keeps the message history and the IDs Poe
+/// needs so callers don't have to rebuild `ChatRequest`s by hand
+///
+/// The bot query protocol returns no server-side chat identifier; continuity
+/// comes from resending the history under a stable `conversation_id`, which
+/// can be stored and restored with [`Conversation::with_id`].
+///
+/// Serializes with its full state, e.g. for a [`ConversationStore`](crate::history::ConversationStore).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conversation {
+    conversation_id: String,
+    user_id: String,
+    messages: Vec<ChatMessage>,
+    temperature: Option<f32>,
+    seed: Option<u64>,
+    carry_bot_files: bool,
+    pending_attachments: Vec<Attachment>,
+    attachments: Vec<ConversationAttachment>,
+    tool_exchanges: Vec<ToolExchange>,
+}
+
+impl Default for Conversation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Conversation {
+    /// Start an empty conversation with a freshly generated ID
+    pub fn new() -> Self {
+        Self {
+            conversation_id: generate_id("conv"),
+            user_id: String::new(),
+            messages: Vec::new(),
+            temperature: None,
+            seed: None,
+            carry_bot_files: false,
+            pending_attachments: Vec::new(),
+            attachments: Vec::new(),
+            tool_exchanges: Vec::new(),
+        }
+    }
+
+    /// Reuse a previously stored conversation ID instead of the generated one
+    pub fn with_id(mut self, conversation_id: &str) -> Self {
+        self.conversation_id = conversation_id.to_string();
+        self
+    }
+
+    /// Set the user ID sent with every request
+    pub fn with_user_id(mut self, user_id: &str) -> Self {
+        self.user_id = user_id.to_string();
+        self
+    }
+
+    /// Set the default sampling temperature for every request
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Set the seed sent with every request, for bots that honor reproducible sampling
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Attach files produced by the bot (see `push_bot_file`) to the next user message
+    pub fn with_carry_bot_files(mut self, carry: bool) -> Self {
+        self.carry_bot_files = carry;
+        self
+    }
+
+    /// Get the conversation ID
+    pub fn id(&self) -> &str {
+        &self.conversation_id
+    }
+
+    /// Get the user ID
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    /// Get the message history
+    pub fn messages(&self) -> &[ChatMessage] {
+        &self.messages
+    }
+
+    /// Get every attachment sent or received so far, in order
+    pub fn attachments(&self) -> &[ConversationAttachment] {
+        &self.attachments
+    }
+
+    /// Get the attachments sent by the user or received from the bot
+    pub fn attachments_from(
+        &self,
+        origin: AttachmentOrigin,
+    ) -> impl Iterator<Item = &ConversationAttachment> {
+        self.attachments
+            .iter()
+            .filter(move |attachment| attachment.origin == origin)
+    }
+
+    /// Get the attachments of the turn at `turn` in the message history
+    pub fn attachments_for_turn(&self, turn: usize) -> impl Iterator<Item = &ConversationAttachment> {
+        self.attachments
+            .iter()
+            .filter(move |attachment| attachment.turn == turn)
+    }
+
+    /// Get the recorded tool calls and results, in order
+    pub fn tool_exchanges(&self) -> &[ToolExchange] {
+        &self.tool_exchanges
+    }
+
+    /// Append a message to the history
+    pub fn push_message(&mut self, mut message: ChatMessage) {
+        // 轉帶的 bot 檔案已在 push_bot_file 時記錄，這裡只記錄訊息本身的附件
+        let origin = if message.role == ROLE_BOT {
+            AttachmentOrigin::Bot
+        } else {
+            AttachmentOrigin::User
+        };
+        for attachment in message.attachments.iter().flatten() {
+            self.attachments.push(ConversationAttachment {
+                url: attachment.url.clone(),
+                content_type: attachment.content_type.clone(),
+                name: None,
+                origin,
+                turn: self.messages.len(),
+            });
+        }
+
+        if message.role == ROLE_USER && !self.pending_attachments.is_empty() {
+            message
+                .attachments
+                .get_or_insert_with(Vec::new)
+                .append(&mut self.pending_attachments);
+        }
+        self.messages.push(message);
+    }
+
+    /// Append a user turn
+    pub fn push_user_message(&mut self, content: &str) {
+        self.push_message(Self::text_message(ROLE_USER, content));
+    }
+
+    /// Append a bot turn, typically the aggregated text of a response
+    pub fn push_bot_message(&mut self, content: &str) {
+        self.push_message(Self::text_message(ROLE_BOT, content));
+    }
+
+    /// Record a file event from the bot; with `with_carry_bot_files` enabled it is
+    /// attached to the next user message
+    pub fn push_bot_file(&mut self, file: &FileData) {
+        // 檔案事件通常早於 bot 訊息寫入歷史，此時歸屬於即將加入的 bot 回合
+        let turn = match self.messages.last() {
+            Some(message) if message.role == ROLE_BOT => self.messages.len() - 1,
+            _ => self.messages.len(),
+        };
+        self.attachments.push(ConversationAttachment {
+            url: file.url.clone(),
+            content_type: Some(file.content_type.clone()),
+            name: Some(file.name.clone()),
+            origin: AttachmentOrigin::Bot,
+            turn,
+        });
+
+        if self.carry_bot_files {
+            self.pending_attachments.push(Attachment::from(file));
+        }
+    }
+
+    /// Record the tool calls of the bot turn in progress together with their
+    /// results, so exports can reproduce the full exchange
+    pub fn push_tool_exchange(
+        &mut self,
+        tool_calls: Vec<ChatToolCall>,
+        tool_results: Vec<ChatToolResult>,
+    ) {
+        // 工具調用發生在 bot 最終回覆寫入歷史之前，歸屬於即將加入的 bot 回合
+        self.tool_exchanges.push(ToolExchange {
+            tool_calls,
+            tool_results,
+            turn: self.messages.len(),
+        });
+    }
+
+    /// Build a request carrying the full history, with a new message ID
+    pub fn to_request(&self) -> ChatRequest {
+        ChatRequest {
+            query: self.messages.clone(),
+            user_id: self.user_id.clone(),
+            conversation_id: self.conversation_id.clone(),
+            temperature: self.temperature,
+            seed: self.seed,
+            ..Default::default()
+        }
+    }
+
+    /// Fork the conversation, keeping the first `turn` messages under a new conversation ID
+    pub fn fork(&self, turn: usize) -> Conversation {
+        let turn = turn.min(self.messages.len());
+        Conversation {
+            conversation_id: generate_id("conv"),
+            user_id: self.user_id.clone(),
+            messages: self.messages[..turn].to_vec(),
+            temperature: self.temperature,
+            seed: self.seed,
+            carry_bot_files: self.carry_bot_files,
+            pending_attachments: Vec::new(),
+            attachments: self
+                .attachments
+                .iter()
+                .filter(|attachment| attachment.turn < turn)
+                .cloned()
+                .collect(),
+            tool_exchanges: self
+                .tool_exchanges
+                .iter()
+                .filter(|exchange| exchange.turn < turn)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Drop the last bot turn and re-issue the request, returning the new response stream.
+    ///
+    /// The caller is expected to record the regenerated answer with `push_bot_message`.
+    pub async fn regenerate_last<'a>(
+        &mut self,
+        client: &'a PoeClient,
+        options: RegenerateOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatResponse, PoeError>> + Send + 'a>>, PoeError>
+    {
+        // 先完成所有檢查並送出請求，成功後才修改對話，失敗時對話保持不變
+        let turns = match self.messages.last() {
+            Some(message) if message.role == ROLE_BOT => self.messages.len() - 1,
+            _ => self.messages.len(),
+        };
+        if turns == 0 || self.messages[turns - 1].role != ROLE_USER {
+            return Err(PoeError::InvalidRoleSequence(
+                "no user turn to regenerate a response for".to_string(),
+            ));
+        }
+
+        let mut request = self.to_request();
+        request.query.truncate(turns);
+        if let Some(temperature) = options.temperature {
+            request.temperature = Some(temperature);
+        }
+        if let Some(seed) = options.seed {
+            request.seed = Some(seed);
+        }
+        let stream = client.stream_request(request).await?;
+
+        self.messages.truncate(turns);
+        self.attachments.retain(|attachment| attachment.turn < turns);
+        self.tool_exchanges.retain(|exchange| exchange.turn < turns);
+        Ok(stream)
+    }
+
+    /// Replace the content of the user message at `index`, drop every later turn
+    /// and re-send the conversation from that point under a new message ID
+    pub async fn edit_message<'a>(
+        &mut self,
+        client: &'a PoeClient,
+        index: usize,
+        new_content: &str,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatResponse, PoeError>> + Send + 'a>>, PoeError>
+    {
+        match self.messages.get(index) {
+            Some(message) if message.role == ROLE_USER => {}
+            Some(message) => {
+                return Err(PoeError::InvalidRoleSequence(format!(
+                    "message {}: only user messages can be edited, found '{}'",
+                    index, message.role
+                )));
+            }
+            None => {
+                return Err(PoeError::InvalidRequest(format!(
+                    "message {}: index out of range ({} messages)",
+                    index,
+                    self.messages.len()
+                )));
+            }
+        }
+
+        let mut request = self.to_request();
+        request.query.truncate(index + 1);
+        request.query[index].content = new_content.to_string();
+        let stream = client.stream_request(request).await?;
+
+        self.messages.truncate(index + 1);
+        self.messages[index].content = new_content.to_string();
+        self.attachments.retain(|attachment| attachment.turn <= index);
+        self.tool_exchanges.retain(|exchange| exchange.turn <= index);
+        Ok(stream)
+    }
+
+    fn text_message(role: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+            attachments: None,
+            content_type: "text/markdown".to_string(),
+        }
+    }
+}
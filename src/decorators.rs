@@ -0,0 +1,423 @@
+use crate::api::{ChatStream, PoeApi};
+use crate::error::PoeError;
+#[cfg(feature = "trace")]
+use crate::logging::TARGET_CLIENT;
+use crate::metrics::ThrottleStats;
+use crate::retry::{RetryBudget, is_transient};
+use crate::runtime::{Timer, default_timer};
+#[cfg(feature = "upload")]
+use crate::types::FileUploadResponse;
+use crate::types::{ChatRequest, ChatResponse, ModelResponse};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+/// [`PoeApi`] decorator caching complete chat responses and the model list.
+///
+/// Identical requests (compared by their serialized form, ignoring the
+/// conversation and message IDs) within the TTL are replayed from memory. A
+/// cache miss buffers the whole response before returning it, and responses
+/// that contain errors are never cached.
+pub struct CachedPoeClient<A> {
+    inner: A,
+    ttl: Duration,
+    capacity: usize,
+    responses: Mutex<HashMap<String, (Instant, Vec<ChatResponse>)>>,
+    models: Mutex<Option<(Instant, ModelResponse)>>,
+}
+
+impl<A: PoeApi> CachedPoeClient<A> {
+    /// Cache up to 256 responses for five minutes
+    pub fn new(inner: A) -> Self {
+        Self {
+            inner,
+            ttl: Duration::from_secs(300),
+            capacity: 256,
+            responses: Mutex::new(HashMap::new()),
+            models: Mutex::new(None),
+        }
+    }
+
+    /// Get the wrapped client
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Set the maximum number of cached chat responses (the oldest is evicted first)
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Drop every cached entry
+    pub fn clear(&self) {
+        self.responses
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+        *self.models.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    fn cached(&self, key: &str) -> Option<Vec<ChatResponse>> {
+        let mut responses = self.responses.lock().unwrap_or_else(|e| e.into_inner());
+        match responses.get(key) {
+            Some((stored_at, events)) if stored_at.elapsed() < self.ttl => Some(events.clone()),
+            Some(_) => {
+                responses.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn store(&self, key: String, events: Vec<ChatResponse>) {
+        let mut responses = self.responses.lock().unwrap_or_else(|e| e.into_inner());
+        responses.retain(|_, (stored_at, _)| stored_at.elapsed() < self.ttl);
+        while responses.len() >= self.capacity {
+            let Some(oldest) = responses
+                .iter()
+                .min_by_key(|(_, (stored_at, _))| *stored_at)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            responses.remove(&oldest);
+        }
+        responses.insert(key, (Instant::now(), events));
+    }
+}
+
+#[async_trait]
+impl<A: PoeApi> PoeApi for CachedPoeClient<A> {
+    async fn stream_request<'a>(
+        &'a self,
+        request: ChatRequest,
+    ) -> Result<ChatStream<'a>, PoeError> {
+        let key = cache_key(&request)?;
+        if let Some(events) = self.cached(&key) {
+            return Ok(Box::pin(futures_util::stream::iter(
+                events.into_iter().map(Ok),
+            )));
+        }
+
+        let items: Vec<Result<ChatResponse, PoeError>> =
+            self.inner.stream_request(request).await?.collect().await;
+        if items.iter().all(Result::is_ok) {
+            let events: Vec<ChatResponse> = items.into_iter().flatten().collect();
+            self.store(key, events.clone());
+            return Ok(Box::pin(futures_util::stream::iter(
+                events.into_iter().map(Ok),
+            )));
+        }
+        Ok(Box::pin(futures_util::stream::iter(items)))
+    }
+
+    #[cfg(feature = "upload")]
+    async fn upload_local_file(
+        &self,
+        file_path: &str,
+        mime_type: Option<&str>,
+    ) -> Result<FileUploadResponse, PoeError> {
+        self.inner.upload_local_file(file_path, mime_type).await
+    }
+
+    #[cfg(feature = "upload")]
+    async fn upload_remote_file(&self, download_url: &str) -> Result<FileUploadResponse, PoeError> {
+        self.inner.upload_remote_file(download_url).await
+    }
+
+    async fn get_v1_model_list(&self) -> Result<ModelResponse, PoeError> {
+        if let Some((stored_at, models)) = &*self.models.lock().unwrap_or_else(|e| e.into_inner())
+            && stored_at.elapsed() < self.ttl
+        {
+            return Ok(models.clone());
+        }
+
+        let models = self.inner.get_v1_model_list().await?;
+        *self.models.lock().unwrap_or_else(|e| e.into_inner()) =
+            Some((Instant::now(), models.clone()));
+        Ok(models)
+    }
+}
+
+/// Serialized request without the per-request conversation and message IDs
+fn cache_key(request: &ChatRequest) -> Result<String, PoeError> {
+    let mut value = serde_json::to_value(request)?;
+    if let Some(fields) = value.as_object_mut() {
+        fields.remove("conversation_id");
+        fields.remove("message_id");
+    }
+    Ok(value.to_string())
+}
+
+type RetryPredicate = Arc<dyn Fn(&PoeError) -> bool + Send + Sync>;
+
+/// [`PoeApi`] decorator retrying failed calls with exponential backoff.
+///
+/// Only failures before a chat stream starts are retried; by default these
+/// are transport errors and 429/5xx responses. A stream that ends early
+/// surfaces `PoeError::StreamTruncated` to the caller and is not retried.
+pub struct RetryPoeClient<A> {
+    inner: A,
+    max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    budget: Option<Arc<RetryBudget>>,
+    retry_if: RetryPredicate,
+    max_retry_after: Option<Duration>,
+    throttled: AtomicU64,
+    throttled_wait_ms: AtomicU64,
+    timer: Arc<dyn Timer>,
+}
+
+impl<A: PoeApi> RetryPoeClient<A> {
+    /// Retry up to `max_retries` times, starting with a 500 ms delay
+    pub fn new(inner: A, max_retries: u32) -> Self {
+        Self {
+            inner,
+            max_retries,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            budget: None,
+            retry_if: Arc::new(is_transient),
+            max_retry_after: None,
+            throttled: AtomicU64::new(0),
+            throttled_wait_ms: AtomicU64::new(0),
+            timer: default_timer(),
+        }
+    }
+
+    /// Get the wrapped client
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Set the first delay (doubled on every attempt) and the delay cap
+    pub fn with_backoff(mut self, base_delay: Duration, max_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self.max_delay = max_delay.max(base_delay);
+        self
+    }
+
+    /// Draw every retry from a shared budget
+    pub fn with_retry_budget(mut self, budget: Arc<RetryBudget>) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Use another timer for the delays between attempts
+    pub fn with_timer(mut self, timer: impl Timer + 'static) -> Self {
+        self.timer = Arc::new(timer);
+        self
+    }
+
+    /// Wait as long as a 429 response's `Retry-After` header asks, up to
+    /// `max_wait`, instead of the backoff delay
+    pub fn with_retry_after(mut self, max_wait: Duration) -> Self {
+        self.max_retry_after = Some(max_wait);
+        self
+    }
+
+    /// Get how often and how long `Retry-After` headers delayed a retry
+    pub fn throttle_stats(&self) -> ThrottleStats {
+        ThrottleStats {
+            throttled: self.throttled.load(Ordering::Relaxed),
+            total_wait: Duration::from_millis(self.throttled_wait_ms.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Decide which errors are retried
+    pub fn with_retry_if(
+        mut self,
+        retry_if: impl Fn(&PoeError) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.retry_if = Arc::new(retry_if);
+        self
+    }
+
+    async fn retry<'a, T, F, Fut>(&'a self, mut call: F) -> Result<T, PoeError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, PoeError>> + 'a,
+    {
+        if let Some(budget) = &self.budget {
+            budget.deposit();
+        }
+
+        let mut attempt = 0;
+        loop {
+            match call().await {
+                Err(error)
+                    if attempt < self.max_retries
+                        && (self.retry_if)(&error)
+                        && self
+                            .budget
+                            .as_ref()
+                            .is_none_or(|budget| budget.try_withdraw()) =>
+                {
+                    let delay = match (self.max_retry_after, error.retry_after()) {
+                        (Some(max_wait), Some(wait)) if error.status() == Some(429) => {
+                            let wait = wait.min(max_wait);
+                            self.throttled.fetch_add(1, Ordering::Relaxed);
+                            self.throttled_wait_ms
+                                .fetch_add(wait.as_millis() as u64, Ordering::Relaxed);
+                            #[cfg(feature = "trace")]
+                            tracing::warn!(
+                                target: TARGET_CLIENT,
+                                "Throttled (HTTP 429), retrying in {:?}",
+                                wait
+                            );
+                            wait
+                        }
+                        _ => self
+                            .base_delay
+                            .saturating_mul(2u32.saturating_pow(attempt))
+                            .min(self.max_delay),
+                    };
+                    self.timer.sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<A: PoeApi> PoeApi for RetryPoeClient<A> {
+    async fn stream_request<'a>(
+        &'a self,
+        request: ChatRequest,
+    ) -> Result<ChatStream<'a>, PoeError> {
+        self.retry(|| self.inner.stream_request(request.clone()))
+            .await
+    }
+
+    #[cfg(feature = "upload")]
+    async fn upload_local_file(
+        &self,
+        file_path: &str,
+        mime_type: Option<&str>,
+    ) -> Result<FileUploadResponse, PoeError> {
+        self.retry(|| self.inner.upload_local_file(file_path, mime_type))
+            .await
+    }
+
+    #[cfg(feature = "upload")]
+    async fn upload_remote_file(&self, download_url: &str) -> Result<FileUploadResponse, PoeError> {
+        self.retry(|| self.inner.upload_remote_file(download_url))
+            .await
+    }
+
+    async fn get_v1_model_list(&self) -> Result<ModelResponse, PoeError> {
+        self.retry(|| self.inner.get_v1_model_list()).await
+    }
+}
+
+/// [`PoeApi`] decorator limiting concurrent calls and spacing out request starts.
+///
+/// A chat stream holds its slot until it is dropped.
+pub struct ThrottledPoeClient<A> {
+    inner: A,
+    permits: Arc<Semaphore>,
+    min_interval: Duration,
+    next_start: tokio::sync::Mutex<Option<Instant>>,
+    timer: Arc<dyn Timer>,
+}
+
+impl<A: PoeApi> ThrottledPoeClient<A> {
+    /// Allow at most `max_concurrent` calls in flight
+    pub fn new(inner: A, max_concurrent: usize) -> Self {
+        Self {
+            inner,
+            permits: Arc::new(Semaphore::new(max_concurrent.max(1))),
+            min_interval: Duration::ZERO,
+            next_start: tokio::sync::Mutex::new(None),
+            timer: default_timer(),
+        }
+    }
+
+    /// Get the wrapped client
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Wait at least `interval` between the starts of consecutive calls
+    pub fn with_min_interval(mut self, interval: Duration) -> Self {
+        self.min_interval = interval;
+        self
+    }
+
+    /// Use another timer for spacing out request starts
+    pub fn with_timer(mut self, timer: impl Timer + 'static) -> Self {
+        self.timer = Arc::new(timer);
+        self
+    }
+
+    async fn acquire(&self) -> tokio::sync::OwnedSemaphorePermit {
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("throttle semaphore is never closed");
+
+        if !self.min_interval.is_zero() {
+            let mut next_start = self.next_start.lock().await;
+            if let Some(at) = *next_start {
+                let wait = at.saturating_duration_since(Instant::now());
+                if !wait.is_zero() {
+                    self.timer.sleep(wait).await;
+                }
+            }
+            *next_start = Some(Instant::now() + self.min_interval);
+        }
+        permit
+    }
+}
+
+#[async_trait]
+impl<A: PoeApi> PoeApi for ThrottledPoeClient<A> {
+    async fn stream_request<'a>(
+        &'a self,
+        request: ChatRequest,
+    ) -> Result<ChatStream<'a>, PoeError> {
+        let permit = self.acquire().await;
+        let stream = self.inner.stream_request(request).await?;
+        Ok(Box::pin(stream.map(move |item| {
+            let _permit = &permit;
+            item
+        })))
+    }
+
+    #[cfg(feature = "upload")]
+    async fn upload_local_file(
+        &self,
+        file_path: &str,
+        mime_type: Option<&str>,
+    ) -> Result<FileUploadResponse, PoeError> {
+        let _permit = self.acquire().await;
+        self.inner.upload_local_file(file_path, mime_type).await
+    }
+
+    #[cfg(feature = "upload")]
+    async fn upload_remote_file(&self, download_url: &str) -> Result<FileUploadResponse, PoeError> {
+        let _permit = self.acquire().await;
+        self.inner.upload_remote_file(download_url).await
+    }
+
+    async fn get_v1_model_list(&self) -> Result<ModelResponse, PoeError> {
+        let _permit = self.acquire().await;
+        self.inner.get_v1_model_list().await
+    }
+}
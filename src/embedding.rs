@@ -0,0 +1,131 @@
+use crate::client::PoeClient;
+use crate::error::PoeError;
+use crate::tools::memory::Embedder;
+use crate::types::{ChatMessage, ChatRequest, ROLE_USER};
+use async_trait::async_trait;
+use serde_json::{Value, json};
+
+const DEFAULT_BATCH_SIZE: usize = 16;
+
+const EMBEDDING_INSTRUCTION: &str = "Return embedding vectors for each string in `input`, \
+in order, as JSON only: {\"data\": [{\"index\": 0, \"embedding\": [...]}, ...]}";
+
+/// [`Embedder`] backed by an embedding-capable Poe bot
+#[derive(Clone)]
+pub struct PoeEmbedder {
+    client: PoeClient,
+    batch_size: usize,
+}
+
+impl PoeEmbedder {
+    /// Embed with `bot_name`, reusing the rest of `client`'s configuration
+    pub fn new(client: &PoeClient, bot_name: &str) -> Self {
+        Self {
+            client: client.clone().with_bot(bot_name),
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+
+    /// Number of texts sent per request (default 16)
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, PoeError> {
+        let request = ChatRequest {
+            query: vec![ChatMessage {
+                role: ROLE_USER.to_string(),
+                content: format!("{}\n\n{}", EMBEDDING_INSTRUCTION, json!({ "input": texts })),
+                content_type: "text/markdown".to_string(),
+                attachments: None,
+            }],
+            ..Default::default()
+        };
+
+        let mut result = self.try_embed_batch(request.clone(), texts.len()).await;
+        // 回應格式錯誤時，於重試預算允許下重試一次
+        if matches!(result, Err(PoeError::InvalidEmbedding(_))) && self.client.allow_retry() {
+            result = self.try_embed_batch(request, texts.len()).await;
+        }
+        result
+    }
+
+    async fn try_embed_batch(
+        &self,
+        request: ChatRequest,
+        expected: usize,
+    ) -> Result<Vec<Vec<f32>>, PoeError> {
+        let text = self.client.collect_text(request).await?;
+        let embeddings = parse_embeddings(&text)?;
+        if embeddings.len() != expected {
+            return Err(PoeError::InvalidEmbedding(format!(
+                "expected {} embeddings, got {}",
+                expected,
+                embeddings.len()
+            )));
+        }
+        Ok(embeddings)
+    }
+}
+
+#[async_trait]
+impl Embedder for PoeEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, PoeError> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(self.batch_size) {
+            embeddings.extend(self.embed_batch(batch).await?);
+        }
+        Ok(embeddings)
+    }
+}
+
+/// Parse embeddings from a bot reply: an OpenAI-style `{"data": [{"embedding": ...}]}`
+/// object, `{"embeddings": [...]}`, or a bare array of vectors, optionally in a code fence
+pub fn parse_embeddings(text: &str) -> Result<Vec<Vec<f32>>, PoeError> {
+    let json = text
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
+    let value: Value =
+        serde_json::from_str(json).map_err(|e| PoeError::InvalidEmbedding(e.to_string()))?;
+
+    let vectors = match &value {
+        Value::Array(vectors) => vectors.clone(),
+        Value::Object(object) => match (object.get("data"), object.get("embeddings")) {
+            (Some(Value::Array(data)), _) => {
+                let mut data = data.clone();
+                data.sort_by_key(|item| item["index"].as_u64());
+                data.into_iter()
+                    .map(|item| item["embedding"].clone())
+                    .collect()
+            }
+            (_, Some(Value::Array(embeddings))) => embeddings.clone(),
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    };
+    if vectors.is_empty() {
+        return Err(PoeError::InvalidEmbedding(
+            "no embeddings in response".to_string(),
+        ));
+    }
+
+    vectors
+        .into_iter()
+        .map(|vector| {
+            serde_json::from_value::<Vec<f32>>(vector)
+                .map_err(|e| PoeError::InvalidEmbedding(e.to_string()))
+        })
+        .collect()
+}
+
+impl PoeClient {
+    /// Embed `texts` with an embedding-capable bot, batching requests and
+    /// retrying malformed replies within the retry budget
+    pub async fn embed(&self, texts: &[String], bot_name: &str) -> Result<Vec<Vec<f32>>, PoeError> {
+        PoeEmbedder::new(self, bot_name).embed(texts).await
+    }
+}
@@ -1,16 +1,43 @@
+use crate::aggregate::CompleteResponse;
+use crate::types::GraphqlError;
+use std::time::Duration;
 use thiserror::Error;
 
+/// Maximum number of body bytes kept in [`PoeError::ApiStatus`]
+#[cfg(feature = "client")]
+const MAX_ERROR_BODY_LENGTH: usize = 2048;
+
+/// Response headers carrying the server-side request ID, in order of preference
+#[cfg(feature = "client")]
+const REQUEST_ID_HEADERS: [&str; 2] = ["x-request-id", "x-correlation-id"];
+
 #[derive(Error, Debug)]
 pub enum PoeError {
+    #[cfg(feature = "reqwest")]
     #[error("HTTP request failed: {0}")]
     RequestFailed(#[from] reqwest::Error),
 
+    #[error("Transport error: {0}")]
+    TransportError(String),
+
     #[error("JSON parsing failed: {0}")]
     JsonParseFailed(#[from] serde_json::Error),
 
     #[error("Bot error: {0}")]
     BotError(String),
 
+    #[error("{endpoint} responded with HTTP {status}: {body}")]
+    ApiStatus {
+        status: u16,
+        /// Response body, truncated to 2 KB
+        body: String,
+        endpoint: String,
+        /// Request ID reported by the server, for support tickets
+        request_id: Option<String>,
+        /// Wait requested by the `Retry-After` header (delta-seconds form only)
+        retry_after: Option<Duration>,
+    },
+
     #[error("Event error: {0}")]
     EventError(String),
 
@@ -29,6 +56,12 @@ pub enum PoeError {
     #[error("Missing required tool call ID: {0}")]
     MissingToolCallId(String),
 
+    #[error("Tool execution failed: {0}")]
+    ToolExecutionFailed(String),
+
+    #[error("Invalid embedding response: {0}")]
+    InvalidEmbedding(String),
+
     // New file upload related errors
     #[error("File not found: {0}")]
     FileNotFound(String),
@@ -45,6 +78,136 @@ pub enum PoeError {
     #[error("File too large: {0}")]
     FileTooLarge(String),
 
+    #[error("Checksum mismatch for {file}: expected SHA-256 {expected}, got {actual}")]
+    ChecksumMismatch {
+        file: String,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("Image processing failed: {0}")]
+    ImageProcessingFailed(String),
+
     #[error("Invalid URL: {0}")]
     InvalidUrl(#[from] url::ParseError),
+
+    #[error("Writing to output failed: {0}")]
+    OutputWriteFailed(std::io::Error),
+
+    #[error("SSE line of {size} bytes exceeds the {limit}-byte limit")]
+    EventTooLarge { size: usize, limit: usize },
+
+    #[cfg(feature = "history-sqlite")]
+    #[error("History storage failed: {0}")]
+    HistoryStorage(#[from] rusqlite::Error),
+
+    #[error("Circuit open for bot {0}")]
+    CircuitOpen(String),
+
+    #[error("Stream ended without a done event")]
+    StreamTruncated,
+
+    #[error("Request aborted")]
+    Aborted,
+
+    #[error("Invalid role sequence: {0}")]
+    InvalidRoleSequence(String),
+
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error("GraphQL query {query_name} failed: {}", format_graphql_errors(.errors))]
+    GraphqlError {
+        query_name: String,
+        errors: Vec<GraphqlError>,
+    },
+}
+
+impl PoeError {
+    /// Build an [`PoeError::ApiStatus`] from the parts of a non-2xx response
+    #[cfg(feature = "client")]
+    pub(crate) fn api_status(
+        endpoint: &str,
+        status: u16,
+        headers: &[(String, String)],
+        body: &str,
+    ) -> Self {
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.trim())
+        };
+        let request_id = REQUEST_ID_HEADERS
+            .iter()
+            .find_map(|name| header(name))
+            .map(str::to_string);
+        let retry_after = header("retry-after")
+            .and_then(|value| value.parse::<u64>().ok())
+            .map(Duration::from_secs);
+        let mut end = body.len().min(MAX_ERROR_BODY_LENGTH);
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+        PoeError::ApiStatus {
+            status,
+            body: body[..end].to_string(),
+            endpoint: endpoint.to_string(),
+            request_id,
+            retry_after,
+        }
+    }
+
+    /// Get the HTTP status of an [`PoeError::ApiStatus`] error
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            PoeError::ApiStatus { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    /// Get the wait requested by the server's `Retry-After` header
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            PoeError::ApiStatus { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// Check whether this is a GraphQL failure caused by an expired query hash
+    pub fn is_graphql_hash_expired(&self) -> bool {
+        matches!(self, PoeError::GraphqlError { errors, .. } if errors.iter().any(GraphqlError::is_hash_expired))
+    }
+}
+
+/// Error that interrupted a response, with the part received before it
+#[derive(Error, Debug)]
+#[error("{error}")]
+pub struct PoeErrorWithPartial {
+    #[source]
+    pub error: PoeError,
+    pub partial: CompleteResponse,
+}
+
+impl From<PoeError> for PoeErrorWithPartial {
+    fn from(error: PoeError) -> Self {
+        Self {
+            error,
+            partial: CompleteResponse::default(),
+        }
+    }
+}
+
+impl From<PoeErrorWithPartial> for PoeError {
+    fn from(error: PoeErrorWithPartial) -> Self {
+        error.error
+    }
+}
+
+fn format_graphql_errors(errors: &[GraphqlError]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
 }
@@ -0,0 +1,219 @@
+use crate::api::{ChatStream, PoeApi};
+use crate::client::PoeClient;
+use crate::error::PoeError;
+#[cfg(feature = "trace")]
+use crate::logging::TARGET_CLIENT;
+use crate::metrics::VariantStats;
+#[cfg(feature = "upload")]
+use crate::types::FileUploadResponse;
+use crate::types::{ChatEventType, ChatRequest, ChatResponse, ModelResponse};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+type AssignFn = Arc<dyn Fn(&str, &ChatRequest) -> String + Send + Sync>;
+
+/// One arm of an [`ExperimentRouter`]: a bot and the request parameters it
+/// overrides
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExperimentVariant {
+    pub name: String,
+    /// Bot serving the variant; `None` keeps the router's bot
+    pub bot: Option<String>,
+    /// Temperature forced on the variant's requests
+    pub temperature: Option<f32>,
+}
+
+impl ExperimentVariant {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            bot: None,
+            temperature: None,
+        }
+    }
+
+    pub fn with_bot(mut self, bot: &str) -> Self {
+        self.bot = Some(bot.to_string());
+        self
+    }
+
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+struct ArmStats {
+    requests: AtomicU64,
+    failures: AtomicU64,
+    completed: AtomicU64,
+}
+
+struct Arm {
+    variant: ExperimentVariant,
+    client: PoeClient,
+    stats: Arc<ArmStats>,
+}
+
+/// Routes each chat request to an experiment variant for controlled model
+/// comparisons in production.
+///
+/// By default users are bucketed by a hash of `ChatRequest::user_id`, so a
+/// user always sees the same variant; [`ExperimentRouter::with_assignment`]
+/// plugs in any other rule. Unknown variant names fall back to the first
+/// variant. Per-variant outcomes are available from [`ExperimentRouter::stats`].
+pub struct ExperimentRouter {
+    arms: Vec<Arm>,
+    assign: AssignFn,
+}
+
+impl ExperimentRouter {
+    /// Split traffic between `variants` (a single `control` variant when empty)
+    pub fn new(template: PoeClient, variants: impl IntoIterator<Item = ExperimentVariant>) -> Self {
+        let mut variants: Vec<ExperimentVariant> = variants.into_iter().collect();
+        if variants.is_empty() {
+            variants.push(ExperimentVariant::new("control"));
+        }
+        let names: Vec<String> = variants.iter().map(|v| v.name.clone()).collect();
+
+        Self {
+            arms: variants
+                .into_iter()
+                .map(|variant| Arm {
+                    client: match &variant.bot {
+                        Some(bot) => template.clone().with_bot(bot),
+                        None => template.clone(),
+                    },
+                    variant,
+                    stats: Arc::default(),
+                })
+                .collect(),
+            assign: Arc::new(move |user_id, _| bucket(user_id, &names)),
+        }
+    }
+
+    /// Choose the variant name from the user ID and the request
+    pub fn with_assignment(
+        mut self,
+        assign: impl Fn(&str, &ChatRequest) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.assign = Arc::new(assign);
+        self
+    }
+
+    pub fn variants(&self) -> impl Iterator<Item = &ExperimentVariant> {
+        self.arms.iter().map(|arm| &arm.variant)
+    }
+
+    /// Get the outcome counts of every variant, in declaration order
+    pub fn stats(&self) -> Vec<VariantStats> {
+        self.arms
+            .iter()
+            .map(|arm| VariantStats {
+                variant: arm.variant.name.clone(),
+                requests: arm.stats.requests.load(Ordering::Relaxed),
+                failures: arm.stats.failures.load(Ordering::Relaxed),
+                completed: arm.stats.completed.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// Send a chat request to its variant, returning the variant together
+    /// with the stream
+    pub async fn stream_request_with_variant<'a>(
+        &'a self,
+        mut request: ChatRequest,
+    ) -> Result<(&'a ExperimentVariant, ChatStream<'a>), PoeError> {
+        let name = (self.assign)(&request.user_id, &request);
+        let arm = self
+            .arms
+            .iter()
+            .find(|arm| arm.variant.name == name)
+            .unwrap_or(&self.arms[0]);
+
+        #[cfg(feature = "trace")]
+        tracing::info!(
+            target: TARGET_CLIENT,
+            "Routing request of user {} to experiment variant {}",
+            request.user_id,
+            arm.variant.name
+        );
+
+        if let Some(temperature) = arm.variant.temperature {
+            request.temperature = Some(temperature);
+        }
+        arm.stats.requests.fetch_add(1, Ordering::Relaxed);
+
+        let stream = match arm.client.stream_request(request).await {
+            Ok(stream) => stream,
+            Err(error) => {
+                arm.stats.failures.fetch_add(1, Ordering::Relaxed);
+                return Err(error);
+            }
+        };
+
+        let stats = arm.stats.clone();
+        let stream = stream.map(move |item| {
+            match &item {
+                Ok(ChatResponse {
+                    event: ChatEventType::Done,
+                    ..
+                }) => {
+                    stats.completed.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(_)
+                | Ok(ChatResponse {
+                    event: ChatEventType::Error,
+                    ..
+                }) => {
+                    stats.failures.fetch_add(1, Ordering::Relaxed);
+                }
+                _ => {}
+            }
+            item
+        });
+        Ok((&arm.variant, Box::pin(stream)))
+    }
+}
+
+// 以 user_id 的 SHA-256 分桶，同一使用者固定落在同一組
+fn bucket(user_id: &str, names: &[String]) -> String {
+    let digest = Sha256::digest(user_id.as_bytes());
+    let value = u64::from_be_bytes(digest[..8].try_into().expect("digest has 32 bytes"));
+    names[(value % names.len() as u64) as usize].clone()
+}
+
+#[async_trait]
+impl PoeApi for ExperimentRouter {
+    async fn stream_request<'a>(
+        &'a self,
+        request: ChatRequest,
+    ) -> Result<ChatStream<'a>, PoeError> {
+        Ok(self.stream_request_with_variant(request).await?.1)
+    }
+
+    #[cfg(feature = "upload")]
+    async fn upload_local_file(
+        &self,
+        file_path: &str,
+        mime_type: Option<&str>,
+    ) -> Result<FileUploadResponse, PoeError> {
+        self.arms[0]
+            .client
+            .upload_local_file(file_path, mime_type)
+            .await
+    }
+
+    #[cfg(feature = "upload")]
+    async fn upload_remote_file(&self, download_url: &str) -> Result<FileUploadResponse, PoeError> {
+        self.arms[0].client.upload_remote_file(download_url).await
+    }
+
+    async fn get_v1_model_list(&self) -> Result<ModelResponse, PoeError> {
+        self.arms[0].client.get_v1_model_list().await
+    }
+}
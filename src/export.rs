@@ -0,0 +1,165 @@
+use crate::conversation::Conversation;
+use crate::error::PoeError;
+use crate::history::ConversationStore;
+use crate::types::{ChatMessage, ChatToolCall, ROLE_BOT, ROLE_SYSTEM, ROLE_TOOL, ROLE_USER};
+use serde_json::{Value, json};
+
+/// Training data layout produced by the exporters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// OpenAI chat fine-tuning: `{"messages": [{"role": ..., "content": ...}]}`
+    OpenAiChat,
+    /// ShareGPT: `{"conversations": [{"from": ..., "value": ...}]}`, with
+    /// `function_call`/`observation` turns for tool use
+    ShareGpt,
+}
+
+// 依時間順序展開的對話內容：工具調用與結果排在其所屬 bot 回合之前
+enum Entry<'a> {
+    Message(&'a ChatMessage),
+    ToolCalls(&'a [ChatToolCall]),
+    ToolResult {
+        tool_call_id: &'a str,
+        content: &'a str,
+    },
+}
+
+fn entries(conversation: &Conversation) -> Vec<Entry<'_>> {
+    let mut entries = Vec::new();
+    for (turn, message) in conversation.messages().iter().enumerate() {
+        push_exchanges(conversation, turn, &mut entries);
+        entries.push(Entry::Message(message));
+    }
+    push_exchanges(conversation, conversation.messages().len(), &mut entries);
+    entries
+}
+
+fn push_exchanges<'a>(conversation: &'a Conversation, turn: usize, entries: &mut Vec<Entry<'a>>) {
+    for exchange in conversation
+        .tool_exchanges()
+        .iter()
+        .filter(|exchange| exchange.turn == turn)
+    {
+        entries.push(Entry::ToolCalls(&exchange.tool_calls));
+        entries.extend(
+            exchange
+                .tool_results
+                .iter()
+                .map(|result| Entry::ToolResult {
+                    tool_call_id: &result.tool_call_id,
+                    content: &result.content,
+                }),
+        );
+    }
+}
+
+/// Convert a conversation to one OpenAI chat fine-tuning example.
+///
+/// Bot turns become `assistant` messages; attachments are not exported.
+pub fn to_openai_chat(conversation: &Conversation) -> Value {
+    let messages: Vec<Value> = entries(conversation)
+        .into_iter()
+        .map(|entry| match entry {
+            Entry::Message(message) => json!({
+                "role": match message.role.as_str() {
+                    ROLE_BOT => "assistant",
+                    role => role,
+                },
+                "content": message.content,
+            }),
+            Entry::ToolCalls(tool_calls) => json!({
+                "role": "assistant",
+                "content": null,
+                "tool_calls": tool_calls
+                    .iter()
+                    .map(|call| json!({
+                        "id": call.id,
+                        "type": call.r#type,
+                        "function": {
+                            "name": call.function.name,
+                            "arguments": call.function.arguments,
+                        },
+                    }))
+                    .collect::<Vec<_>>(),
+            }),
+            Entry::ToolResult {
+                tool_call_id,
+                content,
+            } => json!({
+                "role": ROLE_TOOL,
+                "tool_call_id": tool_call_id,
+                "content": content,
+            }),
+        })
+        .collect();
+
+    json!({ "messages": messages })
+}
+
+/// Convert a conversation to one ShareGPT example.
+///
+/// Each tool call becomes a `function_call` turn holding `{"name", "arguments"}`
+/// and each result an `observation` turn; attachments are not exported.
+pub fn to_sharegpt(conversation: &Conversation) -> Value {
+    let mut turns = Vec::new();
+    for entry in entries(conversation) {
+        match entry {
+            Entry::Message(message) => {
+                let from = match message.role.as_str() {
+                    ROLE_SYSTEM => "system",
+                    ROLE_USER => "human",
+                    ROLE_BOT => "gpt",
+                    role => role,
+                };
+                turns.push(json!({ "from": from, "value": message.content }));
+            }
+            Entry::ToolCalls(tool_calls) => {
+                for call in tool_calls {
+                    // 參數不是合法 JSON 時保留原字串
+                    let arguments = serde_json::from_str::<Value>(&call.function.arguments)
+                        .unwrap_or_else(|_| Value::String(call.function.arguments.clone()));
+                    let value = json!({ "name": call.function.name, "arguments": arguments });
+                    turns.push(json!({ "from": "function_call", "value": value.to_string() }));
+                }
+            }
+            Entry::ToolResult { content, .. } => {
+                turns.push(json!({ "from": "observation", "value": content }));
+            }
+        }
+    }
+
+    json!({ "conversations": turns })
+}
+
+/// Render conversations as JSONL, one training example per line
+pub fn export_jsonl<'a>(
+    conversations: impl IntoIterator<Item = &'a Conversation>,
+    format: ExportFormat,
+) -> String {
+    conversations
+        .into_iter()
+        .map(|conversation| {
+            let example = match format {
+                ExportFormat::OpenAiChat => to_openai_chat(conversation),
+                ExportFormat::ShareGpt => to_sharegpt(conversation),
+            };
+            example.to_string() + "\n"
+        })
+        .collect()
+}
+
+/// Load conversations from a [`ConversationStore`] and render them as JSONL,
+/// skipping IDs the store does not know
+pub async fn export_store(
+    store: &dyn ConversationStore,
+    conversation_ids: &[&str],
+    format: ExportFormat,
+) -> Result<String, PoeError> {
+    let mut conversations = Vec::new();
+    for conversation_id in conversation_ids {
+        if let Some(conversation) = store.load_conversation(conversation_id).await? {
+            conversations.push(conversation);
+        }
+    }
+    Ok(export_jsonl(&conversations, format))
+}
@@ -0,0 +1,176 @@
+use crate::api::{ChatStream, PoeApi};
+use crate::client::PoeClient;
+use crate::error::PoeError;
+#[cfg(feature = "trace")]
+use crate::logging::TARGET_CLIENT;
+#[cfg(feature = "upload")]
+use crate::types::FileUploadResponse;
+use crate::types::{ChatEventType, ChatRequest, ChatResponse, ChatResponseData, ModelResponse};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use std::sync::Arc;
+
+type FailoverPredicate = Arc<dyn Fn(&PoeError) -> bool + Send + Sync>;
+
+/// Bots tried in order for every chat request: when a bot is unavailable,
+/// rate limited or rejects the request before answering, the request is
+/// transparently re-sent to the next bot.
+///
+/// Failover only happens before a response starts; an `error` event as the
+/// first event (after any `meta` and `ping` events, which are replayed to the
+/// caller) counts as a rejection (e.g. a content filter). The last bot's
+/// failure is returned as is. Every bot shares the template's settings and
+/// connection pool.
+pub struct FallbackChain {
+    clients: Vec<PoeClient>,
+    fail_over_if: FailoverPredicate,
+}
+
+impl FallbackChain {
+    /// Try `bots` in order (at least the template's own bot when empty)
+    pub fn new<I, S>(template: PoeClient, bots: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut clients: Vec<PoeClient> = bots
+            .into_iter()
+            .map(|bot| template.clone().with_bot(bot.as_ref()))
+            .collect();
+        if clients.is_empty() {
+            clients.push(template);
+        }
+        Self {
+            clients,
+            fail_over_if: Arc::new(is_failover),
+        }
+    }
+
+    /// Decide which errors move the request to the next bot
+    pub fn with_fail_over_if(
+        mut self,
+        fail_over_if: impl Fn(&PoeError) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.fail_over_if = Arc::new(fail_over_if);
+        self
+    }
+
+    /// Get the bot names in the order they are tried
+    pub fn bots(&self) -> impl Iterator<Item = &str> {
+        self.clients.iter().map(PoeClient::get_bot_name)
+    }
+
+    /// Send a chat request down the chain, returning the bot that answered
+    /// together with its stream
+    pub async fn stream_request_with_bot<'a>(
+        &'a self,
+        request: ChatRequest,
+    ) -> Result<(&'a str, ChatStream<'a>), PoeError> {
+        let (last, fallbacks) = self
+            .clients
+            .split_last()
+            .expect("a fallback chain has at least one bot");
+
+        for client in fallbacks {
+            let failure = match client.stream_request(request.clone()).await {
+                Ok(mut stream) => {
+                    // 先讀取第一個實質事件（略過 meta 與 ping 並暫存），開頭即為錯誤時改用下一個 bot
+                    let mut prelude = Vec::new();
+                    let first = loop {
+                        match stream.next().await {
+                            Some(Ok(response))
+                                if matches!(
+                                    response.event,
+                                    ChatEventType::Meta | ChatEventType::Ping
+                                ) =>
+                            {
+                                prelude.push(Ok(response))
+                            }
+                            next => break next,
+                        }
+                    };
+                    let failure = match &first {
+                        Some(Err(error)) if (self.fail_over_if)(error) => Some(error.to_string()),
+                        Some(Ok(ChatResponse {
+                            event: ChatEventType::Error,
+                            data: Some(ChatResponseData::Error { text, .. }),
+                        })) if (self.fail_over_if)(&PoeError::BotError(text.clone())) => {
+                            Some(text.clone())
+                        }
+                        None if (self.fail_over_if)(&PoeError::StreamTruncated) => {
+                            Some(PoeError::StreamTruncated.to_string())
+                        }
+                        _ => None,
+                    };
+                    match failure {
+                        Some(failure) => failure,
+                        None => {
+                            let stream = futures_util::stream::iter(prelude.into_iter().chain(first))
+                                .chain(stream);
+                            return Ok((client.get_bot_name(), Box::pin(stream)));
+                        }
+                    }
+                }
+                Err(error) if (self.fail_over_if)(&error) => error.to_string(),
+                Err(error) => return Err(error),
+            };
+            #[cfg(feature = "trace")]
+            tracing::warn!(
+                target: TARGET_CLIENT,
+                "Bot {} failed ({}), falling back to the next bot",
+                client.get_bot_name(),
+                failure
+            );
+            #[cfg(not(feature = "trace"))]
+            let _ = failure;
+        }
+
+        let stream = last.stream_request(request).await?;
+        Ok((last.get_bot_name(), stream))
+    }
+}
+
+/// Default failover predicate of [`FallbackChain`]: unavailable bots
+/// (transport errors, 404, 5xx, open circuits), rate limits and rejections
+fn is_failover(error: &PoeError) -> bool {
+    match error {
+        #[cfg(feature = "reqwest")]
+        PoeError::RequestFailed(_) => true,
+        PoeError::TransportError(_)
+        | PoeError::StreamTruncated
+        | PoeError::CircuitOpen(_)
+        | PoeError::BotError(_) => true,
+        PoeError::ApiStatus { status, .. } => matches!(status, 404 | 429) || *status >= 500,
+        _ => false,
+    }
+}
+
+#[async_trait]
+impl PoeApi for FallbackChain {
+    async fn stream_request<'a>(
+        &'a self,
+        request: ChatRequest,
+    ) -> Result<ChatStream<'a>, PoeError> {
+        Ok(self.stream_request_with_bot(request).await?.1)
+    }
+
+    #[cfg(feature = "upload")]
+    async fn upload_local_file(
+        &self,
+        file_path: &str,
+        mime_type: Option<&str>,
+    ) -> Result<FileUploadResponse, PoeError> {
+        self.clients[0]
+            .upload_local_file(file_path, mime_type)
+            .await
+    }
+
+    #[cfg(feature = "upload")]
+    async fn upload_remote_file(&self, download_url: &str) -> Result<FileUploadResponse, PoeError> {
+        self.clients[0].upload_remote_file(download_url).await
+    }
+
+    async fn get_v1_model_list(&self) -> Result<ModelResponse, PoeError> {
+        self.clients[0].get_v1_model_list().await
+    }
+}
@@ -0,0 +1,147 @@
+use crate::conversation::Conversation;
+use crate::error::PoeError;
+use crate::types::{ChatResponse, FileUploadResponse};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[cfg(feature = "history-sqlite")]
+mod sqlite;
+
+#[cfg(feature = "history-sqlite")]
+pub use sqlite::SqliteConversationStore;
+
+/// Persistence backend for conversation history: the conversation state,
+/// the streamed events of each reply (tool calls included) and uploads.
+///
+/// Implement it over Postgres or any other database;
+/// [`InMemoryConversationStore`] is built in, and `SqliteConversationStore`
+/// with the `history-sqlite` feature.
+#[async_trait]
+pub trait ConversationStore: Send + Sync {
+    /// Insert or replace a conversation, keyed by its ID
+    async fn save_conversation(&self, conversation: &Conversation) -> Result<(), PoeError>;
+
+    async fn load_conversation(
+        &self,
+        conversation_id: &str,
+    ) -> Result<Option<Conversation>, PoeError>;
+
+    /// Append a streamed event to a conversation's log
+    async fn append_event(
+        &self,
+        conversation_id: &str,
+        event: &ChatResponse,
+    ) -> Result<(), PoeError>;
+
+    /// Get a conversation's events in the order they were appended
+    async fn events(&self, conversation_id: &str) -> Result<Vec<ChatResponse>, PoeError>;
+
+    async fn record_upload(
+        &self,
+        conversation_id: &str,
+        upload: &FileUploadResponse,
+    ) -> Result<(), PoeError>;
+
+    async fn uploads(&self, conversation_id: &str) -> Result<Vec<FileUploadResponse>, PoeError>;
+
+    /// Delete a conversation with its events and uploads, returning whether it existed
+    async fn delete_conversation(&self, conversation_id: &str) -> Result<bool, PoeError>;
+}
+
+#[derive(Debug, Default)]
+struct StoredConversation {
+    conversation: Option<Conversation>,
+    events: Vec<ChatResponse>,
+    uploads: Vec<FileUploadResponse>,
+}
+
+/// Process-local [`ConversationStore`], lost on restart
+#[derive(Debug, Default)]
+pub struct InMemoryConversationStore {
+    conversations: Mutex<HashMap<String, StoredConversation>>,
+}
+
+impl InMemoryConversationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with_entry<T>(
+        &self,
+        conversation_id: &str,
+        update: impl FnOnce(&mut StoredConversation) -> T,
+    ) -> T {
+        let mut conversations = self.conversations.lock().unwrap_or_else(|e| e.into_inner());
+        update(
+            conversations
+                .entry(conversation_id.to_string())
+                .or_default(),
+        )
+    }
+
+    fn read<T>(
+        &self,
+        conversation_id: &str,
+        read: impl FnOnce(&StoredConversation) -> T,
+    ) -> Option<T> {
+        let conversations = self.conversations.lock().unwrap_or_else(|e| e.into_inner());
+        conversations.get(conversation_id).map(read)
+    }
+}
+
+#[async_trait]
+impl ConversationStore for InMemoryConversationStore {
+    async fn save_conversation(&self, conversation: &Conversation) -> Result<(), PoeError> {
+        self.with_entry(conversation.id(), |stored| {
+            stored.conversation = Some(conversation.clone())
+        });
+        Ok(())
+    }
+
+    async fn load_conversation(
+        &self,
+        conversation_id: &str,
+    ) -> Result<Option<Conversation>, PoeError> {
+        Ok(self
+            .read(conversation_id, |stored| stored.conversation.clone())
+            .flatten())
+    }
+
+    async fn append_event(
+        &self,
+        conversation_id: &str,
+        event: &ChatResponse,
+    ) -> Result<(), PoeError> {
+        self.with_entry(conversation_id, |stored| stored.events.push(event.clone()));
+        Ok(())
+    }
+
+    async fn events(&self, conversation_id: &str) -> Result<Vec<ChatResponse>, PoeError> {
+        Ok(self
+            .read(conversation_id, |stored| stored.events.clone())
+            .unwrap_or_default())
+    }
+
+    async fn record_upload(
+        &self,
+        conversation_id: &str,
+        upload: &FileUploadResponse,
+    ) -> Result<(), PoeError> {
+        self.with_entry(conversation_id, |stored| {
+            stored.uploads.push(upload.clone())
+        });
+        Ok(())
+    }
+
+    async fn uploads(&self, conversation_id: &str) -> Result<Vec<FileUploadResponse>, PoeError> {
+        Ok(self
+            .read(conversation_id, |stored| stored.uploads.clone())
+            .unwrap_or_default())
+    }
+
+    async fn delete_conversation(&self, conversation_id: &str) -> Result<bool, PoeError> {
+        let mut conversations = self.conversations.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(conversations.remove(conversation_id).is_some())
+    }
+}
@@ -0,0 +1,229 @@
+use super::ConversationStore;
+use crate::conversation::Conversation;
+use crate::error::PoeError;
+use crate::types::{ChatResponse, FileUploadResponse};
+use async_trait::async_trait;
+use rusqlite::{Connection, OptionalExtension, params};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Schema migrations, applied in order; the database's `user_version`
+/// records how many have run
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE conversations (
+        id TEXT PRIMARY KEY,
+        user_id TEXT NOT NULL,
+        state TEXT NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE TABLE events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        conversation_id TEXT NOT NULL,
+        event TEXT NOT NULL
+    );
+    CREATE INDEX events_by_conversation ON events (conversation_id, id);
+    CREATE TABLE uploads (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        conversation_id TEXT NOT NULL,
+        attachment_url TEXT NOT NULL,
+        upload TEXT NOT NULL
+    );
+    CREATE INDEX uploads_by_conversation ON uploads (conversation_id, id);",
+];
+
+/// [`ConversationStore`] persisting to a SQLite database, created and
+/// migrated to the latest schema when opened.
+///
+/// Conversations are stored as JSON alongside their ID and user ID; events
+/// (tool calls included) and uploads are appended to their own tables.
+#[derive(Debug, Clone)]
+pub struct SqliteConversationStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteConversationStore {
+    /// Open or create the database file at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, PoeError> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Open a private database that lives as long as the store
+    pub fn open_in_memory() -> Result<Self, PoeError> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    /// Use an already opened connection, applying pending migrations
+    pub fn from_connection(mut connection: Connection) -> Result<Self, PoeError> {
+        migrate(&mut connection)?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Schema version of the database, i.e. the number of applied migrations
+    pub fn schema_version(&self) -> Result<u32, PoeError> {
+        let connection = self.connection.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(schema_version(&connection)?)
+    }
+
+    // rusqlite 為同步 API，在阻塞執行緒上操作以免卡住 async runtime
+    async fn run<T: Send + 'static>(
+        &self,
+        query: impl FnOnce(&mut Connection) -> Result<T, PoeError> + Send + 'static,
+    ) -> Result<T, PoeError> {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection.lock().unwrap_or_else(|e| e.into_inner());
+            query(&mut connection)
+        })
+        .await
+        .unwrap_or_else(|error| std::panic::resume_unwind(error.into_panic()))
+    }
+}
+
+fn schema_version(connection: &Connection) -> rusqlite::Result<u32> {
+    connection.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+fn migrate(connection: &mut Connection) -> rusqlite::Result<()> {
+    let applied = schema_version(connection)? as usize;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", index as u32 + 1)?;
+        transaction.commit()?;
+    }
+    Ok(())
+}
+
+fn unix_time() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[async_trait]
+impl ConversationStore for SqliteConversationStore {
+    async fn save_conversation(&self, conversation: &Conversation) -> Result<(), PoeError> {
+        let id = conversation.id().to_string();
+        let user_id = conversation.user_id().to_string();
+        let state = serde_json::to_string(conversation)?;
+        self.run(move |connection| {
+            connection.execute(
+                "INSERT INTO conversations (id, user_id, state, updated_at)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (id) DO UPDATE SET
+                     user_id = excluded.user_id,
+                     state = excluded.state,
+                     updated_at = excluded.updated_at",
+                params![id, user_id, state, unix_time()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn load_conversation(
+        &self,
+        conversation_id: &str,
+    ) -> Result<Option<Conversation>, PoeError> {
+        let id = conversation_id.to_string();
+        let state: Option<String> = self
+            .run(move |connection| {
+                Ok(connection
+                    .query_row(
+                        "SELECT state FROM conversations WHERE id = ?1",
+                        [id],
+                        |row| row.get(0),
+                    )
+                    .optional()?)
+            })
+            .await?;
+        Ok(state.map(|state| serde_json::from_str(&state)).transpose()?)
+    }
+
+    async fn append_event(
+        &self,
+        conversation_id: &str,
+        event: &ChatResponse,
+    ) -> Result<(), PoeError> {
+        let id = conversation_id.to_string();
+        let event = serde_json::to_string(event)?;
+        self.run(move |connection| {
+            connection.execute(
+                "INSERT INTO events (conversation_id, event) VALUES (?1, ?2)",
+                params![id, event],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn events(&self, conversation_id: &str) -> Result<Vec<ChatResponse>, PoeError> {
+        let id = conversation_id.to_string();
+        let events = self
+            .run(move |connection| {
+                let mut statement = connection
+                    .prepare("SELECT event FROM events WHERE conversation_id = ?1 ORDER BY id")?;
+                let events = statement
+                    .query_map([id], |row| row.get::<_, String>(0))?
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(events)
+            })
+            .await?;
+        events
+            .iter()
+            .map(|event| Ok(serde_json::from_str(event)?))
+            .collect()
+    }
+
+    async fn record_upload(
+        &self,
+        conversation_id: &str,
+        upload: &FileUploadResponse,
+    ) -> Result<(), PoeError> {
+        let id = conversation_id.to_string();
+        let attachment_url = upload.attachment_url.clone();
+        let upload = serde_json::to_string(upload)?;
+        self.run(move |connection| {
+            connection.execute(
+                "INSERT INTO uploads (conversation_id, attachment_url, upload) VALUES (?1, ?2, ?3)",
+                params![id, attachment_url, upload],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn uploads(&self, conversation_id: &str) -> Result<Vec<FileUploadResponse>, PoeError> {
+        let id = conversation_id.to_string();
+        let uploads = self
+            .run(move |connection| {
+                let mut statement = connection
+                    .prepare("SELECT upload FROM uploads WHERE conversation_id = ?1 ORDER BY id")?;
+                let uploads = statement
+                    .query_map([id], |row| row.get::<_, String>(0))?
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(uploads)
+            })
+            .await?;
+        uploads
+            .iter()
+            .map(|upload| Ok(serde_json::from_str(upload)?))
+            .collect()
+    }
+
+    async fn delete_conversation(&self, conversation_id: &str) -> Result<bool, PoeError> {
+        let id = conversation_id.to_string();
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+            let deleted = transaction.execute("DELETE FROM conversations WHERE id = ?1", [&id])?
+                + transaction.execute("DELETE FROM events WHERE conversation_id = ?1", [&id])?
+                + transaction.execute("DELETE FROM uploads WHERE conversation_id = ?1", [&id])?;
+            transaction.commit()?;
+            Ok(deleted > 0)
+        })
+        .await
+    }
+}
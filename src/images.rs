@@ -0,0 +1,170 @@
+use crate::client::{MultipartForm, PoeClient, record_checksum};
+use crate::error::PoeError;
+#[cfg(feature = "trace")]
+use crate::logging::TARGET_UPLOAD;
+use crate::types::{FileUploadRequest, FileUploadResponse};
+use crate::util::to_hex;
+use ::image::codecs::jpeg::JpegEncoder;
+use ::image::imageops::FilterType;
+use ::image::{DynamicImage, GenericImageView, ImageFormat};
+use sha2::{Digest, Sha256};
+use std::io::Cursor;
+use std::path::Path;
+#[cfg(feature = "trace")]
+use tracing::debug;
+
+/// Image re-encoded by [`compress_image`]
+#[derive(Debug, Clone)]
+pub struct CompressedImage {
+    pub bytes: Vec<u8>,
+    pub mime_type: String,
+    pub width: u32,
+    pub height: u32,
+    /// Whether the image was downscaled to fit the maximum dimension
+    pub resized: bool,
+}
+
+/// Result of `PoeClient::upload_image`
+#[derive(Debug, Clone)]
+pub struct ImageUpload {
+    pub upload: FileUploadResponse,
+    /// Size of the local file in bytes
+    pub original_size: u64,
+    /// Size of the uploaded image in bytes
+    pub uploaded_size: u64,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ImageUpload {
+    /// Bytes saved by compressing before upload
+    pub fn saved_bytes(&self) -> u64 {
+        self.original_size.saturating_sub(self.uploaded_size)
+    }
+}
+
+/// Downscale `bytes` so neither side exceeds `max_dimension` (keeping the
+/// aspect ratio) and re-encode it.
+///
+/// Opaque images become JPEG at `quality` (1-100); images with transparency
+/// stay lossless PNG. Animated images keep only their first frame. When the
+/// image already fits and re-encoding would not make it smaller, the original
+/// bytes are returned unchanged.
+pub fn compress_image(
+    bytes: &[u8],
+    max_dimension: u32,
+    quality: u8,
+) -> Result<CompressedImage, PoeError> {
+    let format =
+        ::image::guess_format(bytes).map_err(|e| PoeError::UnsupportedFileType(e.to_string()))?;
+    let image = ::image::load_from_memory_with_format(bytes, format)
+        .map_err(|e| PoeError::ImageProcessingFailed(e.to_string()))?;
+
+    let (width, height) = image.dimensions();
+    let max_dimension = max_dimension.max(1);
+    let resized = width > max_dimension || height > max_dimension;
+    let image = if resized {
+        image.resize(max_dimension, max_dimension, FilterType::Lanczos3)
+    } else {
+        image
+    };
+
+    let (encoded, mime_type) = encode(&image, quality)?;
+    if !resized && encoded.len() >= bytes.len() {
+        return Ok(CompressedImage {
+            bytes: bytes.to_vec(),
+            mime_type: format.to_mime_type().to_string(),
+            width,
+            height,
+            resized,
+        });
+    }
+
+    Ok(CompressedImage {
+        bytes: encoded,
+        mime_type: mime_type.to_string(),
+        width: image.width(),
+        height: image.height(),
+        resized,
+    })
+}
+
+fn encode(image: &DynamicImage, quality: u8) -> Result<(Vec<u8>, &'static str), PoeError> {
+    let mut buffer = Vec::new();
+    let mime_type = if image.color().has_alpha() {
+        image
+            .write_to(&mut Cursor::new(&mut buffer), ImageFormat::Png)
+            .map_err(|e| PoeError::ImageProcessingFailed(e.to_string()))?;
+        "image/png"
+    } else {
+        JpegEncoder::new_with_quality(&mut buffer, quality.clamp(1, 100))
+            .encode_image(&image.to_rgb8())
+            .map_err(|e| PoeError::ImageProcessingFailed(e.to_string()))?;
+        "image/jpeg"
+    };
+    Ok((buffer, mime_type))
+}
+
+impl PoeClient {
+    /// Downscale and compress a local image (see [`compress_image`]) before
+    /// uploading it, reporting the original and uploaded sizes.
+    ///
+    /// Refreshing the returned attachment re-uploads the original file.
+    pub async fn upload_image(
+        &self,
+        file_path: &str,
+        max_dimension: u32,
+        quality: u8,
+    ) -> Result<ImageUpload, PoeError> {
+        let path = Path::new(file_path);
+        if !path.exists() {
+            return Err(PoeError::FileNotFound(file_path.to_string()));
+        }
+
+        let bytes = tokio::fs::read(path).await?;
+        let original_size = bytes.len() as u64;
+        let image =
+            tokio::task::spawn_blocking(move || compress_image(&bytes, max_dimension, quality))
+                .await
+                .map_err(|e| PoeError::ImageProcessingFailed(e.to_string()))??;
+
+        #[cfg(feature = "trace")]
+        debug!(
+            target: TARGET_UPLOAD,
+            "圖片壓縮完成: {} | {} -> {} bytes | {}x{}",
+            file_path,
+            original_size,
+            image.bytes.len(),
+            image.width,
+            image.height
+        );
+
+        let extension = image.mime_type.trim_start_matches("image/");
+        let file_name = Path::new(path.file_name().unwrap_or(path.as_os_str()))
+            .with_extension(extension)
+            .to_string_lossy()
+            .into_owned();
+        let uploaded_size = image.bytes.len() as u64;
+        let digest = to_hex(&Sha256::digest(&image.bytes));
+        let form =
+            MultipartForm::new().file("file", &file_name, &image.mime_type, &image.bytes)?;
+
+        let response = self.send_upload_request(form).await?;
+        let response = record_checksum(file_path, response, digest)?;
+        let upload = self.track_upload(
+            response,
+            FileUploadRequest::LocalFile {
+                file: file_path.to_string(),
+                mime_type: None,
+            },
+        );
+
+        Ok(ImageUpload {
+            upload,
+            original_size,
+            uploaded_size,
+            width: image.width,
+            height: image.height,
+        })
+    }
+}
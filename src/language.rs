@@ -0,0 +1,126 @@
+use crate::client::PoeClient;
+use crate::error::PoeError;
+use crate::types::{ChatRequest, ROLE_USER};
+use whatlang::Lang;
+
+// Locale prefixes (ISO 639-1) mapped to whatlang's ISO 639-3 languages
+const LOCALE_LANGS: &[(&str, Lang)] = &[
+    ("ar", Lang::Ara),
+    ("de", Lang::Deu),
+    ("en", Lang::Eng),
+    ("es", Lang::Spa),
+    ("fr", Lang::Fra),
+    ("hi", Lang::Hin),
+    ("id", Lang::Ind),
+    ("it", Lang::Ita),
+    ("ja", Lang::Jpn),
+    ("ko", Lang::Kor),
+    ("nl", Lang::Nld),
+    ("pl", Lang::Pol),
+    ("pt", Lang::Por),
+    ("ru", Lang::Rus),
+    ("th", Lang::Tha),
+    ("tr", Lang::Tur),
+    ("uk", Lang::Ukr),
+    ("vi", Lang::Vie),
+    ("zh", Lang::Cmn),
+];
+
+/// Result of checking a response against the requested locale
+#[derive(Debug, Clone, PartialEq)]
+pub struct LanguageCheck {
+    /// Detected language as an ISO 639-3 code, if detection was reliable
+    pub detected: Option<&'static str>,
+    /// `false` only when a different language was reliably detected
+    pub matches: bool,
+}
+
+/// Aggregated response text returned by `PoeClient::complete_in_language`
+#[derive(Debug, Clone)]
+pub struct LanguageCheckedText {
+    pub text: String,
+    pub check: LanguageCheck,
+    /// Whether the request was re-sent with a "respond in X" instruction
+    pub retried: bool,
+}
+
+/// Resolve a locale such as `zh-Hant`, `en-US` or `jpn` to a detectable language
+fn locale_lang(language_code: &str) -> Option<Lang> {
+    let primary = language_code
+        .split(['-', '_'])
+        .next()
+        .unwrap_or(language_code)
+        .to_lowercase();
+
+    LOCALE_LANGS
+        .iter()
+        .find(|(code, _)| *code == primary)
+        .map(|(_, lang)| *lang)
+        .or_else(|| Lang::from_code(primary))
+}
+
+/// Detect the language of `text` and compare it with `language_code`.
+///
+/// Unreliable detections (short or mixed text) and unknown locales count as a match.
+pub fn check_language(text: &str, language_code: &str) -> LanguageCheck {
+    let detected = whatlang::detect(text)
+        .filter(|info| info.is_reliable())
+        .map(|info| info.lang());
+
+    let matches = match (detected, locale_lang(language_code)) {
+        (Some(detected), Some(expected)) => detected == expected,
+        _ => true,
+    };
+
+    LanguageCheck {
+        detected: detected.map(|lang| lang.code()),
+        matches,
+    }
+}
+
+impl PoeClient {
+    /// Send the request, collect the response text and, if it is reliably in
+    /// another language than `language_code`, re-send it once with a
+    /// "respond in X" instruction appended to the last user message.
+    ///
+    /// The retry is subject to the client's retry budget.
+    pub async fn complete_in_language(
+        &self,
+        request: ChatRequest,
+        language_code: &str,
+    ) -> Result<LanguageCheckedText, PoeError> {
+        let text = self.collect_text(request.clone()).await?;
+        let check = check_language(&text, language_code);
+        if check.matches || !self.allow_retry() {
+            return Ok(LanguageCheckedText {
+                text,
+                check,
+                retried: false,
+            });
+        }
+
+        let mut request = request;
+        let language_name = locale_lang(language_code)
+            .map(|lang| lang.eng_name())
+            .unwrap_or(language_code);
+        if let Some(message) = request
+            .query
+            .iter_mut()
+            .rev()
+            .find(|message| message.role == ROLE_USER)
+        {
+            message.content.push_str(&format!(
+                "\n\nPlease respond only in {} ({}).",
+                language_name, language_code
+            ));
+        }
+
+        let text = self.collect_text(request).await?;
+        let check = check_language(&text, language_code);
+        Ok(LanguageCheckedText {
+            text,
+            check,
+            retried: true,
+        })
+    }
+}
@@ -1,15 +1,140 @@
+#[cfg(feature = "client")]
+pub mod abort;
+pub mod aggregate;
+#[cfg(feature = "client")]
+pub mod api;
+#[cfg(feature = "upload")]
+pub mod attachments;
+#[cfg(feature = "client")]
+pub mod balancer;
+#[cfg(feature = "client")]
+pub mod circuit;
+#[cfg(feature = "client")]
 pub mod client;
+#[cfg(feature = "client")]
+pub mod conversation;
+#[cfg(feature = "client")]
+pub mod decorators;
+#[cfg(feature = "client")]
+pub mod embedding;
 pub mod error;
+#[cfg(feature = "client")]
+pub mod experiment;
+#[cfg(feature = "client")]
+pub mod export;
+#[cfg(feature = "client")]
+pub mod fallback;
+#[cfg(feature = "client")]
+pub mod history;
+#[cfg(feature = "image")]
+pub mod images;
+#[cfg(feature = "language-detect")]
+pub mod language;
 pub mod types;
 pub mod logging;
+#[cfg(feature = "markdown")]
+pub mod markdown;
+#[cfg(feature = "client")]
+pub mod metrics;
+#[cfg(feature = "client")]
+pub mod mock;
+#[cfg(feature = "client")]
+pub mod pool;
+pub mod prelude;
+#[cfg(feature = "client")]
+pub mod rag;
+#[cfg(feature = "client")]
+pub mod retry;
+#[cfg(feature = "client")]
+pub mod runtime;
+#[cfg(feature = "client")]
+pub mod scheduler;
+pub mod signing;
+pub mod sse;
+#[cfg(feature = "client")]
+pub mod stream_ext;
+#[cfg(feature = "client")]
+pub mod tools;
+#[cfg(feature = "client")]
+pub mod transport;
+pub mod util;
 
-#[cfg(feature = "xml")]
+#[cfg(any(feature = "xml-parse", feature = "xml-prompt"))]
 pub mod xml;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "reqwest", feature = "upload", feature = "gql"))]
 pub mod test;
 
-pub use client::{PoeClient, get_model_list};
-pub use error::PoeError;
+#[cfg(feature = "client")]
+pub use abort::AbortHandle;
+pub use aggregate::{CompleteResponse, ResponseAggregator, resolve_inline_refs};
+#[cfg(feature = "client")]
+pub use api::{ChatStream, PoeApi};
+#[cfg(feature = "upload")]
+pub use attachments::{AttachmentExpansion, ExpansionAction, TextExtractor};
+#[cfg(feature = "client")]
+pub use balancer::{BalanceStrategy, BotPool};
+#[cfg(feature = "client")]
+pub use circuit::{CircuitBreaker, CircuitState};
+#[cfg(feature = "client")]
+pub use client::{BotCapabilities, PoeClient, PoeClientBuilder};
+#[cfg(feature = "gql")]
+pub use client::get_model_list;
+#[cfg(feature = "client")]
+pub use conversation::{
+    AttachmentOrigin, Conversation, ConversationAttachment, RegenerateOptions, ToolExchange,
+};
+#[cfg(feature = "client")]
+pub use decorators::{CachedPoeClient, RetryPoeClient, ThrottledPoeClient};
+#[cfg(feature = "client")]
+pub use embedding::PoeEmbedder;
+pub use error::{PoeError, PoeErrorWithPartial};
+#[cfg(feature = "client")]
+pub use experiment::{ExperimentRouter, ExperimentVariant};
+#[cfg(feature = "client")]
+pub use export::ExportFormat;
+#[cfg(feature = "client")]
+pub use fallback::FallbackChain;
+#[cfg(feature = "client")]
+pub use history::{ConversationStore, InMemoryConversationStore};
+#[cfg(feature = "history-sqlite")]
+pub use history::SqliteConversationStore;
+#[cfg(feature = "language-detect")]
+pub use language::{LanguageCheck, LanguageCheckedText, check_language};
 pub use types::*;
 pub use logging::*;
+#[cfg(feature = "image")]
+pub use images::{CompressedImage, ImageUpload, compress_image};
+#[cfg(feature = "markdown")]
+pub use markdown::{MarkdownOutput, MarkdownRenderer};
+#[cfg(feature = "client")]
+pub use metrics::{BotLoadStats, StreamStats, ThrottleStats, VariantStats};
+#[cfg(feature = "client")]
+pub use mock::MockPoeClient;
+#[cfg(feature = "client")]
+pub use pool::PoeClientPool;
+#[cfg(feature = "client")]
+pub use rag::{Citation, RagDocument, RagResponse};
+#[cfg(feature = "client")]
+pub use retry::{RetryBudget, RetryBudgetStats, RetryPolicy};
+#[cfg(feature = "client")]
+pub use runtime::{ThreadTimer, Timer};
+#[cfg(feature = "runtime-tokio")]
+pub use runtime::TokioTimer;
+#[cfg(feature = "client")]
+pub use scheduler::{RequestScheduler, ScheduledPoeClient, SchedulerPermit};
+pub use signing::{RequestSigner, SigningRequest};
+pub use sse::{SSE_CONTENT_TYPE, SseEvent, SseFrame, SseParser, SseWriter};
+#[cfg(feature = "http-server")]
+pub use sse::sse_body;
+#[cfg(feature = "client")]
+pub use stream_ext::{ChatStreamExt, ReplaceStrategy};
+#[cfg(feature = "client")]
+pub use tools::memory::{Embedder, MemoryRecord, MemoryStore};
+#[cfg(feature = "client")]
+pub use tools::{Tool, ToolRegistry};
+#[cfg(feature = "client")]
+pub use transport::{HttpExchange, HttpRequest, HttpResponse, HttpTap, HttpTransport};
+#[cfg(feature = "reqwest")]
+pub use transport::ReqwestTransport;
+pub use util::{TextChunker, TextSanitizer, sniff_mime};
@@ -2,6 +2,26 @@ use crate::types::*;
 use std::time::SystemTime;
 use std::time::UNIX_EPOCH;
 
+/// Tracing target of requests, model lists, downloads and errors.
+///
+/// Every subsystem logs under its own `poe::*` target, so a single one can be
+/// enabled at runtime, e.g. `RUST_LOG=poe::sse=debug` or `RUST_LOG=poe=debug`.
+pub const TARGET_CLIENT: &str = "poe::client";
+/// Tracing target of SSE decoding, including raw `trace-wire` chunks
+pub const TARGET_SSE: &str = "poe::sse";
+/// Tracing target of XML tool call parsing and conversion
+pub const TARGET_XML: &str = "poe::xml";
+/// Tracing target of file uploads, attachment expansion and image compression
+pub const TARGET_UPLOAD: &str = "poe::upload";
+/// Tracing target of the GraphQL model list
+pub const TARGET_GQL: &str = "poe::gql";
+
+/// Check whether a header carries credentials and must be masked
+pub(crate) fn is_sensitive_header(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.contains("authorization") || name.contains("cookie")
+}
+
 /// Logging configuration for request/response logging
 #[derive(Debug, Clone)]
 pub struct LoggingConfig {
@@ -10,6 +30,8 @@ pub struct LoggingConfig {
     pub log_headers: bool,
     pub log_body: bool,
     pub max_body_length: usize,
+    /// Maximum number of raw bytes shown per SSE chunk when `trace-wire` is enabled
+    pub max_wire_chunk_length: usize,
 }
 
 impl Default for LoggingConfig {
@@ -20,6 +42,7 @@ impl Default for LoggingConfig {
             log_headers: true,
             log_body: true,
             max_body_length: 10000, // 10KB max body length
+            max_wire_chunk_length: 1024,
         }
     }
 }
@@ -66,34 +89,34 @@ impl LoggingHelper {
         output.push_str(&format!("   Method: {}\n", log.method));
         output.push_str(&format!("   URL: {}\n", log.url));
         
-        if config.log_headers {
-            if let Some(headers) = &log.headers {
-                output.push_str("   Headers:\n");
-                for (key, value) in headers {
-                    // Mask sensitive headers
-                    let masked_value = if key.to_lowercase().contains("authorization") {
-                        "***MASKED***".to_string()
-                    } else {
-                        value.clone()
-                    };
-                    output.push_str(&format!("     {}: {}\n", key, masked_value));
-                }
-            }
-        }
-        
-        if config.log_body {
-            if let Some(body) = &log.body {
-                let truncated_body = if body.len() > config.max_body_length {
-                    format!("{}... [truncated, {} bytes total]", 
-                           &body[..config.max_body_length], body.len())
+        if config.log_headers
+            && let Some(headers) = &log.headers
+        {
+            output.push_str("   Headers:\n");
+            for (key, value) in headers {
+                // Mask sensitive headers
+                let masked_value = if is_sensitive_header(key) {
+                    "***MASKED***".to_string()
                 } else {
-                    body.clone()
+                    value.clone()
                 };
-                output.push_str(&format!("   Body ({} bytes):\n", log.body_size.unwrap_or(0)));
-                output.push_str(&format!("     {}\n", truncated_body));
+                output.push_str(&format!("     {}: {}\n", key, masked_value));
             }
         }
         
+        if config.log_body
+            && let Some(body) = &log.body
+        {
+            let truncated_body = if body.len() > config.max_body_length {
+                format!("{}... [truncated, {} bytes total]", 
+                       &body[..body.floor_char_boundary(config.max_body_length)], body.len())
+            } else {
+                body.clone()
+            };
+            output.push_str(&format!("   Body ({} bytes):\n", log.body_size.unwrap_or(0)));
+            output.push_str(&format!("     {}\n", truncated_body));
+        }
+        
         output
     }
 
@@ -116,26 +139,26 @@ impl LoggingHelper {
             output.push_str(&format!("   Duration: {}ms\n", duration));
         }
         
-        if config.log_headers {
-            if let Some(headers) = &log.headers {
-                output.push_str("   Headers:\n");
-                for (key, value) in headers {
-                    output.push_str(&format!("     {}: {}\n", key, value));
-                }
+        if config.log_headers
+            && let Some(headers) = &log.headers
+        {
+            output.push_str("   Headers:\n");
+            for (key, value) in headers {
+                output.push_str(&format!("     {}: {}\n", key, value));
             }
         }
         
-        if config.log_body {
-            if let Some(body) = &log.body {
-                let truncated_body = if body.len() > config.max_body_length {
-                    format!("{}... [truncated, {} bytes total]", 
-                           &body[..config.max_body_length], body.len())
-                } else {
-                    body.clone()
-                };
-                output.push_str(&format!("   Body ({} bytes):\n", log.body_size.unwrap_or(0)));
-                output.push_str(&format!("     {}\n", truncated_body));
-            }
+        if config.log_body
+            && let Some(body) = &log.body
+        {
+            let truncated_body = if body.len() > config.max_body_length {
+                format!("{}... [truncated, {} bytes total]", 
+                       &body[..body.floor_char_boundary(config.max_body_length)], body.len())
+            } else {
+                body.clone()
+            };
+            output.push_str(&format!("   Body ({} bytes):\n", log.body_size.unwrap_or(0)));
+            output.push_str(&format!("     {}\n", truncated_body));
         }
         
         output
@@ -170,6 +193,14 @@ impl LoggingHelper {
         if let Some(temperature) = &request.temperature {
             output.push_str(&format!("   Temperature: {}\n", temperature));
         }
+
+        if let Some(tool_choice) = &request.tool_choice {
+            output.push_str(&format!("   Tool Choice: {:?}\n", tool_choice));
+        }
+
+        if let Some(seed) = &request.seed {
+            output.push_str(&format!("   Seed: {}\n", seed));
+        }
         
         output
     }
@@ -198,9 +229,27 @@ impl LoggingHelper {
             Some(ChatResponseData::File(file_data)) => {
                 output.push_str(&format!("   File: {} ({})\n", file_data.name, file_data.content_type));
             }
+            Some(ChatResponseData::Done(info)) => {
+                output.push_str(&format!("   Done: message {}\n", info.message_id.as_deref().unwrap_or("-")));
+            }
+            Some(ChatResponseData::Meta(meta)) => {
+                output.push_str(&format!("   Meta: content type {}\n", meta.content_type.as_deref().unwrap_or("-")));
+            }
+            Some(ChatResponseData::SuggestedReply { text }) => {
+                output.push_str(&format!("   Suggested reply: {} chars\n", text.len()));
+            }
+            Some(ChatResponseData::Usage(usage)) => {
+                output.push_str(&format!("   Usage: {:?} tokens, {:?} points\n", usage.total(), usage.points));
+            }
             Some(ChatResponseData::Empty) => {
                 output.push_str("   Status: Empty\n");
             }
+            Some(ChatResponseData::KeepAlive) => {
+                output.push_str("   Status: Keep-alive\n");
+            }
+            Some(ChatResponseData::Raw(raw)) => {
+                output.push_str(&format!("   Raw: {} chars\n", raw.to_string().len()));
+            }
             None => {
                 output.push_str("   Status: No data\n");
             }
@@ -219,40 +268,71 @@ impl LoggingHelper {
         
         output
     }
+
+    /// Format a raw SSE chunk with its sequence number, escaping non-printable
+    /// bytes and capping the output at `max_wire_chunk_length` bytes
+    pub fn format_wire_chunk(sequence: u64, chunk: &[u8], config: &LoggingConfig) -> String {
+        let shown = &chunk[..chunk.len().min(config.max_wire_chunk_length)];
+        let escaped: String = shown
+            .iter()
+            .flat_map(|byte| std::ascii::escape_default(*byte))
+            .map(char::from)
+            .collect();
+
+        if shown.len() < chunk.len() {
+            format!(
+                "🔌 WIRE #{} ({} bytes): \"{}\"... [truncated]",
+                sequence,
+                chunk.len(),
+                escaped
+            )
+        } else {
+            format!("🔌 WIRE #{} ({} bytes): \"{}\"", sequence, chunk.len(), escaped)
+        }
+    }
 }
 
 #[cfg(feature = "trace")]
 use tracing::{debug, error};
+#[cfg(feature = "trace-wire")]
+use tracing::trace;
 
 #[cfg(feature = "trace")]
 impl LoggingHelper {
     /// Log request with tracing
     pub fn log_request(log: &RequestLog, config: &LoggingConfig) {
         let formatted = Self::format_request_log(log, config);
-        debug!("{}", formatted);
+        debug!(target: TARGET_CLIENT, "{}", formatted);
     }
 
     /// Log response with tracing
     pub fn log_response(log: &ResponseLog, config: &LoggingConfig) {
         let formatted = Self::format_response_log(log, config);
-        debug!("{}", formatted);
+        debug!(target: TARGET_CLIENT, "{}", formatted);
     }
 
     /// Log chat request with tracing
     pub fn log_chat_request(request: &ChatRequest) {
         let formatted = Self::format_chat_request(request);
-        debug!("{}", formatted);
+        debug!(target: TARGET_CLIENT, "{}", formatted);
     }
 
     /// Log chat response with tracing
     pub fn log_chat_response(response: &ChatResponse) {
         let formatted = Self::format_chat_response(response);
-        debug!("{}", formatted);
+        debug!(target: TARGET_SSE, "{}", formatted);
     }
 
     /// Log error with tracing
     pub fn log_error(error: &crate::error::PoeError) {
         let formatted = Self::format_error(error);
-        error!("{}", formatted);
+        error!(target: TARGET_CLIENT, "{}", formatted);
+    }
+
+    /// Log a raw SSE chunk with tracing
+    #[cfg(feature = "trace-wire")]
+    pub fn log_wire_chunk(sequence: u64, chunk: &[u8], config: &LoggingConfig) {
+        let formatted = Self::format_wire_chunk(sequence, chunk, config);
+        trace!(target: TARGET_SSE, "{}", formatted);
     }
 }
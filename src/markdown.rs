@@ -0,0 +1,243 @@
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd, html};
+
+const RESET: &str = "\x1b[0m";
+
+/// Output format of [`MarkdownRenderer`] segments
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MarkdownOutput {
+    #[default]
+    Html,
+    /// Terminal text styled with ANSI escape sequences
+    Ansi,
+}
+
+/// Incremental Markdown renderer for streamed text.
+///
+/// Text deltas are buffered and only complete blocks (ended by a blank line
+/// outside a fenced code block) are rendered, so a UI can append each
+/// segment once instead of re-rendering half-written tables, lists or code
+/// fences on every delta. Show [`MarkdownRenderer::pending`] as plain text
+/// until it is rendered.
+#[derive(Debug, Clone, Default)]
+pub struct MarkdownRenderer {
+    output: MarkdownOutput,
+    buffer: String,
+}
+
+impl MarkdownRenderer {
+    pub fn new(output: MarkdownOutput) -> Self {
+        Self {
+            output,
+            buffer: String::new(),
+        }
+    }
+
+    /// Feed a text delta and return rendered segments for every completed block
+    pub fn push(&mut self, delta: &str) -> Vec<String> {
+        self.buffer.push_str(delta);
+
+        let Some(boundary) = block_boundary(&self.buffer) else {
+            return Vec::new();
+        };
+        let complete: String = self.buffer.drain(..boundary).collect();
+        vec![self.render(&complete)]
+    }
+
+    /// Restart from a full replacement text (`replace_response`), returning
+    /// its completed blocks
+    pub fn replace(&mut self, text: &str) -> Vec<String> {
+        self.buffer.clear();
+        self.push(text)
+    }
+
+    /// Render whatever is still buffered at the end of the stream
+    pub fn finish(&mut self) -> Option<String> {
+        let remaining = std::mem::take(&mut self.buffer);
+        (!remaining.trim().is_empty()).then(|| self.render(&remaining))
+    }
+
+    /// Markdown received but not rendered yet
+    pub fn pending(&self) -> &str {
+        &self.buffer
+    }
+
+    fn render(&self, markdown: &str) -> String {
+        let options =
+            Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+        let parser = Parser::new_ext(markdown, options);
+        match self.output {
+            MarkdownOutput::Html => {
+                let mut output = String::new();
+                html::push_html(&mut output, parser);
+                output
+            }
+            MarkdownOutput::Ansi => render_ansi(parser),
+        }
+    }
+}
+
+/// Byte offset just past the last blank line (or closing fence) that ends a
+/// block outside a fenced code block
+fn block_boundary(text: &str) -> Option<usize> {
+    let mut fence: Option<(char, usize)> = None;
+    let mut boundary = None;
+    let mut offset = 0;
+
+    for line in text.split_inclusive('\n') {
+        offset += line.len();
+        if !line.ends_with('\n') {
+            break;
+        }
+
+        let trimmed = line.trim_start();
+        let marker = trimmed.chars().next().filter(|c| *c == '`' || *c == '~');
+        let run = marker.map_or(0, |c| trimmed.chars().take_while(|x| *x == c).count());
+
+        match (fence, marker) {
+            (None, Some(c)) if run >= 3 => fence = Some((c, run)),
+            (Some((open, length)), Some(c))
+                if c == open && run >= length && trimmed[run..].trim().is_empty() =>
+            {
+                fence = None;
+                boundary = Some(offset);
+            }
+            (None, _) if line.trim().is_empty() => boundary = Some(offset),
+            _ => {}
+        }
+    }
+
+    boundary
+}
+
+fn render_ansi<'a>(parser: impl Iterator<Item = Event<'a>>) -> String {
+    let mut output = String::new();
+    let mut styles: Vec<&'static str> = Vec::new();
+    // 每層清單的下一個序號（無序清單為 None）
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut links: Vec<String> = Vec::new();
+    let mut quote_depth = 0;
+
+    let restyle = |output: &mut String, styles: &[&str]| {
+        output.push_str(RESET);
+        styles.iter().for_each(|style| output.push_str(style));
+    };
+    let newline = |output: &mut String, quote_depth: usize| {
+        output.push('\n');
+        output.push_str(&"│ ".repeat(quote_depth));
+    };
+
+    for event in parser {
+        match event {
+            Event::Start(tag) => {
+                let style = match &tag {
+                    Tag::Heading { level, .. } => Some(if *level == HeadingLevel::H1 {
+                        "\x1b[1;4m"
+                    } else {
+                        "\x1b[1m"
+                    }),
+                    Tag::Strong => Some("\x1b[1m"),
+                    Tag::Emphasis => Some("\x1b[3m"),
+                    Tag::Strikethrough => Some("\x1b[9m"),
+                    Tag::CodeBlock(_) => Some("\x1b[2m"),
+                    Tag::Link { .. } => Some("\x1b[4m"),
+                    _ => None,
+                };
+                match tag {
+                    Tag::BlockQuote(_) => {
+                        quote_depth += 1;
+                        output.push_str("│ ");
+                    }
+                    Tag::CodeBlock(CodeBlockKind::Fenced(language)) if !language.is_empty() => {
+                        output.push_str(&format!("\x1b[2m[{}]{}", language, RESET));
+                        newline(&mut output, quote_depth);
+                    }
+                    Tag::List(start) => {
+                        if !lists.is_empty() {
+                            newline(&mut output, quote_depth);
+                        }
+                        lists.push(start);
+                    }
+                    Tag::Item => {
+                        output.push_str(&"  ".repeat(lists.len().saturating_sub(1)));
+                        match lists.last_mut() {
+                            Some(Some(number)) => {
+                                output.push_str(&format!("{}. ", number));
+                                *number += 1;
+                            }
+                            _ => output.push_str("• "),
+                        }
+                    }
+                    Tag::Link { dest_url, .. } => links.push(dest_url.to_string()),
+                    _ => {}
+                }
+                if let Some(style) = style {
+                    styles.push(style);
+                    output.push_str(style);
+                }
+            }
+            Event::End(tag) => {
+                if matches!(
+                    tag,
+                    TagEnd::Heading(_)
+                        | TagEnd::Strong
+                        | TagEnd::Emphasis
+                        | TagEnd::Strikethrough
+                        | TagEnd::CodeBlock
+                        | TagEnd::Link
+                ) {
+                    styles.pop();
+                    restyle(&mut output, &styles);
+                }
+                match tag {
+                    TagEnd::Link => {
+                        if let Some(url) = links.pop() {
+                            output.push_str(&format!(" ({})", url));
+                        }
+                    }
+                    TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::CodeBlock | TagEnd::Table
+                        if lists.is_empty() =>
+                    {
+                        newline(&mut output, quote_depth);
+                    }
+                    TagEnd::Item => newline(&mut output, quote_depth),
+                    TagEnd::List(_) => {
+                        lists.pop();
+                    }
+                    TagEnd::BlockQuote(_) => quote_depth = quote_depth.saturating_sub(1),
+                    TagEnd::TableCell => output.push_str(" │ "),
+                    TagEnd::TableHead | TagEnd::TableRow => newline(&mut output, quote_depth),
+                    _ => {}
+                }
+            }
+            Event::Text(text) => {
+                let mut lines = text.split('\n').peekable();
+                while let Some(line) = lines.next() {
+                    output.push_str(line);
+                    if lines.peek().is_some_and(|next| !next.is_empty()) {
+                        newline(&mut output, quote_depth);
+                    }
+                }
+                if text.ends_with('\n') {
+                    newline(&mut output, quote_depth);
+                }
+            }
+            Event::Code(code) => {
+                output.push_str("\x1b[36m");
+                output.push_str(&code);
+                restyle(&mut output, &styles);
+            }
+            Event::SoftBreak | Event::HardBreak => newline(&mut output, quote_depth),
+            Event::Rule => {
+                output.push_str("────────");
+                newline(&mut output, quote_depth);
+            }
+            Event::TaskListMarker(checked) => {
+                output.push_str(if checked { "[x] " } else { "[ ] " })
+            }
+            Event::Html(html) | Event::InlineHtml(html) => output.push_str(&html),
+            _ => {}
+        }
+    }
+
+    output.trim_end_matches(['\n', ' ', '│']).to_string() + "\n"
+}
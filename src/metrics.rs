@@ -0,0 +1,117 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Snapshot of the memory held by a client's in-flight streams
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StreamStats {
+    /// Streams returned by `stream_request` that have not ended or been dropped yet
+    pub active_streams: usize,
+    /// Bytes held in SSE line and XML detection buffers
+    pub buffered_bytes: usize,
+    /// Bytes of partially received tool calls
+    pub tool_call_bytes: usize,
+}
+
+/// Snapshot of the 429 responses a retry layer waited out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ThrottleStats {
+    /// Retries delayed by a `Retry-After` header
+    pub throttled: u64,
+    /// Total time spent waiting on those headers
+    pub total_wait: Duration,
+}
+
+/// Snapshot of one bot's load in a `BotPool`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BotLoadStats {
+    pub bot_name: String,
+    /// Streams currently open against the bot
+    pub in_flight: usize,
+    /// Requests routed to the bot
+    pub requests: u64,
+    /// Requests that failed or whose stream yielded an error
+    pub failures: u64,
+    /// Moving average of the time to the first event, once measured
+    pub latency: Option<Duration>,
+}
+
+/// Snapshot of one variant's outcomes in an `ExperimentRouter`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariantStats {
+    pub variant: String,
+    /// Requests routed to the variant
+    pub requests: u64,
+    /// Requests that failed or whose stream yielded an error
+    pub failures: u64,
+    /// Streams that reached their `done` event
+    pub completed: u64,
+}
+
+// Client-wide gauges, shared by every clone of a client
+#[derive(Debug, Default)]
+pub(crate) struct StreamGauges {
+    active_streams: AtomicUsize,
+    buffered_bytes: AtomicUsize,
+    tool_call_bytes: AtomicUsize,
+}
+
+impl StreamGauges {
+    pub(crate) fn snapshot(&self) -> StreamStats {
+        StreamStats {
+            active_streams: self.active_streams.load(Ordering::Relaxed),
+            buffered_bytes: self.buffered_bytes.load(Ordering::Relaxed),
+            tool_call_bytes: self.tool_call_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Register a new stream; the returned guard releases its share when dropped
+    pub(crate) fn track(self: &Arc<Self>) -> StreamGuard {
+        self.active_streams.fetch_add(1, Ordering::Relaxed);
+        StreamGuard {
+            gauges: self.clone(),
+            buffered_bytes: 0,
+            tool_call_bytes: 0,
+        }
+    }
+}
+
+// Per-stream contribution to the client gauges
+pub(crate) struct StreamGuard {
+    gauges: Arc<StreamGauges>,
+    buffered_bytes: usize,
+    tool_call_bytes: usize,
+}
+
+impl StreamGuard {
+    /// Report the stream's current buffer sizes
+    pub(crate) fn update(&mut self, buffered_bytes: usize, tool_call_bytes: usize) {
+        Self::adjust(
+            &self.gauges.buffered_bytes,
+            self.buffered_bytes,
+            buffered_bytes,
+        );
+        Self::adjust(
+            &self.gauges.tool_call_bytes,
+            self.tool_call_bytes,
+            tool_call_bytes,
+        );
+        self.buffered_bytes = buffered_bytes;
+        self.tool_call_bytes = tool_call_bytes;
+    }
+
+    fn adjust(gauge: &AtomicUsize, old: usize, new: usize) {
+        if new > old {
+            gauge.fetch_add(new - old, Ordering::Relaxed);
+        } else {
+            gauge.fetch_sub(old - new, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.update(0, 0);
+        self.gauges.active_streams.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::api::{ChatStream, PoeApi};
+use crate::error::PoeError;
+#[cfg(feature = "upload")]
+use crate::types::FileUploadResponse;
+use crate::types::{
+    ChatEventType, ChatRequest, ChatResponse, ChatResponseData, FileUploadRequest, ModelResponse,
+};
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// In-memory [`PoeApi`] implementation that replays scripted responses and
+/// records every call, for testing code that depends on `dyn PoeApi`.
+///
+/// Each `stream_request` consumes the next scripted response; the last one
+/// is replayed once the script runs out (an immediate `done` if none was set).
+#[derive(Debug)]
+pub struct MockPoeClient {
+    responses: Mutex<VecDeque<Vec<ChatResponse>>>,
+    models: ModelResponse,
+    requests: Mutex<Vec<ChatRequest>>,
+    uploads: Mutex<Vec<FileUploadRequest>>,
+}
+
+impl Default for MockPoeClient {
+    fn default() -> Self {
+        Self {
+            responses: Mutex::new(VecDeque::new()),
+            models: ModelResponse { data: Vec::new() },
+            requests: Mutex::new(Vec::new()),
+            uploads: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl MockPoeClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue the events returned by the next `stream_request`
+    pub fn with_response(self, events: Vec<ChatResponse>) -> Self {
+        self.responses
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push_back(events);
+        self
+    }
+
+    /// Queue a reply consisting of one `text` event followed by `done`
+    pub fn with_text(self, text: &str) -> Self {
+        self.with_response(vec![
+            ChatResponse {
+                event: ChatEventType::Text,
+                data: Some(ChatResponseData::Text {
+                    text: text.to_string(),
+                }),
+            },
+            ChatResponse {
+                event: ChatEventType::Done,
+                data: None,
+            },
+        ])
+    }
+
+    /// Set the model list returned by `get_v1_model_list`
+    pub fn with_models(mut self, models: ModelResponse) -> Self {
+        self.models = models;
+        self
+    }
+
+    /// Chat requests received so far
+    pub fn requests(&self) -> Vec<ChatRequest> {
+        self.requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Uploads received so far
+    pub fn uploads(&self) -> Vec<FileUploadRequest> {
+        self.uploads
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    #[cfg(feature = "upload")]
+    fn record_upload(
+        &self,
+        source: FileUploadRequest,
+        mime_type: Option<&str>,
+    ) -> FileUploadResponse {
+        let mut uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        uploads.push(source.clone());
+        FileUploadResponse {
+            attachment_url: format!("https://mock.poe.invalid/attachments/{}", uploads.len()),
+            mime_type: mime_type.map(str::to_string),
+            size: None,
+            expires_at: None,
+            sha256: None,
+            source: Some(source),
+        }
+    }
+}
+
+#[async_trait]
+impl PoeApi for MockPoeClient {
+    async fn stream_request<'a>(
+        &'a self,
+        request: ChatRequest,
+    ) -> Result<ChatStream<'a>, PoeError> {
+        self.requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(request);
+
+        let events = {
+            let mut responses = self.responses.lock().unwrap_or_else(|e| e.into_inner());
+            match responses.len() {
+                0 => vec![ChatResponse {
+                    event: ChatEventType::Done,
+                    data: None,
+                }],
+                1 => responses[0].clone(),
+                _ => responses.pop_front().unwrap_or_default(),
+            }
+        };
+        Ok(Box::pin(futures_util::stream::iter(
+            events.into_iter().map(Ok),
+        )))
+    }
+
+    #[cfg(feature = "upload")]
+    async fn upload_local_file(
+        &self,
+        file_path: &str,
+        mime_type: Option<&str>,
+    ) -> Result<FileUploadResponse, PoeError> {
+        let source = FileUploadRequest::LocalFile {
+            file: file_path.to_string(),
+            mime_type: mime_type.map(str::to_string),
+        };
+        Ok(self.record_upload(source, mime_type))
+    }
+
+    #[cfg(feature = "upload")]
+    async fn upload_remote_file(&self, download_url: &str) -> Result<FileUploadResponse, PoeError> {
+        url::Url::parse(download_url)?;
+        let source = FileUploadRequest::RemoteFile {
+            download_url: download_url.to_string(),
+        };
+        Ok(self.record_upload(source, None))
+    }
+
+    async fn get_v1_model_list(&self) -> Result<ModelResponse, PoeError> {
+        Ok(self.models.clone())
+    }
+}
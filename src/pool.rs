@@ -0,0 +1,105 @@
+use crate::client::PoeClient;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PoolKey {
+    access_key: String,
+    bot_name: String,
+}
+
+struct PooledClient {
+    client: PoeClient,
+    last_used: u64,
+}
+
+/// Clients per `(access_key, bot)` pair for multi-tenant backends, evicting
+/// the least recently used pair once `capacity` is reached.
+///
+/// Every client is derived from one template, so they all share its
+/// connection pool and settings (URLs, logging, retry budget, circuit breaker).
+pub struct PoeClientPool {
+    template: PoeClient,
+    capacity: usize,
+    clients: Mutex<(u64, HashMap<PoolKey, PooledClient>)>,
+}
+
+impl PoeClientPool {
+    pub fn new(template: PoeClient, capacity: usize) -> Self {
+        Self {
+            template,
+            capacity: capacity.max(1),
+            clients: Mutex::new((0, HashMap::new())),
+        }
+    }
+
+    /// Get the client for `access_key` and `bot_name`, creating it if needed
+    pub fn get(&self, access_key: &str, bot_name: &str) -> PoeClient {
+        let key = PoolKey {
+            access_key: access_key.to_string(),
+            bot_name: bot_name.to_string(),
+        };
+        let mut guard = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        let (clock, clients) = &mut *guard;
+        *clock += 1;
+
+        if let Some(pooled) = clients.get_mut(&key) {
+            pooled.last_used = *clock;
+            return pooled.client.clone();
+        }
+
+        if clients.len() >= self.capacity
+            && let Some(oldest) = clients
+                .iter()
+                .min_by_key(|(_, pooled)| pooled.last_used)
+                .map(|(key, _)| key.clone())
+        {
+            clients.remove(&oldest);
+        }
+
+        let client = self
+            .template
+            .clone()
+            .with_access_key(access_key)
+            .with_bot(bot_name);
+        clients.insert(
+            key,
+            PooledClient {
+                client: client.clone(),
+                last_used: *clock,
+            },
+        );
+        client
+    }
+
+    /// Drop every client of `access_key`, e.g. when a customer's key is revoked,
+    /// returning how many were removed
+    pub fn remove_access_key(&self, access_key: &str) -> usize {
+        let mut guard = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        let clients = &mut guard.1;
+        let before = clients.len();
+        clients.retain(|key, _| key.access_key != access_key);
+        before - clients.len()
+    }
+
+    /// Number of pooled clients
+    pub fn len(&self) -> usize {
+        self.clients
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .1
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.clients
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .1
+            .clear();
+    }
+}
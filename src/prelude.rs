@@ -0,0 +1,25 @@
+//! Most-used types in one import: `use poe_api_process::prelude::*;`
+//!
+//! Only the core client surface lives here; everything else stays available
+//! from the crate root.
+
+pub use crate::aggregate::CompleteResponse;
+pub use crate::error::PoeError;
+pub use crate::types::{
+    Attachment, ChatEventType, ChatMessage, ChatRequest, ChatRequestBuilder, ChatResponse,
+    ChatResponseData, ChatTool, ChatToolBuilder, ChatToolCall, ChatToolResult, ContentType,
+    ParamType, ToolChoice,
+};
+
+#[cfg(feature = "client")]
+pub use crate::abort::AbortHandle;
+#[cfg(feature = "client")]
+pub use crate::api::{ChatStream, PoeApi};
+#[cfg(feature = "client")]
+pub use crate::client::{PoeClient, PoeClientBuilder};
+#[cfg(feature = "client")]
+pub use crate::conversation::Conversation;
+#[cfg(feature = "client")]
+pub use crate::retry::RetryPolicy;
+#[cfg(feature = "client")]
+pub use crate::stream_ext::ChatStreamExt;
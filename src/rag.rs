@@ -0,0 +1,129 @@
+use crate::client::PoeClient;
+use crate::error::PoeError;
+use crate::types::{ChatRequest, ROLE_USER};
+
+const RAG_INSTRUCTION: &str = "Answer using the sources below. Cite the sources you use \
+with their bracketed numbers, e.g. [1] or [2, 3].";
+
+/// A retrieved document to ground the answer in
+#[derive(Debug, Clone, PartialEq)]
+pub struct RagDocument {
+    /// Caller's identifier for the source (database id, path, ...)
+    pub id: String,
+    pub title: Option<String>,
+    pub url: Option<String>,
+    pub content: String,
+}
+
+impl RagDocument {
+    pub fn new(id: &str, content: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            title: None,
+            url: None,
+            content: content.to_string(),
+        }
+    }
+
+    pub fn with_title(mut self, title: &str) -> Self {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    pub fn with_url(mut self, url: &str) -> Self {
+        self.url = Some(url.to_string());
+        self
+    }
+}
+
+/// A citation marker found in the answer, resolved to its source
+#[derive(Debug, Clone, PartialEq)]
+pub struct Citation {
+    /// Number used in the prompt and answer (`[n]`), 1-based
+    pub marker: usize,
+    pub document: RagDocument,
+}
+
+/// Answer text with the sources it cites, in order of first citation
+#[derive(Debug, Clone, PartialEq)]
+pub struct RagResponse {
+    pub text: String,
+    pub citations: Vec<Citation>,
+}
+
+/// Format documents as a numbered source list; document `i` is cited as `[i + 1]`
+pub fn format_sources(documents: &[RagDocument]) -> String {
+    let mut context = String::from(RAG_INSTRUCTION);
+    for (index, document) in documents.iter().enumerate() {
+        context.push_str(&format!("\n\n[{}]", index + 1));
+        if let Some(title) = &document.title {
+            context.push_str(&format!(" {}", title));
+        }
+        if let Some(url) = &document.url {
+            context.push_str(&format!(" <{}>", url));
+        }
+        context.push('\n');
+        context.push_str(document.content.trim());
+    }
+    context
+}
+
+/// Map bracketed citations (`[1]`, `[1, 3]`) in `text` back to `documents`,
+/// ignoring numbers without a matching document
+pub fn extract_citations(text: &str, documents: &[RagDocument]) -> Vec<Citation> {
+    let mut citations: Vec<Citation> = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('[') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find(']') else {
+            break;
+        };
+
+        let markers: Option<Vec<usize>> = rest[..end]
+            .split(',')
+            .map(|marker| marker.trim().parse().ok())
+            .collect();
+        for marker in markers.unwrap_or_default() {
+            if let Some(document) = marker.checked_sub(1).and_then(|i| documents.get(i))
+                && !citations.iter().any(|citation| citation.marker == marker)
+            {
+                citations.push(Citation {
+                    marker,
+                    document: document.clone(),
+                });
+            }
+        }
+    }
+
+    citations
+}
+
+impl PoeClient {
+    /// Ground the request in `documents`: the numbered sources are prepended to
+    /// the last user message, and citations in the answer are resolved back
+    /// to their documents
+    pub async fn complete_with_sources(
+        &self,
+        mut request: ChatRequest,
+        documents: &[RagDocument],
+    ) -> Result<RagResponse, PoeError> {
+        if !documents.is_empty()
+            && let Some(message) = request
+                .query
+                .iter_mut()
+                .rev()
+                .find(|message| message.role == ROLE_USER)
+        {
+            message.content = format!(
+                "{}\n\n---\n\n{}",
+                format_sources(documents),
+                message.content
+            );
+        }
+
+        let text = self.collect_text(request).await?;
+        let citations = extract_citations(&text, documents);
+        Ok(RagResponse { text, citations })
+    }
+}
@@ -0,0 +1,130 @@
+use crate::error::PoeError;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Client-wide cap on retries, shared by every clone of a client.
+///
+/// Each request deposits `retry_ratio` tokens (up to `max_tokens`) and each
+/// retry withdraws one, so retries stay a bounded fraction of traffic during
+/// an outage instead of multiplying the load.
+#[derive(Debug)]
+pub struct RetryBudget {
+    balance: Mutex<f64>,
+    max_tokens: f64,
+    retry_ratio: f64,
+    exhausted: AtomicU64,
+}
+
+/// Snapshot of a [`RetryBudget`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryBudgetStats {
+    /// Retries currently available
+    pub balance: f64,
+    /// Retries refused because the budget was empty
+    pub exhausted: u64,
+}
+
+impl RetryBudget {
+    /// Create a full budget of `max_tokens` retries, refilled by `retry_ratio` per request
+    pub fn new(max_tokens: u32, retry_ratio: f64) -> Self {
+        Self {
+            balance: Mutex::new(max_tokens as f64),
+            max_tokens: max_tokens as f64,
+            retry_ratio: retry_ratio.max(0.0),
+            exhausted: AtomicU64::new(0),
+        }
+    }
+
+    /// Record an outgoing request
+    pub fn deposit(&self) {
+        let mut balance = self.balance.lock().unwrap_or_else(|e| e.into_inner());
+        *balance = (*balance + self.retry_ratio).min(self.max_tokens);
+    }
+
+    /// Take one retry from the budget, returning `false` when it is exhausted
+    pub fn try_withdraw(&self) -> bool {
+        let mut balance = self.balance.lock().unwrap_or_else(|e| e.into_inner());
+        if *balance >= 1.0 {
+            *balance -= 1.0;
+            true
+        } else {
+            self.exhausted.fetch_add(1, Ordering::Relaxed);
+            false
+        }
+    }
+
+    /// Get the current balance and exhaustion count
+    pub fn stats(&self) -> RetryBudgetStats {
+        RetryBudgetStats {
+            balance: *self.balance.lock().unwrap_or_else(|e| e.into_inner()),
+            exhausted: self.exhausted.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for RetryBudget {
+    /// 10 retries in reserve, plus one retry per five requests
+    fn default() -> Self {
+        Self::new(10, 0.2)
+    }
+}
+
+/// Exponential backoff used by `PoeClient::with_retry_policy`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled on every attempt
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Fraction of each delay that is randomized (0.0 to 1.0), so clients
+    /// that failed together do not retry in lockstep
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    /// Three retries starting at 500 ms, capped at 30 s, with 20% jitter
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Retry up to `max_retries` times with the default backoff
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            ..Self::default()
+        }
+    }
+
+    /// Get the delay before retry number `attempt` (starting at 0)
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        // RandomState 每次建立都帶有新的隨機鍵，足以打散重試時間
+        let random = RandomState::new().hash_one(attempt) as f64 / u64::MAX as f64;
+        delay.mul_f64(1.0 - self.jitter.clamp(0.0, 1.0) * random)
+    }
+}
+
+/// Check whether an error is worth retrying before a stream starts: transport
+/// errors and 429/5xx responses
+pub(crate) fn is_transient(error: &PoeError) -> bool {
+    match error {
+        #[cfg(feature = "reqwest")]
+        PoeError::RequestFailed(_) => true,
+        PoeError::TransportError(_) => true,
+        PoeError::ApiStatus { status, .. } => *status == 429 || *status >= 500,
+        _ => false,
+    }
+}
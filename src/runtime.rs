@@ -0,0 +1,117 @@
+use async_trait::async_trait;
+use futures_util::future::{Either, select};
+use std::future::Future;
+use std::pin::pin;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+/// Timer behind retry back-off, request throttling and stream quiescence
+/// windows, so the client runs on any async runtime.
+///
+/// [`TokioTimer`] is the default with the `runtime-tokio` feature,
+/// [`ThreadTimer`] without it.
+#[async_trait]
+pub trait Timer: Send + Sync {
+    async fn sleep(&self, duration: Duration);
+}
+
+#[async_trait]
+impl<T: Timer + ?Sized> Timer for Arc<T> {
+    async fn sleep(&self, duration: Duration) {
+        (**self).sleep(duration).await
+    }
+}
+
+/// Timer backed by `tokio::time`
+#[cfg(feature = "runtime-tokio")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioTimer;
+
+#[cfg(feature = "runtime-tokio")]
+#[async_trait]
+impl Timer for TokioTimer {
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
+
+/// Runtime-independent timer waking sleepers from one shared background thread
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThreadTimer;
+
+type Deadline = (Instant, oneshot::Sender<()>);
+
+#[async_trait]
+impl Timer for ThreadTimer {
+    async fn sleep(&self, duration: Duration) {
+        // 時間溢位視同永不到期
+        let Some(at) = Instant::now().checked_add(duration) else {
+            return std::future::pending().await;
+        };
+        let (sender, receiver) = oneshot::channel();
+        if timer_thread().send((at, sender)).is_ok() {
+            let _ = receiver.await;
+        }
+    }
+}
+
+fn timer_thread() -> &'static mpsc::Sender<Deadline> {
+    static SENDER: OnceLock<mpsc::Sender<Deadline>> = OnceLock::new();
+    SENDER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("poe-timer".to_string())
+            .spawn(move || run_timer_thread(receiver))
+            .expect("failed to spawn the timer thread");
+        sender
+    })
+}
+
+// 喚醒到期的 sleep，並移除已被丟棄（接收端關閉）的項目
+fn run_timer_thread(receiver: mpsc::Receiver<Deadline>) {
+    let mut pending: Vec<Deadline> = Vec::new();
+    loop {
+        let now = Instant::now();
+        let (due, waiting): (Vec<_>, Vec<_>) =
+            pending.drain(..).partition(|(at, _)| *at <= now);
+        for (_, sender) in due {
+            let _ = sender.send(());
+        }
+        pending = waiting
+            .into_iter()
+            .filter(|(_, sender)| !sender.is_closed())
+            .collect();
+
+        let received = match pending.iter().map(|(at, _)| *at).min() {
+            Some(next) => receiver.recv_timeout(next.saturating_duration_since(now)),
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(deadline) => pending.push(deadline),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// Default timer for the enabled runtime features
+pub(crate) fn default_timer() -> Arc<dyn Timer> {
+    #[cfg(feature = "runtime-tokio")]
+    return Arc::new(TokioTimer);
+    #[cfg(not(feature = "runtime-tokio"))]
+    return Arc::new(ThreadTimer);
+}
+
+/// Run `future` unless `duration` elapses first, returning `None` on timeout
+pub(crate) async fn timeout<F: Future>(
+    timer: &dyn Timer,
+    duration: Duration,
+    future: F,
+) -> Option<F::Output> {
+    match select(pin!(future), timer.sleep(duration)).await {
+        Either::Left((output, _)) => Some(output),
+        Either::Right(_) => None,
+    }
+}
@@ -0,0 +1,262 @@
+use crate::api::{ChatStream, PoeApi};
+use crate::error::PoeError;
+#[cfg(feature = "upload")]
+use crate::types::FileUploadResponse;
+use crate::types::{ChatRequest, ModelResponse, RequestPriority};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{Notify, oneshot};
+
+struct Waiter {
+    priority: RequestPriority,
+    // 同優先級時先到先服務
+    sequence: u64,
+    grant: oneshot::Sender<()>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+struct SchedulerState {
+    running: usize,
+    next_sequence: u64,
+    waiters: BinaryHeap<Waiter>,
+}
+
+struct SchedulerInner {
+    max_concurrent: usize,
+    state: Mutex<SchedulerState>,
+    // 有新的等待者加入時通知，供執行中的低優先級工作讓出
+    enqueued: Notify,
+}
+
+impl SchedulerInner {
+    fn lock(&self) -> std::sync::MutexGuard<'_, SchedulerState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Hand a released slot to the highest-priority waiter still listening
+    fn release(&self) {
+        let mut state = self.lock();
+        while let Some(waiter) = state.waiters.pop() {
+            if waiter.grant.send(()).is_ok() {
+                return;
+            }
+        }
+        state.running -= 1;
+    }
+}
+
+/// Concurrency limiter that grants free slots to the highest-priority waiter
+/// first (first come, first served within a priority).
+///
+/// Running work cannot be interrupted, but long background jobs can check
+/// [`SchedulerPermit::should_yield`] or await [`SchedulerPermit::preempted`]
+/// and release their slot when more urgent requests queue up.
+#[derive(Clone)]
+pub struct RequestScheduler {
+    inner: Arc<SchedulerInner>,
+}
+
+impl RequestScheduler {
+    pub fn new(max_concurrent: usize) -> Self {
+        Self {
+            inner: Arc::new(SchedulerInner {
+                max_concurrent: max_concurrent.max(1),
+                state: Mutex::new(SchedulerState {
+                    running: 0,
+                    next_sequence: 0,
+                    waiters: BinaryHeap::new(),
+                }),
+                enqueued: Notify::new(),
+            }),
+        }
+    }
+
+    /// Wait for a slot; the slot is released when the permit is dropped
+    pub async fn acquire(&self, priority: RequestPriority) -> SchedulerPermit {
+        let receiver = {
+            let mut state = self.inner.lock();
+            if state.running < self.inner.max_concurrent && state.waiters.is_empty() {
+                state.running += 1;
+                return self.permit(priority);
+            }
+
+            let (grant, receiver) = oneshot::channel();
+            let sequence = state.next_sequence;
+            state.next_sequence += 1;
+            state.waiters.push(Waiter {
+                priority,
+                sequence,
+                grant,
+            });
+            receiver
+        };
+        self.inner.enqueued.notify_waiters();
+
+        let mut pending = PendingGrant {
+            inner: &self.inner,
+            receiver,
+            granted: false,
+        };
+        // The sender lives in the queue until a slot is handed over
+        let _ = (&mut pending.receiver).await;
+        pending.granted = true;
+        self.permit(priority)
+    }
+
+    /// Number of requests waiting for a slot
+    pub fn queued(&self) -> usize {
+        self.inner.lock().waiters.len()
+    }
+
+    /// Number of slots in use
+    pub fn running(&self) -> usize {
+        self.inner.lock().running
+    }
+
+    fn permit(&self, priority: RequestPriority) -> SchedulerPermit {
+        SchedulerPermit {
+            inner: self.inner.clone(),
+            priority,
+        }
+    }
+}
+
+// 等待中的 acquire 被取消時，若名額已交付則歸還
+struct PendingGrant<'a> {
+    inner: &'a SchedulerInner,
+    receiver: oneshot::Receiver<()>,
+    granted: bool,
+}
+
+impl Drop for PendingGrant<'_> {
+    fn drop(&mut self) {
+        if !self.granted {
+            self.receiver.close();
+            if self.receiver.try_recv().is_ok() {
+                self.inner.release();
+            }
+        }
+    }
+}
+
+/// A slot of a [`RequestScheduler`], released on drop
+pub struct SchedulerPermit {
+    inner: Arc<SchedulerInner>,
+    priority: RequestPriority,
+}
+
+impl SchedulerPermit {
+    pub fn priority(&self) -> RequestPriority {
+        self.priority
+    }
+
+    /// Check whether a higher-priority request is waiting for a slot
+    pub fn should_yield(&self) -> bool {
+        self.inner
+            .lock()
+            .waiters
+            .peek()
+            .is_some_and(|waiter| waiter.priority > self.priority)
+    }
+
+    /// Resolve once a higher-priority request is waiting for a slot
+    pub async fn preempted(&self) {
+        loop {
+            let enqueued = self.inner.enqueued.notified();
+            if self.should_yield() {
+                return;
+            }
+            enqueued.await;
+        }
+    }
+}
+
+impl Drop for SchedulerPermit {
+    fn drop(&mut self) {
+        self.inner.release();
+    }
+}
+
+/// [`PoeApi`] decorator running every call through a [`RequestScheduler`],
+/// using `ChatRequest::priority` for chat requests and `Normal` otherwise.
+///
+/// A chat stream holds its slot until it is dropped.
+pub struct ScheduledPoeClient<A> {
+    inner: A,
+    scheduler: RequestScheduler,
+}
+
+impl<A: PoeApi> ScheduledPoeClient<A> {
+    pub fn new(inner: A, scheduler: RequestScheduler) -> Self {
+        Self { inner, scheduler }
+    }
+
+    /// Get the wrapped client
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    pub fn scheduler(&self) -> &RequestScheduler {
+        &self.scheduler
+    }
+}
+
+#[async_trait]
+impl<A: PoeApi> PoeApi for ScheduledPoeClient<A> {
+    async fn stream_request<'a>(
+        &'a self,
+        request: ChatRequest,
+    ) -> Result<ChatStream<'a>, PoeError> {
+        let permit = self.scheduler.acquire(request.priority).await;
+        let stream = self.inner.stream_request(request).await?;
+        Ok(Box::pin(stream.map(move |item| {
+            let _permit = &permit;
+            item
+        })))
+    }
+
+    #[cfg(feature = "upload")]
+    async fn upload_local_file(
+        &self,
+        file_path: &str,
+        mime_type: Option<&str>,
+    ) -> Result<FileUploadResponse, PoeError> {
+        let _permit = self.scheduler.acquire(RequestPriority::Normal).await;
+        self.inner.upload_local_file(file_path, mime_type).await
+    }
+
+    #[cfg(feature = "upload")]
+    async fn upload_remote_file(&self, download_url: &str) -> Result<FileUploadResponse, PoeError> {
+        let _permit = self.scheduler.acquire(RequestPriority::Normal).await;
+        self.inner.upload_remote_file(download_url).await
+    }
+
+    async fn get_v1_model_list(&self) -> Result<ModelResponse, PoeError> {
+        let _permit = self.scheduler.acquire(RequestPriority::Normal).await;
+        self.inner.get_v1_model_list().await
+    }
+}
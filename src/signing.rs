@@ -0,0 +1,19 @@
+use crate::error::PoeError;
+
+/// Outbound request details handed to a `RequestSigner`
+#[derive(Debug, Clone, Copy)]
+pub struct SigningRequest<'a> {
+    pub method: &'a str,
+    pub url: &'a str,
+    /// Serialized request body; empty for GET requests and streamed multipart uploads
+    pub body: &'a [u8],
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+/// Adds signature headers (e.g. HMAC) required by gateways in front of Poe.
+///
+/// Invoked for chat, file upload and model list calls right before sending.
+pub trait RequestSigner: Send + Sync {
+    fn sign(&self, request: &SigningRequest<'_>) -> Result<Vec<(String, String)>, PoeError>;
+}
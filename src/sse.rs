@@ -0,0 +1,448 @@
+use crate::error::PoeError;
+use crate::types::{ChatEventType, ChatResponse, ChatResponseData, ChatToolCall};
+#[cfg(feature = "http-server")]
+use bytes::Bytes;
+#[cfg(feature = "http-server")]
+use futures_util::{Stream, StreamExt};
+use serde_json::{Value, json};
+#[cfg(feature = "http-server")]
+use std::convert::Infallible;
+use std::time::Duration;
+
+/// `Content-Type` of a Poe SSE response
+pub const SSE_CONTENT_TYPE: &str = "text/event-stream";
+
+impl ChatEventType {
+    /// Event name used on the Poe SSE wire
+    pub fn as_sse_name(&self) -> &str {
+        match self {
+            ChatEventType::Text => "text",
+            ChatEventType::ReplaceResponse => "replace_response",
+            ChatEventType::Json => "json",
+            ChatEventType::File => "file",
+            ChatEventType::Done => "done",
+            ChatEventType::Error => "error",
+            ChatEventType::Ping => "ping",
+            ChatEventType::Meta => "meta",
+            ChatEventType::SuggestedReply => "suggested_reply",
+            ChatEventType::Usage => "usage",
+            ChatEventType::Unknown(name) => name,
+        }
+    }
+}
+
+impl ChatResponse {
+    /// Serialize the event as a Poe SSE frame (`event: ...\ndata: ...\n\n`)
+    pub fn to_sse_frame(&self) -> String {
+        // 一般 json 事件以原始 JSON 文字保存，原樣寫回，每行各自一個 data 欄位
+        if let (ChatEventType::Json, Some(ChatResponseData::Text { text })) =
+            (&self.event, &self.data)
+        {
+            let mut frame = format!("event: {}\n", self.event.as_sse_name());
+            for line in text.lines() {
+                frame.push_str(&format!("data: {}\n", line));
+            }
+            frame.push('\n');
+            return frame;
+        }
+
+        let data = match &self.data {
+            Some(ChatResponseData::Text { text })
+            | Some(ChatResponseData::SuggestedReply { text }) => json!({ "text": text }),
+            Some(ChatResponseData::Error { text, allow_retry }) => {
+                json!({ "text": text, "allow_retry": allow_retry })
+            }
+            Some(ChatResponseData::ToolCalls(tool_calls)) => tool_calls_chunk(tool_calls),
+            Some(ChatResponseData::File(file)) => json!(file),
+            Some(ChatResponseData::Done(info)) => json!(info),
+            Some(ChatResponseData::Meta(meta)) => json!(meta),
+            Some(ChatResponseData::Usage(usage)) => json!(usage),
+            Some(ChatResponseData::Raw(raw)) => raw.clone(),
+            Some(ChatResponseData::Empty) | Some(ChatResponseData::KeepAlive) | None => {
+                json!({})
+            }
+        };
+
+        format!("event: {}\ndata: {}\n\n", self.event.as_sse_name(), data)
+    }
+}
+
+// Tool calls are re-emitted as a single OpenAI-style completion chunk
+fn tool_calls_chunk(tool_calls: &[ChatToolCall]) -> Value {
+    let tool_calls: Vec<Value> = tool_calls
+        .iter()
+        .enumerate()
+        .map(|(index, tool_call)| {
+            json!({
+                "index": index,
+                "id": tool_call.id,
+                "type": tool_call.r#type,
+                "function": {
+                    "name": tool_call.function.name,
+                    "arguments": tool_call.function.arguments,
+                },
+            })
+        })
+        .collect();
+
+    json!({
+        "choices": [{
+            "index": 0,
+            "delta": { "tool_calls": tool_calls },
+            "finish_reason": "tool_calls",
+        }]
+    })
+}
+
+/// Re-serializes a decoded response stream into Poe's SSE wire format.
+///
+/// Stream errors become `error` events, and exactly one `done` event closes
+/// the stream (see [`SseWriter::finish`]).
+#[derive(Debug, Default)]
+pub struct SseWriter {
+    done: bool,
+}
+
+impl SseWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Encode one stream item; nothing is emitted after `done`
+    pub fn write(&mut self, item: &Result<ChatResponse, PoeError>) -> Vec<u8> {
+        if self.done {
+            return Vec::new();
+        }
+
+        match item {
+            Ok(response) => {
+                self.done = response.event == ChatEventType::Done;
+                response.to_sse_frame().into_bytes()
+            }
+            Err(error) => {
+                let mut frame = ChatResponse {
+                    event: ChatEventType::Error,
+                    data: Some(ChatResponseData::Error {
+                        text: error.to_string(),
+                        allow_retry: false,
+                    }),
+                }
+                .to_sse_frame()
+                .into_bytes();
+                frame.extend(self.finish());
+                frame
+            }
+        }
+    }
+
+    /// Keep-alive comment frame
+    pub fn ping(&self) -> Vec<u8> {
+        b": ping\n\n".to_vec()
+    }
+
+    /// Emit the closing `done` event if it has not been written yet
+    pub fn finish(&mut self) -> Vec<u8> {
+        if self.done {
+            return Vec::new();
+        }
+
+        self.done = true;
+        ChatResponse {
+            event: ChatEventType::Done,
+            data: None,
+        }
+        .to_sse_frame()
+        .into_bytes()
+    }
+
+    /// Check whether the closing `done` event has been written
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+/// Bridge a response stream into an SSE body for axum (`Body::from_stream`),
+/// actix-web (`HttpResponse::streaming`) or hyper.
+///
+/// Errors are sent as `error` frames, a `done` frame always closes the body,
+/// and `: ping` comments are emitted whenever the upstream is idle for
+/// `keep_alive`.
+#[cfg(feature = "http-server")]
+pub fn sse_body<S>(
+    stream: S,
+    keep_alive: Duration,
+) -> impl Stream<Item = Result<Bytes, Infallible>> + Send
+where
+    S: Stream<Item = Result<ChatResponse, PoeError>> + Send + Unpin,
+{
+    let mut keep_alive =
+        tokio::time::interval_at(tokio::time::Instant::now() + keep_alive, keep_alive);
+    keep_alive.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    futures_util::stream::unfold(
+        (stream, SseWriter::new(), keep_alive),
+        |(mut stream, mut writer, mut keep_alive)| async move {
+            loop {
+                if writer.is_done() {
+                    return None;
+                }
+
+                let frame = tokio::select! {
+                    item = stream.next() => {
+                        keep_alive.reset();
+                        match item {
+                            Some(item) => writer.write(&item),
+                            None => writer.finish(),
+                        }
+                    }
+                    _ = keep_alive.tick() => writer.ping(),
+                };
+
+                if !frame.is_empty() {
+                    return Some((Ok(Bytes::from(frame)), (stream, writer, keep_alive)));
+                }
+            }
+        },
+    )
+}
+
+/// Field values of one dispatched server-sent event
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SseEvent {
+    /// `event:` field; `None` stands for the default `message` type
+    pub event: Option<String>,
+    /// `data:` lines joined with `\n`
+    pub data: String,
+    /// Last event ID set by an `id:` field, which persists across events
+    pub id: Option<String>,
+}
+
+/// Item produced by an [`SseParser`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SseFrame {
+    Event(SseEvent),
+    /// Comment line (`: ping`), without the leading colon and space
+    Comment(String),
+}
+
+/// Incremental `text/event-stream` parser following the HTML SSE spec:
+/// `\r\n`, `\n` and `\r` line endings (also split across chunks), multi-line
+/// `data:` fields, `id:`/`retry:` fields and comments.
+///
+/// Feed chunks with [`SseParser::push_bytes`] (or already decoded text with
+/// [`SseParser::push`]) and pull frames with
+/// [`SseParser::next_frame`]; an event is dispatched by the blank line that
+/// ends it, and events without data are dropped as the spec requires.
+#[derive(Debug)]
+pub struct SseParser {
+    // 尚未組成完整 UTF-8 字元的位元組（多位元組字元被網路塊切開時）
+    pending_bytes: Vec<u8>,
+    buffer: String,
+    // 上一塊以 \r 結尾時，下一塊開頭的 \n 屬於同一個換行
+    skip_line_feed: bool,
+    started: bool,
+    event: Option<String>,
+    data: String,
+    last_event_id: Option<String>,
+    retry: Option<Duration>,
+    max_line_length: usize,
+}
+
+impl Default for SseParser {
+    fn default() -> Self {
+        Self {
+            pending_bytes: Vec::new(),
+            buffer: String::new(),
+            skip_line_feed: false,
+            started: false,
+            event: None,
+            data: String::new(),
+            last_event_id: None,
+            retry: None,
+            max_line_length: usize::MAX,
+        }
+    }
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fail with `PoeError::EventTooLarge` once a single line, or the data
+    /// accumulated for one event, exceeds `bytes`
+    pub fn with_max_line_length(mut self, bytes: usize) -> Self {
+        self.max_line_length = bytes;
+        self
+    }
+
+    /// Append raw bytes of the response body, decoding only complete UTF-8
+    /// sequences so multi-byte characters split across chunks stay intact.
+    ///
+    /// Invalid bytes are replaced with U+FFFD.
+    pub fn push_bytes(&mut self, chunk: &[u8]) {
+        self.pending_bytes.extend_from_slice(chunk);
+
+        let mut decoded = String::new();
+        let mut input = self.pending_bytes.as_slice();
+        while !input.is_empty() {
+            match std::str::from_utf8(input) {
+                Ok(text) => {
+                    decoded.push_str(text);
+                    input = &[];
+                }
+                Err(error) => {
+                    let (valid, rest) = input.split_at(error.valid_up_to());
+                    decoded.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                    match error.error_len() {
+                        Some(invalid) => {
+                            decoded.push(char::REPLACEMENT_CHARACTER);
+                            input = &rest[invalid..];
+                        }
+                        // 結尾是不完整的字元，等下一塊補齊
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        let consumed = self.pending_bytes.len() - input.len();
+        self.pending_bytes.drain(..consumed);
+        self.push(&decoded);
+    }
+
+    /// Append already decoded text of the response body
+    pub fn push(&mut self, chunk: &str) {
+        let mut chunk = chunk;
+        if !self.started && !chunk.is_empty() {
+            self.started = true;
+            chunk = chunk.strip_prefix('\u{feff}').unwrap_or(chunk);
+        }
+        if self.skip_line_feed && !chunk.is_empty() {
+            self.skip_line_feed = false;
+            chunk = chunk.strip_prefix('\n').unwrap_or(chunk);
+        }
+        self.buffer.push_str(chunk);
+    }
+
+    /// Get the next complete frame, or `None` until more data is pushed
+    pub fn next_frame(&mut self) -> Result<Option<SseFrame>, PoeError> {
+        loop {
+            let Some(line_end) = self.buffer.find(['\r', '\n']) else {
+                return match self.buffer.len() {
+                    size if size > self.max_line_length => Err(self.overflow(size)),
+                    _ => Ok(None),
+                };
+            };
+            if line_end > self.max_line_length {
+                return Err(self.overflow(line_end));
+            }
+
+            let next_start = match &self.buffer[line_end..] {
+                rest if rest.starts_with("\r\n") => line_end + 2,
+                "\r" => {
+                    self.skip_line_feed = true;
+                    line_end + 1
+                }
+                _ => line_end + 1,
+            };
+            let line = self.buffer[..line_end].to_string();
+            self.buffer.drain(..next_start);
+            if let Some(frame) = self.process_line(&line) {
+                return Ok(Some(frame));
+            }
+            // 每行都未超限時，多行 data 累積起來仍可能無限增長
+            if self.data.len() > self.max_line_length {
+                return Err(self.overflow(self.data.len()));
+            }
+        }
+    }
+
+    /// Treat the end of the body as a final line break and blank line,
+    /// dispatching an event the server did not terminate
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        // 串流在字元中途結束
+        if !self.pending_bytes.is_empty() {
+            self.pending_bytes.clear();
+            self.buffer.push(char::REPLACEMENT_CHARACTER);
+        }
+        if !self.buffer.is_empty() {
+            let line = std::mem::take(&mut self.buffer);
+            if let Some(SseFrame::Event(event)) = self.process_line(&line) {
+                return Some(event);
+            }
+        }
+        self.dispatch()
+    }
+
+    /// Get the last event ID, to resume with a `Last-Event-ID` header
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    /// Get the reconnection time requested by the last `retry:` field
+    pub fn retry(&self) -> Option<Duration> {
+        self.retry
+    }
+
+    /// Bytes held for incomplete lines and events
+    pub fn buffered_len(&self) -> usize {
+        self.pending_bytes.len() + self.buffer.len() + self.data.len()
+    }
+
+    fn overflow(&mut self, size: usize) -> PoeError {
+        self.buffer.clear();
+        self.data.clear();
+        self.event = None;
+        PoeError::EventTooLarge {
+            size,
+            limit: self.max_line_length,
+        }
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseFrame> {
+        if line.is_empty() {
+            return self.dispatch().map(SseFrame::Event);
+        }
+        if let Some(comment) = line.strip_prefix(':') {
+            let comment = comment.strip_prefix(' ').unwrap_or(comment);
+            return Some(SseFrame::Comment(comment.to_string()));
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = Some(value.to_string()).filter(|event| !event.is_empty()),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "id" if !value.contains('\0') => {
+                self.last_event_id = Some(value.to_string()).filter(|id| !id.is_empty())
+            }
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                if let Ok(millis) = value.parse() {
+                    self.retry = Some(Duration::from_millis(millis));
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        if self.data.is_empty() {
+            return None;
+        }
+        let mut data = std::mem::take(&mut self.data);
+        data.pop();
+        Some(SseEvent {
+            event,
+            data,
+            id: self.last_event_id.clone(),
+        })
+    }
+}
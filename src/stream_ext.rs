@@ -0,0 +1,88 @@
+use crate::aggregate::{CompleteResponse, ResponseAggregator};
+use crate::error::{PoeError, PoeErrorWithPartial};
+use crate::types::{ChatEventType, ChatResponse, ChatResponseData};
+use crate::util::TextSanitizer;
+use futures_util::Stream;
+use futures_util::StreamExt;
+use futures_util::stream::Inspect;
+use std::future::Future;
+
+/// Combinators for streams returned by `PoeClient::stream_request`
+pub trait ChatStreamExt: Stream<Item = Result<ChatResponse, PoeError>> + Sized {
+    /// Invoke `f` for every event (including errors) without consuming it,
+    /// e.g. for debug logging or metrics
+    fn tap_events<F>(self, f: F) -> Inspect<Self, F>
+    where
+        F: FnMut(&Result<ChatResponse, PoeError>),
+    {
+        self.inspect(f)
+    }
+
+    /// Strip ANSI escape sequences and control characters from `text` and
+    /// `replace_response` events (see [`TextSanitizer`])
+    fn sanitize_text(self) -> impl Stream<Item = Result<ChatResponse, PoeError>> {
+        let mut sanitizer = TextSanitizer::new();
+        self.map(move |mut item| {
+            if let Ok(ChatResponse {
+                event,
+                data: Some(ChatResponseData::Text { text }),
+            }) = &mut item
+            {
+                if *event == ChatEventType::ReplaceResponse {
+                    sanitizer.reset();
+                }
+                *text = sanitizer.push(text);
+            }
+            item
+        })
+    }
+
+    /// Consume the stream up to its `done` event and aggregate it into a
+    /// [`CompleteResponse`].
+    ///
+    /// On a transport failure, `error` event or truncated stream, the error
+    /// carries everything received before it.
+    fn collect_response(
+        self,
+    ) -> impl Future<Output = Result<CompleteResponse, PoeErrorWithPartial>> {
+        async move {
+            let mut stream = std::pin::pin!(self);
+            let mut aggregator = ResponseAggregator::new();
+            while let Some(item) = stream.next().await {
+                if let Err(error) = item.and_then(|event| aggregator.push(event)) {
+                    return Err(aggregator.into_partial(error));
+                }
+                if aggregator.is_done() {
+                    break;
+                }
+            }
+            Ok(aggregator.into_response())
+        }
+    }
+}
+
+impl<S> ChatStreamExt for S where S: Stream<Item = Result<ChatResponse, PoeError>> + Sized {}
+
+/// How `PoeClient::pipe_text_to` renders a `replace_response` event
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplaceStrategy {
+    /// Return to the start of the line, clear it (`\r\x1b[2K`) and write the
+    /// new text; suited to single-line terminal output
+    #[default]
+    CarriageReturn,
+    /// Write the new text on a fresh line; suited to files and sockets
+    NewLine,
+    /// Drop replacements and keep the text written so far
+    Ignore,
+}
+
+impl ReplaceStrategy {
+    /// Prefix written before the replacement text
+    pub(crate) fn prefix(&self) -> Option<&'static str> {
+        match self {
+            ReplaceStrategy::CarriageReturn => Some("\r\x1b[2K"),
+            ReplaceStrategy::NewLine => Some("\n"),
+            ReplaceStrategy::Ignore => None,
+        }
+    }
+}
@@ -1,8 +1,19 @@
 use crate::types::{
-    ChatEventType, ChatMessage, ChatRequest, ChatResponseData, ChatTool, ChatToolCall,
-    FunctionDefinition, FunctionParameters,
+    ChatEventType, ChatMessage, ChatRequest, ChatResponse, ChatResponseData, ChatTool, ChatToolCall,
+    ChatToolResult, FileData, FunctionCall, FunctionDefinition, FunctionParameters, GraphqlError,
+    GraphqlHashSource, LogitBias, ModelFilter, ModelListPolicy, ParamType, PoeWebSession,
+    RequestPriority, TOOL_TYPE_FUNCTION, Tokenizer, ToolCallCompletion, ToolChoice,
+};
+use crate::{
+    AbortHandle, Attachment, BalanceStrategy, BotCapabilities, BotPool, CachedPoeClient,
+    ChatStreamExt, CircuitBreaker, CircuitState, Conversation, ExperimentRouter, ExperimentVariant,
+    ExportFormat, FallbackChain, FileUploadRequest, HttpRequest, HttpResponse, HttpTap,
+    HttpTransport, LoggingConfig, LoggingHelper, MockPoeClient, PoeApi,
+    PoeClient, PoeError, RegenerateOptions, ReplaceStrategy, RequestSigner, ReqwestTransport,
+    ResponseAggregator, RetryBudget, RetryPoeClient, RetryPolicy, SigningRequest, SseEvent,
+    SseFrame, SseParser, SseWriter, StreamStats, TextChunker, ThrottleStats, ThrottledPoeClient,
+    get_model_list,
 };
-use crate::{Attachment, FileUploadRequest, PoeClient, get_model_list};
 use dotenvy::dotenv;
 use futures_util::StreamExt;
 use serde_json::json;
@@ -36,6 +47,195 @@ fn get_access_key() -> String {
     }
 }
 
+/// Minimal local HTTP server used by offline tests
+struct MockServer {
+    url: String,
+    requests: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+/// Canned HTTP response served by `MockServer`
+struct MockResponse {
+    status: u16,
+    content_type: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl MockResponse {
+    fn json(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            headers: Vec::new(),
+            body: body.to_string(),
+        }
+    }
+
+    fn sse(body: &str) -> Self {
+        Self {
+            status: 200,
+            content_type: "text/event-stream",
+            headers: Vec::new(),
+            body: body.to_string(),
+        }
+    }
+
+    fn with_header(mut self, name: &'static str, value: &str) -> Self {
+        self.headers.push((name, value.to_string()));
+        self
+    }
+}
+
+/// Extract the JSON body of a captured raw HTTP request
+fn captured_json(raw: &str) -> serde_json::Value {
+    let body = raw.split("\r\n\r\n").nth(1).unwrap_or_default();
+    serde_json::from_str(body).expect("Captured request body should be JSON")
+}
+
+/// Start a mock server answering each connection with the next canned response (the last one repeats)
+async fn start_mock_server(responses: Vec<MockResponse>) -> MockServer {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let captured = requests.clone();
+
+    tokio::spawn(async move {
+        let mut index = 0;
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let mut raw = Vec::new();
+            let mut buf = [0u8; 8192];
+            loop {
+                let n = socket.read(&mut buf).await.unwrap_or(0);
+                if n == 0 {
+                    break;
+                }
+                raw.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&raw);
+                if let Some(header_end) = text.find("\r\n\r\n") {
+                    let content_length = text[..header_end]
+                        .lines()
+                        .find_map(|line| {
+                            let lower = line.to_lowercase();
+                            lower
+                                .strip_prefix("content-length:")
+                                .map(|v| v.trim().parse::<usize>().unwrap_or(0))
+                        })
+                        .unwrap_or(0);
+                    if raw.len() >= header_end + 4 + content_length {
+                        break;
+                    }
+                }
+            }
+            captured
+                .lock()
+                .unwrap()
+                .push(String::from_utf8_lossy(&raw).to_string());
+
+            let response = &responses[index.min(responses.len() - 1)];
+            index += 1;
+            let extra_headers: String = response
+                .headers
+                .iter()
+                .map(|(name, value)| format!("{}: {}\r\n", name, value))
+                .collect();
+            let reply = format!(
+                "HTTP/1.1 {} MOCK\r\ncontent-type: {}\r\ncontent-length: {}\r\n{}connection: close\r\n\r\n{}",
+                response.status,
+                response.content_type,
+                response.body.len(),
+                extra_headers,
+                response.body
+            );
+            let _ = socket.write_all(reply.as_bytes()).await;
+            let _ = socket.shutdown().await;
+        }
+    });
+
+    MockServer { url, requests }
+}
+
+/// Captures tracing events on the current thread while alive, so tests can
+/// assert on the logging contract (masked credentials, truncated bodies)
+#[cfg(feature = "trace")]
+struct TraceCapture {
+    events: std::sync::Arc<std::sync::Mutex<Vec<(String, String)>>>,
+    _guard: tracing::subscriber::DefaultGuard,
+}
+
+#[cfg(feature = "trace")]
+impl TraceCapture {
+    fn start() -> Self {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let subscriber = tracing_subscriber::registry().with(CaptureLayer(events.clone()));
+        Self {
+            events,
+            _guard: tracing::subscriber::set_default(subscriber),
+        }
+    }
+
+    /// Every captured event as `message key=value ...`
+    fn events(&self) -> Vec<String> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, event)| event.clone())
+            .collect()
+    }
+
+    /// Captured events logged under `target`
+    fn events_for(&self, target: &str) -> Vec<String> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(event_target, _)| event_target == target)
+            .map(|(_, event)| event.clone())
+            .collect()
+    }
+}
+
+#[cfg(feature = "trace")]
+struct CaptureLayer(std::sync::Arc<std::sync::Mutex<Vec<(String, String)>>>);
+
+#[cfg(feature = "trace")]
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for CaptureLayer {
+    fn on_event(&self, event: &tracing::Event<'_>, _: tracing_subscriber::layer::Context<'_, S>) {
+        struct Visitor(String);
+
+        impl tracing::field::Visit for Visitor {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                if field.name() == "message" {
+                    self.0.insert_str(0, &format!("{:?}", value));
+                } else {
+                    self.0.push_str(&format!(" {}={:?}", field.name(), value));
+                }
+            }
+        }
+
+        let mut visitor = Visitor(String::new());
+        event.record(&mut visitor);
+        self.0
+            .lock()
+            .unwrap()
+            .push((event.metadata().target().to_string(), visitor.0));
+    }
+}
+
+/// Create a client pointed at a mock server
+fn mock_client(server: &MockServer) -> PoeClient {
+    PoeClient::new(
+        "TestBot",
+        "test_key",
+        &server.url,
+        &format!("{}/file_upload", server.url),
+    )
+}
+
 #[test_log::test(tokio::test)]
 async fn test_stream_request() {
     setup();
@@ -49,23 +249,13 @@ async fn test_stream_request() {
     );
 
     let request = ChatRequest {
-        version: "1.1".to_string(),
-        r#type: "query".to_string(),
         query: vec![ChatMessage {
             role: "user".to_string(),
             content: "Hello".to_string(),
             content_type: "text/markdown".to_string(),
             attachments: None,
         }],
-        temperature: None,
-        user_id: String::new(),
-        conversation_id: String::new(),
-        message_id: String::new(),
-        tools: None,
-        tool_calls: None,
-        tool_results: None,
-        logit_bias: None,
-        stop_sequences: None,
+        ..Default::default()
     };
 
     debug!("Sending stream request");
@@ -148,23 +338,13 @@ async fn test_stream_content_verification() {
     );
 
     let request = ChatRequest {
-        version: "1.1".to_string(),
-        r#type: "query".to_string(),
         query: vec![ChatMessage {
             role: "user".to_string(),
             content: "Say 'hello' only".to_string(),
             content_type: "text/markdown".to_string(),
             attachments: None,
         }],
-        temperature: None,
-        user_id: String::new(),
-        conversation_id: String::new(),
-        message_id: String::new(),
-        tools: None,
-        tool_calls: None,
-        tool_results: None,
-        logit_bias: None,
-        stop_sequences: None,
+        ..Default::default()
     };
 
     debug!("Sending stream request to verify content");
@@ -234,18 +414,12 @@ async fn test_stream_tool_content_verification() {
 
     // Create request with tool definitions
     let request = ChatRequest {
-        version: "1.1".to_string(),
-        r#type: "query".to_string(),
         query: vec![ChatMessage {
             role: "user".to_string(),
             content: "What's the current weather in Taipei? Use the weather tool.".to_string(),
             content_type: "text/markdown".to_string(),
             attachments: None,
         }],
-        temperature: None,
-        user_id: String::new(),
-        conversation_id: String::new(),
-        message_id: String::new(),
         tools: Some(vec![ChatTool {
             r#type: "function".to_string(),
             function: FunctionDefinition {
@@ -268,10 +442,7 @@ async fn test_stream_tool_content_verification() {
                 }),
             },
         }]),
-        tool_calls: None,
-        tool_results: None,
-        logit_bias: None,
-        stop_sequences: None,
+        ..Default::default()
     };
 
     debug!("Sending stream request with tool definitions");
@@ -511,8 +682,6 @@ async fn test_file_upload() {
         .await
         .expect("File upload failed");
     let request = ChatRequest {
-        version: "1.1".to_string(),
-        r#type: "query".to_string(),
         query: vec![ChatMessage {
             role: "user".to_string(),
             content: "This is a message with an attached file, please analyze the file content".to_string(),
@@ -520,17 +689,10 @@ async fn test_file_upload() {
             attachments: Some(vec![Attachment {
                 url: file_upload_response.attachment_url,
                 content_type: file_upload_response.mime_type,
+                ..Default::default()
             }]),
         }],
-        temperature: None,
-        user_id: String::new(),
-        conversation_id: String::new(),
-        message_id: String::new(),
-        tools: None,
-        tool_calls: None,
-        tool_results: None,
-        logit_bias: None,
-        stop_sequences: None,
+        ..Default::default()
     };
     debug!("Sending message request with attachments");
     let result = client.stream_request(request).await;
@@ -548,10 +710,10 @@ async fn test_file_upload() {
                     received_response = true;
                     debug!("Received message event with attachments: {:?}", event);
                     // Check if response mentions attachments or files
-                    if let Some(ChatResponseData::Text { text }) = &event.data {
-                        if text.contains("file") || text.contains("content") {
-                            debug!("Response mentions file or content, confirming attachment was processed");
-                        }
+                    if let Some(ChatResponseData::Text { text }) = &event.data
+                        && (text.contains("file") || text.contains("content"))
+                    {
+                        debug!("Response mentions file or content, confirming attachment was processed");
                     }
                 }
                 Err(e) => {
@@ -674,7 +836,7 @@ async fn test_get_v1_model_list() {
 }
 
 // XML parsing test cases
-#[cfg(feature = "xml")]
+#[cfg(feature = "xml-parse")]
 #[test_log::test(tokio::test)]
 async fn test_xml_tool_call_detection() {
     setup();
@@ -691,7 +853,7 @@ async fn test_xml_tool_call_detection() {
     debug!("XML tool call detection test completed");
 }
 
-#[cfg(feature = "xml")]
+#[cfg(feature = "xml-parse")]
 #[test_log::test(tokio::test)]
 async fn test_xml_tool_call_extraction() {
     setup();
@@ -721,7 +883,7 @@ async fn test_xml_tool_call_extraction() {
     debug!("XML tool call extraction test completed");
 }
 
-#[cfg(feature = "xml")]
+#[cfg(feature = "xml-parse")]
 #[test_log::test(tokio::test)]
 async fn test_multiple_xml_tool_calls() {
     setup();
@@ -765,7 +927,7 @@ async fn test_multiple_xml_tool_calls() {
     debug!("Multiple XML tool calls test completed");
 }
 
-#[cfg(feature = "xml")]
+#[cfg(feature = "xml-parse")]
 #[test_log::test(tokio::test)]
 async fn test_xml_tool_call_with_complex_parameters() {
     setup();
@@ -799,7 +961,7 @@ async fn test_xml_tool_call_with_complex_parameters() {
     debug!("XML tool call with complex parameters test completed");
 }
 
-#[cfg(feature = "xml")]
+#[cfg(feature = "xml-parse")]
 #[test_log::test(tokio::test)]
 async fn test_no_xml_tool_calls() {
     setup();
@@ -822,7 +984,7 @@ async fn test_no_xml_tool_calls() {
     debug!("No XML tool calls test completed");
 }
 
-#[cfg(feature = "xml")]
+#[cfg(feature = "xml-parse")]
 #[test_log::test(tokio::test)]
 async fn test_xml_tool_call_with_empty_parameters() {
     setup();
@@ -853,7 +1015,7 @@ async fn test_xml_tool_call_with_empty_parameters() {
     debug!("XML tool call with empty parameters test completed");
 }
 
-#[cfg(feature = "xml")]
+#[cfg(feature = "xml-parse")]
 #[test_log::test(tokio::test)]
 async fn test_xml_tool_call_parsing_error_handling() {
     setup();
@@ -875,7 +1037,7 @@ async fn test_xml_tool_call_parsing_error_handling() {
     debug!("XML tool call parsing error handling test completed");
 }
 
-#[cfg(feature = "xml")]
+#[cfg(feature = "xml-parse")]
 #[test_log::test(tokio::test)]
 async fn test_xml_entity_decoding() {
     setup();
@@ -899,10 +1061,37 @@ async fn test_xml_entity_decoding() {
         "XML entities should be correctly decoded"
     );
 
+    use crate::xml::{XmlEntityMode, XmlToolCallParser};
+    let decode = |text: &str, mode| XmlToolCallParser::decode_xml_entities(text, mode);
+    assert_eq!(
+        decode("&amp;lt;b&amp;gt; &amp;quot;", XmlEntityMode::Lenient).unwrap(),
+        "&lt;b&gt; &quot;",
+        "Entities should be decoded exactly once"
+    );
+    assert_eq!(
+        decode("it&#x27;s &#39;ok&#39; &#X263A;", XmlEntityMode::Strict).unwrap(),
+        "it's 'ok' \u{263A}"
+    );
+    assert_eq!(
+        decode("a & b &nbsp; &#xD800; &;", XmlEntityMode::Lenient).unwrap(),
+        "a & b &nbsp; &#xD800; &;",
+        "Lenient mode should keep unknown entities verbatim"
+    );
+    for invalid in ["a & b", "&nbsp;", "&#xD800;", "&#;"] {
+        assert!(
+            matches!(
+                decode(invalid, XmlEntityMode::Strict),
+                Err(PoeError::ToolCallParseFailed(_))
+            ),
+            "Strict mode should reject {:?}",
+            invalid
+        );
+    }
+
     debug!("XML entity decoding test completed");
 }
 
-#[cfg(feature = "xml")]
+#[cfg(feature = "xml-parse")]
 #[test_log::test(tokio::test)]
 async fn test_dynamic_xml_tool_call_detection() {
     setup();
@@ -976,7 +1165,7 @@ async fn test_dynamic_xml_tool_call_detection() {
     debug!("Dynamic XML tool call detection test completed");
 }
 
-#[cfg(feature = "xml")]
+#[cfg(feature = "xml-parse")]
 #[test_log::test(tokio::test)]
 async fn test_dynamic_xml_tool_call_extraction() {
     setup();
@@ -1053,7 +1242,7 @@ async fn test_dynamic_xml_tool_call_extraction() {
     debug!("Dynamic XML tool call extraction test completed");
 }
 
-#[cfg(feature = "xml")]
+#[cfg(feature = "xml-parse")]
 #[test_log::test(tokio::test)]
 async fn test_potential_tool_name_detection() {
     setup();
@@ -1139,7 +1328,7 @@ async fn test_potential_tool_name_detection() {
     debug!("Potential tool name detection test completed");
 }
 
-#[cfg(feature = "xml")]
+#[cfg(feature = "xml-parse")]
 #[test_log::test(tokio::test)]
 async fn test_mixed_tool_call_formats() {
     setup();
@@ -1190,7 +1379,7 @@ Processing..."#
     let tool_calls = message.extract_xml_tool_calls_with_tools(&tools);
 
     // Should be able to parse both formats of tool calls
-    assert!(tool_calls.len() >= 1, "Should extract at least one tool call");
+    assert!(!tool_calls.is_empty(), "Should extract at least one tool call");
 
     // Check if contains standard tool
     let has_standard_tool = tool_calls
@@ -1201,7 +1390,7 @@ Processing..."#
     debug!("Mixed tool call formats test completed");
 }
 
-#[cfg(feature = "xml")]
+#[cfg(feature = "xml-parse")]
 #[test_log::test(tokio::test)]
 async fn test_remove_xml_tool_calls_with_tool_cells() {
     setup();
@@ -1245,7 +1434,7 @@ Please wait a moment, I'm querying the weather for Taipei."#;
     debug!("XML tool call removal test completed");
 }
 
-#[cfg(feature = "xml")]
+#[cfg(feature = "xml-parse")]
 #[test_log::test(tokio::test)]
 async fn test_remove_xml_tool_calls_without_tool_cells() {
     setup();
@@ -1267,3 +1456,5541 @@ I can provide general help and information."#;
 
     debug!("XML removal without tool calls test completed");
 }
+
+#[test_log::test(tokio::test)]
+async fn test_text_chunker_split() {
+    setup();
+    debug!("Starting text chunker split test");
+
+    let chunker = TextChunker::new(4);
+    let text = "ab你好👨‍👩‍👧e\u{301}";
+    let chunks = chunker.split(text);
+
+    assert_eq!(chunks.concat(), text, "Chunks should reassemble to the original text");
+    assert_eq!(chunks[0], "ab", "CJK character must not be split across chunks");
+    assert!(
+        chunks.contains(&"👨‍👩‍👧"),
+        "Oversized grapheme should be emitted as its own chunk"
+    );
+    assert!(
+        chunks.contains(&"e\u{301}"),
+        "Combining sequence should stay in one chunk"
+    );
+
+    debug!("Text chunker split test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_text_chunker_streaming() {
+    setup();
+    debug!("Starting text chunker streaming test");
+
+    let mut chunker = TextChunker::new(8);
+    let mut output = Vec::new();
+    output.extend(chunker.push("hello e"));
+    output.extend(chunker.push("\u{301} world"));
+    output.extend(chunker.flush());
+
+    assert_eq!(output.concat(), "hello e\u{301} world", "Streamed chunks should reassemble");
+    assert!(
+        output.iter().all(|chunk| chunk.len() <= 8),
+        "Every chunk should respect max_bytes"
+    );
+    assert!(
+        output.iter().any(|chunk| chunk.contains("e\u{301}")),
+        "Grapheme split across deltas should be kept together"
+    );
+
+    debug!("Text chunker streaming test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_accept_language_configuration() {
+    setup();
+    debug!("Starting Accept-Language configuration test");
+
+    let client = PoeClient::new(
+        "Claude-3.7-Sonnet",
+        "test_key",
+        "https://api.poe.com",
+        "https://www.quora.com/poe_api/file_upload_3RD_PARTY_POST",
+    );
+    assert_eq!(
+        client.accept_language_header(None),
+        "zh-TW,zh;q=0.9,en-US;q=0.8,en;q=0.7",
+        "Default Accept-Language should be preserved"
+    );
+
+    let client = client.with_language_code("ja-JP");
+    assert_eq!(client.get_language_code(), Some("ja-JP"));
+    assert_eq!(
+        client.accept_language_header(client.get_language_code()),
+        "ja-JP,ja;q=0.9,en;q=0.8",
+        "Accept-Language should follow the client locale"
+    );
+    assert_eq!(client.accept_language_header(Some("en")), "en");
+
+    let client = client.with_accept_language("fr-FR");
+    assert_eq!(
+        client.accept_language_header(Some("ja-JP")),
+        "fr-FR",
+        "Explicit Accept-Language should override the locale"
+    );
+
+    debug!("Accept-Language configuration test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_web_session_cookie_and_masking() {
+    setup();
+    debug!("Starting web session cookie test");
+
+    let client = PoeClient::new(
+        "Claude-3.7-Sonnet",
+        "test_key",
+        "https://api.poe.com",
+        "https://www.quora.com/poe_api/file_upload_3RD_PARTY_POST",
+    );
+    assert_eq!(client.gql_cookie(None), None, "No cookie without locale or session");
+    assert_eq!(
+        client.gql_cookie(Some("en")).as_deref(),
+        Some("Poe-Language-Code=en; p-b=1"),
+        "Anonymous cookie should keep the placeholder p-b"
+    );
+
+    let session = PoeWebSession::new("secret_pb", "secret_lat");
+    assert!(
+        !format!("{:?}", session).contains("secret"),
+        "Debug output must not leak session tokens"
+    );
+
+    let client = client.with_web_session(session);
+    assert!(client.has_web_session());
+    assert_eq!(
+        client.gql_cookie(Some("en")).as_deref(),
+        Some("Poe-Language-Code=en; p-b=secret_pb; p-lat=secret_lat"),
+        "Session cookies should be sent to GraphQL"
+    );
+
+    debug!("Web session cookie test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_graphql_error_parsing() {
+    setup();
+    debug!("Starting GraphQL error parsing test");
+
+    let body = json!({
+        "data": null,
+        "errors": [
+            {
+                "message": "PersistedQueryNotFound",
+                "extensions": { "code": "PERSISTED_QUERY_NOT_FOUND" },
+                "path": ["exploreBotsConnection"]
+            },
+            { "message": "Rate limited" },
+            { "message": "Persisted query not found" },
+            { "message": "Invalid hash for argument \"cursor\"" }
+        ]
+    });
+
+    let errors = GraphqlError::from_response(&body);
+    assert_eq!(errors.len(), 4, "Should parse every GraphQL error");
+    assert_eq!(errors[0].code.as_deref(), Some("PERSISTED_QUERY_NOT_FOUND"));
+    assert_eq!(errors[0].path, Some(vec![json!("exploreBotsConnection")]));
+    assert!(errors[0].is_hash_expired(), "Persisted query error should be detected");
+    assert!(!errors[1].is_hash_expired());
+    assert!(errors[2].is_hash_expired());
+    assert!(!errors[3].is_hash_expired(), "Unrelated errors mentioning a hash are not expiry");
+
+    let error = PoeError::GraphqlError {
+        query_name: "ExploreBotsListPaginationQuery".to_string(),
+        errors,
+    };
+    assert!(error.is_graphql_hash_expired());
+    assert!(
+        error.to_string().contains("ExploreBotsListPaginationQuery"),
+        "Error message should include the query name"
+    );
+
+    debug!("GraphQL error parsing test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_get_models_policy() {
+    setup();
+    debug!("Starting model list policy test");
+
+    let server = start_mock_server(vec![MockResponse::json(
+        200,
+        r#"{"data":[{"id":"Claude-3.7-Sonnet","object":"model","created":0,"owned_by":"poe"}]}"#,
+    )])
+    .await;
+    let models = mock_client(&server)
+        .get_models()
+        .await
+        .expect("v1 model list should be used first by default");
+    assert_eq!(models.data[0].id, "Claude-3.7-Sonnet");
+    assert!(
+        server.requests.lock().unwrap()[0].starts_with("GET /v1/models"),
+        "Default policy should query v1/models"
+    );
+
+    let server = start_mock_server(vec![MockResponse::json(403, r#"{"error":"forbidden"}"#)]).await;
+    let result = mock_client(&server)
+        .with_model_list_policy(ModelListPolicy::V1Only)
+        .get_models()
+        .await;
+    assert!(result.is_err(), "V1Only should not fall back");
+
+    debug!("Model list policy test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_strict_role_validation() {
+    setup();
+    debug!("Starting strict role validation test");
+
+    let message = |role: &str| ChatMessage {
+        role: role.to_string(),
+        content: "Hello".to_string(),
+        attachments: None,
+        content_type: "text/markdown".to_string(),
+    };
+    let request_with = |roles: &[&str]| ChatRequest {
+        query: roles.iter().map(|role| message(role)).collect(),
+        ..Default::default()
+    };
+
+    assert!(request_with(&["system", "user", "bot", "user"]).validate_roles().is_ok());
+    assert!(request_with(&["user", "user"]).validate_roles().is_err(), "Consecutive user turns");
+    assert!(request_with(&["bot", "user"]).validate_roles().is_err(), "Must start with user");
+    assert!(request_with(&["user", "bot"]).validate_roles().is_err(), "Must end with user");
+    assert!(request_with(&["user", "tool"]).validate_roles().is_err(), "Unknown role");
+
+    let client = PoeClient::new("TestBot", "test_key", "http://127.0.0.1:9", "http://127.0.0.1:9")
+        .with_strict_roles(true);
+    let result = client.stream_request(request_with(&["user", "user"])).await;
+    assert!(
+        matches!(result, Err(PoeError::InvalidRoleSequence(_))),
+        "Strict client should reject before sending"
+    );
+
+    debug!("Strict role validation test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_conversation_fork() {
+    setup();
+    debug!("Starting conversation fork test");
+
+    let mut conversation = Conversation::new().with_user_id("user_1");
+    conversation.push_user_message("Hello");
+    conversation.push_bot_message("Hi there");
+    conversation.push_user_message("Tell me a joke");
+    conversation.push_bot_message("Why did the crab never share?");
+
+    let fork = conversation.fork(3);
+    assert_ne!(fork.id(), conversation.id(), "Fork should get a new conversation ID");
+    assert_eq!(fork.user_id(), "user_1");
+    assert_eq!(fork.messages().len(), 3, "Fork should keep history up to the chosen turn");
+    assert_eq!(conversation.messages().len(), 4, "Original conversation should be untouched");
+
+    let request = fork.to_request();
+    assert_eq!(request.conversation_id, fork.id());
+    assert_eq!(request.query.last().unwrap().content, "Tell me a joke");
+    assert_ne!(
+        request.message_id,
+        fork.to_request().message_id,
+        "Each request should get a fresh message ID"
+    );
+
+    let resumed = Conversation::new().with_id(conversation.id());
+    assert_eq!(
+        resumed.to_request().conversation_id,
+        conversation.id(),
+        "Resumed conversation should reuse the stored ID"
+    );
+
+    debug!("Conversation fork test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_conversation_regenerate_last() {
+    setup();
+    debug!("Starting conversation regenerate test");
+
+    let server = start_mock_server(vec![MockResponse::sse(
+        "event: text\ndata: {\"text\": \"Second answer\"}\n\nevent: done\ndata: {}\n\n",
+    )])
+    .await;
+    let client = mock_client(&server);
+
+    let mut conversation = Conversation::new();
+    conversation.push_user_message("Hello");
+    conversation.push_bot_message("First answer");
+
+    let mut stream = conversation
+        .regenerate_last(
+            &client,
+            RegenerateOptions {
+                temperature: Some(0.2),
+                seed: Some(42),
+            },
+        )
+        .await
+        .expect("Regenerate should send a request");
+
+    let mut text = String::new();
+    while let Some(Ok(event)) = stream.next().await {
+        if let Some(ChatResponseData::Text { text: delta }) = event.data {
+            text.push_str(&delta);
+        }
+    }
+    assert_eq!(text, "Second answer");
+    assert_eq!(conversation.messages().len(), 1, "Last bot turn should be removed");
+
+    let body = captured_json(&server.requests.lock().unwrap()[0]);
+    assert_eq!(body["query"].as_array().unwrap().len(), 1);
+    assert!((body["temperature"].as_f64().unwrap() - 0.2).abs() < 1e-6);
+    assert_eq!(body["seed"], 42, "Seed override should be serialized");
+
+    debug!("Conversation regenerate test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_conversation_regenerate_last_failure_keeps_history() {
+    setup();
+    debug!("Starting conversation regenerate failure test");
+
+    let server = start_mock_server(vec![MockResponse::json(
+        400,
+        "{\"text\": \"Bad request\"}",
+    )])
+    .await;
+    let client = mock_client(&server);
+
+    let mut conversation = Conversation::new();
+    conversation.push_bot_message("Welcome");
+    conversation.push_bot_message("Anything else?");
+    assert!(
+        conversation
+            .regenerate_last(&client, RegenerateOptions::default())
+            .await
+            .is_err(),
+        "A history without a user turn cannot be regenerated"
+    );
+    assert_eq!(
+        conversation.messages().len(),
+        2,
+        "Rejected regenerate should not drop the last bot turn"
+    );
+    assert!(server.requests.lock().unwrap().is_empty());
+
+    let mut conversation = Conversation::new();
+    conversation.push_user_message("Hello");
+    conversation.push_bot_file(&FileData {
+        url: "https://example.com/a.png".to_string(),
+        content_type: "image/png".to_string(),
+        name: "a.png".to_string(),
+        inline_ref: String::new(),
+    });
+    conversation.push_bot_message("First answer");
+    assert!(
+        conversation
+            .regenerate_last(&client, RegenerateOptions::default())
+            .await
+            .is_err(),
+        "Server error should be returned"
+    );
+    assert_eq!(conversation.messages().len(), 2, "Failed request should keep the bot turn");
+    assert_eq!(conversation.attachments().len(), 1, "Failed request should keep attachments");
+
+    debug!("Conversation regenerate failure test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_conversation_edit_message() {
+    setup();
+    debug!("Starting conversation edit message test");
+
+    let server = start_mock_server(vec![MockResponse::sse("event: done\ndata: {}\n\n")]).await;
+    let client = mock_client(&server);
+
+    let mut conversation = Conversation::new();
+    conversation.push_user_message("What is 2 + 2?");
+    conversation.push_bot_message("4");
+    conversation.push_user_message("And times 3?");
+    conversation.push_bot_message("12");
+
+    assert!(
+        conversation.edit_message(&client, 1, "5").await.is_err(),
+        "Bot messages should not be editable"
+    );
+    assert!(
+        matches!(
+            conversation.edit_message(&client, 9, "?").await,
+            Err(PoeError::InvalidRequest(_))
+        ),
+        "Out-of-range index should be an invalid request"
+    );
+    assert_eq!(conversation.messages().len(), 4, "Rejected edits should keep every turn");
+    assert!(server.requests.lock().unwrap().is_empty());
+
+    let stream = conversation
+        .edit_message(&client, 0, "What is 3 + 3?")
+        .await
+        .expect("Editing a user message should re-send");
+    drop(stream);
+
+    assert_eq!(conversation.messages().len(), 1, "Later turns should be truncated");
+    assert_eq!(conversation.messages()[0].content, "What is 3 + 3?");
+
+    let body = captured_json(&server.requests.lock().unwrap()[0]);
+    assert_eq!(body["query"][0]["content"], "What is 3 + 3?");
+
+    debug!("Conversation edit message test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_seed_serialization() {
+    setup();
+    debug!("Starting seed serialization test");
+
+    let mut request = Conversation::new().with_seed(7).to_request();
+    assert_eq!(request.seed, Some(7), "Conversation seed should propagate to requests");
+    let value = serde_json::to_value(&request).unwrap();
+    assert_eq!(value["seed"], 7);
+
+    request.seed = None;
+    let value = serde_json::to_value(&request).unwrap();
+    assert!(value.get("seed").is_none(), "Unset seed should be omitted");
+
+    debug!("Seed serialization test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_logit_bias_modes() {
+    setup();
+    debug!("Starting logit bias test");
+
+    struct ByteTokenizer;
+    impl Tokenizer for ByteTokenizer {
+        fn encode(&self, text: &str) -> Vec<u32> {
+            text.bytes().map(u32::from).collect()
+        }
+    }
+
+    let bias = LogitBias::token_ids()
+        .ban_token(50256)
+        .boost_word(&ByteTokenizer, "hi", 5.0);
+    assert_eq!(bias.len(), 3);
+    let value = serde_json::to_value(&bias).unwrap();
+    assert_eq!(value, json!({"104": 5.0, "105": 5.0, "50256": -100.0}));
+
+    let parsed: LogitBias = serde_json::from_value(value).unwrap();
+    assert_eq!(parsed, bias, "Token ID maps should roundtrip");
+
+    let strings = LogitBias::strings().string("hello", -2.0);
+    let value = serde_json::to_value(&strings).unwrap();
+    assert_eq!(value, json!({"hello": -2.0}));
+    let parsed: LogitBias = serde_json::from_value(value).unwrap();
+    assert!(matches!(parsed, LogitBias::Strings(_)), "Word keys should stay in string mode");
+
+    // Mixing modes keeps every entry: token IDs join a string map as decimal keys
+    let mixed = LogitBias::strings()
+        .string("hello", 5.0)
+        .ban_token(42)
+        .boost_word(&ByteTokenizer, "a", 1.0);
+    assert_eq!(
+        serde_json::to_value(&mixed).unwrap(),
+        json!({"hello": 5.0, "42": -100.0, "97": 1.0})
+    );
+    let mixed = LogitBias::token_ids().ban_token(42).string("hello", 5.0);
+    assert_eq!(
+        serde_json::to_value(&mixed).unwrap(),
+        json!({"hello": 5.0, "42": -100.0})
+    );
+
+    debug!("Logit bias test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_chat_request_default() {
+    setup();
+    debug!("Starting ChatRequest default test");
+
+    let request = ChatRequest::default();
+    assert_eq!(request.version, "1.1");
+    assert_eq!(request.r#type, "query");
+    assert!(!request.conversation_id.is_empty(), "Conversation ID should be generated");
+    assert!(!request.message_id.is_empty(), "Message ID should be generated");
+    assert_ne!(request.message_id, ChatRequest::default().message_id);
+    assert!(request.tools.is_none() && request.temperature.is_none() && request.seed.is_none());
+
+    debug!("ChatRequest default test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_tap_events() {
+    setup();
+    debug!("Starting tap events test");
+
+    let server = start_mock_server(vec![MockResponse::sse(
+        "event: text\ndata: {\"text\": \"Hi\"}\n\nevent: done\ndata: {}\n\n",
+    )])
+    .await;
+    let client = mock_client(&server);
+    let request = ChatRequest {
+        query: vec![ChatMessage {
+            role: "user".to_string(),
+            content: "Hello".to_string(),
+            content_type: "text/markdown".to_string(),
+            attachments: None,
+        }],
+        ..Default::default()
+    };
+
+    let mut tapped = Vec::new();
+    let events: Vec<_> = client
+        .stream_request(request)
+        .await
+        .unwrap()
+        .tap_events(|event| tapped.push(event.as_ref().map(|e| e.event.clone()).ok()))
+        .collect()
+        .await;
+
+    assert_eq!(events.len(), 2, "Tapping should not consume events");
+    assert_eq!(
+        tapped,
+        vec![Some(ChatEventType::Text), Some(ChatEventType::Done)],
+        "Callback should see every event in order"
+    );
+
+    debug!("Tap events test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_tool_choice_serialization() {
+    setup();
+    debug!("Starting tool choice serialization test");
+
+    let cases = [
+        (ToolChoice::Auto, json!("auto")),
+        (ToolChoice::None, json!("none")),
+        (ToolChoice::Required, json!("required")),
+        (
+            ToolChoice::Function("get_weather".to_string()),
+            json!({"type": "function", "function": {"name": "get_weather"}}),
+        ),
+    ];
+    for (choice, expected) in cases {
+        let value = serde_json::to_value(&choice).unwrap();
+        assert_eq!(value, expected, "tool_choice should match OpenAI wire format");
+        let parsed: ToolChoice = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed, choice, "tool_choice should roundtrip");
+    }
+    assert!(serde_json::from_value::<ToolChoice>(json!("sometimes")).is_err());
+
+    let request = ChatRequest {
+        tool_choice: Some(ToolChoice::Required),
+        ..Default::default()
+    };
+    assert_eq!(serde_json::to_value(&request).unwrap()["tool_choice"], "required");
+
+    debug!("Tool choice serialization test completed");
+}
+
+#[cfg(feature = "xml-prompt")]
+#[test_log::test(tokio::test)]
+async fn test_tool_choice_xml_instruction() {
+    setup();
+    debug!("Starting tool choice XML instruction test");
+
+    let mut request = ChatRequest {
+        query: vec![ChatMessage {
+            role: "user".to_string(),
+            content: "Weather?".to_string(),
+            attachments: None,
+            content_type: "text/markdown".to_string(),
+        }],
+        tools: Some(vec![ChatTool {
+            r#type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "get_weather".to_string(),
+                description: None,
+                parameters: None,
+            },
+        }]),
+        tool_choice: Some(ToolChoice::Function("get_weather".to_string())),
+        ..Default::default()
+    };
+    request.append_tools_as_xml();
+
+    assert!(
+        request.query[0]
+            .content
+            .ends_with("You MUST call the \"get_weather\" tool in this response."),
+        "Forced tool should be reflected in the XML prompt"
+    );
+
+    debug!("Tool choice XML instruction test completed");
+}
+
+/// Replay a recorded SSE capture through the mock server and collect the decoded events
+async fn replay_fixture(capture: &str) -> Vec<ChatResponse> {
+    let server = start_mock_server(vec![MockResponse::sse(capture)]).await;
+    let client = mock_client(&server);
+    let request = ChatRequest {
+        query: vec![ChatMessage {
+            role: "user".to_string(),
+            content: "What's the weather in Taipei?".to_string(),
+            content_type: "text/markdown".to_string(),
+            attachments: None,
+        }],
+        ..Default::default()
+    };
+
+    client
+        .stream_request(request)
+        .await
+        .expect("Mock stream request should succeed")
+        .map(|event| event.expect("Fixture should decode without errors"))
+        .collect()
+        .await
+}
+
+/// Collect all tool calls emitted in a decoded event sequence
+fn collected_tool_calls(events: &[ChatResponse]) -> Vec<ChatToolCall> {
+    events
+        .iter()
+        .filter_map(|event| match &event.data {
+            Some(ChatResponseData::ToolCalls(calls)) => Some(calls.clone()),
+            _ => None,
+        })
+        .flatten()
+        .collect()
+}
+
+#[test_log::test(tokio::test)]
+async fn test_fixture_gpt4o_tool_calls() {
+    setup();
+    debug!("Starting GPT-4o tool call fixture test");
+
+    let events = replay_fixture(include_str!("../tests/fixtures/gpt4o_tool_calls.sse")).await;
+    let tool_calls = collected_tool_calls(&events);
+
+    assert_eq!(tool_calls.len(), 1, "Deltas should be merged into one tool call");
+    assert_eq!(tool_calls[0].id, "call_gpt4o_1");
+    assert_eq!(tool_calls[0].function.name, "get_weather");
+    assert_eq!(tool_calls[0].function.arguments, "{\"location\":\"Taipei\"}");
+    assert_eq!(
+        events.last().map(|event| &event.event),
+        Some(&ChatEventType::Done),
+        "Tool calls should be emitted before the done event"
+    );
+
+    debug!("GPT-4o tool call fixture test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_fixture_claude_out_of_order_tool_calls() {
+    setup();
+    debug!("Starting Claude parallel tool call fixture test");
+
+    let events =
+        replay_fixture(include_str!("../tests/fixtures/claude_parallel_tool_calls.sse")).await;
+    let tool_calls = collected_tool_calls(&events);
+
+    assert_eq!(tool_calls.len(), 2, "Both parallel tool calls should be emitted");
+    assert_eq!(tool_calls[0].function.name, "get_weather", "Calls should be ordered by index");
+    assert_eq!(tool_calls[0].function.arguments, "{\"location\":\"Taipei\"}");
+    assert_eq!(tool_calls[1].function.name, "get_time");
+    assert_eq!(tool_calls[1].function.arguments, "{\"timezone\":\"Asia/Taipei\"}");
+
+    debug!("Claude parallel tool call fixture test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_fixture_gemini_single_chunk_tool_call() {
+    setup();
+    debug!("Starting Gemini single chunk tool call fixture test");
+
+    let events =
+        replay_fixture(include_str!("../tests/fixtures/gemini_single_chunk_tool_call.sse")).await;
+    let tool_calls = collected_tool_calls(&events);
+
+    assert_eq!(tool_calls.len(), 1);
+    assert_eq!(tool_calls[0].id, "call_gemini_1");
+    assert_eq!(
+        tool_calls[0].function.arguments,
+        "{\"location\":\"Tokyo\",\"unit\":\"celsius\"}"
+    );
+
+    debug!("Gemini single chunk tool call fixture test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_fixture_replace_response() {
+    setup();
+    debug!("Starting replace_response fixture test");
+
+    let events = replay_fixture(include_str!("../tests/fixtures/replace_response.sse")).await;
+    let sequence: Vec<(ChatEventType, String)> = events
+        .iter()
+        .filter_map(|event| match &event.data {
+            Some(ChatResponseData::Text { text }) => Some((event.event.clone(), text.clone())),
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(
+        sequence,
+        vec![
+            (ChatEventType::Text, "Hel".to_string()),
+            (ChatEventType::Text, "lo".to_string()),
+            (ChatEventType::ReplaceResponse, "Hello, world".to_string()),
+            (ChatEventType::Text, "!".to_string()),
+        ],
+        "replace_response should be surfaced in order"
+    );
+    assert_eq!(events.last().unwrap().event, ChatEventType::Done);
+
+    debug!("replace_response fixture test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_request_signer_headers() {
+    setup();
+    debug!("Starting request signer test");
+
+    struct LengthSigner;
+    impl RequestSigner for LengthSigner {
+        fn sign(&self, request: &SigningRequest<'_>) -> Result<Vec<(String, String)>, PoeError> {
+            Ok(vec![(
+                "x-signature".to_string(),
+                format!("{}:{}:{}", request.method, request.body.len(), request.timestamp > 0),
+            )])
+        }
+    }
+
+    let server = start_mock_server(vec![
+        MockResponse::sse("event: done\ndata: {}\n\n"),
+        MockResponse::json(
+            200,
+            r#"{"data":[{"id":"Claude-3.7-Sonnet","object":"model","created":0,"owned_by":"poe"}]}"#,
+        ),
+    ])
+    .await;
+    let client = mock_client(&server).with_request_signer(LengthSigner);
+
+    let request = Conversation::new().to_request();
+    let body_len = serde_json::to_vec(&request).unwrap().len();
+    let _ = client.stream_request(request).await.unwrap();
+    client.get_v1_model_list().await.unwrap();
+
+    let requests = server.requests.lock().unwrap();
+    assert!(
+        requests[0].contains(&format!("x-signature: POST:{}:true", body_len)),
+        "Chat request should be signed over its serialized body"
+    );
+    assert!(
+        requests[1].contains("x-signature: GET:0:true"),
+        "Model list request should be signed"
+    );
+
+    debug!("Request signer test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_format_wire_chunk() {
+    setup();
+    debug!("Starting wire chunk formatting test");
+
+    let config = LoggingConfig {
+        max_wire_chunk_length: 16,
+        ..Default::default()
+    };
+
+    let formatted = LoggingHelper::format_wire_chunk(3, b"event: done\r\n", &config);
+    assert_eq!(formatted, r#"🔌 WIRE #3 (13 bytes): "event: done\r\n""#);
+
+    let formatted = LoggingHelper::format_wire_chunk(4, "data: \"你好\"\n\n".as_bytes(), &config);
+    assert!(
+        formatted.contains(r#"data: \"\xe4\xbd\xa0"#),
+        "Non-ASCII bytes should be hex escaped: {}",
+        formatted
+    );
+
+    let formatted = LoggingHelper::format_wire_chunk(5, &[b'x'; 40], &config);
+    assert!(
+        formatted.starts_with("🔌 WIRE #5 (40 bytes)") && formatted.ends_with("... [truncated]"),
+        "Oversized chunks should be capped: {}",
+        formatted
+    );
+
+    debug!("Wire chunk formatting test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_graphql_hash_refresh() {
+    setup();
+    debug!("Starting GraphQL hash refresh test");
+
+    let server = start_mock_server(vec![
+        MockResponse::json(
+            200,
+            r#"{"data":null,"errors":[{"message":"PersistedQueryNotFound","extensions":{"code":"PERSISTED_QUERY_NOT_FOUND"}}]}"#,
+        ),
+        MockResponse::json(200, r#"{"ExploreBotsListPaginationQuery":["stale-hash","fresh-hash"]}"#),
+        MockResponse::json(
+            200,
+            r#"{"data":null,"errors":[{"message":"PersistedQueryNotFound","extensions":{"code":"PERSISTED_QUERY_NOT_FOUND"}}]}"#,
+        ),
+        MockResponse::json(
+            200,
+            r#"{"data":{"exploreBotsConnection":{"edges":[{"node":{"handle":"Claude-3.7-Sonnet"}}]}}}"#,
+        ),
+    ])
+    .await;
+    let client = mock_client(&server)
+        .with_graphql_url(&format!("{}/api/gql_POST", server.url))
+        .with_graphql_hash_sources(vec![GraphqlHashSource::Remote(format!(
+            "{}/hashes.json",
+            server.url
+        ))]);
+
+    let models = client
+        .get_model_list(None)
+        .await
+        .expect("Model list should succeed after refreshing the hash");
+    assert_eq!(models.data[0].id, "Claude-3.7-Sonnet");
+
+    client.clone().get_model_list(None).await.unwrap();
+
+    {
+        let requests = server.requests.lock().unwrap();
+        assert!(requests[1].starts_with("GET /hashes.json"), "Remote source should be queried");
+        assert!(requests[2].contains(r#""hash":"stale-hash""#));
+        assert!(requests[3].contains(r#""hash":"fresh-hash""#));
+        assert!(
+            requests[4].contains(r#""hash":"fresh-hash""#),
+            "Refreshed hash should be reused by later calls"
+        );
+    }
+
+    let server = start_mock_server(vec![MockResponse::json(
+        200,
+        r#"{"data":null,"errors":[{"message":"PersistedQueryNotFound","extensions":{"code":"PERSISTED_QUERY_NOT_FOUND"}}]}"#,
+    )])
+    .await;
+    let result = mock_client(&server)
+        .with_graphql_url(&format!("{}/api/gql_POST", server.url))
+        .with_graphql_hash_sources(Vec::new())
+        .get_model_list(None)
+        .await;
+    assert!(
+        matches!(&result, Err(e) if e.is_graphql_hash_expired()),
+        "Original error should surface when no candidate works"
+    );
+    assert_eq!(server.requests.lock().unwrap().len(), 1);
+
+    debug!("GraphQL hash refresh test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_custom_http_client() {
+    setup();
+    debug!("Starting custom HTTP client test");
+
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert("x-proxy-tag", "edge-1".parse().unwrap());
+    let http_client = reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .unwrap();
+
+    let server = start_mock_server(vec![MockResponse::json(
+        200,
+        r#"{"data":{"exploreBotsConnection":{"edges":[{"node":{"handle":"Claude-3.7-Sonnet"}}]}}}"#,
+    )])
+    .await;
+    let client = mock_client(&server)
+        .with_http_client(http_client)
+        .with_graphql_url(&format!("{}/api/gql_POST", server.url));
+
+    client.get_model_list(Some("en")).await.unwrap();
+
+    let requests = server.requests.lock().unwrap();
+    assert!(
+        requests[0].contains("x-proxy-tag: edge-1"),
+        "GraphQL call should go through the configured HTTP client"
+    );
+    assert!(requests[0].contains("accept-language: en"));
+
+    debug!("Custom HTTP client test completed");
+}
+
+#[cfg(feature = "cookies")]
+#[test_log::test(tokio::test)]
+async fn test_graphql_cookie_jar() {
+    setup();
+    debug!("Starting GraphQL cookie jar test");
+
+    let models_body =
+        r#"{"data":{"exploreBotsConnection":{"edges":[{"node":{"handle":"Claude-3.7-Sonnet"}}]}}}"#;
+    let server = start_mock_server(vec![
+        MockResponse::json(200, models_body)
+            .with_header("set-cookie", "cf_clearance=abc; Path=/")
+            .with_header("set-cookie", "Poe-Language-Code=ja; Path=/"),
+        MockResponse::json(200, models_body),
+    ])
+    .await;
+    let client = mock_client(&server).with_graphql_url(&format!("{}/api/gql_POST", server.url));
+
+    client.get_model_list(Some("en")).await.unwrap();
+    client.clone().get_model_list(Some("en")).await.unwrap();
+
+    {
+        let requests = server.requests.lock().unwrap();
+        assert!(!requests[0].contains("cf_clearance"));
+        assert!(
+            requests[1].contains("cookie: Poe-Language-Code=en; p-b=1; cf_clearance=abc"),
+            "Stored cookies should be sent without overriding explicit ones: {}",
+            requests[1]
+        );
+    }
+
+    let other = mock_client(&server)
+        .with_graphql_url(&format!("{}/api/gql_POST", server.url))
+        .with_cookie_jar(client.cookie_jar());
+    let shared = other.gql_cookie(None).unwrap_or_default();
+    assert!(
+        shared.contains("cf_clearance=abc") && shared.contains("Poe-Language-Code=ja"),
+        "Shared jar should carry cookies to another client: {}",
+        shared
+    );
+
+    debug!("GraphQL cookie jar test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_stream_truncated() {
+    setup();
+    debug!("Starting stream truncation test");
+
+    let server = start_mock_server(vec![MockResponse::sse(
+        "event: text\ndata: {\"text\": \"Partial\"}\n\n",
+    )])
+    .await;
+    let events: Vec<_> = mock_client(&server)
+        .stream_request(Conversation::new().to_request())
+        .await
+        .unwrap()
+        .collect()
+        .await;
+    assert_eq!(events.len(), 2, "Text event should be followed by a truncation error");
+    assert!(events[0].is_ok());
+    assert!(
+        matches!(events[1], Err(PoeError::StreamTruncated)),
+        "Missing done event should surface as StreamTruncated"
+    );
+
+    let events = replay_fixture("event: text\ndata: {\"text\": \"Done\"}\n\nevent: done\ndata: {}\n\n").await;
+    assert_eq!(
+        events.last().map(|event| &event.event),
+        Some(&ChatEventType::Done),
+        "Completed streams should not report truncation"
+    );
+
+    debug!("Stream truncation test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_conversation_carry_bot_files() {
+    setup();
+    debug!("Starting bot file carry-forward test");
+
+    let file = FileData {
+        url: "https://pfst.cf2.poecdn.net/base/image/chart.png".to_string(),
+        name: "chart.png".to_string(),
+        content_type: "image/png".to_string(),
+        inline_ref: "ref_1".to_string(),
+    };
+    let attachment = Attachment::from(&file);
+    assert_eq!(attachment.url, file.url);
+    assert_eq!(attachment.content_type.as_deref(), Some("image/png"));
+
+    let mut conversation = Conversation::new().with_carry_bot_files(true);
+    conversation.push_user_message("Draw a chart");
+    conversation.push_bot_file(&file);
+    conversation.push_bot_message("Here is the chart");
+    conversation.push_user_message("Make the bars blue");
+
+    let messages = conversation.messages();
+    assert!(messages[1].attachments.is_none(), "Bot turn should not carry attachments");
+    let attachments = messages[2].attachments.as_ref().expect("File should be carried forward");
+    assert_eq!(attachments.len(), 1);
+    assert_eq!(attachments[0].url, file.url);
+
+    conversation.push_bot_message("Done");
+    conversation.push_user_message("Thanks");
+    assert!(
+        conversation.messages()[4].attachments.is_none(),
+        "Files should only be attached to the next user message"
+    );
+
+    let mut conversation = Conversation::new();
+    conversation.push_bot_file(&file);
+    conversation.push_user_message("Hello");
+    assert!(conversation.messages()[0].attachments.is_none(), "Carrying is opt-in");
+
+    debug!("Bot file carry-forward test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_prepare_request() {
+    setup();
+    debug!("Starting prepare request test");
+
+    let client = PoeClient::new("TestBot", "secret_key", "https://api.poe.com/", "http://127.0.0.1:9");
+    let mut conversation = Conversation::new();
+    conversation.push_user_message("Weather?");
+    let mut request = conversation.to_request();
+    request.tools = Some(vec![ChatTool {
+        r#type: "function".to_string(),
+        function: FunctionDefinition {
+            name: "get_weather".to_string(),
+            description: None,
+            parameters: None,
+        },
+    }]);
+
+    let prepared = client.prepare_request(request.clone()).unwrap();
+    assert_eq!(prepared.url, "https://api.poe.com/bot/TestBot");
+    assert!(
+        prepared
+            .headers
+            .contains(&("Authorization".to_string(), "Bearer ***MASKED***".to_string())),
+        "Access key should be masked"
+    );
+    assert!(!format!("{:?}", prepared).contains("secret_key"));
+    assert_eq!(prepared.body["conversation_id"], json!(conversation.id()));
+
+    #[cfg(feature = "xml-prompt")]
+    {
+        assert!(prepared.body.get("tools").is_none(), "Tools should be moved into the prompt");
+        assert!(
+            prepared.body["query"][0]["content"]
+                .as_str()
+                .unwrap()
+                .contains("get_weather"),
+            "Prepared body should include the XML tool prompt"
+        );
+    }
+    #[cfg(not(feature = "xml-prompt"))]
+    assert_eq!(prepared.body["tools"][0]["function"]["name"], "get_weather");
+
+    request.query.clear();
+    let result = client.with_strict_roles(true).prepare_request(request);
+    assert!(
+        matches!(result, Err(PoeError::InvalidRoleSequence(_))),
+        "Validation should run before preparing"
+    );
+
+    debug!("Prepare request test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_event_too_large() {
+    setup();
+    debug!("Starting oversized event test");
+
+    let long_text = "x".repeat(200);
+    let server = start_mock_server(vec![MockResponse::sse(&format!(
+        "event: text\ndata: {{\"text\": \"{}\"}}\n\nevent: done\ndata: {{}}\n\n",
+        long_text
+    ))])
+    .await;
+    let events: Vec<_> = mock_client(&server)
+        .with_max_event_size(64)
+        .stream_request(Conversation::new().to_request())
+        .await
+        .unwrap()
+        .collect()
+        .await;
+    assert_eq!(events.len(), 1, "Stream should stop after the oversized line: {:?}", events);
+    assert!(
+        matches!(events[0], Err(PoeError::EventTooLarge { limit: 64, .. })),
+        "Oversized line should surface as EventTooLarge"
+    );
+
+    let server = start_mock_server(vec![MockResponse::sse(&format!(
+        "event: text\ndata: {{\"text\": \"{}",
+        long_text
+    ))])
+    .await;
+    let events: Vec<_> = mock_client(&server)
+        .with_max_event_size(64)
+        .stream_request(Conversation::new().to_request())
+        .await
+        .unwrap()
+        .collect()
+        .await;
+    assert!(
+        matches!(events.as_slice(), [Err(PoeError::EventTooLarge { .. })]),
+        "Unterminated oversized line should also be rejected: {:?}",
+        events
+    );
+
+    let server = start_mock_server(vec![MockResponse::sse(&format!(
+        "event: text\n{}\nevent: done\ndata: {{}}\n\n",
+        "data: xxxxxxxxxx\n".repeat(20)
+    ))])
+    .await;
+    let events: Vec<_> = mock_client(&server)
+        .with_max_event_size(64)
+        .stream_request(Conversation::new().to_request())
+        .await
+        .unwrap()
+        .collect()
+        .await;
+    assert!(
+        matches!(events.as_slice(), [Err(PoeError::EventTooLarge { limit: 64, .. })]),
+        "Many small data lines should not bypass the limit: {:?}",
+        events
+    );
+
+    debug!("Oversized event test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_stream_stats() {
+    setup();
+    debug!("Starting stream stats test");
+
+    let server = start_mock_server(vec![MockResponse::sse(
+        "event: text\ndata: {\"text\": \"Hi\"}\n\nevent: text\ndata: {\"partial",
+    )])
+    .await;
+    let client = mock_client(&server);
+    assert_eq!(client.stream_stats(), StreamStats::default());
+
+    let mut stream = client
+        .stream_request(Conversation::new().to_request())
+        .await
+        .unwrap();
+    stream.next().await.unwrap().unwrap();
+
+    let stats = client.clone().stream_stats();
+    assert_eq!(stats.active_streams, 1, "Unfinished stream should be counted");
+    assert_eq!(
+        stats.buffered_bytes,
+        "data: {\"partial".len(),
+        "Unterminated line should be reported as buffered"
+    );
+
+    drop(stream);
+    assert_eq!(
+        client.stream_stats(),
+        StreamStats::default(),
+        "Dropping the stream should release its gauges"
+    );
+
+    debug!("Stream stats test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_custom_transport() {
+    setup();
+    debug!("Starting custom transport test");
+
+    struct CannedTransport {
+        requests: std::sync::Mutex<Vec<HttpRequest>>,
+    }
+
+    #[async_trait::async_trait]
+    impl HttpTransport for CannedTransport {
+        async fn send(&self, request: HttpRequest) -> Result<HttpResponse, PoeError> {
+            self.requests.lock().unwrap().push(request);
+            let chunks = ["event: text\ndata: {\"text\": \"Hel", "lo\"}\n\nevent: done\ndata: {}\n\n"];
+            Ok(HttpResponse {
+                status: 200,
+                headers: vec![("Content-Type".to_string(), "text/event-stream".to_string())],
+                body: futures_util::stream::iter(
+                    chunks.map(|chunk| Ok(bytes::Bytes::from_static(chunk.as_bytes()))),
+                )
+                .boxed(),
+            })
+        }
+    }
+
+    let transport = std::sync::Arc::new(CannedTransport {
+        requests: std::sync::Mutex::new(Vec::new()),
+    });
+
+    let client = PoeClient::new("TestBot", "test_key", "http://transport.invalid", "")
+        .with_transport(transport.clone());
+    let events: Vec<_> = client
+        .stream_request(Conversation::new().to_request())
+        .await
+        .unwrap()
+        .collect()
+        .await;
+
+    assert!(
+        matches!(&events[0], Ok(ChatResponse { data: Some(ChatResponseData::Text { text }), .. }) if text == "Hello"),
+        "Text split across transport chunks should be reassembled: {:?}",
+        events
+    );
+    assert!(matches!(&events[1], Ok(ChatResponse { event: ChatEventType::Done, .. })));
+
+    let requests = transport.requests.lock().unwrap();
+    assert_eq!(requests[0].method, "POST");
+    assert_eq!(requests[0].url, "http://transport.invalid/bot/TestBot");
+    assert!(
+        requests[0]
+            .headers
+            .contains(&("Authorization".to_string(), "Bearer test_key".to_string()))
+    );
+
+    debug!("Custom transport test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_sse_writer_roundtrip() {
+    setup();
+    debug!("Starting SSE writer roundtrip test");
+
+    let original = replay_fixture(include_str!("../tests/fixtures/gpt4o_tool_calls.sse")).await;
+
+    let mut writer = SseWriter::new();
+    let mut wire = Vec::new();
+    wire.extend(writer.write(&Ok(ChatResponse {
+        event: ChatEventType::Text,
+        data: Some(ChatResponseData::Text {
+            text: "Line 1\nLine \"2\" 你好".to_string(),
+        }),
+    })));
+    wire.extend(writer.ping());
+    wire.extend(writer.write(&Ok(ChatResponse {
+        event: ChatEventType::File,
+        data: Some(ChatResponseData::File(FileData {
+            url: "https://example.com/a.png".to_string(),
+            name: "a.png".to_string(),
+            content_type: "image/png".to_string(),
+            inline_ref: "ref".to_string(),
+        })),
+    })));
+    // Generic json chunks are kept as their raw JSON text and must be written back verbatim
+    let chunk = r#"{"id":"chatcmpl-0002","choices":[{"index":0,"delta":{"role":"assistant"},"finish_reason":null}]}"#;
+    let json_event = Ok(ChatResponse {
+        event: ChatEventType::Json,
+        data: Some(ChatResponseData::Text {
+            text: chunk.to_string(),
+        }),
+    });
+    assert_eq!(
+        String::from_utf8(SseWriter::new().write(&json_event)).unwrap(),
+        format!("event: json\ndata: {}\n\n", chunk)
+    );
+    wire.extend(writer.write(&json_event));
+    for event in &original {
+        wire.extend(writer.write(&Ok(event.clone())));
+    }
+    assert!(writer.is_done());
+    assert!(writer.finish().is_empty(), "done should only be written once");
+
+    let replayed = replay_fixture(&String::from_utf8(wire).unwrap()).await;
+    assert!(
+        matches!(&replayed[0].data, Some(ChatResponseData::Text { text }) if text == "Line 1\nLine \"2\" 你好"),
+        "Text should survive the roundtrip: {:?}",
+        replayed[0]
+    );
+    assert!(matches!(&replayed[1].data, Some(ChatResponseData::File(file)) if file.inline_ref == "ref"));
+    assert!(
+        matches!(&replayed[2], ChatResponse { event: ChatEventType::Json, data: Some(ChatResponseData::Text { text }) } if text == chunk),
+        "Json chunks should survive the roundtrip: {:?}",
+        replayed[2]
+    );
+    assert_eq!(
+        serde_json::to_value(collected_tool_calls(&replayed)).unwrap(),
+        serde_json::to_value(collected_tool_calls(&original)).unwrap(),
+        "Tool calls should survive the roundtrip"
+    );
+    assert_eq!(replayed.last().unwrap().event, ChatEventType::Done);
+
+    let mut writer = SseWriter::new();
+    let frames = String::from_utf8(writer.write(&Err(PoeError::StreamTruncated))).unwrap();
+    assert_eq!(
+        frames,
+        "event: error\ndata: {\"allow_retry\":false,\"text\":\"Stream ended without a done event\"}\n\nevent: done\ndata: {}\n\n",
+        "Errors should be followed by a closing done event"
+    );
+
+    debug!("SSE writer roundtrip test completed");
+}
+
+#[cfg(feature = "http-server")]
+#[test_log::test(tokio::test)]
+async fn test_sse_body_bridge() {
+    setup();
+    debug!("Starting SSE body bridge test");
+
+    let upstream = futures_util::stream::iter(vec![Ok(ChatResponse {
+        event: ChatEventType::Text,
+        data: Some(ChatResponseData::Text {
+            text: "Hi".to_string(),
+        }),
+    })])
+    .chain(futures_util::stream::once(async {
+        tokio::time::sleep(std::time::Duration::from_millis(120)).await;
+        Err(PoeError::StreamTruncated)
+    }))
+    .boxed();
+
+    let frames: Vec<String> = crate::sse_body(upstream, std::time::Duration::from_millis(30))
+        .map(|frame| String::from_utf8(frame.unwrap().to_vec()).unwrap())
+        .collect()
+        .await;
+
+    assert_eq!(frames[0], "event: text\ndata: {\"text\":\"Hi\"}\n\n");
+    assert!(
+        frames.iter().any(|frame| frame == ": ping\n\n"),
+        "Idle upstream should produce keep-alive comments: {:?}",
+        frames
+    );
+    assert!(
+        frames.last().unwrap().ends_with("event: done\ndata: {}\n\n"),
+        "Errors should be closed with a done frame"
+    );
+    assert!(frames.last().unwrap().starts_with("event: error\n"));
+
+    debug!("SSE body bridge test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_retry_budget() {
+    setup();
+    debug!("Starting retry budget test");
+
+    let budget = RetryBudget::new(1, 0.5);
+    assert!(budget.try_withdraw(), "Initial reserve should allow a retry");
+    assert!(!budget.try_withdraw(), "Empty budget should refuse retries");
+    budget.deposit();
+    budget.deposit();
+    assert!(budget.try_withdraw(), "Two requests at 50% should earn one retry");
+    budget.deposit();
+    budget.deposit();
+    budget.deposit();
+    assert_eq!(budget.stats().balance, 1.0, "Balance should be capped at max_tokens");
+    assert_eq!(budget.stats().exhausted, 1);
+
+    let server = start_mock_server(vec![
+        MockResponse::json(
+            200,
+            r#"{"data":null,"errors":[{"message":"PersistedQueryNotFound","extensions":{"code":"PERSISTED_QUERY_NOT_FOUND"}}]}"#,
+        ),
+        MockResponse::json(200, r#"{"ExploreBotsListPaginationQuery":"fresh-hash"}"#),
+    ])
+    .await;
+    let client = mock_client(&server)
+        .with_graphql_url(&format!("{}/api/gql_POST", server.url))
+        .with_graphql_hash_sources(vec![GraphqlHashSource::Remote(format!(
+            "{}/hashes.json",
+            server.url
+        ))])
+        .with_retry_budget(RetryBudget::new(0, 0.0));
+    let result = client.get_model_list(None).await;
+
+    assert!(matches!(&result, Err(e) if e.is_graphql_hash_expired()));
+    assert_eq!(
+        server.requests.lock().unwrap().len(),
+        2,
+        "Only the original query and the hash lookup should be sent"
+    );
+    assert_eq!(client.retry_budget_stats().unwrap().exhausted, 1);
+    assert!(mock_client(&server).retry_budget_stats().is_none());
+
+    debug!("Retry budget test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_circuit_breaker() {
+    setup();
+    debug!("Starting circuit breaker test");
+
+    let server = start_mock_server(vec![
+        MockResponse::json(503, r#"{"error":"unavailable"}"#),
+        MockResponse::json(503, r#"{"error":"unavailable"}"#),
+        MockResponse::sse("event: done\ndata: {}\n\n"),
+    ])
+    .await;
+    let breaker = std::sync::Arc::new(CircuitBreaker::new(
+        2,
+        std::time::Duration::from_millis(50),
+    ));
+    let client = mock_client(&server).with_circuit_breaker(breaker.clone());
+    let request = Conversation::new().to_request();
+
+    assert!(client.stream_request(request.clone()).await.is_err());
+    assert_eq!(breaker.state("TestBot"), CircuitState::Closed);
+    assert!(client.stream_request(request.clone()).await.is_err());
+    assert_eq!(breaker.state("TestBot"), CircuitState::Open);
+
+    let result = client.stream_request(request.clone()).await;
+    assert!(
+        matches!(&result, Err(PoeError::CircuitOpen(bot)) if bot == "TestBot"),
+        "Open circuit should fail fast"
+    );
+    assert_eq!(server.requests.lock().unwrap().len(), 2, "No request should be sent while open");
+    assert_eq!(breaker.state("OtherBot"), CircuitState::Closed, "Circuits are per bot");
+
+    tokio::time::sleep(std::time::Duration::from_millis(60)).await;
+    assert!(client.stream_request(request).await.is_ok(), "Probe should be let through");
+    assert_eq!(
+        breaker.state("TestBot"),
+        CircuitState::Closed,
+        "Successful probe should close the circuit"
+    );
+
+    breaker.record_failure("TestBot");
+    breaker.record_failure("TestBot");
+    tokio::time::sleep(std::time::Duration::from_millis(60)).await;
+    assert!(breaker.check("TestBot").is_ok());
+    assert_eq!(breaker.state("TestBot"), CircuitState::HalfOpen);
+    assert!(breaker.check("TestBot").is_err(), "Only one probe at a time");
+    breaker.record_failure("TestBot");
+    assert_eq!(breaker.state("TestBot"), CircuitState::Open, "Failed probe should re-open");
+
+    debug!("Circuit breaker test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_circuit_breaker_cancelled_probe() {
+    setup();
+    debug!("Starting cancelled circuit probe test");
+
+    // A server that accepts connections but never answers
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let mut sockets = Vec::new();
+        while let Ok((socket, _)) = listener.accept().await {
+            sockets.push(socket);
+        }
+    });
+
+    let breaker = std::sync::Arc::new(CircuitBreaker::new(
+        1,
+        std::time::Duration::from_millis(50),
+    ));
+    let client =
+        PoeClient::new("TestBot", "test_key", &url, &url).with_circuit_breaker(breaker.clone());
+    breaker.record_failure("TestBot");
+    tokio::time::sleep(std::time::Duration::from_millis(60)).await;
+
+    let probe = tokio::time::timeout(
+        std::time::Duration::from_millis(20),
+        client.stream_request(Conversation::new().to_request()),
+    )
+    .await;
+    assert!(probe.is_err(), "Probe should be cancelled by the timeout");
+    assert_eq!(breaker.state("TestBot"), CircuitState::HalfOpen);
+    assert!(breaker.check("TestBot").is_err(), "In-flight probe should still block requests");
+
+    tokio::time::sleep(std::time::Duration::from_millis(60)).await;
+    assert!(breaker.is_available("TestBot"), "Stale probe should expire");
+    assert!(breaker.check("TestBot").is_ok(), "A new probe should be let through");
+    assert!(breaker.check("TestBot").is_err(), "Only one probe at a time");
+    breaker.record_success("TestBot");
+    assert_eq!(breaker.state("TestBot"), CircuitState::Closed);
+
+    debug!("Cancelled circuit probe test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_model_list_filtering() {
+    setup();
+    debug!("Starting model list filtering test");
+
+    let server = start_mock_server(vec![MockResponse::json(
+        200,
+        r#"{"data":[
+            {"id":"Claude-Sonnet-4","object":"model","created":0,"owned_by":"Anthropic",
+             "architecture":{"input_modalities":["text","image"],"output_modalities":["text"]}},
+            {"id":"Claude-Haiku-3","object":"model","created":0,"owned_by":"Anthropic",
+             "architecture":{"input_modalities":["text"],"output_modalities":["text"]}},
+            {"id":"GPT-Image-1","object":"model","created":0,"owned_by":"OpenAI",
+             "architecture":{"input_modalities":["text"],"output_modalities":["image"]}}
+        ]}"#,
+    )])
+    .await;
+    let client = mock_client(&server);
+
+    let models = client.get_v1_model_list().await.unwrap();
+    assert!(models.contains("claude-sonnet-4"), "Lookup should be case-insensitive");
+    assert_eq!(models.find("GPT-Image-1").unwrap().owned_by, "OpenAI");
+    assert!(models.find("Unknown").is_none());
+
+    let filter = ModelFilter::default().owned_by("anthropic").capability("image");
+    let ids: Vec<_> = models.clone().filtered(&filter).data.into_iter().map(|m| m.id).collect();
+    assert_eq!(ids, vec!["Claude-Sonnet-4"]);
+
+    let image_output = client
+        .get_v1_model_list_filtered(&ModelFilter::default().search("image"))
+        .await
+        .unwrap();
+    assert_eq!(image_output.data.len(), 1);
+    assert_eq!(image_output.data[0].id, "GPT-Image-1");
+
+    debug!("Model list filtering test completed");
+}
+
+#[cfg(feature = "language-detect")]
+#[test_log::test(tokio::test)]
+async fn test_language_enforcement() {
+    setup();
+    debug!("Starting language enforcement test");
+
+    let english = "The farm has a red barn, several cows and a large field of wheat that the family harvests every summer.";
+    let french = "La ferme a une grange rouge, plusieurs vaches et un grand champ de blé que la famille récolte chaque été.";
+    assert!(crate::check_language(english, "en-US").matches);
+    assert!(!crate::check_language(english, "fr").matches);
+    assert_eq!(crate::check_language(english, "fra").detected, Some("eng"));
+    assert!(crate::check_language("ok", "fr").matches, "Unreliable detection should pass");
+
+    let sse = |text: &str| {
+        MockResponse::sse(&format!(
+            "event: text\ndata: {{\"text\": \"{}\"}}\n\nevent: done\ndata: {{}}\n\n",
+            text
+        ))
+    };
+    let server = start_mock_server(vec![sse(english), sse(french)]).await;
+    let request = ChatRequest {
+        query: vec![ChatMessage {
+            role: "user".to_string(),
+            content: "Describe the farm".to_string(),
+            content_type: "text/markdown".to_string(),
+            attachments: None,
+        }],
+        ..Default::default()
+    };
+
+    let result = mock_client(&server)
+        .complete_in_language(request.clone(), "fr-FR")
+        .await
+        .expect("Language enforcement should succeed");
+    assert!(result.retried, "English response should trigger a retry");
+    assert!(result.check.matches);
+    assert_eq!(result.text, french);
+    {
+        let requests = server.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        let body = captured_json(&requests[1]);
+        assert!(
+            body["query"][0]["content"]
+                .as_str()
+                .unwrap()
+                .ends_with("Please respond only in French (fr-FR)."),
+            "Retry should carry the language instruction"
+        );
+    }
+
+    let server = start_mock_server(vec![sse(english)]).await;
+    let result = mock_client(&server)
+        .with_retry_budget(RetryBudget::new(0, 0.0))
+        .complete_in_language(request, "fr")
+        .await
+        .unwrap();
+    assert!(!result.retried, "Exhausted retry budget should prevent the retry");
+    assert!(!result.check.matches);
+    assert_eq!(server.requests.lock().unwrap().len(), 1);
+
+    debug!("Language enforcement test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_pipe_text_to() {
+    setup();
+    debug!("Starting pipe text test");
+
+    let server = start_mock_server(vec![MockResponse::sse(concat!(
+        "event: text\ndata: {\"text\": \"Hel\"}\n\n",
+        "event: text\ndata: {\"text\": \"lo\"}\n\n",
+        "event: replace_response\ndata: {\"text\": \"Hi\"}\n\n",
+        "event: text\ndata: {\"text\": \" there\"}\n\n",
+        "event: done\ndata: {}\n\n",
+    ))])
+    .await;
+    let client = mock_client(&server);
+    let request = ChatRequest {
+        query: vec![ChatMessage {
+            role: "user".to_string(),
+            content: "Hello".to_string(),
+            content_type: "text/markdown".to_string(),
+            attachments: None,
+        }],
+        ..Default::default()
+    };
+
+    let mut output = Vec::new();
+    let text = client
+        .pipe_text_to(request.clone(), &mut output)
+        .await
+        .expect("Piping should succeed");
+    assert_eq!(text, "Hi there");
+    assert_eq!(String::from_utf8(output).unwrap(), "Hello\r\x1b[2KHi there");
+
+    let mut output = Vec::new();
+    client
+        .pipe_text_to_with(request.clone(), &mut output, ReplaceStrategy::NewLine)
+        .await
+        .unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "Hello\nHi there");
+
+    let mut output = Vec::new();
+    let text = client
+        .pipe_text_to_with(request, &mut output, ReplaceStrategy::Ignore)
+        .await
+        .unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "Hello there");
+    assert_eq!(text, "Hello there", "Ignored replacements should not change the text");
+
+    debug!("Pipe text test completed");
+}
+
+#[cfg(feature = "tools-process")]
+#[test_log::test(tokio::test)]
+async fn test_process_tool() {
+    use crate::ToolRegistry;
+    use crate::tools::process::ProcessTool;
+
+    setup();
+    debug!("Starting process tool test");
+
+    let registry = ToolRegistry::new().register(
+        ProcessTool::new(["echo", "sleep", "yes"])
+            .with_timeout(std::time::Duration::from_millis(200))
+            .with_max_output_bytes(16),
+    );
+    assert_eq!(registry.definitions().len(), 1);
+    assert_eq!(registry.definitions()[0].function.name, "run_command");
+
+    let call = |id: &str, arguments: serde_json::Value| ChatToolCall {
+        id: id.to_string(),
+        r#type: "function".to_string(),
+        function: crate::types::FunctionCall {
+            name: "run_command".to_string(),
+            arguments: arguments.to_string(),
+        },
+        inferred: false,
+        choice_index: 0,
+    };
+    let results = registry
+        .execute_all(&[
+            call("1", json!({ "command": "echo", "args": ["hello"] })),
+            call("2", json!({ "command": "rm", "args": ["-rf", "/"] })),
+            call("3", json!({ "command": "sleep", "args": ["5"] })),
+            call("4", json!({ "command": "yes" })),
+        ])
+        .await;
+    let outputs: Vec<serde_json::Value> = results
+        .iter()
+        .map(|result| serde_json::from_str(&result.content).unwrap())
+        .collect();
+
+    assert_eq!(results[0].tool_call_id, "1");
+    assert_eq!(results[0].role, "tool");
+    assert_eq!(outputs[0]["stdout"], "hello\n");
+    assert_eq!(outputs[0]["exit_code"], 0);
+    assert!(
+        outputs[1]["error"].as_str().unwrap().contains("not allowed"),
+        "Commands outside the allowlist should be refused"
+    );
+    assert!(
+        outputs[2]["error"].as_str().unwrap().contains("timed out"),
+        "Long-running commands should be killed"
+    );
+    assert_eq!(outputs[3]["truncated"], true, "Output should be capped");
+    assert_eq!(outputs[3]["stdout"].as_str().unwrap().len(), 16);
+
+    let unknown = registry
+        .execute(&ChatToolCall {
+            function: crate::types::FunctionCall {
+                name: "missing".to_string(),
+                arguments: "{}".to_string(),
+            },
+            ..call("5", json!({}))
+        })
+        .await;
+    assert!(unknown.content.contains("unknown tool"));
+
+    debug!("Process tool test completed");
+}
+
+#[cfg(feature = "tools-http")]
+#[test_log::test(tokio::test)]
+async fn test_http_fetch_tool() {
+    use crate::Tool;
+    use crate::tools::http_fetch::{HttpFetchTool, html_to_text};
+
+    setup();
+    debug!("Starting HTTP fetch tool test");
+
+    assert_eq!(
+        html_to_text(
+            "<html><head><style>p { color: red; }</style><SCRIPT>alert(1)</script></head>\
+             <body><h1>Title</h1><p>Fish &amp; chips&nbsp;today</p></body></html>"
+        ),
+        "Title\nFish & chips today"
+    );
+
+    let server = start_mock_server(vec![MockResponse {
+        status: 200,
+        content_type: "text/html; charset=utf-8",
+        headers: Vec::new(),
+        body: "<p>Hello <b>world</b></p>".to_string(),
+    }])
+    .await;
+    let url = format!("{}/page", server.url);
+
+    // Internal addresses are refused by default, literal or resolved, before connecting
+    let port = url::Url::parse(&server.url).unwrap().port().unwrap();
+    for internal in [
+        url.clone(),
+        format!("http://localhost:{}/page", port),
+        "http://169.254.169.254/latest/meta-data/".to_string(),
+        "http://10.0.0.1/".to_string(),
+        "http://[::1]/".to_string(),
+        "http://[::ffff:127.0.0.1]/".to_string(),
+        "http://0x7f000001/".to_string(),
+    ] {
+        assert!(
+            HttpFetchTool::new().call(json!({ "url": internal })).await.is_err(),
+            "{} should be rejected",
+            internal
+        );
+    }
+    assert!(
+        HttpFetchTool::new()
+            .with_allowed_hosts(["localhost"])
+            .call(json!({ "url": format!("http://localhost:{}/page", port) }))
+            .await
+            .is_err(),
+        "An allowlisted name resolving to loopback should still be rejected"
+    );
+    assert!(server.requests.lock().unwrap().is_empty());
+
+    let tool = HttpFetchTool::new()
+        .with_allowed_hosts(["127.0.0.1"])
+        .with_private_networks(true);
+    let output: serde_json::Value =
+        serde_json::from_str(&tool.call(json!({ "url": url })).await.unwrap()).unwrap();
+    assert_eq!(output["status"], 200);
+    assert_eq!(output["body"], "Hello world");
+    assert_eq!(output["truncated"], false);
+
+    let raw: serde_json::Value = serde_json::from_str(
+        &tool
+            .clone()
+            .with_html_to_text(false)
+            .with_max_bytes(8)
+            .call(json!({ "url": url }))
+            .await
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(raw["body"], "<p>Hello");
+    assert_eq!(raw["truncated"], true);
+
+    let denied = tool
+        .clone()
+        .with_denied_hosts(["127.0.0.1"])
+        .call(json!({ "url": url }))
+        .await;
+    assert!(matches!(denied, Err(PoeError::ToolExecutionFailed(_))));
+    for url in ["https://example.com/", "file:///etc/passwd"] {
+        assert!(
+            tool.call(json!({ "url": url })).await.is_err(),
+            "{} should be rejected",
+            url
+        );
+    }
+    assert_eq!(
+        server.requests.lock().unwrap().len(),
+        2,
+        "Rejected URLs should never be requested"
+    );
+
+    debug!("HTTP fetch tool test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_memory_tool() {
+    use crate::tools::memory::{InMemoryStore, MemoryTool};
+    use crate::{Embedder, MemoryStore};
+
+    setup();
+    debug!("Starting memory tool test");
+
+    // Toy embedder: counts of "cat" and "dog"
+    struct CountEmbedder;
+
+    #[async_trait::async_trait]
+    impl Embedder for CountEmbedder {
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, PoeError> {
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    vec![
+                        text.matches("cat").count() as f32,
+                        text.matches("dog").count() as f32,
+                    ]
+                })
+                .collect())
+        }
+    }
+
+    let call = |arguments: serde_json::Value| ChatToolCall {
+        id: "call_1".to_string(),
+        r#type: "function".to_string(),
+        function: crate::types::FunctionCall {
+            name: "memory".to_string(),
+            arguments: arguments.to_string(),
+        },
+        inferred: false,
+        choice_index: 0,
+    };
+    let output = |result: ChatToolResult| -> serde_json::Value {
+        serde_json::from_str(&result.content).unwrap()
+    };
+
+    let registry = crate::ToolRegistry::new()
+        .register(MemoryTool::new(std::sync::Arc::new(InMemoryStore::new())));
+    for (key, text) in [("pets", "User owns a dog"), ("food", "User likes sushi")] {
+        let saved = registry
+            .execute(&call(json!({ "action": "save", "key": key, "text": text })))
+            .await;
+        assert_eq!(output(saved)["saved"], key);
+    }
+
+    let found = output(
+        registry
+            .execute(&call(json!({ "action": "search", "text": "what dog", "limit": 1 })))
+            .await,
+    );
+    assert_eq!(found["results"].as_array().unwrap().len(), 1);
+    assert_eq!(found["results"][0]["key"], "pets");
+
+    let deleted = registry
+        .execute(&call(json!({ "action": "delete", "key": "food" })))
+        .await;
+    assert_eq!(output(deleted)["deleted"], true);
+    let invalid = registry.execute(&call(json!({ "action": "forget" }))).await;
+    assert!(output(invalid)["error"].as_str().unwrap().contains("unknown action"));
+
+    let vector_store = std::sync::Arc::new(InMemoryStore::new());
+    let registry = crate::ToolRegistry::new().register(
+        MemoryTool::new(vector_store.clone()).with_embedder(std::sync::Arc::new(CountEmbedder)),
+    );
+    for (key, text) in [("a", "cat cat"), ("b", "dog dog cat")] {
+        registry
+            .execute(&call(json!({ "action": "save", "key": key, "text": text })))
+            .await;
+    }
+    let records = vector_store
+        .query(&crate::tools::memory::MemoryQuery {
+            text: "dog".to_string(),
+            embedding: Some(vec![0.0, 1.0]),
+            limit: 5,
+        })
+        .await
+        .unwrap();
+    assert_eq!(records[0].key, "b", "Closest embedding should rank first");
+    assert_eq!(records[0].embedding, Some(vec![1.0, 2.0]));
+    assert_eq!(records.len(), 1, "Orthogonal embeddings should not match");
+
+    debug!("Memory tool test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_embed() {
+    setup();
+    debug!("Starting embedding test");
+
+    let reply = |text: &str| {
+        MockResponse::sse(&format!(
+            "event: text\ndata: {}\n\nevent: done\ndata: {{}}\n\n",
+            json!({ "text": text })
+        ))
+    };
+    let server = start_mock_server(vec![
+        reply("Sorry, I can't do that."),
+        reply(r#"{"data": [{"index": 1, "embedding": [0.0, 1.0]}, {"index": 0, "embedding": [1.0, 0.0]}]}"#),
+        reply("```json\n[[0.5, 0.5]]\n```"),
+    ])
+    .await;
+    let texts: Vec<String> = ["a", "b", "c"].iter().map(|s| s.to_string()).collect();
+
+    let embedder = crate::PoeEmbedder::new(&mock_client(&server), "Embed-Bot").with_batch_size(2);
+    let embeddings = crate::Embedder::embed(&embedder, &texts)
+        .await
+        .expect("Embedding should succeed after one retry");
+    assert_eq!(embeddings, vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![0.5, 0.5]]);
+    {
+        let requests = server.requests.lock().unwrap();
+        assert_eq!(requests.len(), 3, "Malformed reply should be retried once");
+        assert!(requests[0].starts_with("POST /bot/Embed-Bot "));
+        let body = captured_json(&requests[2]);
+        assert!(body["query"][0]["content"].as_str().unwrap().ends_with(r#"{"input":["c"]}"#));
+    }
+
+    let server = start_mock_server(vec![reply("[[1.0]]")]).await;
+    let result = mock_client(&server).embed(&texts, "Embed-Bot").await;
+    assert!(
+        matches!(result, Err(PoeError::InvalidEmbedding(_))),
+        "Count mismatch should surface as InvalidEmbedding"
+    );
+
+    debug!("Embedding test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_complete_with_sources() {
+    use crate::rag::{extract_citations, format_sources};
+    use crate::{Citation, RagDocument};
+
+    setup();
+    debug!("Starting RAG citation test");
+
+    let documents = vec![
+        RagDocument::new("doc-a", "Rust 1.0 was released in May 2015.").with_title("Rust history"),
+        RagDocument::new("doc-b", "Cargo is Rust's package manager.")
+            .with_url("https://doc.rust-lang.org/cargo/"),
+    ];
+    let sources = format_sources(&documents);
+    assert!(sources.contains("[1] Rust history\nRust 1.0 was released in May 2015."));
+    assert!(sources.contains("[2] <https://doc.rust-lang.org/cargo/>\nCargo"));
+
+    let citations = extract_citations("Both [2, 1] and [2][7] and [note]", &documents);
+    assert_eq!(
+        citations.iter().map(|c| c.marker).collect::<Vec<_>>(),
+        vec![2, 1],
+        "Citations should be deduplicated in first-use order, skipping unknown markers"
+    );
+
+    let server = start_mock_server(vec![MockResponse::sse(concat!(
+        "event: text\ndata: {\"text\": \"Rust shipped in 2015 [1].\"}\n\n",
+        "event: done\ndata: {}\n\n",
+    ))])
+    .await;
+    let request = ChatRequest {
+        query: vec![ChatMessage {
+            role: "user".to_string(),
+            content: "When did Rust ship?".to_string(),
+            content_type: "text/markdown".to_string(),
+            attachments: None,
+        }],
+        ..Default::default()
+    };
+
+    let response = mock_client(&server)
+        .complete_with_sources(request, &documents)
+        .await
+        .expect("RAG request should succeed");
+    assert_eq!(response.text, "Rust shipped in 2015 [1].");
+    assert_eq!(
+        response.citations,
+        vec![Citation {
+            marker: 1,
+            document: documents[0].clone(),
+        }]
+    );
+    {
+        let requests = server.requests.lock().unwrap();
+        let content = captured_json(&requests[0])["query"][0]["content"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert!(content.starts_with(&sources), "Sources should precede the question");
+        assert!(content.ends_with("When did Rust ship?"));
+    }
+
+    debug!("RAG citation test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_attach_local_files() {
+    use crate::{AttachmentExpansion, ExpansionAction};
+
+    setup();
+    debug!("Starting attachment expansion test");
+
+    let dir = tempfile::tempdir().unwrap();
+    let write = |name: &str, content: &[u8]| {
+        let path = dir.path().join(name);
+        std::fs::write(&path, content).unwrap();
+        path.to_string_lossy().into_owned()
+    };
+    let notes = write("notes.MD", b"# Notes\nShip it\n");
+    let table = write("table.csv", b"a,b\n1,2\n3,4\n5,6\n7,8\n");
+    let report = write("report.pdf", b"%PDF-1.7");
+    let image = write("image.png", &[0x89, b'P', b'N', b'G']);
+
+    let server = start_mock_server(vec![MockResponse::json(
+        200,
+        r#"{"attachment_url": "https://pfst.cf2.poecdn.net/base/file", "mime_type": "application/octet-stream"}"#,
+    )])
+    .await;
+    let expansion = AttachmentExpansion::new()
+        .with_inline_limit(16)
+        .with_extractor("pdf", |_: &[u8]| Ok("Quarterly report".to_string()));
+    let mut message = ChatMessage {
+        role: "user".to_string(),
+        content: "Summarize these".to_string(),
+        content_type: "text/markdown".to_string(),
+        attachments: None,
+    };
+
+    let actions = mock_client(&server)
+        .attach_local_files(&mut message, &[&notes, &table, &report, &image], &expansion)
+        .await
+        .expect("Expansion should succeed");
+
+    assert_eq!(
+        actions,
+        vec![
+            ExpansionAction::Inlined { path: notes, chars: 16 },
+            ExpansionAction::Attached {
+                path: table,
+                url: "https://pfst.cf2.poecdn.net/base/file".to_string(),
+            },
+            ExpansionAction::Inlined { path: report, chars: 16 },
+            ExpansionAction::Attached {
+                path: image,
+                url: "https://pfst.cf2.poecdn.net/base/file".to_string(),
+            },
+        ]
+    );
+    assert_eq!(
+        message.content,
+        "Summarize these\n\n--- notes.MD ---\n# Notes\nShip it\n--- end of notes.MD ---\
+         \n\n--- report.pdf ---\nQuarterly report\n--- end of report.pdf ---"
+    );
+    assert_eq!(message.attachments.as_ref().map(Vec::len), Some(2));
+    assert_eq!(
+        server.requests.lock().unwrap().len(),
+        2,
+        "Only files that were not inlined should be uploaded"
+    );
+
+    let missing = mock_client(&server)
+        .attach_local_files(&mut message, &["/nonexistent/file.txt"], &expansion)
+        .await;
+    assert!(matches!(missing, Err(PoeError::FileNotFound(_))));
+
+    debug!("Attachment expansion test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_role_mapping() {
+    setup();
+    debug!("Starting role mapping test");
+
+    let message = |role: &str, content: &str| ChatMessage {
+        role: role.to_string(),
+        content: content.to_string(),
+        content_type: "text/markdown".to_string(),
+        attachments: None,
+    };
+    let request = ChatRequest {
+        query: vec![
+            message("system", "Be brief"),
+            message("user", "Hi"),
+            message("bot", "Hello"),
+            message("user", "Bye"),
+        ],
+        ..Default::default()
+    };
+
+    let client = PoeClient::new("TestBot", "test_key", "https://api.poe.com", "https://example.com")
+        .with_role_mapping("bot", "model")
+        .with_role_mapping("bot", "assistant");
+    let prepared = client
+        .with_strict_roles(true)
+        .prepare_request(request.clone())
+        .expect("Strict validation should run on the original roles");
+    let roles: Vec<&str> = prepared.body["query"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|message| message["role"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        roles,
+        vec!["system", "user", "assistant", "user"],
+        "Later mappings for the same role should replace earlier ones"
+    );
+    assert_eq!(request.query[2].role, "bot", "Caller's history should be untouched");
+
+    debug!("Role mapping test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_keep_alive_events() {
+    setup();
+    debug!("Starting keep-alive events test");
+
+    let server = start_mock_server(vec![MockResponse::sse(concat!(
+        ": ping\n\n",
+        "event: text\ndata: {\"text\": \"Hi\"}\n\n",
+        "event: ping\ndata: {}\n\n",
+        "event: done\ndata: {}\n\n",
+    ))])
+    .await;
+    let request = ChatRequest {
+        query: vec![ChatMessage {
+            role: "user".to_string(),
+            content: "Hello".to_string(),
+            content_type: "text/markdown".to_string(),
+            attachments: None,
+        }],
+        ..Default::default()
+    };
+
+    let events = |client: PoeClient| {
+        let request = request.clone();
+        async move {
+            client
+                .stream_request(request)
+                .await
+                .unwrap()
+                .map(|event| event.unwrap().event)
+                .collect::<Vec<_>>()
+                .await
+        }
+    };
+
+    assert_eq!(
+        events(mock_client(&server)).await,
+        vec![ChatEventType::Text, ChatEventType::Done],
+        "Pings should be dropped by default"
+    );
+    assert_eq!(
+        events(mock_client(&server).with_keep_alive_events(true)).await,
+        vec![
+            ChatEventType::Ping,
+            ChatEventType::Text,
+            ChatEventType::Ping,
+            ChatEventType::Done,
+        ]
+    );
+
+    debug!("Keep-alive events test completed");
+}
+
+#[cfg(feature = "trace")]
+#[test_log::test(tokio::test)]
+async fn test_logging_contract() {
+    setup();
+    debug!("Starting logging contract test");
+
+    let server = start_mock_server(vec![MockResponse::sse(concat!(
+        "event: text\ndata: {\"text\": \"Hi\"}\n\n",
+        "event: done\ndata: {}\n\n",
+    ))])
+    .await;
+    let client = PoeClient::new(
+        "TestBot",
+        "secret_access_key",
+        &server.url,
+        &format!("{}/file_upload", server.url),
+    )
+    .with_logging_config(LoggingConfig {
+        max_body_length: 64,
+        ..Default::default()
+    });
+    let request = ChatRequest {
+        query: vec![ChatMessage {
+            role: "user".to_string(),
+            content: "é".repeat(100),
+            content_type: "text/markdown".to_string(),
+            attachments: None,
+        }],
+        ..Default::default()
+    };
+
+    let capture = TraceCapture::start();
+    let events: Vec<_> = client.stream_request(request).await.unwrap().collect().await;
+    let logs = capture.events();
+    drop(capture);
+
+    assert_eq!(events.len(), 2);
+    let request_log = logs
+        .iter()
+        .find(|log| log.starts_with("📤 OUTGOING REQUEST"))
+        .expect("Outgoing request should be logged");
+    assert!(request_log.contains(&format!("URL: {}/bot/TestBot", server.url)));
+    assert!(request_log.contains("Authorization: ***MASKED***"));
+    assert!(
+        request_log.contains("... [truncated, "),
+        "Request body should be truncated at max_body_length"
+    );
+    assert!(
+        logs.iter().all(|log| !log.contains("secret_access_key")),
+        "The access key must never be logged"
+    );
+    assert!(
+        logs.iter().any(|log| log.starts_with("📥 INCOMING RESPONSE") && log.contains("200")),
+        "Response status should be logged"
+    );
+
+    debug!("Logging contract test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_upload_mime_mismatch() {
+    use crate::{MimeMismatch, sniff_mime};
+    use std::path::Path;
+
+    setup();
+    debug!("Starting upload MIME mismatch test");
+
+    assert_eq!(sniff_mime(Path::new("photo.bin"), b"\x89PNG\r\n\x1a\n...."), Some("image/png"));
+    assert_eq!(sniff_mime(Path::new("notes.MD"), b"# Notes"), Some("text/markdown"));
+    assert_eq!(sniff_mime(Path::new("data.bin"), b"\x00\x01"), None);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("chart.png");
+    std::fs::write(&path, b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR").unwrap();
+    let path = path.to_string_lossy().into_owned();
+
+    let server = start_mock_server(vec![MockResponse::json(
+        200,
+        r#"{"attachment_url": "https://pfst.cf2.poecdn.net/base/chart", "mime_type": "image/jpeg"}"#,
+    )])
+    .await;
+    let mismatches = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let recorded = mismatches.clone();
+    let client = mock_client(&server)
+        .with_mime_mismatch_handler(move |mismatch| recorded.lock().unwrap().push(mismatch.clone()));
+
+    client.upload_local_file(&path, None).await.unwrap();
+    client.upload_local_file(&path, Some("image/jpeg; q=1")).await.unwrap();
+    assert_eq!(
+        *mismatches.lock().unwrap(),
+        vec![MimeMismatch {
+            file: path.clone(),
+            expected: "image/png".to_string(),
+            reported: "image/jpeg".to_string(),
+        }],
+        "Only the sniffed PNG should be reported; matching declared types are ignored"
+    );
+
+    debug!("Upload MIME mismatch test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_attachment_refresh() {
+    setup();
+    debug!("Starting attachment refresh test");
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, "notes").unwrap();
+    let path = path.to_string_lossy().into_owned();
+
+    let server = start_mock_server(vec![
+        MockResponse::json(200, r#"{"attachment_url": "https://pfst.cf2.poecdn.net/base/old"}"#),
+        MockResponse::json(200, r#"{"attachment_url": "https://pfst.cf2.poecdn.net/base/new"}"#),
+        MockResponse::sse("event: text\ndata: {\"text\": \"Read\"}\n\nevent: done\ndata: {}\n\n"),
+    ])
+    .await;
+    let client = mock_client(&server).with_attachment_ttl(std::time::Duration::ZERO);
+
+    let uploaded = client.upload_local_file(&path, Some("text/plain")).await.unwrap();
+    assert!(uploaded.expires_at.is_some(), "TTL should set an expiry");
+    assert_eq!(
+        uploaded.source,
+        Some(FileUploadRequest::LocalFile {
+            file: path.clone(),
+            mime_type: Some("text/plain".to_string()),
+        })
+    );
+
+    let mut fresh = Attachment {
+        url: "https://pfst.cf2.poecdn.net/base/fresh".to_string(),
+        ..Default::default()
+    };
+    assert!(
+        !client.refresh_attachment(&mut fresh).await.unwrap(),
+        "Attachments without an expiry are never refreshed"
+    );
+
+    let request = ChatRequest {
+        query: vec![ChatMessage {
+            role: "user".to_string(),
+            content: "Read this".to_string(),
+            content_type: "text/markdown".to_string(),
+            attachments: Some(vec![uploaded.to_attachment()]),
+        }],
+        ..Default::default()
+    };
+    let events: Vec<_> = client.stream_request(request).await.unwrap().collect().await;
+    assert_eq!(events.len(), 2);
+
+    {
+        let requests = server.requests.lock().unwrap();
+        assert_eq!(requests.len(), 3, "The stale attachment should be re-uploaded once");
+        let body = captured_json(&requests[2]);
+        assert_eq!(
+            body["query"][0]["attachments"],
+            json!([{ "url": "https://pfst.cf2.poecdn.net/base/new" }]),
+            "Refreshed URL should be sent without local tracking fields"
+        );
+    }
+
+    debug!("Attachment refresh test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_retryable_error_policy() {
+    use crate::{RetryableErrorPolicy, Timer};
+    use std::time::Duration;
+
+    setup();
+    debug!("Starting retryable error policy test");
+
+    let failing = concat!(
+        "event: text\ndata: {\"text\": \"Par\"}\n\n",
+        "event: error\ndata: {\"text\": \"Overloaded\", \"allow_retry\": true}\n\n",
+        "event: text\ndata: {\"text\": \"tial\"}\n\n",
+        "event: done\ndata: {}\n\n",
+    );
+    let succeeding = "event: text\ndata: {\"text\": \"Full\"}\n\nevent: done\ndata: {}\n\n";
+    let request = |policy| ChatRequest {
+        query: vec![ChatMessage {
+            role: "user".to_string(),
+            content: "Hello".to_string(),
+            content_type: "text/markdown".to_string(),
+            attachments: None,
+        }],
+        retryable_error_policy: Some(policy),
+        ..Default::default()
+    };
+    let describe = |item: Result<ChatResponse, PoeError>| match item.unwrap() {
+        ChatResponse {
+            data: Some(ChatResponseData::Text { text }),
+            event,
+        } => format!("{}:{}", event.as_sse_name(), text),
+        response => response.event.as_sse_name().to_string(),
+    };
+
+    let server = start_mock_server(vec![MockResponse::sse(failing)]).await;
+    let events: Vec<String> = mock_client(&server)
+        .stream_request(request(RetryableErrorPolicy::YieldAndContinue))
+        .await
+        .unwrap()
+        .map(describe)
+        .collect()
+        .await;
+    assert_eq!(events, vec!["text:Par", "error", "text:tial", "done"]);
+
+    let events: Vec<String> = mock_client(&server)
+        .stream_request(request(RetryableErrorPolicy::Terminate))
+        .await
+        .unwrap()
+        .map(describe)
+        .collect()
+        .await;
+    assert_eq!(events, vec!["text:Par", "error"], "Stream should end after the error");
+
+    #[derive(Default)]
+    struct RecordingTimer {
+        delays: std::sync::Mutex<Vec<Duration>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Timer for RecordingTimer {
+        async fn sleep(&self, duration: Duration) {
+            self.delays.lock().unwrap().push(duration);
+        }
+    }
+
+    let server = start_mock_server(vec![
+        MockResponse::sse(failing),
+        MockResponse::sse(succeeding),
+    ])
+    .await;
+    let timer = std::sync::Arc::new(RecordingTimer::default());
+    let events: Vec<String> = mock_client(&server)
+        .with_timer(timer.clone())
+        .stream_request(request(RetryableErrorPolicy::Retry { max_retries: 1 }))
+        .await
+        .unwrap()
+        .map(describe)
+        .collect()
+        .await;
+    assert_eq!(
+        events,
+        vec!["text:Par", "replace_response:", "text:Full", "done"],
+        "Retried stream should reset the partial text and continue"
+    );
+    assert_eq!(server.requests.lock().unwrap().len(), 2);
+    let delays = timer.delays.lock().unwrap().clone();
+    assert!(
+        delays.len() == 1 && delays[0] > Duration::ZERO,
+        "Retries without a client retry policy should still back off"
+    );
+
+    // A yielded file cannot be taken back, so the error is yielded instead of retrying
+    let server = start_mock_server(vec![
+        MockResponse::sse(concat!(
+            "event: file\ndata: {\"url\": \"https://example.com/a.png\", \"name\": \"a.png\", ",
+            "\"content_type\": \"image/png\", \"inline_ref\": \"a\"}\n\n",
+            "event: error\ndata: {\"text\": \"Overloaded\", \"allow_retry\": true}\n\n",
+        )),
+        MockResponse::sse(succeeding),
+    ])
+    .await;
+    let events: Vec<String> = mock_client(&server)
+        .with_timer(timer.clone())
+        .stream_request(request(RetryableErrorPolicy::Retry { max_retries: 1 }))
+        .await
+        .unwrap()
+        .map(describe)
+        .collect()
+        .await;
+    assert_eq!(events, vec!["file", "error"]);
+    assert_eq!(server.requests.lock().unwrap().len(), 1);
+
+    let server = start_mock_server(vec![MockResponse::sse(failing)]).await;
+    let events: Vec<String> = mock_client(&server)
+        .with_retry_budget(RetryBudget::new(0, 0.0))
+        .stream_request(request(RetryableErrorPolicy::Retry { max_retries: 3 }))
+        .await
+        .unwrap()
+        .map(describe)
+        .collect()
+        .await;
+    assert_eq!(
+        events,
+        vec!["text:Par", "error", "text:tial", "done"],
+        "An exhausted retry budget should fall back to yielding the error"
+    );
+
+    debug!("Retryable error policy test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_sanitize_text() {
+    use crate::TextSanitizer;
+
+    setup();
+    debug!("Starting text sanitizer test");
+
+    assert_eq!(
+        TextSanitizer::sanitize("\x1b[1;31mRed\x1b[0m\tcell\r\nbell\x07 \x1b]0;title\x07done\u{9b}"),
+        "Red\tcell\nbell done"
+    );
+
+    let mut sanitizer = TextSanitizer::new();
+    assert_eq!(sanitizer.push("Hi \x1b[3"), "Hi ", "Incomplete escape should be held back");
+    assert_eq!(sanitizer.push("2mthere"), "there");
+
+    let server = start_mock_server(vec![MockResponse::sse(concat!(
+        "event: text\ndata: {\"text\": \"A\\u001b[1\"}\n\n",
+        "event: replace_response\ndata: {\"text\": \"B\\u0000\"}\n\n",
+        "event: text\ndata: {\"text\": \"mC\"}\n\n",
+        "event: done\ndata: {}\n\n",
+    ))])
+    .await;
+    let request = ChatRequest {
+        query: vec![ChatMessage {
+            role: "user".to_string(),
+            content: "Hello".to_string(),
+            content_type: "text/markdown".to_string(),
+            attachments: None,
+        }],
+        ..Default::default()
+    };
+    let texts: Vec<String> = mock_client(&server)
+        .stream_request(request)
+        .await
+        .unwrap()
+        .sanitize_text()
+        .filter_map(|item| async move {
+            match item.unwrap().data {
+                Some(ChatResponseData::Text { text }) => Some(text),
+                _ => None,
+            }
+        })
+        .collect()
+        .await;
+    assert_eq!(
+        texts,
+        vec!["A", "B", "mC"],
+        "replace_response should discard a held-back escape"
+    );
+
+    debug!("Text sanitizer test completed");
+}
+
+#[cfg(feature = "markdown")]
+#[test_log::test(tokio::test)]
+async fn test_markdown_renderer() {
+    use crate::{MarkdownOutput, MarkdownRenderer};
+
+    setup();
+    debug!("Starting markdown renderer test");
+
+    let mut renderer = MarkdownRenderer::new(MarkdownOutput::Html);
+    assert!(renderer.push("# Ti").is_empty());
+    assert!(renderer.push("tle\n").is_empty(), "A block ends at a blank line");
+    assert_eq!(renderer.push("\nSome **bold"), vec!["<h1>Title</h1>\n"]);
+    assert_eq!(renderer.pending(), "Some **bold");
+
+    // Blank lines inside a fenced code block do not end the block
+    assert_eq!(
+        renderer.push("** text\n\n```rust\nfn main() {\n\n"),
+        vec!["<p>Some <strong>bold</strong> text</p>\n"]
+    );
+    assert_eq!(
+        renderer.push("}\n```\n| a |\n|---|\n| 1 |"),
+        vec!["<pre><code class=\"language-rust\">fn main() {\n\n}\n</code></pre>\n"]
+    );
+    assert_eq!(
+        renderer.finish().unwrap(),
+        "<table><thead><tr><th>a</th></tr></thead><tbody>\n<tr><td>1</td></tr>\n</tbody></table>\n"
+    );
+    assert!(renderer.finish().is_none());
+
+    let mut renderer = MarkdownRenderer::new(MarkdownOutput::Ansi);
+    assert!(renderer.push("stale").is_empty());
+    let segments = renderer.replace("- one\n- `two`\n\n");
+    assert_eq!(segments, vec!["• one\n• \x1b[36mtwo\x1b[0m\n"]);
+    assert_eq!(renderer.pending(), "");
+
+    debug!("Markdown renderer test completed");
+}
+
+#[cfg(feature = "image")]
+#[test_log::test(tokio::test)]
+async fn test_upload_image() {
+    setup();
+    debug!("Starting image upload test");
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("photo.png");
+    ::image::RgbImage::from_fn(400, 200, |x, y| {
+        ::image::Rgb([(x % 256) as u8, (y % 256) as u8, ((x * y) % 256) as u8])
+    })
+    .save(&path)
+    .unwrap();
+    let path = path.to_string_lossy().into_owned();
+
+    let server = start_mock_server(vec![MockResponse::json(
+        200,
+        r#"{"attachment_url": "https://pfst.cf2.poecdn.net/base/photo", "mime_type": "image/jpeg"}"#,
+    )])
+    .await;
+    let uploaded = mock_client(&server)
+        .upload_image(&path, 100, 80)
+        .await
+        .expect("Image upload should succeed");
+
+    assert_eq!((uploaded.width, uploaded.height), (100, 50));
+    assert_eq!(uploaded.original_size, std::fs::metadata(&path).unwrap().len());
+    assert!(uploaded.uploaded_size < uploaded.original_size);
+    assert_eq!(
+        uploaded.saved_bytes(),
+        uploaded.original_size - uploaded.uploaded_size
+    );
+    assert_eq!(
+        uploaded.upload.attachment_url,
+        "https://pfst.cf2.poecdn.net/base/photo"
+    );
+    {
+        let requests = server.requests.lock().unwrap();
+        assert!(requests[0].contains("filename=\"photo.jpeg\""));
+        assert!(requests[0].contains("image/jpeg"));
+    }
+
+    // Images that already fit are not re-encoded when that would not shrink them
+    let small = crate::compress_image(&std::fs::read(&path).unwrap(), 1000, 100).unwrap();
+    assert!(!small.resized);
+    assert_eq!((small.width, small.height), (400, 200));
+
+    let invalid = crate::compress_image(b"not an image", 100, 80);
+    assert!(matches!(invalid, Err(PoeError::UnsupportedFileType(_))));
+
+    debug!("Image upload test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_upload_checksums() {
+    setup();
+    debug!("Starting upload checksum test");
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("report.txt");
+    std::fs::write(&path, b"abc").unwrap();
+    let path = path.to_string_lossy().into_owned();
+    let abc = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    let server = start_mock_server(vec![
+        MockResponse::json(200, r#"{"attachment_url": "https://pfst.cf2.poecdn.net/base/a"}"#),
+        MockResponse::json(
+            200,
+            r#"{"attachment_url": "https://pfst.cf2.poecdn.net/base/b", "sha256": "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD"}"#,
+        ),
+        MockResponse::json(
+            200,
+            r#"{"attachment_url": "https://pfst.cf2.poecdn.net/base/c", "sha256": "0000"}"#,
+        ),
+    ])
+    .await;
+    let client = mock_client(&server);
+
+    let upload = client.upload_local_file(&path, Some("text/plain")).await.unwrap();
+    assert_eq!(upload.sha256.as_deref(), Some(abc));
+    let upload = client.upload_local_file(&path, Some("text/plain")).await.unwrap();
+    assert_eq!(
+        upload.sha256.as_deref(),
+        Some(abc),
+        "Server hash should match case-insensitively"
+    );
+    let mismatch = client.upload_local_file(&path, Some("text/plain")).await;
+    assert!(matches!(
+        mismatch,
+        Err(PoeError::ChecksumMismatch { ref expected, ref actual, .. })
+            if expected == abc && actual == "0000"
+    ));
+
+    let server = start_mock_server(vec![MockResponse {
+        status: 200,
+        content_type: "application/octet-stream",
+        headers: Vec::new(),
+        body: "abc".to_string(),
+    }])
+    .await;
+    let client = mock_client(&server);
+    let url = format!("{}/file", server.url);
+    assert_eq!(client.download_file(&url, Some(abc)).await.unwrap(), b"abc");
+    assert_eq!(client.download_file(&url, None).await.unwrap(), b"abc");
+    let corrupted = client.download_file(&url, Some(&"0".repeat(64))).await;
+    assert!(matches!(
+        corrupted,
+        Err(PoeError::ChecksumMismatch { ref actual, .. }) if actual == abc
+    ));
+
+    debug!("Upload checksum test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_poe_api_trait() {
+    setup();
+    debug!("Starting PoeApi trait test");
+
+    async fn ask(api: &dyn PoeApi, question: &str) -> String {
+        let request = ChatRequest {
+            query: vec![ChatMessage {
+                role: "user".to_string(),
+                content: question.to_string(),
+                content_type: "text/markdown".to_string(),
+                attachments: None,
+            }],
+            ..Default::default()
+        };
+        let mut stream = api.stream_request(request).await.unwrap();
+        let mut text = String::new();
+        while let Some(item) = stream.next().await {
+            if let Some(ChatResponseData::Text { text: delta }) = item.unwrap().data {
+                text.push_str(&delta);
+            }
+        }
+        text
+    }
+
+    let mock = MockPoeClient::new().with_text("first").with_text("again");
+    assert_eq!(ask(&mock, "one").await, "first");
+    assert_eq!(ask(&mock, "two").await, "again");
+    assert_eq!(ask(&mock, "three").await, "again", "Last response should repeat");
+    let contents: Vec<String> = mock
+        .requests()
+        .into_iter()
+        .map(|request| request.query[0].content.clone())
+        .collect();
+    assert_eq!(contents, vec!["one", "two", "three"]);
+
+    let api: std::sync::Arc<dyn PoeApi> = std::sync::Arc::new(mock);
+    let upload = api
+        .upload_local_file("notes.txt", Some("text/plain"))
+        .await
+        .unwrap();
+    assert_eq!(upload.attachment_url, "https://mock.poe.invalid/attachments/1");
+    assert!(api.upload_remote_file("not a url").await.is_err());
+    assert!(api.get_v1_model_list().await.unwrap().data.is_empty());
+
+    let server = start_mock_server(vec![MockResponse::sse(concat!(
+        "event: text\ndata: {\"text\": \"from server\"}\n\n",
+        "event: done\ndata: {}\n\n",
+    ))])
+    .await;
+    let client: Box<dyn PoeApi> = Box::new(mock_client(&server));
+    assert_eq!(ask(client.as_ref(), "hi").await, "from server");
+
+    debug!("PoeApi trait test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_decorator_clients() {
+    use crate::{ChatStream, FileUploadResponse, ModelResponse};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
+    setup();
+    debug!("Starting decorator clients test");
+
+    // Fails the first `failures` chat requests with a transport error
+    struct FlakyApi {
+        mock: MockPoeClient,
+        failures: AtomicU32,
+        calls: AtomicU32,
+    }
+
+    #[async_trait::async_trait]
+    impl PoeApi for FlakyApi {
+        async fn stream_request<'a>(
+            &'a self,
+            request: ChatRequest,
+        ) -> Result<ChatStream<'a>, PoeError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.failures.load(Ordering::SeqCst) > 0 {
+                self.failures.fetch_sub(1, Ordering::SeqCst);
+                return Err(PoeError::TransportError("connection reset".to_string()));
+            }
+            self.mock.stream_request(request).await
+        }
+
+        async fn upload_local_file(
+            &self,
+            file_path: &str,
+            mime_type: Option<&str>,
+        ) -> Result<FileUploadResponse, PoeError> {
+            self.mock.upload_local_file(file_path, mime_type).await
+        }
+
+        async fn upload_remote_file(
+            &self,
+            download_url: &str,
+        ) -> Result<FileUploadResponse, PoeError> {
+            self.mock.upload_remote_file(download_url).await
+        }
+
+        async fn get_v1_model_list(&self) -> Result<ModelResponse, PoeError> {
+            Err(PoeError::BotError("invalid access key".to_string()))
+        }
+    }
+
+    let request = |content: &str| ChatRequest {
+        query: vec![ChatMessage {
+            role: "user".to_string(),
+            content: content.to_string(),
+            content_type: "text/markdown".to_string(),
+            attachments: None,
+        }],
+        ..Default::default()
+    };
+    async fn collect(stream: ChatStream<'_>) -> Vec<String> {
+        stream
+            .filter_map(|item| async move {
+                match item.unwrap().data {
+                    Some(ChatResponseData::Text { text }) => Some(text),
+                    _ => None,
+                }
+            })
+            .collect()
+            .await
+    }
+
+    let flaky = FlakyApi {
+        mock: MockPoeClient::new().with_text("cached answer"),
+        failures: AtomicU32::new(2),
+        calls: AtomicU32::new(0),
+    };
+    let api = ThrottledPoeClient::new(
+        RetryPoeClient::new(CachedPoeClient::new(flaky), 3)
+            .with_backoff(Duration::from_millis(1), Duration::from_millis(5)),
+        1,
+    );
+
+    let first = api.stream_request(request("hi")).await.unwrap();
+    assert_eq!(collect(first).await, vec!["cached answer"]);
+    let second = api.stream_request(request("hi")).await.unwrap();
+    assert_eq!(collect(second).await, vec!["cached answer"]);
+    let other = api.stream_request(request("other")).await.unwrap();
+    drop(other);
+    assert!(
+        matches!(api.get_v1_model_list().await, Err(PoeError::BotError(_))),
+        "Non-transient errors should not be retried"
+    );
+
+    assert_eq!(
+        api.inner().inner().inner().calls.load(Ordering::SeqCst),
+        4,
+        "Two retried failures, one cache miss per distinct request"
+    );
+
+    // With one slot, a live stream blocks the next call until it is dropped
+    let throttled = ThrottledPoeClient::new(MockPoeClient::new().with_text("slow"), 1);
+    let held = throttled.stream_request(request("a")).await.unwrap();
+    assert!(
+        tokio::time::timeout(Duration::from_millis(50), throttled.stream_request(request("b")))
+            .await
+            .is_err()
+    );
+    drop(held);
+    assert!(
+        tokio::time::timeout(Duration::from_millis(50), throttled.stream_request(request("b")))
+            .await
+            .is_ok()
+    );
+
+    let budget = std::sync::Arc::new(RetryBudget::new(0, 0.0));
+    let exhausted = RetryPoeClient::new(
+        FlakyApi {
+            mock: MockPoeClient::new(),
+            failures: AtomicU32::new(1),
+            calls: AtomicU32::new(0),
+        },
+        3,
+    )
+    .with_retry_budget(budget.clone());
+    assert!(matches!(
+        exhausted.stream_request(request("hi")).await,
+        Err(PoeError::TransportError(_))
+    ));
+    assert_eq!(budget.stats().exhausted, 1);
+
+    debug!("Decorator clients test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_runtime_reconfiguration() {
+    setup();
+    debug!("Starting runtime reconfiguration test");
+
+    let server = start_mock_server(vec![MockResponse::sse("event: done\ndata: {}\n\n")]).await;
+    let client = PoeClient::new(
+        "TestBot",
+        "old_key",
+        &format!("{}/", server.url),
+        &format!("{}/file_upload/", server.url),
+    );
+    assert_eq!(client.get_bot_name(), "TestBot");
+    assert_eq!(client.get_base_url(), server.url);
+    assert_eq!(client.get_file_upload_url(), format!("{}/file_upload", server.url));
+
+    let other = client.clone().with_bot("OtherBot").with_access_key("new_key");
+    assert_eq!(other.get_bot_name(), "OtherBot");
+    assert_eq!(client.get_bot_name(), "TestBot", "The original client is unchanged");
+
+    let request = ChatRequest {
+        query: vec![ChatMessage {
+            role: "user".to_string(),
+            content: "Hello".to_string(),
+            content_type: "text/markdown".to_string(),
+            attachments: None,
+        }],
+        ..Default::default()
+    };
+    let _ = other
+        .stream_request(request)
+        .await
+        .unwrap()
+        .collect::<Vec<_>>()
+        .await;
+    {
+        let requests = server.requests.lock().unwrap();
+        assert!(requests[0].starts_with("POST /bot/OtherBot "));
+        assert!(requests[0].to_lowercase().contains("authorization: bearer new_key"));
+    }
+
+    debug!("Runtime reconfiguration test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_wire_snapshots() {
+    use crate::{
+        DoneInfo, FileUploadResponse, GraphqlError, MetaData, ModelResponse, ToolChoice, Usage,
+    };
+    use insta::assert_json_snapshot;
+
+    setup();
+    debug!("Starting wire snapshot test");
+
+    let message = ChatMessage {
+        role: "user".to_string(),
+        content: "Hi".to_string(),
+        content_type: "text/markdown".to_string(),
+        attachments: None,
+    };
+    let minimal = ChatRequest {
+        query: vec![message.clone()],
+        conversation_id: "conv-1".to_string(),
+        message_id: "msg-1".to_string(),
+        ..Default::default()
+    };
+    assert_json_snapshot!("request_minimal", minimal);
+
+    let tool = ChatTool {
+        r#type: "function".to_string(),
+        function: FunctionDefinition {
+            name: "get_weather".to_string(),
+            description: None,
+            parameters: Some(FunctionParameters {
+                r#type: "object".to_string(),
+                properties: serde_json::json!({"city": {"type": "string"}}),
+                required: vec!["city".to_string()],
+            }),
+        },
+    };
+    let tool_call = ChatToolCall {
+        id: "call_1".to_string(),
+        r#type: "function".to_string(),
+        function: FunctionCall {
+            name: "get_weather".to_string(),
+            arguments: r#"{"city":"Taipei"}"#.to_string(),
+        },
+        inferred: false,
+        choice_index: 0,
+    };
+    let tool_result = ChatToolResult {
+        role: "tool".to_string(),
+        tool_call_id: "call_1".to_string(),
+        name: "get_weather".to_string(),
+        content: "Sunny".to_string(),
+    };
+    let full = ChatRequest {
+        query: vec![ChatMessage {
+            attachments: Some(vec![Attachment {
+                url: "https://pfst.cf2.poecdn.net/base/a".to_string(),
+                content_type: Some("image/png".to_string()),
+                expires_at: Some(1),
+                source: Some(FileUploadRequest::RemoteFile {
+                    download_url: "https://example.com/a.png".to_string(),
+                }),
+            }]),
+            ..message.clone()
+        }],
+        user_id: "u-1".to_string(),
+        conversation_id: "conv-1".to_string(),
+        message_id: "msg-1".to_string(),
+        tools: Some(vec![tool]),
+        tool_calls: Some(vec![tool_call.clone()]),
+        tool_results: Some(vec![tool_result]),
+        temperature: Some(0.5),
+        logit_bias: Some(LogitBias::TokenIds([(42, -100.0)].into_iter().collect())),
+        stop_sequences: Some(vec!["END".to_string()]),
+        seed: Some(7),
+        tool_choice: Some(ToolChoice::Function("get_weather".to_string())),
+        ..Default::default()
+    };
+    assert_json_snapshot!("request_full", full);
+
+    assert_json_snapshot!(
+        "tool_choice",
+        [
+            ToolChoice::Auto,
+            ToolChoice::None,
+            ToolChoice::Required,
+            ToolChoice::Function("get_weather".to_string()),
+        ]
+    );
+
+    let extra = |key: &str, value: serde_json::Value| {
+        let mut extra = serde_json::Map::new();
+        extra.insert(key.to_string(), value);
+        extra
+    };
+    let responses = [
+        ChatResponse {
+            event: ChatEventType::Text,
+            data: Some(ChatResponseData::Text { text: "Hi".to_string() }),
+        },
+        ChatResponse {
+            event: ChatEventType::Error,
+            data: Some(ChatResponseData::Error {
+                text: "Overloaded".to_string(),
+                allow_retry: true,
+            }),
+        },
+        ChatResponse {
+            event: ChatEventType::Json,
+            data: Some(ChatResponseData::ToolCalls(vec![tool_call])),
+        },
+        ChatResponse {
+            event: ChatEventType::File,
+            data: Some(ChatResponseData::File(FileData {
+                url: "https://pfst.cf2.poecdn.net/base/f".to_string(),
+                name: "f.png".to_string(),
+                content_type: "image/png".to_string(),
+                inline_ref: "ref_1".to_string(),
+            })),
+        },
+        ChatResponse {
+            event: ChatEventType::Meta,
+            data: Some(ChatResponseData::Meta(MetaData {
+                content_type: Some("text/markdown".to_string()),
+                extra: extra("refetch_settings", serde_json::json!(true)),
+                ..Default::default()
+            })),
+        },
+        ChatResponse {
+            event: ChatEventType::SuggestedReply,
+            data: Some(ChatResponseData::SuggestedReply {
+                text: "Tell me more".to_string(),
+            }),
+        },
+        ChatResponse {
+            event: ChatEventType::Usage,
+            data: Some(ChatResponseData::Usage(Usage {
+                prompt_tokens: Some(12),
+                points: Some(1.5),
+                ..Default::default()
+            })),
+        },
+        ChatResponse {
+            event: ChatEventType::Unknown("x_trace".to_string()),
+            data: Some(ChatResponseData::Raw(serde_json::json!({"span": "a1"}))),
+        },
+        ChatResponse {
+            event: ChatEventType::Done,
+            data: Some(ChatResponseData::Done(DoneInfo {
+                message_id: Some("msg_42".to_string()),
+                extra: extra("cost", serde_json::json!(7)),
+                ..Default::default()
+            })),
+        },
+        ChatResponse {
+            event: ChatEventType::Done,
+            data: Some(ChatResponseData::Empty),
+        },
+        ChatResponse {
+            event: ChatEventType::Done,
+            data: None,
+        },
+    ];
+    assert_json_snapshot!("chat_responses", responses);
+
+    assert_json_snapshot!(
+        "file_upload_requests",
+        [
+            FileUploadRequest::LocalFile {
+                file: "a.txt".to_string(),
+                mime_type: None,
+            },
+            FileUploadRequest::RemoteFile {
+                download_url: "https://example.com/a.txt".to_string(),
+            },
+        ]
+    );
+
+    // Unknown fields are ignored, absent optional fields stay omitted on re-serialization
+    let upload: FileUploadResponse = serde_json::from_str(
+        r#"{"attachment_url":"https://pfst.cf2.poecdn.net/base/a","mime_type":"text/plain","extra":1}"#,
+    )
+    .unwrap();
+    assert_json_snapshot!("file_upload_response", upload);
+
+    let models: ModelResponse = serde_json::from_str(
+        r#"{"object":"list","data":[{"id":"Claude-Sonnet-4","object":"model","created":1,"owned_by":"Anthropic","pricing":null}]}"#,
+    )
+    .unwrap();
+    assert_json_snapshot!("model_response", models);
+
+    assert_json_snapshot!(
+        "graphql_error",
+        GraphqlError {
+            message: "PersistedQueryNotFound".to_string(),
+            code: None,
+            path: None,
+        }
+    );
+
+    debug!("Wire snapshot test completed");
+}
+
+#[test]
+fn test_chat_response_roundtrip() {
+    use crate::{DoneInfo, MetaData, Usage};
+
+    setup();
+    debug!("Starting response round-trip test");
+
+    // Data is untagged on the wire; the event decides which variant it reads back as
+    let responses = [
+        (ChatEventType::Text, ChatResponseData::Text { text: "Hi".to_string() }),
+        (
+            ChatEventType::Error,
+            ChatResponseData::Error {
+                text: "Overloaded".to_string(),
+                allow_retry: true,
+            },
+        ),
+        (
+            ChatEventType::Json,
+            ChatResponseData::ToolCalls(vec![ChatToolCall {
+                id: "call_1".to_string(),
+                r#type: "function".to_string(),
+                function: FunctionCall {
+                    name: "get_weather".to_string(),
+                    arguments: "{}".to_string(),
+                },
+                inferred: false,
+                choice_index: 0,
+            }]),
+        ),
+        (
+            ChatEventType::Json,
+            ChatResponseData::Text {
+                text: r#"{"choices":[]}"#.to_string(),
+            },
+        ),
+        (
+            ChatEventType::File,
+            ChatResponseData::File(FileData {
+                url: "https://pfst.cf2.poecdn.net/base/f".to_string(),
+                name: "f.png".to_string(),
+                content_type: "image/png".to_string(),
+                inline_ref: "ref_1".to_string(),
+            }),
+        ),
+        (
+            ChatEventType::Done,
+            ChatResponseData::Done(DoneInfo {
+                message_id: Some("msg_1".to_string()),
+                ..Default::default()
+            }),
+        ),
+        (
+            ChatEventType::Meta,
+            ChatResponseData::Meta(MetaData {
+                content_type: Some("text/markdown".to_string()),
+                linkify: Some(true),
+                ..Default::default()
+            }),
+        ),
+        (
+            ChatEventType::SuggestedReply,
+            ChatResponseData::SuggestedReply {
+                text: "Tell me more".to_string(),
+            },
+        ),
+        (
+            ChatEventType::Usage,
+            ChatResponseData::Usage(Usage {
+                prompt_tokens: Some(10),
+                completion_tokens: Some(5),
+                ..Default::default()
+            }),
+        ),
+        (ChatEventType::Done, ChatResponseData::Empty),
+        (ChatEventType::Ping, ChatResponseData::KeepAlive),
+        (
+            ChatEventType::Unknown("x_trace".to_string()),
+            ChatResponseData::Raw(serde_json::json!({"text": "raw"})),
+        ),
+        (
+            ChatEventType::Unknown("x_trace".to_string()),
+            ChatResponseData::Raw(serde_json::json!({})),
+        ),
+    ];
+    for (event, data) in responses {
+        let response = ChatResponse {
+            event,
+            data: Some(data),
+        };
+        let json = serde_json::to_value(&response).unwrap();
+        let parsed: ChatResponse = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(parsed.event, response.event);
+        assert_eq!(
+            std::mem::discriminant(parsed.data.as_ref().unwrap()),
+            std::mem::discriminant(response.data.as_ref().unwrap()),
+            "{} should read back as the same variant",
+            json
+        );
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), json);
+    }
+
+    // A missing payload stays None
+    let parsed: ChatResponse = serde_json::from_str(r#"{"event":"Done"}"#).unwrap();
+    assert!(parsed.data.is_none());
+
+    debug!("Response round-trip test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_conversation_store() {
+    use crate::{ConversationStore, InMemoryConversationStore};
+
+    setup();
+    debug!("Starting conversation store test");
+
+    let store = InMemoryConversationStore::new();
+    let mut conversation = Conversation::new().with_id("conv-stored");
+    conversation.push_user_message("Hello");
+    store.save_conversation(&conversation).await.unwrap();
+    store
+        .append_event(
+            "conv-stored",
+            &ChatResponse {
+                event: ChatEventType::Text,
+                data: Some(ChatResponseData::Text {
+                    text: "Hi there".to_string(),
+                }),
+            },
+        )
+        .await
+        .unwrap();
+    let upload = serde_json::from_str(r#"{"attachment_url": "https://pfst.cf2.poecdn.net/base/a"}"#)
+        .unwrap();
+    store.record_upload("conv-stored", &upload).await.unwrap();
+
+    let loaded = store.load_conversation("conv-stored").await.unwrap().unwrap();
+    assert_eq!(loaded.messages().len(), 1);
+    assert_eq!(loaded.messages()[0].content, "Hello");
+    assert_eq!(store.events("conv-stored").await.unwrap().len(), 1);
+    assert_eq!(
+        store.uploads("conv-stored").await.unwrap()[0].attachment_url,
+        "https://pfst.cf2.poecdn.net/base/a"
+    );
+
+    assert!(store.load_conversation("missing").await.unwrap().is_none());
+    assert!(store.events("missing").await.unwrap().is_empty());
+    assert!(store.delete_conversation("conv-stored").await.unwrap());
+    assert!(!store.delete_conversation("conv-stored").await.unwrap());
+    assert!(store.load_conversation("conv-stored").await.unwrap().is_none());
+
+    debug!("Conversation store test completed");
+}
+
+#[cfg(feature = "history-sqlite")]
+#[test_log::test(tokio::test)]
+async fn test_sqlite_conversation_store() {
+    use crate::{ConversationStore, SqliteConversationStore};
+
+    setup();
+    debug!("Starting SQLite conversation store test");
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("history.db");
+    let store = SqliteConversationStore::open(&path).unwrap();
+    assert_eq!(store.schema_version().unwrap(), 1, "Migrations should run on open");
+
+    let mut conversation = Conversation::new().with_id("conv-sql").with_user_id("u-1");
+    conversation.push_user_message("Weather in Paris?");
+    conversation.push_tool_exchange(
+        vec![ChatToolCall {
+            id: "call_1".to_string(),
+            r#type: "function".to_string(),
+            function: FunctionCall {
+                name: "get_weather".to_string(),
+                arguments: "{\"city\":\"Paris\"}".to_string(),
+            },
+            inferred: false,
+            choice_index: 0,
+        }],
+        Vec::new(),
+    );
+    conversation.push_bot_file(&FileData {
+        url: "https://pfst.cf2.poecdn.net/base/map".to_string(),
+        name: "map.png".to_string(),
+        content_type: "image/png".to_string(),
+        inline_ref: String::new(),
+    });
+    conversation.push_bot_message("Sunny");
+    store.save_conversation(&conversation).await.unwrap();
+
+    let events = [
+        ChatResponse {
+            event: ChatEventType::Text,
+            data: Some(ChatResponseData::Text { text: "Sunny".to_string() }),
+        },
+        ChatResponse {
+            event: ChatEventType::SuggestedReply,
+            data: Some(ChatResponseData::SuggestedReply {
+                text: "And tomorrow?".to_string(),
+            }),
+        },
+        ChatResponse {
+            event: ChatEventType::Done,
+            data: Some(ChatResponseData::Empty),
+        },
+    ];
+    for event in &events {
+        store.append_event("conv-sql", event).await.unwrap();
+    }
+    let upload = serde_json::from_str(
+        r#"{"attachment_url": "https://pfst.cf2.poecdn.net/base/a", "sha256": "ab"}"#,
+    )
+    .unwrap();
+    store.record_upload("conv-sql", &upload).await.unwrap();
+    drop(store);
+
+    // Reopening keeps the data and does not re-run migrations
+    let store = SqliteConversationStore::open(&path).unwrap();
+    assert_eq!(store.schema_version().unwrap(), 1);
+    let loaded = store.load_conversation("conv-sql").await.unwrap().unwrap();
+    assert_eq!(loaded.id(), "conv-sql");
+    assert_eq!(loaded.user_id(), "u-1");
+    assert_eq!(loaded.messages().len(), 2);
+    assert_eq!(loaded.tool_exchanges()[0].tool_calls[0].function.name, "get_weather");
+    assert_eq!(loaded.attachments(), conversation.attachments());
+
+    let stored = store.events("conv-sql").await.unwrap();
+    assert_eq!(stored.len(), 3);
+    assert!(matches!(
+        &stored[1].data,
+        Some(ChatResponseData::SuggestedReply { text }) if text == "And tomorrow?"
+    ));
+    assert!(matches!(stored[2].data, Some(ChatResponseData::Empty)));
+    let uploads = store.uploads("conv-sql").await.unwrap();
+    assert_eq!(uploads[0].sha256.as_deref(), Some("ab"));
+
+    // Saving again replaces the stored state
+    conversation.push_user_message("Thanks");
+    store.save_conversation(&conversation).await.unwrap();
+    let loaded = store.load_conversation("conv-sql").await.unwrap().unwrap();
+    assert_eq!(loaded.messages().len(), 3);
+
+    assert!(store.load_conversation("missing").await.unwrap().is_none());
+    assert!(store.events("missing").await.unwrap().is_empty());
+    assert!(store.delete_conversation("conv-sql").await.unwrap());
+    assert!(!store.delete_conversation("conv-sql").await.unwrap());
+    assert!(store.events("conv-sql").await.unwrap().is_empty());
+    assert!(store.uploads("conv-sql").await.unwrap().is_empty());
+
+    // In-memory databases are private to their store
+    let memory = SqliteConversationStore::open_in_memory().unwrap();
+    assert!(memory.load_conversation("conv-sql").await.unwrap().is_none());
+
+    debug!("SQLite conversation store test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_client_pool() {
+    use crate::PoeClientPool;
+
+    setup();
+    debug!("Starting client pool test");
+
+    let pool = PoeClientPool::new(
+        PoeClient::new("DefaultBot", "", "http://127.0.0.1:9", "http://127.0.0.1:9/upload"),
+        2,
+    );
+    let client = pool.get("key-a", "BotA");
+    assert_eq!(client.get_bot_name(), "BotA");
+    assert_eq!(client.get_base_url(), "http://127.0.0.1:9");
+
+    pool.get("key-b", "BotA");
+    pool.get("key-a", "BotA"); // refresh key-a
+    pool.get("key-a", "BotB"); // evicts key-b, the least recently used
+    assert_eq!(pool.len(), 2);
+    assert_eq!(pool.remove_access_key("key-b"), 0, "key-b should have been evicted");
+    assert_eq!(pool.remove_access_key("key-a"), 2);
+    assert!(pool.is_empty());
+
+    debug!("Client pool test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_request_scheduler() {
+    use crate::{RequestPriority, RequestScheduler, ScheduledPoeClient};
+    use std::time::Duration;
+
+    setup();
+    debug!("Starting request scheduler test");
+
+    let scheduler = RequestScheduler::new(1);
+    let background = scheduler.acquire(RequestPriority::Background).await;
+    assert!(!background.should_yield());
+
+    let order = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut waiting = Vec::new();
+    for (name, priority) in [
+        ("batch", RequestPriority::Background),
+        ("normal", RequestPriority::Normal),
+        ("interactive", RequestPriority::Interactive),
+    ] {
+        let scheduler = scheduler.clone();
+        let order = order.clone();
+        waiting.push(tokio::spawn(async move {
+            let _permit = scheduler.acquire(priority).await;
+            order.lock().unwrap().push(name);
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(scheduler.queued(), 3);
+
+    // A cancelled waiter does not take a slot
+    let cancelled = tokio::time::timeout(
+        Duration::from_millis(10),
+        scheduler.acquire(RequestPriority::Interactive),
+    )
+    .await;
+    assert!(cancelled.is_err());
+
+    tokio::time::timeout(Duration::from_millis(100), background.preempted())
+        .await
+        .expect("Background work should be told to yield");
+    assert!(background.should_yield());
+    drop(background);
+    for task in waiting {
+        task.await.unwrap();
+    }
+    assert_eq!(*order.lock().unwrap(), vec!["interactive", "normal", "batch"]);
+    assert_eq!(scheduler.running(), 0);
+
+    let client = ScheduledPoeClient::new(MockPoeClient::new().with_text("ok"), scheduler.clone());
+    let request = ChatRequest {
+        priority: RequestPriority::Interactive,
+        ..Default::default()
+    };
+    let stream = client.stream_request(request).await.unwrap();
+    assert_eq!(scheduler.running(), 1, "The stream holds its slot");
+    drop(stream);
+    assert_eq!(scheduler.running(), 0);
+
+    debug!("Request scheduler test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_done_event_payload() {
+    setup();
+    debug!("Starting done event payload test");
+
+    let events = replay_fixture(concat!(
+        "event: text\ndata: {\"text\": \"Hi\"}\n\n",
+        "event: done\ndata: {\"message_id\": \"m-42\", \"prompt_tokens\": 12, ",
+        "\"completion_tokens\": 3, \"cost\": 7}\n\n",
+    ))
+    .await;
+    let Some(ChatResponseData::Done(info)) = &events.last().unwrap().data else {
+        panic!("Done payload should be parsed into DoneInfo");
+    };
+    assert_eq!(info.message_id.as_deref(), Some("m-42"));
+    assert_eq!(info.prompt_tokens, Some(12));
+    assert_eq!(info.completion_tokens, Some(3));
+    assert_eq!(info.extra.get("cost"), Some(&serde_json::json!(7)));
+
+    let frame = events.last().unwrap().to_sse_frame();
+    assert!(frame.contains("\"message_id\":\"m-42\""), "SSE frame should keep the payload");
+    assert!(frame.contains("\"cost\":7"));
+
+    for payload in ["{}", "null"] {
+        let events = replay_fixture(&format!(
+            "event: text\ndata: {{\"text\": \"Hi\"}}\n\nevent: done\ndata: {}\n\n",
+            payload
+        ))
+        .await;
+        assert!(
+            matches!(events.last().unwrap().data, Some(ChatResponseData::Empty)),
+            "Done without payload should stay Empty: {:?}",
+            payload
+        );
+    }
+
+    let decoded: ChatResponse =
+        serde_json::from_str(r#"{"event":"Done","data":{"message_id":"m-1"}}"#).unwrap();
+    assert!(matches!(
+        decoded.data,
+        Some(ChatResponseData::Done(ref info)) if info.message_id.as_deref() == Some("m-1")
+    ));
+
+    debug!("Done event payload test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_collect_response_partial() {
+    setup();
+    debug!("Starting partial response test");
+
+    let server = start_mock_server(vec![MockResponse::sse(concat!(
+        "event: text\ndata: {\"text\": \"Hel\"}\n\n",
+        "event: file\ndata: {\"url\": \"https://example.com/a.png\", \"name\": \"a.png\", ",
+        "\"content_type\": \"image/png\", \"inline_ref\": \"a\"}\n\n",
+        "event: text\ndata: {\"text\": \"lo\"}\n\n",
+        "event: error\ndata: {\"text\": \"overloaded\", \"allow_retry\": true}\n\n",
+    ))])
+    .await;
+    let client = mock_client(&server);
+    let error = client
+        .stream_request(Conversation::new().to_request())
+        .await
+        .unwrap()
+        .collect_response()
+        .await
+        .expect_err("Error event should fail the response");
+    assert!(matches!(error.error, PoeError::BotError(ref message) if message == "overloaded"));
+    assert_eq!(error.partial.text, "Hello");
+    assert_eq!(error.partial.files.len(), 1);
+    assert!(!error.partial.completed);
+
+    let server = start_mock_server(vec![MockResponse::sse(
+        "event: text\ndata: {\"text\": \"Cut\"}\n\n",
+    )])
+    .await;
+    let error = mock_client(&server)
+        .stream_request(Conversation::new().to_request())
+        .await
+        .unwrap()
+        .collect_response()
+        .await
+        .expect_err("Truncated stream should fail the response");
+    assert!(matches!(error.error, PoeError::StreamTruncated));
+    assert_eq!(error.partial.text, "Cut");
+    assert!(matches!(PoeError::from(error), PoeError::StreamTruncated));
+
+    let response = replay_fixture(concat!(
+        "event: text\ndata: {\"text\": \"Draft\"}\n\n",
+        "event: replace_response\ndata: {\"text\": \"Final\"}\n\n",
+        "event: done\ndata: {\"message_id\": \"m-7\"}\n\n",
+    ))
+    .await
+    .into_iter()
+    .try_fold(ResponseAggregator::new(), |mut aggregator, event| {
+        aggregator.push(event).map(|_| aggregator)
+    })
+    .unwrap()
+    .into_response();
+    assert_eq!(response.text, "Final");
+    assert!(response.completed);
+    assert_eq!(response.done.and_then(|done| done.message_id).as_deref(), Some("m-7"));
+
+    debug!("Partial response test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_tool_parameter_defaults() {
+    setup();
+    debug!("Starting tool parameter defaults test");
+
+    let definition = FunctionDefinition {
+        name: "search".to_string(),
+        description: None,
+        parameters: Some(FunctionParameters {
+            r#type: "object".to_string(),
+            properties: json!({
+                "query": { "type": "string", "default": "ignored" },
+                "limit": { "type": "integer", "default": 10 },
+                "lang": { "type": "string" }
+            }),
+            required: vec!["query".to_string()],
+        }),
+    };
+
+    struct EchoTool(FunctionDefinition);
+
+    #[async_trait::async_trait]
+    impl crate::Tool for EchoTool {
+        fn definition(&self) -> ChatTool {
+            ChatTool {
+                r#type: "function".to_string(),
+                function: self.0.clone(),
+            }
+        }
+
+        async fn call(&self, arguments: serde_json::Value) -> Result<String, PoeError> {
+            Ok(arguments.to_string())
+        }
+    }
+
+    let registry = crate::ToolRegistry::new().register(EchoTool(definition.clone()));
+    let call = |arguments: serde_json::Value| ChatToolCall {
+        id: "call_1".to_string(),
+        r#type: "function".to_string(),
+        function: crate::types::FunctionCall {
+            name: "search".to_string(),
+            arguments: arguments.to_string(),
+        },
+        inferred: false,
+        choice_index: 0,
+    };
+    let output = |result: ChatToolResult| -> serde_json::Value {
+        serde_json::from_str(&result.content).unwrap()
+    };
+
+    let filled = output(registry.execute(&call(json!({ "query": "rust" }))).await);
+    assert_eq!(filled, json!({ "query": "rust", "limit": 10 }));
+    let explicit = output(registry.execute(&call(json!({ "query": "rust", "limit": 3 }))).await);
+    assert_eq!(explicit["limit"], 3, "Provided arguments should win over defaults");
+    let missing_required = output(registry.execute(&call(json!({}))).await);
+    assert!(
+        missing_required.get("query").is_none(),
+        "Required parameters should not be defaulted"
+    );
+
+    #[cfg(feature = "xml-parse")]
+    {
+        let tools = vec![ChatTool {
+            r#type: "function".to_string(),
+            function: definition,
+        }];
+        let tool_calls = crate::xml::XmlToolCallParser::parse_xml_tool_calls_with_tools(
+            concat!(
+                "<tool_call><invoke name=\"search\">",
+                "<parameter name=\"query\">rust</parameter></invoke></tool_call>",
+            ),
+            &tools,
+        );
+        assert_eq!(tool_calls.len(), 1);
+        let arguments: serde_json::Value =
+            serde_json::from_str(&tool_calls[0].function.arguments).unwrap();
+        assert_eq!(arguments, json!({ "query": "rust", "limit": 10 }));
+    }
+
+    debug!("Tool parameter defaults test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_streamed_tool_call_deltas() {
+    setup();
+    debug!("Starting streamed tool call delta test");
+
+    let events = replay_fixture(concat!(
+        "event: json\ndata: {\"choices\": [{\"delta\": {\"tool_calls\": [{\"index\": 0, \"id\": \"call_1\", ",
+        "\"type\": \"function\", \"function\": {\"name\": \"get_weather\", \"arguments\": \"{\\\"ci\"}}]}}]}\n\n",
+        "event: json\ndata: {\"choices\": [{\"delta\": {\"tool_calls\": [{\"index\": 0, \"id\": \"call_1\", ",
+        "\"type\": \"function\", \"function\": {\"arguments\": \"ty\\\": \\\"Taipei\\\"}\"}}]}}]}\n\n",
+        "event: json\ndata: {\"choices\": [{\"delta\": {}, \"finish_reason\": \"tool_calls\"}]}\n\n",
+        "event: done\ndata: {}\n\n",
+    ))
+    .await;
+
+    let tool_calls: Vec<&ChatToolCall> = events
+        .iter()
+        .filter_map(|event| match &event.data {
+            Some(ChatResponseData::ToolCalls(tool_calls)) => Some(tool_calls),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+    assert_eq!(tool_calls.len(), 1, "Repeated ids should not split the tool call");
+    assert_eq!(tool_calls[0].id, "call_1");
+    assert_eq!(tool_calls[0].r#type, TOOL_TYPE_FUNCTION);
+    assert_eq!(tool_calls[0].function.name, "get_weather");
+    assert_eq!(tool_calls[0].function.arguments, "{\"city\": \"Taipei\"}");
+    assert!(!tool_calls[0].inferred, "finish_reason completes calls explicitly");
+    assert_eq!(events.last().unwrap().event, ChatEventType::Done);
+
+    debug!("Streamed tool call delta test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_tool_call_completion_fallbacks() {
+    setup();
+    debug!("Starting tool call completion fallback test");
+
+    const DELTA: &str = concat!(
+        "event: json\ndata: {\"choices\": [{\"delta\": {\"tool_calls\": [{\"index\": 0, \"id\": \"call_1\", ",
+        "\"type\": \"function\", \"function\": {\"name\": \"lookup\", \"arguments\": \"{}\"}}]}}]}\n\n",
+    );
+    async fn events(
+        completion: ToolCallCompletion,
+        body: String,
+    ) -> Vec<Result<ChatResponse, PoeError>> {
+        let server = start_mock_server(vec![MockResponse::sse(&body)]).await;
+        mock_client(&server)
+            .with_tool_call_completion(completion)
+            .stream_request(Conversation::new().to_request())
+            .await
+            .unwrap()
+            .collect()
+            .await
+    }
+    let is_tool_calls = |item: &Result<ChatResponse, PoeError>| {
+        matches!(
+            item,
+            Ok(ChatResponse {
+                data: Some(ChatResponseData::ToolCalls(_)),
+                ..
+            })
+        )
+    };
+
+    let default = events(ToolCallCompletion::default(), DELTA.to_string()).await;
+    assert!(
+        !default.iter().any(is_tool_calls),
+        "Without fallbacks a truncated stream should not complete calls"
+    );
+
+    let on_stream_end = ToolCallCompletion {
+        on_stream_end: true,
+        ..Default::default()
+    };
+    let truncated = events(on_stream_end, DELTA.to_string()).await;
+    assert_eq!(truncated.len(), 2);
+    assert!(is_tool_calls(&truncated[0]));
+    assert!(matches!(truncated[1], Err(PoeError::StreamTruncated)));
+
+    struct StalledTransport;
+
+    #[async_trait::async_trait]
+    impl HttpTransport for StalledTransport {
+        async fn send(&self, _request: HttpRequest) -> Result<HttpResponse, PoeError> {
+            Ok(HttpResponse {
+                status: 200,
+                headers: Vec::new(),
+                body: futures_util::stream::iter([Ok(bytes::Bytes::from_static(
+                    DELTA.as_bytes(),
+                ))])
+                .chain(futures_util::stream::pending())
+                .boxed(),
+            })
+        }
+    }
+
+    let client = PoeClient::new("TestBot", "test_key", "http://transport.invalid", "")
+        .with_transport(StalledTransport)
+        .with_tool_call_completion(ToolCallCompletion {
+            quiescence: Some(std::time::Duration::from_millis(50)),
+            ..Default::default()
+        });
+    let mut stream = client
+        .stream_request(Conversation::new().to_request())
+        .await
+        .unwrap();
+    let first = tokio::time::timeout(std::time::Duration::from_secs(2), stream.next())
+        .await
+        .expect("Quiescence should flush the stalled tool call")
+        .unwrap();
+    assert!(is_tool_calls(&first));
+
+    debug!("Tool call completion fallback test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_tool_calls_flushed_on_done() {
+    setup();
+    debug!("Starting tool call flush on done test");
+
+    // Deltas followed directly by done, with no finish_reason chunk
+    let events = replay_fixture(concat!(
+        "event: json\ndata: {\"choices\": [{\"delta\": {\"tool_calls\": [{\"index\": 0, \"id\": \"call_1\", ",
+        "\"type\": \"function\", \"function\": {\"name\": \"lookup\", \"arguments\": \"{\\\"q\\\": \"}}]}}]}\n\n",
+        "event: json\ndata: {\"choices\": [{\"delta\": {\"tool_calls\": [{\"index\": 0, ",
+        "\"function\": {\"arguments\": \"\\\"rust\\\"}\"}}]}}]}\n\n",
+        "event: done\ndata: {}\n\n",
+    ))
+    .await;
+
+    assert_eq!(events.len(), 2, "Tool calls should be emitted before done: {:?}", events);
+    let Some(ChatResponseData::ToolCalls(tool_calls)) = &events[0].data else {
+        panic!("Accumulated tool calls should not be dropped on done");
+    };
+    assert_eq!(tool_calls.len(), 1);
+    assert_eq!(tool_calls[0].function.arguments, "{\"q\": \"rust\"}");
+    assert!(tool_calls[0].inferred, "Calls completed by done should be flagged as inferred");
+    assert_eq!(events[1].event, ChatEventType::Done);
+
+    debug!("Tool call flush on done test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_multi_choice_tool_calls() {
+    setup();
+    debug!("Starting multi-choice tool call test");
+
+    let events = replay_fixture(concat!(
+        "event: json\ndata: {\"choices\": [",
+        "{\"index\": 0, \"delta\": {\"tool_calls\": [{\"index\": 0, \"id\": \"a\", \"type\": \"function\", ",
+        "\"function\": {\"name\": \"search\", \"arguments\": \"{\\\"q\\\": 1}\"}}]}}, ",
+        "{\"index\": 1, \"delta\": {\"tool_calls\": [{\"index\": 0, \"id\": \"b\", \"type\": \"function\", ",
+        "\"function\": {\"name\": \"lookup\", \"arguments\": \"{\"}}]}}]}\n\n",
+        "event: json\ndata: {\"choices\": [",
+        "{\"index\": 1, \"delta\": {\"tool_calls\": [{\"index\": 0, \"function\": {\"arguments\": \"}\"}}]}, ",
+        "\"finish_reason\": \"tool_calls\"}]}\n\n",
+        "event: done\ndata: {}\n\n",
+    ))
+    .await;
+
+    let Some(ChatResponseData::ToolCalls(tool_calls)) = &events[0].data else {
+        panic!("Expected a tool call event, got {:?}", events);
+    };
+    let summary: Vec<(usize, &str, &str)> = tool_calls
+        .iter()
+        .map(|call| (call.choice_index, call.id.as_str(), call.function.arguments.as_str()))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![(0, "a", "{\"q\": 1}"), (1, "b", "{}")],
+        "Deltas of the second choice should not be dropped or merged into the first"
+    );
+    assert!(tool_calls.iter().all(|call| !call.inferred));
+
+    debug!("Multi-choice tool call test completed");
+}
+
+#[test]
+fn test_chat_tool_builder() {
+    setup();
+    debug!("Starting ChatTool builder test");
+
+    let tool = ChatTool::function("get_weather")
+        .description("Get the current weather")
+        .param("location", ParamType::String, true)
+        .description("City name")
+        .param("unit", ParamType::String, false)
+        .enum_values(["celsius", "fahrenheit"])
+        .default_value("celsius")
+        .param("days", ParamType::Array, false)
+        .items(ParamType::Integer)
+        .build();
+
+    assert_eq!(
+        serde_json::to_value(&tool).unwrap(),
+        json!({
+            "type": "function",
+            "function": {
+                "name": "get_weather",
+                "description": "Get the current weather",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "location": { "type": "string", "description": "City name" },
+                        "unit": {
+                            "type": "string",
+                            "enum": ["celsius", "fahrenheit"],
+                            "default": "celsius"
+                        },
+                        "days": { "type": "array", "items": { "type": "integer" } }
+                    },
+                    "required": ["location"]
+                }
+            }
+        })
+    );
+
+    // Adding a parameter again replaces it
+    let tool: ChatTool = ChatTool::function("ping")
+        .param("host", ParamType::String, true)
+        .param("host", ParamType::String, false)
+        .into();
+    let parameters = tool.function.parameters.unwrap();
+    assert!(parameters.required.is_empty());
+    assert_eq!(tool.function.description, None);
+
+    debug!("ChatTool builder test completed");
+}
+
+#[cfg(all(feature = "trace", feature = "xml-parse"))]
+#[test_log::test(tokio::test)]
+async fn test_trace_targets() {
+    use crate::xml::XmlToolCallParser;
+    use crate::{TARGET_CLIENT, TARGET_SSE, TARGET_XML};
+
+    setup();
+    debug!("Starting trace targets test");
+
+    let server = start_mock_server(vec![MockResponse::sse(concat!(
+        "event: text\ndata: {\"text\": \"Hi\"}\n\n",
+        "event: done\ndata: {}\n\n",
+    ))])
+    .await;
+    let client = mock_client(&server);
+
+    let capture = TraceCapture::start();
+    let events: Vec<_> = client
+        .stream_request(ChatRequest::default())
+        .await
+        .unwrap()
+        .collect()
+        .await;
+    XmlToolCallParser::parse_xml_tool_calls(
+        "<tool_call><invoke name=\"ping\"></invoke></tool_call>",
+    );
+
+    assert_eq!(events.len(), 2);
+    assert!(
+        capture
+            .events_for(TARGET_CLIENT)
+            .iter()
+            .any(|log| log.starts_with("Sending request to URL")),
+        "Request setup should log under poe::client"
+    );
+    let sse_logs = capture.events_for(TARGET_SSE);
+    assert!(sse_logs.iter().any(|log| log.contains("收到完成事件")));
+    assert!(sse_logs.iter().all(|log| !log.starts_with("Sending request")));
+    assert!(!capture.events_for(TARGET_XML).is_empty());
+    drop(capture);
+
+    debug!("Trace targets test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_api_status_error() {
+    setup();
+    debug!("Starting API status error test");
+
+    let server = start_mock_server(vec![
+        MockResponse::json(503, &format!("{{\"error\": \"{}\"}}", "é".repeat(2000)))
+            .with_header("X-Request-Id", "req_123"),
+        MockResponse::json(401, r#"{"error": "invalid access key"}"#),
+    ])
+    .await;
+    let client = mock_client(&server);
+
+    let error = match client.stream_request(ChatRequest::default()).await {
+        Err(error) => error,
+        Ok(_) => panic!("A 503 response should fail the request"),
+    };
+    match &error {
+        PoeError::ApiStatus {
+            status,
+            body,
+            endpoint,
+            request_id,
+            ..
+        } => {
+            assert_eq!(*status, 503);
+            assert_eq!(endpoint, &format!("{}/bot/TestBot", server.url));
+            assert_eq!(request_id.as_deref(), Some("req_123"));
+            assert!(body.starts_with("{\"error\": \"é"));
+            assert!(body.len() <= 2048, "The body should be truncated on a char boundary");
+        }
+        other => panic!("Expected ApiStatus, got {:?}", other),
+    }
+    assert_eq!(error.status(), Some(503));
+
+    let error = client.get_v1_model_list().await.unwrap_err();
+    assert!(matches!(
+        error,
+        PoeError::ApiStatus { status: 401, ref body, ref endpoint, request_id: None, .. }
+            if body.contains("invalid access key") && endpoint.ends_with("/v1/models")
+    ));
+
+    debug!("API status error test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_retry_after_on_429() {
+    use std::time::Duration;
+
+    setup();
+    debug!("Starting Retry-After test");
+
+    let throttled = || {
+        MockResponse::json(429, r#"{"error": "rate limited"}"#).with_header("Retry-After", "5")
+    };
+    let server = start_mock_server(vec![
+        throttled(),
+        MockResponse::sse("event: text\ndata: {\"text\": \"ok\"}\n\nevent: done\ndata: {}\n\n"),
+        throttled(),
+    ])
+    .await;
+    // The backoff is long enough that only honoring Retry-After finishes in time
+    let api = RetryPoeClient::new(mock_client(&server), 1)
+        .with_backoff(Duration::from_secs(60), Duration::from_secs(60))
+        .with_retry_after(Duration::from_millis(20));
+
+    let stream = api.stream_request(ChatRequest::default()).await.unwrap();
+    assert_eq!(stream.collect_response().await.unwrap().text, "ok");
+
+    let error = match api.stream_request(ChatRequest::default()).await {
+        Err(error) => error,
+        Ok(_) => panic!("Retries should be exhausted"),
+    };
+    assert_eq!(error.status(), Some(429));
+    assert_eq!(error.retry_after(), Some(Duration::from_secs(5)));
+    assert_eq!(
+        api.throttle_stats(),
+        ThrottleStats {
+            throttled: 2,
+            total_wait: Duration::from_millis(40),
+        }
+    );
+
+    debug!("Retry-After test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_client_builder() {
+    use std::time::Duration;
+
+    setup();
+    debug!("Starting client builder test");
+
+    let server = start_mock_server(vec![MockResponse::json(200, r#"{"data": []}"#)]).await;
+    let client = PoeClient::builder()
+        .bot("TestBot")
+        .access_key("test_key")
+        .base_url(&format!("{}/", server.url))
+        .timeout(Duration::from_secs(30))
+        .connect_timeout(Duration::from_secs(5))
+        .default_header("X-Tenant", "acme")
+        .user_agent("my-app/1.0")
+        .build()
+        .unwrap();
+    assert_eq!(client.get_bot_name(), "TestBot");
+    assert_eq!(client.get_base_url(), server.url);
+
+    let _ = client.get_v1_model_list().await;
+    let raw = server.requests.lock().unwrap()[0].to_ascii_lowercase();
+    assert!(raw.starts_with("get /v1/models"));
+    assert!(raw.contains("x-tenant: acme"));
+    assert!(raw.contains("user-agent: my-app/1.0"));
+    assert!(raw.contains("authorization: bearer test_key"));
+
+    // A prebuilt client is used as is, so the builder's HTTP settings are ignored
+    let shared = reqwest::Client::new();
+    let client = PoeClient::builder()
+        .bot("OtherBot")
+        .default_header("X-Tenant", "ignored")
+        .http_client(shared)
+        .build()
+        .unwrap();
+    assert_eq!(client.get_base_url(), "https://api.poe.com");
+
+    assert!(matches!(
+        PoeClient::builder().default_header("bad header", "x").build(),
+        Err(PoeError::TransportError(_))
+    ));
+
+    debug!("Client builder test completed");
+}
+
+/// Compare `actual` with `tests/golden/<name>`; set `UPDATE_GOLDEN=1` to rewrite the file
+fn assert_golden(name: &str, actual: &str) {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("Missing golden file {}: {}", path.display(), e));
+    assert_eq!(
+        actual,
+        expected,
+        "Wire format changed; rerun with UPDATE_GOLDEN=1 if intended"
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_http_tap_golden() {
+    setup();
+    debug!("Starting HTTP tap golden test");
+
+    let server = start_mock_server(vec![MockResponse::sse(concat!(
+        "event: text\ndata: {\"text\": \"Sunny\"}\n\n",
+        "event: done\ndata: {}\n\n",
+    ))])
+    .await;
+    let tap = std::sync::Arc::new(HttpTap::new(ReqwestTransport::default()));
+    let client = mock_client(&server).with_transport(tap.clone());
+
+    let request = ChatRequest {
+        query: vec![ChatMessage {
+            role: "user".to_string(),
+            content: "Weather in Paris?".to_string(),
+            content_type: "text/markdown".to_string(),
+            attachments: None,
+        }],
+        tools: Some(vec![
+            ChatTool::function("get_weather")
+                .description("Get the current weather")
+                .param("city", ParamType::String, true)
+                .build(),
+        ]),
+        conversation_id: "conv_golden".to_string(),
+        message_id: "msg_golden".to_string(),
+        ..Default::default()
+    };
+    let response = client
+        .stream_request(request)
+        .await
+        .unwrap()
+        .collect_response()
+        .await
+        .unwrap();
+    assert_eq!(response.text, "Sunny");
+
+    let exchanges = tap.exchanges();
+    assert_eq!(exchanges.len(), 1);
+    assert!(
+        exchanges[0]
+            .request
+            .headers
+            .iter()
+            .any(|(name, value)| name == "Authorization" && value == "***MASKED***")
+    );
+    let golden = if cfg!(feature = "xml-prompt") {
+        "chat_request_xml.txt"
+    } else {
+        "chat_request.txt"
+    };
+    assert_golden(golden, &exchanges[0].to_golden());
+
+    tap.clear();
+    assert!(tap.exchanges().is_empty());
+
+    debug!("HTTP tap golden test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_uploads_use_transport() {
+    setup();
+    debug!("Starting upload transport test");
+
+    // Record every request and answer like the upload endpoint
+    #[derive(Default)]
+    struct UploadTransport {
+        requests: std::sync::Mutex<Vec<HttpRequest>>,
+    }
+
+    #[async_trait::async_trait]
+    impl HttpTransport for UploadTransport {
+        async fn send(&self, request: HttpRequest) -> Result<HttpResponse, PoeError> {
+            self.requests.lock().unwrap().push(request);
+            let body = r#"{"attachment_url": "https://pfst.cf2.poecdn.net/notes.txt"}"#;
+            Ok(HttpResponse {
+                status: 200,
+                headers: Vec::new(),
+                body: futures_util::stream::once(async move { Ok(bytes::Bytes::from(body)) })
+                    .boxed(),
+            })
+        }
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, "shopping list").unwrap();
+
+    let transport = std::sync::Arc::new(UploadTransport::default());
+    let client = PoeClient::new("TestBot", "test_key", "", "http://upload.invalid/file_upload")
+        .with_transport(transport.clone());
+    let local = client
+        .upload_local_file(path.to_str().unwrap(), Some("text/plain"))
+        .await
+        .unwrap();
+    assert_eq!(local.attachment_url, "https://pfst.cf2.poecdn.net/notes.txt");
+    client
+        .upload_remote_file("https://example.com/report.pdf")
+        .await
+        .unwrap();
+
+    let requests = transport.requests.lock().unwrap().clone();
+    assert_eq!(requests.len(), 2);
+    for request in requests.iter() {
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.url, "http://upload.invalid/file_upload");
+        assert!(request.headers.iter().any(|(name, value)| {
+            name == "Content-Type" && value.starts_with("multipart/form-data; boundary=")
+        }));
+    }
+    let local_body = String::from_utf8_lossy(&requests[0].body);
+    assert!(local_body.contains("name=\"file\"; filename=\"notes.txt\""));
+    assert!(local_body.contains("Content-Type: text/plain\r\n\r\nshopping list\r\n"));
+    let remote_body = String::from_utf8_lossy(&requests[1].body);
+    assert!(remote_body.contains("name=\"download_url\"\r\n\r\nhttps://example.com/report.pdf\r\n"));
+
+    // Malformed MIME types are rejected before anything is sent
+    let result = client
+        .upload_local_file(path.to_str().unwrap(), Some("text plain"))
+        .await;
+    assert!(matches!(result, Err(PoeError::FileUploadFailed(_))));
+    assert_eq!(transport.requests.lock().unwrap().len(), 2);
+
+    debug!("Upload transport test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_custom_timer() {
+    use crate::{ThreadTimer, Timer};
+    use std::time::Duration;
+    setup();
+    debug!("Starting custom timer test");
+
+    // Return at once, recording the requested delays
+    #[derive(Default)]
+    struct RecordingTimer {
+        delays: std::sync::Mutex<Vec<Duration>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Timer for RecordingTimer {
+        async fn sleep(&self, duration: Duration) {
+            self.delays.lock().unwrap().push(duration);
+        }
+    }
+
+    let server = start_mock_server(vec![
+        MockResponse::json(503, r#"{"error": "overloaded"}"#),
+        MockResponse::sse("event: text\ndata: {\"text\": \"ok\"}\n\nevent: done\ndata: {}\n\n"),
+    ])
+    .await;
+    let timer = std::sync::Arc::new(RecordingTimer::default());
+    let policy = RetryPolicy {
+        max_retries: 1,
+        base_delay: Duration::from_secs(3600),
+        max_delay: Duration::from_secs(3600),
+        jitter: 0.0,
+    };
+    let client = mock_client(&server)
+        .with_retry_policy(policy)
+        .with_timer(timer.clone());
+    let response = client.send_request(ChatRequest::default()).await.unwrap();
+    assert_eq!(response.text, "ok");
+    assert_eq!(*timer.delays.lock().unwrap(), vec![Duration::from_secs(3600)]);
+
+    // The runtime-independent timer sleeps without tokio's clock
+    let started = std::time::Instant::now();
+    ThreadTimer.sleep(Duration::from_millis(20)).await;
+    assert!(started.elapsed() >= Duration::from_millis(20));
+    futures_util::future::join(
+        ThreadTimer.sleep(Duration::from_millis(10)),
+        ThreadTimer.sleep(Duration::from_millis(30)),
+    )
+    .await;
+    assert!(started.elapsed() >= Duration::from_millis(50));
+
+    debug!("Custom timer test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_client_retry_policy() {
+    use crate::RetryableErrorPolicy;
+    use std::time::Duration;
+
+    setup();
+    debug!("Starting client retry policy test");
+
+    let policy = RetryPolicy {
+        max_retries: 2,
+        base_delay: Duration::from_millis(4),
+        max_delay: Duration::from_millis(10),
+        jitter: 0.5,
+    };
+    for attempt in 0..4 {
+        let ceiling = Duration::from_millis(4 << attempt).min(policy.max_delay);
+        let delay = policy.delay(attempt);
+        assert!(delay <= ceiling && delay >= ceiling / 2, "{:?} outside jitter range", delay);
+    }
+
+    let server = start_mock_server(vec![
+        MockResponse::json(503, r#"{"error": "overloaded"}"#),
+        MockResponse::sse(concat!(
+            "event: text\ndata: {\"text\": \"partial\"}\n\n",
+            "event: error\ndata: {\"allow_retry\": true, \"text\": \"busy\"}\n\n",
+        )),
+        MockResponse::sse("event: text\ndata: {\"text\": \"ok\"}\n\nevent: done\ndata: {}\n\n"),
+    ])
+    .await;
+    let client = mock_client(&server).with_retry_policy(policy);
+
+    let events: Vec<_> = client
+        .stream_request(ChatRequest::default())
+        .await
+        .unwrap()
+        .collect()
+        .await;
+    assert!(events.iter().all(|event| !matches!(
+        event,
+        Err(_) | Ok(ChatResponse { event: ChatEventType::Error, .. })
+    )));
+    let text = futures_util::stream::iter(events)
+        .collect_response()
+        .await
+        .unwrap()
+        .text;
+    assert_eq!(text, "ok", "Text from the failed attempt should be reset");
+    assert_eq!(server.requests.lock().unwrap().len(), 3);
+
+    // Requests with an explicit policy keep it
+    let request = ChatRequest {
+        retryable_error_policy: Some(RetryableErrorPolicy::Terminate),
+        ..Default::default()
+    };
+    let server = start_mock_server(vec![MockResponse::sse(
+        "event: error\ndata: {\"allow_retry\": true, \"text\": \"busy\"}\n\n",
+    )])
+    .await;
+    let client = mock_client(&server).with_retry_policy(policy);
+    let events: Vec<_> = client.stream_request(request).await.unwrap().collect().await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(server.requests.lock().unwrap().len(), 1);
+
+    // An explicit YieldAndContinue is not upgraded to the client's retries
+    let request = ChatRequest {
+        retryable_error_policy: Some(RetryableErrorPolicy::YieldAndContinue),
+        ..Default::default()
+    };
+    let events: Vec<_> = client.stream_request(request).await.unwrap().collect().await;
+    assert!(matches!(
+        events.as_slice(),
+        [Ok(ChatResponse { event: ChatEventType::Error, .. })]
+    ));
+    assert_eq!(server.requests.lock().unwrap().len(), 2);
+
+    debug!("Client retry policy test completed");
+}
+
+#[test]
+fn test_conversation_attachments() {
+    use crate::{AttachmentOrigin, Conversation};
+
+    setup();
+    debug!("Starting conversation attachments test");
+
+    let mut conversation = Conversation::new().with_carry_bot_files(true);
+    conversation.push_message(ChatMessage {
+        role: "user".to_string(),
+        content: "Summarize this report".to_string(),
+        content_type: "text/markdown".to_string(),
+        attachments: Some(vec![Attachment {
+            url: "https://pfst.cf2.poecdn.net/base/report".to_string(),
+            content_type: Some("application/pdf".to_string()),
+            expires_at: None,
+            source: None,
+        }]),
+    });
+    conversation.push_bot_file(&FileData {
+        url: "https://pfst.cf2.poecdn.net/base/chart".to_string(),
+        name: "chart.png".to_string(),
+        content_type: "image/png".to_string(),
+        inline_ref: "ref_1".to_string(),
+    });
+    conversation.push_bot_message("Here is a chart");
+    // The carried chart is not recorded a second time
+    conversation.push_user_message("Make it blue");
+
+    let attachments = conversation.attachments();
+    assert_eq!(attachments.len(), 2);
+    assert_eq!(attachments[0].origin, AttachmentOrigin::User);
+    assert_eq!(attachments[0].turn, 0);
+    assert_eq!(attachments[1].name.as_deref(), Some("chart.png"));
+    assert_eq!(attachments[1].turn, 1);
+    assert_eq!(
+        conversation
+            .attachments_from(AttachmentOrigin::Bot)
+            .map(|attachment| attachment.url.as_str())
+            .collect::<Vec<_>>(),
+        vec!["https://pfst.cf2.poecdn.net/base/chart"]
+    );
+    assert_eq!(conversation.attachments_for_turn(2).count(), 0);
+
+    let fork = conversation.fork(1);
+    assert_eq!(fork.attachments().len(), 1);
+    assert_eq!(fork.attachments()[0].origin, AttachmentOrigin::User);
+
+    debug!("Conversation attachments test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_probe_capabilities() {
+    setup();
+    debug!("Starting capability probe test");
+
+    let numbers = (1..=1000).map(|n| n.to_string()).collect::<Vec<_>>().join(" ");
+    let server = start_mock_server(vec![
+        MockResponse::sse(concat!(
+            "event: json\ndata: {\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,",
+            "\"id\":\"call_1\",\"type\":\"function\",\"function\":{\"name\":\"report_probe_result\",",
+            "\"arguments\":\"{\\\"status\\\":\\\"ok\\\"}\"}}]},\"finish_reason\":\"tool_calls\"}]}\n\n",
+            "event: done\ndata: {}\n\n",
+        )),
+        MockResponse::sse(
+            "event: text\ndata: {\"text\": \"{\\\"ok\\\": true}\"}\n\nevent: done\ndata: {}\n\n",
+        ),
+        MockResponse::sse(&format!(
+            "event: text\ndata: {{\"text\": \"{}\"}}\n\nevent: done\ndata: {{}}\n\n",
+            numbers
+        )),
+        MockResponse::json(
+            200,
+            r#"{"data": [{"id": "VisionBot", "object": "model", "created": 0, "owned_by": "poe",
+                "architecture": {"input_modalities": ["text", "image"], "output_modalities": ["text"]}}]}"#,
+        ),
+    ])
+    .await;
+    let client = mock_client(&server);
+
+    let capabilities = client.probe_capabilities("VisionBot").await.unwrap();
+    assert_eq!(
+        capabilities,
+        BotCapabilities {
+            supports_tools: true,
+            supports_images: Some(true),
+            json_mode: true,
+            output_length: numbers.len(),
+            output_complete: true,
+        }
+    );
+    assert!(server.requests.lock().unwrap()[0].starts_with("POST /bot/VisionBot"));
+
+    // Cached across clones, no further requests
+    let again = client.clone().probe_capabilities("VisionBot").await.unwrap();
+    assert_eq!(again, capabilities);
+    assert_eq!(server.requests.lock().unwrap().len(), 4);
+
+    client.clear_capability_cache();
+    assert_eq!(client.cached_capabilities("VisionBot"), None);
+
+    debug!("Capability probe test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_send_request() {
+    setup();
+    debug!("Starting send_request test");
+
+    let server = start_mock_server(vec![
+        MockResponse::sse(concat!(
+            "event: text\ndata: {\"text\": \"Draft\"}\n\n",
+            "event: replace_response\ndata: {\"text\": \"Here is \"}\n\n",
+            "event: text\ndata: {\"text\": \"your chart\"}\n\n",
+            "event: file\ndata: {\"url\": \"https://pfst.cf2.poecdn.net/base/chart\", ",
+            "\"name\": \"chart.png\", \"content_type\": \"image/png\", \"inline_ref\": \"ref_1\"}\n\n",
+            "event: done\ndata: {\"message_id\": \"msg_42\"}\n\n",
+        )),
+        MockResponse::sse(concat!(
+            "event: text\ndata: {\"text\": \"Par\"}\n\n",
+            "event: error\ndata: {\"allow_retry\": false, \"text\": \"overloaded\"}\n\n",
+        )),
+        MockResponse::sse("event: text\ndata: {\"text\": \"Partial answer\"}\n\n"),
+    ])
+    .await;
+    let client = mock_client(&server);
+
+    let response = client.send_request(ChatRequest::default()).await.unwrap();
+    assert_eq!(response.text, "Here is your chart");
+    assert_eq!(response.files.len(), 1);
+    assert_eq!(response.files[0].name, "chart.png");
+    assert!(response.tool_calls.is_empty());
+    assert!(response.completed);
+    assert_eq!(
+        response.done.and_then(|done| done.message_id).as_deref(),
+        Some("msg_42")
+    );
+
+    let error = client.send_request(ChatRequest::default()).await.unwrap_err();
+    assert!(matches!(&error.error, PoeError::BotError(message) if message == "overloaded"));
+    assert_eq!(error.partial.text, "Par", "Text before the error should survive");
+
+    let error = client.send_request(ChatRequest::default()).await.unwrap_err();
+    assert!(matches!(error.error, PoeError::StreamTruncated));
+    assert_eq!(error.partial.text, "Partial answer");
+
+    debug!("send_request test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_fallback_chain() {
+    setup();
+    debug!("Starting fallback chain test");
+
+    let server = start_mock_server(vec![
+        MockResponse::json(503, "{\"error\": \"unavailable\"}"),
+        MockResponse::sse(
+            "event: error\ndata: {\"allow_retry\": false, \"text\": \"content filtered\"}\n\n",
+        ),
+        MockResponse::sse(concat!(
+            "event: text\ndata: {\"text\": \"Hello from C\"}\n\n",
+            "event: done\ndata: {}\n\n",
+        )),
+    ])
+    .await;
+    let chain = FallbackChain::new(mock_client(&server), ["A", "B", "C"]);
+    assert_eq!(chain.bots().collect::<Vec<_>>(), ["A", "B", "C"]);
+
+    // A fails with 503 and B rejects with an error event, so C answers
+    let (bot, stream) = chain
+        .stream_request_with_bot(ChatRequest::default())
+        .await
+        .unwrap();
+    assert_eq!(bot, "C");
+    assert_eq!(stream.collect_response().await.unwrap().text, "Hello from C");
+    {
+        let requests = server.requests.lock().unwrap();
+        assert!(requests[0].starts_with("POST /bot/A "));
+        assert!(requests[1].starts_with("POST /bot/B "));
+        assert!(requests[2].starts_with("POST /bot/C "));
+    }
+
+    // Leading meta and ping events do not hide a rejection and are replayed on success
+    let server = start_mock_server(vec![
+        MockResponse::sse(concat!(
+            "event: meta\ndata: {\"content_type\": \"text/markdown\"}\n\n",
+            "event: ping\ndata: {}\n\n",
+            "event: error\ndata: {\"allow_retry\": false, \"text\": \"content filtered\"}\n\n",
+        )),
+        MockResponse::sse(concat!(
+            "event: meta\ndata: {\"content_type\": \"text/markdown\"}\n\n",
+            "event: ping\ndata: {}\n\n",
+            "event: text\ndata: {\"text\": \"Hello from B\"}\n\n",
+            "event: done\ndata: {}\n\n",
+        )),
+    ])
+    .await;
+    let client = mock_client(&server).with_keep_alive_events(true);
+    let chain = FallbackChain::new(client, ["A", "B"]);
+    let (bot, stream) = chain
+        .stream_request_with_bot(ChatRequest::default())
+        .await
+        .unwrap();
+    assert_eq!(bot, "B");
+    let events: Vec<ChatEventType> = stream.map(|event| event.unwrap().event).collect().await;
+    assert_eq!(
+        events,
+        [ChatEventType::Meta, ChatEventType::Ping, ChatEventType::Text, ChatEventType::Done]
+    );
+    assert_eq!(server.requests.lock().unwrap().len(), 2);
+
+    // Errors outside the predicate are returned without trying other bots
+    let server = start_mock_server(vec![MockResponse::json(401, "{\"error\": \"bad key\"}")]).await;
+    let chain = FallbackChain::new(mock_client(&server), ["A", "B"]);
+    assert!(matches!(
+        chain.stream_request(ChatRequest::default()).await,
+        Err(PoeError::ApiStatus { status: 401, .. })
+    ));
+    assert_eq!(server.requests.lock().unwrap().len(), 1);
+
+    debug!("Fallback chain test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_ask() {
+    setup();
+    debug!("Starting ask test");
+
+    let server = start_mock_server(vec![MockResponse::sse(concat!(
+        "event: text\ndata: {\"text\": \"Paris\"}\n\n",
+        "event: text\ndata: {\"text\": \" is the capital.\"}\n\n",
+        "event: done\ndata: {}\n\n",
+    ))])
+    .await;
+    let client = mock_client(&server);
+
+    let answer = client.ask("What is the capital of France?").await.unwrap();
+    assert_eq!(answer, "Paris is the capital.");
+
+    // The request carries the protocol defaults and a single user message
+    let body = captured_json(&server.requests.lock().unwrap()[0]);
+    assert_eq!(body["version"], "1.1");
+    assert_eq!(body["type"], "query");
+    assert_eq!(body["query"].as_array().unwrap().len(), 1);
+    assert_eq!(body["query"][0]["role"], "user");
+    assert_eq!(body["query"][0]["content"], "What is the capital of France?");
+
+    debug!("ask test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_bot_pool() {
+    setup();
+    debug!("Starting bot pool test");
+
+    let server = start_mock_server(vec![
+        MockResponse::json(503, "{\"error\": \"unavailable\"}"),
+        MockResponse::sse("event: text\ndata: {\"text\": \"ok\"}\n\nevent: done\ndata: {}\n\n"),
+    ])
+    .await;
+    let breaker = std::sync::Arc::new(CircuitBreaker::new(1, std::time::Duration::from_secs(60)));
+    let pool = BotPool::new(mock_client(&server), ["A", "B", "C"], BalanceStrategy::RoundRobin)
+        .with_circuit_breaker(breaker.clone());
+
+    // A fails and its circuit opens, so later requests go to B and C only
+    assert!(pool.stream_request(ChatRequest::default()).await.is_err());
+    assert_eq!(breaker.state("A"), CircuitState::Open);
+    let mut served = Vec::new();
+    for _ in 0..4 {
+        let (bot, stream) = pool
+            .stream_request_with_bot(ChatRequest::default())
+            .await
+            .unwrap();
+        served.push(bot.to_string());
+        assert_eq!(stream.collect_response().await.unwrap().text, "ok");
+    }
+    served.sort();
+    assert_eq!(served, ["B", "B", "C", "C"]);
+
+    let stats = pool.stats();
+    assert_eq!(stats[0].bot_name, "A");
+    assert_eq!((stats[0].requests, stats[0].failures), (1, 1));
+    assert_eq!(stats[0].latency, None);
+    assert_eq!((stats[1].requests, stats[1].failures), (2, 0));
+    assert!(stats[1].latency.is_some());
+    assert!(stats.iter().all(|stats| stats.in_flight == 0));
+
+    // Least in-flight routes around a bot holding an open stream
+    let pool = BotPool::new(mock_client(&server), ["X", "Y"], BalanceStrategy::LeastInFlight);
+    let (first, _held) = pool
+        .stream_request_with_bot(ChatRequest::default())
+        .await
+        .unwrap();
+    let (second, _) = pool
+        .stream_request_with_bot(ChatRequest::default())
+        .await
+        .unwrap();
+    assert_ne!(first, second);
+    assert_eq!(pool.stats().iter().map(|stats| stats.in_flight).sum::<usize>(), 1);
+
+    debug!("Bot pool test completed");
+}
+
+#[test]
+fn test_chat_request_builder() {
+    let request = ChatRequest::builder()
+        .system_message("Be brief.")
+        .user_message("Hi")
+        .bot_message("Hello!")
+        .user_message("What's the weather in Paris?")
+        .attachment(Attachment {
+            url: "https://pfst.cf2.poecdn.net/base/map".to_string(),
+            content_type: Some("image/png".to_string()),
+            ..Default::default()
+        })
+        .tool(
+            ChatTool::function("get_weather")
+                .description("Get the current weather")
+                .param("city", ParamType::String, true),
+        )
+        .tool_choice(ToolChoice::Function("get_weather".to_string()))
+        .temperature(0.7)
+        .stop_sequence("###")
+        .conversation_id("conv_1")
+        .priority(RequestPriority::Interactive)
+        .build()
+        .unwrap();
+
+    // Protocol defaults are filled in
+    assert_eq!(request.version, "1.1");
+    assert_eq!(request.r#type, "query");
+    assert!(!request.message_id.is_empty());
+    assert_eq!(request.conversation_id, "conv_1");
+
+    let roles: Vec<&str> = request.query.iter().map(|m| m.role.as_str()).collect();
+    assert_eq!(roles, ["system", "user", "bot", "user"]);
+    assert_eq!(request.query[3].attachments.as_ref().unwrap().len(), 1);
+    assert!(request.query[1].attachments.is_none());
+    assert_eq!(request.tools.as_ref().unwrap()[0].function.name, "get_weather");
+    assert_eq!(request.temperature, Some(0.7));
+    assert_eq!(request.stop_sequences, Some(vec!["###".to_string()]));
+    assert_eq!(request.priority, RequestPriority::Interactive);
+
+    // Invalid requests are rejected at build time
+    assert!(matches!(
+        ChatRequest::builder().build(),
+        Err(PoeError::InvalidRoleSequence(_))
+    ));
+    assert!(matches!(
+        ChatRequest::builder().user_message("Hi").temperature(-1.0).build(),
+        Err(PoeError::InvalidRequest(_))
+    ));
+    assert!(matches!(
+        ChatRequest::builder()
+            .user_message("Hi")
+            .tool_choice(ToolChoice::Function("missing".to_string()))
+            .build(),
+        Err(PoeError::InvalidRequest(message)) if message.contains("missing")
+    ));
+    assert!(matches!(
+        ChatRequest::builder()
+            .user_message("Hi")
+            .tool_choice(ToolChoice::Required)
+            .build(),
+        Err(PoeError::InvalidRequest(_))
+    ));
+}
+
+#[test_log::test(tokio::test)]
+async fn test_experiment_router() {
+    setup();
+    debug!("Starting experiment router test");
+
+    let server = start_mock_server(vec![MockResponse::sse(
+        "event: text\ndata: {\"text\": \"ok\"}\n\nevent: done\ndata: {}\n\n",
+    )])
+    .await;
+    let router = ExperimentRouter::new(
+        mock_client(&server),
+        [
+            ExperimentVariant::new("control").with_bot("ModelA"),
+            ExperimentVariant::new("treatment")
+                .with_bot("ModelB")
+                .with_temperature(0.2),
+        ],
+    )
+    .with_assignment(|user_id, _| {
+        if user_id.starts_with("beta_") {
+            "treatment".to_string()
+        } else {
+            "control".to_string()
+        }
+    });
+
+    let request = |user_id: &str| {
+        let mut request = ChatRequest::builder().user_message("Hi").build().unwrap();
+        request.user_id = user_id.to_string();
+        request
+    };
+
+    let (variant, stream) = router
+        .stream_request_with_variant(request("beta_1"))
+        .await
+        .unwrap();
+    assert_eq!(variant.name, "treatment");
+    stream.collect_response().await.unwrap();
+    let (variant, stream) = router
+        .stream_request_with_variant(request("user_2"))
+        .await
+        .unwrap();
+    assert_eq!(variant.name, "control");
+    stream.collect_response().await.unwrap();
+
+    // The variant's bot and parameter overrides are applied
+    {
+        let requests = server.requests.lock().unwrap();
+        assert!(requests[0].starts_with("POST /bot/ModelB "));
+        assert!((captured_json(&requests[0])["temperature"].as_f64().unwrap() - 0.2).abs() < 1e-6);
+        assert!(requests[1].starts_with("POST /bot/ModelA "));
+        assert!(captured_json(&requests[1]).get("temperature").is_none());
+    }
+
+    let stats = router.stats();
+    assert_eq!(stats[0].variant, "control");
+    assert_eq!((stats[0].requests, stats[0].completed, stats[0].failures), (1, 1, 0));
+    assert_eq!((stats[1].requests, stats[1].completed, stats[1].failures), (1, 1, 0));
+
+    // The default assignment keeps a user in the same bucket
+    let router = ExperimentRouter::new(
+        mock_client(&server),
+        [ExperimentVariant::new("a"), ExperimentVariant::new("b")],
+    );
+    let first = router
+        .stream_request_with_variant(request("user_42"))
+        .await
+        .unwrap()
+        .0
+        .name
+        .clone();
+    for _ in 0..3 {
+        let (variant, _) = router
+            .stream_request_with_variant(request("user_42"))
+            .await
+            .unwrap();
+        assert_eq!(variant.name, first);
+    }
+
+    debug!("Experiment router test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_stream_request_with_cancel() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    setup();
+    debug!("Starting cancellable stream test");
+
+    // A bot that sends one event and then keeps the connection open
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (closed_tx, closed_rx) = tokio::sync::oneshot::channel();
+    tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut raw = Vec::new();
+        let mut buf = [0u8; 8192];
+        while !String::from_utf8_lossy(&raw).contains("\r\n\r\n") {
+            let n = socket.read(&mut buf).await.unwrap();
+            raw.extend_from_slice(&buf[..n]);
+        }
+        let event = "event: text\ndata: {\"text\": \"Hel\"}\n\n";
+        let reply = format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ntransfer-encoding: chunked\r\n\r\n{:x}\r\n{}\r\n",
+            event.len(),
+            event
+        );
+        socket.write_all(reply.as_bytes()).await.unwrap();
+        // Wait for the client to hang up
+        while socket.read(&mut buf).await.map(|n| n > 0).unwrap_or(false) {}
+        let _ = closed_tx.send(());
+    });
+    let client = PoeClient::new("TestBot", "test_key", &url, &format!("{}/file_upload", url));
+
+    // An already aborted handle fails before anything is sent
+    let aborted = AbortHandle::new();
+    aborted.abort();
+    assert!(matches!(
+        client
+            .stream_request_with_cancel(ChatRequest::default(), aborted)
+            .await,
+        Err(PoeError::Aborted)
+    ));
+
+    let abort = AbortHandle::new();
+    let mut stream = client
+        .stream_request_with_cancel(ChatRequest::default(), abort.clone())
+        .await
+        .unwrap();
+    let first = stream.next().await.unwrap().unwrap();
+    assert!(matches!(first.data, Some(ChatResponseData::Text { text }) if text == "Hel"));
+
+    abort.abort();
+    assert!(abort.is_aborted());
+    assert!(matches!(stream.next().await, Some(Err(PoeError::Aborted))));
+    assert!(stream.next().await.is_none());
+
+    // The HTTP connection is closed without dropping the stream
+    tokio::time::timeout(std::time::Duration::from_secs(5), closed_rx)
+        .await
+        .expect("connection should be closed after abort")
+        .unwrap();
+
+    debug!("Cancellable stream test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_sse_parser() {
+    setup();
+    debug!("Starting SSE parser test");
+
+    let mut parser = SseParser::new();
+    // CRLF endings, a CR split from its LF across chunks and a multi-line data field
+    parser.push("\u{feff}: hello\r\nevent: text\r\nid: 7\r\ndata: {\"text\":\r");
+    parser.push("\ndata: \"Hi\"}\r\nretry: 3000\r\n\r\n");
+    assert_eq!(
+        parser.next_frame().unwrap(),
+        Some(SseFrame::Comment("hello".to_string()))
+    );
+    assert_eq!(
+        parser.next_frame().unwrap(),
+        Some(SseFrame::Event(SseEvent {
+            event: Some("text".to_string()),
+            data: "{\"text\":\n\"Hi\"}".to_string(),
+            id: Some("7".to_string()),
+        }))
+    );
+    assert_eq!(parser.next_frame().unwrap(), None);
+    assert_eq!(parser.retry(), Some(std::time::Duration::from_secs(3)));
+
+    // CR-only endings, unknown fields ignored, events without data dropped,
+    // and the ID carried over to later events
+    parser.push("event: ping\r\rfoo: bar\rdata:no-space\r\r");
+    assert_eq!(
+        parser.next_frame().unwrap(),
+        Some(SseFrame::Event(SseEvent {
+            event: None,
+            data: "no-space".to_string(),
+            id: Some("7".to_string()),
+        }))
+    );
+    assert_eq!(parser.last_event_id(), Some("7"));
+
+    // An unterminated event is dispatched when the body ends
+    parser.push("event: done\ndata: {}");
+    assert_eq!(parser.next_frame().unwrap(), None);
+    assert_eq!(parser.finish().unwrap().data, "{}");
+
+    // Over-long lines fail instead of buffering forever
+    let mut parser = SseParser::new().with_max_line_length(8);
+    parser.push("data: 0123456789");
+    assert!(matches!(
+        parser.next_frame(),
+        Err(PoeError::EventTooLarge { limit: 8, .. })
+    ));
+
+    // Many short data lines cannot grow one event past the limit either
+    let mut parser = SseParser::new().with_max_line_length(64);
+    parser.push(&"data: 01234567\n".repeat(20));
+    assert!(matches!(
+        parser.next_frame(),
+        Err(PoeError::EventTooLarge { limit: 64, size }) if size > 64
+    ));
+    assert!(parser.buffered_len() <= 64, "Oversized event data should be discarded");
+
+    // The client decodes JSON payloads split across data lines and CRLF frames
+    let server = start_mock_server(vec![MockResponse::sse(concat!(
+        "event: text\r\ndata: {\"text\":\r\ndata: \"Hello, \"}\r\n\r\n",
+        ": keep-alive\r\n\r\n",
+        "event: text\r\ndata: {\"text\": \"world\"}\r\n\r\n",
+        "event: done\r\ndata: {}\r\n\r\n",
+    ))])
+    .await;
+    let response = mock_client(&server)
+        .send_request(ChatRequest::builder().user_message("Hi").build().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.text, "Hello, world");
+    assert!(response.completed);
+
+    debug!("SSE parser test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_conversation_export() {
+    use crate::export::{export_store, to_openai_chat, to_sharegpt};
+    use crate::{ConversationStore, InMemoryConversationStore};
+    setup();
+    debug!("Starting conversation export test");
+
+    let mut conversation = Conversation::new();
+    conversation.push_user_message("What's the weather in Paris?");
+    conversation.push_tool_exchange(
+        vec![ChatToolCall {
+            id: "call_1".to_string(),
+            r#type: "function".to_string(),
+            function: FunctionCall {
+                name: "get_weather".to_string(),
+                arguments: "{\"city\":\"Paris\"}".to_string(),
+            },
+            inferred: false,
+            choice_index: 0,
+        }],
+        vec![ChatToolResult {
+            role: "tool".to_string(),
+            tool_call_id: "call_1".to_string(),
+            name: "get_weather".to_string(),
+            content: "18°C, sunny".to_string(),
+        }],
+    );
+    conversation.push_bot_message("It's 18°C and sunny in Paris.");
+    assert_eq!(conversation.tool_exchanges()[0].turn, 1);
+
+    // OpenAI chat fine-tuning layout
+    let example = to_openai_chat(&conversation);
+    let messages = example["messages"].as_array().unwrap();
+    let roles: Vec<&str> = messages.iter().map(|m| m["role"].as_str().unwrap()).collect();
+    assert_eq!(roles, ["user", "assistant", "tool", "assistant"]);
+    assert!(messages[1]["content"].is_null());
+    assert_eq!(messages[1]["tool_calls"][0]["function"]["name"], "get_weather");
+    assert_eq!(messages[1]["tool_calls"][0]["function"]["arguments"], "{\"city\":\"Paris\"}");
+    assert_eq!(messages[2]["tool_call_id"], "call_1");
+    assert_eq!(messages[3]["content"], "It's 18°C and sunny in Paris.");
+
+    // ShareGPT layout
+    let example = to_sharegpt(&conversation);
+    let turns = example["conversations"].as_array().unwrap();
+    let from: Vec<&str> = turns.iter().map(|t| t["from"].as_str().unwrap()).collect();
+    assert_eq!(from, ["human", "function_call", "observation", "gpt"]);
+    let call: serde_json::Value = serde_json::from_str(turns[1]["value"].as_str().unwrap()).unwrap();
+    assert_eq!(call["arguments"]["city"], "Paris");
+
+    // Forking before the bot turn drops its tool exchange
+    assert!(conversation.fork(1).tool_exchanges().is_empty());
+
+    // Export from a store as JSONL, skipping unknown IDs
+    let store = InMemoryConversationStore::new();
+    store.save_conversation(&conversation).await.unwrap();
+    let jsonl = export_store(&store, &[conversation.id(), "missing"], ExportFormat::OpenAiChat)
+        .await
+        .unwrap();
+    assert_eq!(jsonl.lines().count(), 1);
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(jsonl.lines().next().unwrap()).unwrap(),
+        to_openai_chat(&conversation)
+    );
+
+    debug!("Conversation export test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_utf8_split_across_chunks() {
+    setup();
+    debug!("Starting UTF-8 chunk boundary test");
+
+    const BODY: &str = "event: text\ndata: {\"text\": \"你好，世界 🌍\"}\n\nevent: done\ndata: {}\n\n";
+
+    // Deliver the body one byte at a time so every multi-byte character is split
+    struct ByteTransport;
+
+    #[async_trait::async_trait]
+    impl HttpTransport for ByteTransport {
+        async fn send(&self, _request: HttpRequest) -> Result<HttpResponse, PoeError> {
+            Ok(HttpResponse {
+                status: 200,
+                headers: Vec::new(),
+                body: futures_util::stream::iter(
+                    BODY.as_bytes()
+                        .iter()
+                        .map(|byte| Ok(bytes::Bytes::copy_from_slice(&[*byte]))),
+                )
+                .boxed(),
+            })
+        }
+    }
+
+    let client = PoeClient::new("TestBot", "test_key", "http://transport.invalid", "")
+        .with_transport(ByteTransport);
+    let response = client
+        .send_request(Conversation::new().to_request())
+        .await
+        .unwrap();
+    assert_eq!(response.text, "你好，世界 🌍");
+
+    // Invalid bytes become U+FFFD without swallowing the following text
+    let mut parser = SseParser::new();
+    parser.push_bytes(b"data: a\xffb\n\n");
+    assert!(matches!(
+        parser.next_frame().unwrap(),
+        Some(SseFrame::Event(event)) if event.data == "a\u{fffd}b"
+    ));
+
+    // A character cut off by the end of the body is replaced too
+    parser.push_bytes(&"data: 好".as_bytes()[..8]);
+    assert_eq!(parser.next_frame().unwrap(), None);
+    assert_eq!(parser.finish().unwrap().data, "\u{fffd}");
+
+    debug!("UTF-8 chunk boundary test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_prelude() {
+    use crate::prelude::*;
+    setup();
+    debug!("Starting prelude test");
+
+    let server = start_mock_server(vec![MockResponse::sse(concat!(
+        "event: text\ndata: {\"text\": \"Hi\"}\n\n",
+        "event: done\ndata: {}\n\n",
+    ))])
+    .await;
+    let client: PoeClient = PoeClient::builder()
+        .bot("TestBot")
+        .access_key("test_key")
+        .base_url(&server.url)
+        .build()
+        .unwrap();
+    let request: ChatRequest = ChatRequest::builder()
+        .user_message("Hello")
+        .tool(ChatTool::function("noop").param("value", ParamType::String, false))
+        .build()
+        .unwrap();
+
+    // Trait methods from the prelude are in scope
+    let api: &dyn PoeApi = &client;
+    let stream: ChatStream = api.stream_request(request).await.unwrap();
+    let response: Result<CompleteResponse, _> = stream.collect_response().await;
+    assert_eq!(response.unwrap().text, "Hi");
+
+    debug!("Prelude test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_typed_endpoint_urls() {
+    setup();
+    debug!("Starting typed endpoint URL test");
+
+    let request = || ChatRequest::builder().user_message("Hi").build().unwrap();
+
+    // Trailing slashes are dropped and path prefixes kept when joining
+    let client = PoeClient::new(
+        "TestBot",
+        "test_key",
+        "http://127.0.0.1:9/proxy//",
+        "http://127.0.0.1:9/upload/",
+    );
+    assert_eq!(client.get_base_url(), "http://127.0.0.1:9/proxy");
+    assert_eq!(client.get_file_upload_url(), "http://127.0.0.1:9/upload");
+    let prepared = client.prepare_request(request()).unwrap();
+    assert_eq!(prepared.url, "http://127.0.0.1:9/proxy/bot/TestBot");
+
+    // Bot names are encoded as a single path segment
+    let prepared = client.with_bot("a/b c").prepare_request(request()).unwrap();
+    assert_eq!(prepared.url, "http://127.0.0.1:9/proxy/bot/a%2Fb%20c");
+
+    // The builder rejects bad URLs up front
+    assert!(matches!(
+        PoeClient::builder().base_url("not a url").build(),
+        Err(PoeError::InvalidUrl(_))
+    ));
+    assert!(matches!(
+        PoeClient::builder().file_upload_url("mailto:poe@example.com").build(),
+        Err(PoeError::InvalidUrl(_))
+    ));
+
+    // `PoeClient::new` stays infallible and reports the error on use
+    let client = PoeClient::new("TestBot", "test_key", "api.poe.com", "");
+    assert_eq!(client.get_base_url(), "");
+    assert!(matches!(
+        client.prepare_request(request()),
+        Err(PoeError::InvalidUrl(_))
+    ));
+    assert!(matches!(
+        client.get_v1_model_list().await,
+        Err(PoeError::InvalidUrl(_))
+    ));
+
+    debug!("Typed endpoint URL test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_unknown_sse_events() {
+    setup();
+    debug!("Starting unknown SSE event test");
+
+    let server = start_mock_server(vec![MockResponse::sse(concat!(
+        "event: x_trace\ndata: {\"span\": 7}\n\n",
+        "event: text\ndata: {\"text\": \"Hi\"}\n\n",
+        "event: x_note\ndata: plain words\n\n",
+        "event: done\ndata: {}\n\n",
+    ))])
+    .await;
+    let client = mock_client(&server);
+    let request = ChatRequest::builder().user_message("Hello").build().unwrap();
+    let events: Vec<ChatResponse> = client
+        .stream_request(request)
+        .await
+        .unwrap()
+        .map(|item| item.unwrap())
+        .collect()
+        .await;
+    assert_eq!(events.len(), 4);
+
+    // Unknown events keep their name and JSON payload
+    assert_eq!(events[0].event, ChatEventType::Unknown("x_trace".to_string()));
+    assert!(matches!(
+        &events[0].data,
+        Some(ChatResponseData::Raw(raw)) if raw["span"] == 7
+    ));
+    assert_eq!(events[1].event, ChatEventType::Text);
+
+    // Data that is not JSON is passed through as a string
+    assert_eq!(
+        events[2].event,
+        ChatEventType::Unknown("x_note".to_string())
+    );
+    assert!(matches!(
+        &events[2].data,
+        Some(ChatResponseData::Raw(serde_json::Value::String(text))) if text == "plain words"
+    ));
+
+    // Re-serializing keeps the original event name
+    assert_eq!(
+        events[0].to_sse_frame(),
+        "event: x_trace\ndata: {\"span\":7}\n\n"
+    );
+
+    debug!("Unknown SSE event test completed");
+}
+
+#[test]
+fn test_content_type() {
+    use crate::ContentType;
+    setup();
+    debug!("Starting content type test");
+
+    // Aliases and case differences resolve to the canonical value
+    assert_eq!(ContentType::parse("markdown"), ContentType::Markdown);
+    assert_eq!(ContentType::parse(" Text/Markdown "), ContentType::Markdown);
+    assert_eq!(ContentType::parse("plain"), ContentType::PlainText);
+    assert_eq!(ContentType::parse("text/plain"), ContentType::PlainText);
+    let other = ContentType::parse("text/html");
+    assert_eq!(other, ContentType::Other("text/html".to_string()));
+    assert!(!other.is_known());
+    assert_eq!(other.as_str(), "text/html");
+
+    // Serde uses the wire names and accepts the aliases
+    assert_eq!(
+        serde_json::to_string(&ContentType::PlainText).unwrap(),
+        r#""text/plain""#
+    );
+    assert_eq!(
+        serde_json::from_str::<ContentType>(r#""md""#).unwrap(),
+        ContentType::Markdown
+    );
+    assert_eq!(
+        serde_json::from_str::<ContentType>(r#""text/html""#).unwrap(),
+        other
+    );
+
+    // Builder messages default to markdown and can be overridden
+    let request = ChatRequest::builder()
+        .user_message("Hello")
+        .content_type(ContentType::PlainText)
+        .bot_message("Hi")
+        .user_message("Again")
+        .build()
+        .unwrap();
+    assert_eq!(request.query[0].content_type, "text/plain");
+    assert_eq!(
+        request.query[0].parsed_content_type(),
+        ContentType::PlainText
+    );
+    assert_eq!(request.query[1].content_type, "text/markdown");
+
+    let message = request.query[1]
+        .clone()
+        .with_content_type(ContentType::PlainText);
+    assert_eq!(message.content_type, "text/plain");
+
+    debug!("Content type test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_meta_events() {
+    use crate::{ContentType, MetaData};
+    setup();
+    debug!("Starting meta event test");
+
+    let server = start_mock_server(vec![MockResponse::sse(concat!(
+        "event: meta\ndata: {\"content_type\": \"text/plain\", \"linkify\": true, ",
+        "\"suggested_replies\": false, \"refetch_settings\": false}\n\n",
+        "event: text\ndata: {\"text\": \"Hi\"}\n\n",
+        "event: done\ndata: {}\n\n",
+    ))])
+    .await;
+    let client = mock_client(&server);
+    let request = ChatRequest::builder().user_message("Hello").build().unwrap();
+    let events: Vec<ChatResponse> = client
+        .stream_request(request.clone())
+        .await
+        .unwrap()
+        .map(|item| item.unwrap())
+        .collect()
+        .await;
+
+    assert_eq!(events[0].event, ChatEventType::Meta);
+    let Some(ChatResponseData::Meta(meta)) = &events[0].data else {
+        panic!("expected meta data, got {:?}", events[0].data);
+    };
+    assert_eq!(meta.parsed_content_type(), Some(ContentType::PlainText));
+    assert_eq!(meta.linkify, Some(true));
+    assert_eq!(meta.suggested_replies, Some(false));
+    // Fields without a typed slot are kept
+    assert_eq!(meta.extra["refetch_settings"], false);
+    let frame = events[0].to_sse_frame();
+    assert!(frame.starts_with("event: meta\ndata: {"));
+    assert!(frame.contains("\"refetch_settings\":false"));
+
+    // The aggregated response keeps the hints
+    let response = client
+        .stream_request(request)
+        .await
+        .unwrap()
+        .collect_response()
+        .await
+        .unwrap();
+    assert_eq!(response.text, "Hi");
+    assert_eq!(response.meta.as_ref(), Some(meta));
+    assert_eq!(MetaData::default().parsed_content_type(), None);
+
+    debug!("Meta event test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_stream_request_raw_json() {
+    setup();
+    debug!("Starting raw JSON request test");
+
+    let server = start_mock_server(vec![MockResponse::sse(concat!(
+        "event: text\ndata: {\"text\": \"Hi\"}\n\n",
+        "event: done\ndata: {}\n\n",
+    ))])
+    .await;
+    let client = mock_client(&server).with_role_mapping("user", "human");
+    let body = serde_json::json!({
+        "version": "1.2",
+        "type": "query",
+        "query": [{"role": "user", "content": "Hello", "content_type": "text/markdown"}],
+        "user_id": "",
+        "conversation_id": "",
+        "message_id": "",
+        "experimental_field": {"enabled": true},
+    });
+
+    let response = client
+        .stream_request_raw_json(body.clone())
+        .await
+        .unwrap()
+        .collect_response()
+        .await
+        .unwrap();
+    assert_eq!(response.text, "Hi");
+
+    // The body reaches the bot untouched, without role mapping
+    let raw = server.requests.lock().unwrap()[0].clone();
+    assert!(raw.starts_with("POST /bot/TestBot"));
+    assert_eq!(captured_json(&raw), body);
+
+    debug!("Raw JSON request test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_suggested_reply_events() {
+    setup();
+    debug!("Starting suggested reply test");
+
+    let server = start_mock_server(vec![MockResponse::sse(concat!(
+        "event: text\ndata: {\"text\": \"Paris.\"}\n\n",
+        "event: suggested_reply\ndata: {\"text\": \"What about Lyon?\"}\n\n",
+        "event: suggested_reply\ndata: {\"text\": \"Tell me more\"}\n\n",
+        "event: done\ndata: {}\n\n",
+    ))])
+    .await;
+    let client = mock_client(&server);
+    let request = ChatRequest::builder().user_message("Capital of France?").build().unwrap();
+
+    let events: Vec<ChatResponse> = client
+        .stream_request(request.clone())
+        .await
+        .unwrap()
+        .map(|item| item.unwrap())
+        .collect()
+        .await;
+    assert_eq!(events[1].event, ChatEventType::SuggestedReply);
+    assert!(matches!(
+        &events[1].data,
+        Some(ChatResponseData::SuggestedReply { text }) if text == "What about Lyon?"
+    ));
+    assert_eq!(
+        events[1].to_sse_frame(),
+        "event: suggested_reply\ndata: {\"text\":\"What about Lyon?\"}\n\n"
+    );
+
+    // Suggestions are collected apart from the answer text
+    let response = client
+        .stream_request(request)
+        .await
+        .unwrap()
+        .collect_response()
+        .await
+        .unwrap();
+    assert_eq!(response.text, "Paris.");
+    assert_eq!(response.suggested_replies, ["What about Lyon?", "Tell me more"]);
+
+    debug!("Suggested reply test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_usage_events() {
+    use crate::Usage;
+    setup();
+    debug!("Starting usage event test");
+
+    let server = start_mock_server(vec![MockResponse::sse(concat!(
+        "event: text\ndata: {\"text\": \"Hi\"}\n\n",
+        "event: json\ndata: {\"choices\": [], \"usage\": {\"prompt_tokens\": 12, \"completion_tokens\": 3}}\n\n",
+        "event: done\ndata: {\"message_id\": \"m1\", \"completion_tokens\": 4, \"cost\": 30}\n\n",
+    ))])
+    .await;
+    let client = mock_client(&server);
+    let request = ChatRequest::builder().user_message("Hello").build().unwrap();
+
+    let events: Vec<ChatResponse> = client
+        .stream_request(request.clone())
+        .await
+        .unwrap()
+        .map(|item| item.unwrap())
+        .collect()
+        .await;
+    let usages: Vec<&Usage> = events
+        .iter()
+        .filter_map(|event| match (&event.event, &event.data) {
+            (ChatEventType::Usage, Some(ChatResponseData::Usage(usage))) => Some(usage),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(usages.len(), 2);
+    assert_eq!(usages[0].prompt_tokens, Some(12));
+    assert_eq!(usages[0].total(), Some(15));
+    // `cost` is read as points, and the usage event precedes `done`
+    assert_eq!(usages[1].points, Some(30.0));
+    assert_eq!(events.last().unwrap().event, ChatEventType::Done);
+    assert_eq!(events[events.len() - 2].event, ChatEventType::Usage);
+
+    // The non-streaming API merges the reports
+    let response = client.send_request(request).await.unwrap();
+    assert_eq!(
+        response.usage,
+        Some(Usage {
+            prompt_tokens: Some(12),
+            completion_tokens: Some(4),
+            total_tokens: None,
+            points: Some(30.0),
+        })
+    );
+    assert_eq!(response.done.unwrap().message_id.as_deref(), Some("m1"));
+
+    // Payloads without accounting report no usage
+    assert_eq!(Usage::from_payload(&serde_json::json!({"message_id": "m1"})), None);
+
+    debug!("Usage event test completed");
+}
+
+#[cfg(feature = "upload")]
+#[test_log::test(tokio::test)]
+async fn test_post_message_attachment() {
+    use crate::MessageAttachmentResponse;
+    setup();
+    debug!("Starting message attachment test");
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("chart.png");
+    std::fs::write(&path, b"not really a png").unwrap();
+    let path = path.to_string_lossy().into_owned();
+
+    let server = start_mock_server(vec![MockResponse::json(
+        200,
+        r#"{"inline_ref": "ab12", "attachment_url": "https://pfst.cf2.poecdn.net/base/chart"}"#,
+    )])
+    .await;
+    let client = mock_client(&server)
+        .with_file_attachment_url(&format!("{}/file_attachment", server.url));
+
+    let response = client
+        .post_message_attachment(
+            "m-42",
+            FileUploadRequest::LocalFile {
+                file: path,
+                mime_type: Some("image/png".to_string()),
+            },
+            true,
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        response,
+        MessageAttachmentResponse {
+            inline_ref: Some("ab12".to_string()),
+            attachment_url: Some("https://pfst.cf2.poecdn.net/base/chart".to_string()),
+        }
+    );
+
+    // Remote files are passed by URL
+    client
+        .post_message_attachment(
+            "m-42",
+            FileUploadRequest::RemoteFile {
+                download_url: "https://example.com/report.pdf".to_string(),
+            },
+            false,
+        )
+        .await
+        .unwrap();
+
+    {
+        let requests = server.requests.lock().unwrap();
+        let local = requests[0].to_ascii_lowercase();
+        assert!(local.starts_with("post /file_attachment"));
+        // The server-bot protocol sends the access key without a scheme
+        assert!(local.contains("authorization: test_key\r\n"));
+        assert!(local.contains("name=\"message_id\"") && local.contains("\r\nm-42\r\n"));
+        assert!(local.contains("name=\"is_inline\"") && local.contains("\r\ntrue\r\n"));
+        assert!(local.contains("filename=\"chart.png\""));
+        assert!(requests[1].contains("name=\"download_url\""));
+        assert!(requests[1].contains("https://example.com/report.pdf"));
+        assert!(requests[1].contains("\r\nfalse\r\n"));
+    }
+
+    assert!(matches!(
+        client
+            .post_message_attachment(
+                "m-42",
+                FileUploadRequest::LocalFile {
+                    file: "/nonexistent/file.png".to_string(),
+                    mime_type: None,
+                },
+                false,
+            )
+            .await,
+        Err(PoeError::FileNotFound(_))
+    ));
+
+    debug!("Message attachment test completed");
+}
+
+#[test]
+fn test_resolve_inline_refs() {
+    use crate::{CompleteResponse, resolve_inline_refs};
+    setup();
+    debug!("Starting inline reference test");
+
+    let file = |name: &str, content_type: &str, inline_ref: &str| FileData {
+        url: format!("https://pfst.cf2.poecdn.net/base/{}", name),
+        name: name.to_string(),
+        content_type: content_type.to_string(),
+        inline_ref: inline_ref.to_string(),
+    };
+    let response = CompleteResponse {
+        text: "Chart: ![sales][r1]\nSee [the report][r2], [][r2] and [broken][zz] [x].".to_string(),
+        files: vec![
+            file("chart.png", "image/png", "r1"),
+            file("report.pdf", "application/pdf", "r2"),
+            file("other.txt", "text/plain", ""),
+        ],
+        ..Default::default()
+    };
+
+    assert_eq!(
+        response.text_with_files(),
+        "Chart: ![sales](https://pfst.cf2.poecdn.net/base/chart.png)\n\
+         See [the report](https://pfst.cf2.poecdn.net/base/report.pdf), \
+         [report.pdf](https://pfst.cf2.poecdn.net/base/report.pdf) and [broken][zz] [x]."
+    );
+
+    // Non-image files written with image syntax become links, and URLs that
+    // would break the link are wrapped
+    let mut spaced = file("my notes.md", "text/markdown", "n1");
+    spaced.url = "https://example.com/my notes.md".to_string();
+    assert_eq!(
+        resolve_inline_refs("![notes][n1] [[n1]", &[spaced]),
+        "[notes](<https://example.com/my notes.md>) [[n1]"
+    );
+
+    debug!("Inline reference test completed");
+}
+
+#[test_log::test(tokio::test)]
+async fn test_report_feedback() {
+    use crate::{FeedbackType, ReportFeedbackRequest};
+    setup();
+    debug!("Starting report feedback test");
+
+    let server = start_mock_server(vec![MockResponse::json(200, "{}")]).await;
+    let client = mock_client(&server);
+    client
+        .report_feedback("msg-1", "conv-1", FeedbackType::Like)
+        .await
+        .unwrap();
+    client
+        .send_feedback(
+            ReportFeedbackRequest::new("msg-2", "conv-1", FeedbackType::Dislike)
+                .with_user_id("u-7"),
+        )
+        .await
+        .unwrap();
+
+    {
+        let requests = server.requests.lock().unwrap();
+        assert!(requests[0].starts_with("POST /bot/TestBot"));
+        assert_eq!(
+            captured_json(&requests[0]),
+            serde_json::json!({
+                "version": "1.1",
+                "type": "report_feedback",
+                "message_id": "msg-1",
+                "user_id": "",
+                "conversation_id": "conv-1",
+                "feedback_type": "like",
+            })
+        );
+        let second = captured_json(&requests[1]);
+        assert_eq!(second["feedback_type"], "dislike");
+        assert_eq!(second["user_id"], "u-7");
+    }
+
+    // HTTP errors are surfaced with their status
+    let failing = start_mock_server(vec![MockResponse::json(403, r#"{"error": "forbidden"}"#)]).await;
+    let error = mock_client(&failing)
+        .report_feedback("msg-1", "conv-1", FeedbackType::Like)
+        .await
+        .unwrap_err();
+    assert_eq!(error.status(), Some(403));
+
+    debug!("Report feedback test completed");
+}
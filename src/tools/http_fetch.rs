@@ -0,0 +1,327 @@
+use super::Tool;
+use crate::error::PoeError;
+use crate::types::{ChatTool, ParamType};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use reqwest::Client;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect::Policy;
+use serde_json::{Value, json};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use url::{Host, Url};
+
+const MAX_REDIRECTS: usize = 5;
+
+/// Host allow/deny rules; a rule matches the host itself and its subdomains
+#[derive(Debug, Clone, Default)]
+struct HostRules {
+    allow: Vec<String>,
+    deny: Vec<String>,
+    private_networks: bool,
+}
+
+impl HostRules {
+    fn permits(&self, url: &Url) -> bool {
+        if !matches!(url.scheme(), "http" | "https") {
+            return false;
+        }
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        // IP 位址不經 DNS 解析，在此直接擋下內部網路
+        let ip = match url.host() {
+            Some(Host::Ipv4(ip)) => Some(IpAddr::V4(ip)),
+            Some(Host::Ipv6(ip)) => Some(IpAddr::V6(ip)),
+            _ => None,
+        };
+        if !self.private_networks && ip.is_some_and(|ip| !is_public(ip)) {
+            return false;
+        }
+        let host = host.to_lowercase();
+        let matches = |rule: &String| {
+            host == *rule
+                || host
+                    .strip_suffix(rule.as_str())
+                    .is_some_and(|prefix| prefix.ends_with('.'))
+        };
+
+        !self.deny.iter().any(matches) && (self.allow.is_empty() || self.allow.iter().any(matches))
+    }
+}
+
+/// Tool giving a bot read access to web pages.
+///
+/// Only http(s) URLs permitted by the host rules are fetched (redirects are
+/// checked too), bodies are capped at `max_bytes`, and HTML can be reduced
+/// to plain text before it is returned to the model.
+///
+/// Loopback, private, link-local and other non-public addresses are refused
+/// unless [`HttpFetchTool::with_private_networks`] allows them, whether they
+/// appear in the URL, in a redirect or as the DNS result of a host name.
+#[derive(Debug, Clone)]
+pub struct HttpFetchTool {
+    name: String,
+    rules: Arc<HostRules>,
+    max_bytes: usize,
+    html_to_text: bool,
+}
+
+impl Default for HttpFetchTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HttpFetchTool {
+    /// Create a `fetch_url` tool allowing every public host until an allowlist is set
+    pub fn new() -> Self {
+        Self {
+            name: "fetch_url".to_string(),
+            rules: Arc::new(HostRules::default()),
+            max_bytes: 256 * 1024,
+            html_to_text: true,
+        }
+    }
+
+    /// Function name exposed to the model
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Only fetch these hosts (and their subdomains)
+    pub fn with_allowed_hosts<I, S>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Arc::make_mut(&mut self.rules).allow =
+            hosts.into_iter().map(|h| h.into().to_lowercase()).collect();
+        self
+    }
+
+    /// Never fetch these hosts (and their subdomains); takes precedence over the allowlist
+    pub fn with_denied_hosts<I, S>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Arc::make_mut(&mut self.rules).deny =
+            hosts.into_iter().map(|h| h.into().to_lowercase()).collect();
+        self
+    }
+
+    /// Allow fetching loopback, private and link-local addresses (refused by
+    /// default), e.g. for intranet pages
+    pub fn with_private_networks(mut self, allow: bool) -> Self {
+        Arc::make_mut(&mut self.rules).private_networks = allow;
+        self
+    }
+
+    /// Cap the returned body at `bytes` (default 256 KiB)
+    pub fn with_max_bytes(mut self, bytes: usize) -> Self {
+        self.max_bytes = bytes;
+        self
+    }
+
+    /// Whether HTML responses are converted to plain text (default `true`)
+    pub fn with_html_to_text(mut self, html_to_text: bool) -> Self {
+        self.html_to_text = html_to_text;
+        self
+    }
+
+    fn http_client(&self) -> Result<Client, PoeError> {
+        let rules = self.rules.clone();
+        let policy = Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if rules.permits(attempt.url()) {
+                attempt.follow()
+            } else {
+                attempt.error("redirect to a host that is not allowed")
+            }
+        });
+
+        let mut builder = Client::builder().redirect(policy);
+        if !self.rules.private_networks {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+        Ok(builder.build()?)
+    }
+
+    async fn fetch(&self, url: Url) -> Result<Value, PoeError> {
+        let response = self.http_client()?.get(url).send().await?;
+        let status = response.status().as_u16();
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+
+        let mut body = Vec::new();
+        let mut truncated = false;
+        let mut chunks = response.bytes_stream();
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk?;
+            let remaining = self.max_bytes - body.len();
+            if chunk.len() > remaining {
+                body.extend_from_slice(&chunk[..remaining]);
+                truncated = true;
+                break;
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        let mut text = String::from_utf8_lossy(&body).into_owned();
+        if self.html_to_text && content_type.contains("html") {
+            text = html_to_text(&text);
+        }
+
+        Ok(json!({
+            "status": status,
+            "content_type": content_type,
+            "body": text,
+            "truncated": truncated,
+        }))
+    }
+}
+
+/// DNS resolver refusing host names that resolve to a non-public address
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .collect();
+            // 任一位址指向內部網路即拒絕，避免混合解析結果繞過檢查
+            if let Some(addr) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+                return Err(format!(
+                    "{} resolves to the non-public address {}",
+                    name.as_str(),
+                    addr.ip()
+                )
+                .into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Check whether an address is publicly routable
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || first == 0
+                // 100.64.0.0/10 電信級 NAT
+                || (first == 100 && (second & 0xc0) == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // fc00::/7 唯一本地位址、fe80::/10 鏈路本地位址
+                    || (first & 0xfe00) == 0xfc00
+                    || (first & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+/// Reduce an HTML document to readable text: drop scripts, styles and tags,
+/// decode common entities and collapse whitespace
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find('>') else {
+            rest = "";
+            break;
+        };
+
+        let tag = rest[1..end].trim_start_matches('/').to_lowercase();
+        let tag_name = tag
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default();
+        rest = &rest[end + 1..];
+
+        if matches!(tag_name, "script" | "style" | "noscript") && !rest.is_empty() {
+            let closing = format!("</{}", tag_name);
+            rest = match rest.to_ascii_lowercase().find(&closing) {
+                Some(index) => rest[index..]
+                    .find('>')
+                    .map_or("", |i| &rest[index + i + 1..]),
+                None => "",
+            };
+        }
+        if matches!(
+            tag_name,
+            "p" | "br" | "div" | "li" | "tr" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6"
+        ) {
+            text.push('\n');
+        } else {
+            text.push(' ');
+        }
+    }
+    text.push_str(rest);
+
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[async_trait]
+impl Tool for HttpFetchTool {
+    fn definition(&self) -> ChatTool {
+        ChatTool::function(&self.name)
+            .description(
+                "Fetch a web page over HTTP(S) and return its status, content type and body",
+            )
+            .param("url", ParamType::String, true)
+            .description("Absolute http or https URL to fetch")
+            .build()
+    }
+
+    async fn call(&self, arguments: Value) -> Result<String, PoeError> {
+        let url = arguments["url"]
+            .as_str()
+            .ok_or_else(|| PoeError::ToolCallParseFailed("missing url".to_string()))?;
+        let url = Url::parse(url)?;
+        if !self.rules.permits(&url) {
+            return Err(PoeError::ToolExecutionFailed(format!(
+                "URL not allowed: {}",
+                url
+            )));
+        }
+
+        Ok(self.fetch(url).await?.to_string())
+    }
+}
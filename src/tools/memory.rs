@@ -0,0 +1,251 @@
+use super::Tool;
+use crate::error::PoeError;
+use crate::types::{ChatTool, ParamType};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// A note kept in a [`MemoryStore`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryRecord {
+    pub key: String,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<Vec<f32>>,
+    /// Relevance score filled in by `MemoryStore::query`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f32>,
+}
+
+/// Lookup passed to `MemoryStore::query`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryQuery {
+    pub text: String,
+    /// Embedding of `text`, when the tool has an [`Embedder`]
+    pub embedding: Option<Vec<f32>>,
+    pub limit: usize,
+}
+
+/// Storage backend for the memory tool (qdrant, sqlite, ...)
+#[async_trait]
+pub trait MemoryStore: Send + Sync {
+    /// Insert the record or replace the one with the same key
+    async fn upsert(&self, record: MemoryRecord) -> Result<(), PoeError>;
+
+    /// Return up to `query.limit` records, most relevant first
+    async fn query(&self, query: &MemoryQuery) -> Result<Vec<MemoryRecord>, PoeError>;
+
+    /// Remove a record, returning whether it existed
+    async fn delete(&self, key: &str) -> Result<bool, PoeError>;
+}
+
+/// Turns texts into embedding vectors for vector-backed stores
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, PoeError>;
+}
+
+/// Process-local [`MemoryStore`] ranking by cosine similarity when both
+/// sides have embeddings, and by shared words otherwise
+#[derive(Debug, Default)]
+pub struct InMemoryStore {
+    records: Mutex<HashMap<String, MemoryRecord>>,
+}
+
+impl InMemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 { 0.0 } else { dot / norms }
+}
+
+fn keyword_score(query: &str, text: &str) -> f32 {
+    let text = text.to_lowercase();
+    query
+        .to_lowercase()
+        .split_whitespace()
+        .filter(|word| text.contains(word))
+        .count() as f32
+}
+
+#[async_trait]
+impl MemoryStore for InMemoryStore {
+    async fn upsert(&self, record: MemoryRecord) -> Result<(), PoeError> {
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        records.insert(record.key.clone(), record);
+        Ok(())
+    }
+
+    async fn query(&self, query: &MemoryQuery) -> Result<Vec<MemoryRecord>, PoeError> {
+        let records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        let mut matches: Vec<MemoryRecord> = records
+            .values()
+            .map(|record| {
+                let score = match (&query.embedding, &record.embedding) {
+                    (Some(query), Some(embedding)) => cosine_similarity(query, embedding),
+                    _ => keyword_score(&query.text, &record.text),
+                };
+                MemoryRecord {
+                    score: Some(score),
+                    ..record.clone()
+                }
+            })
+            .filter(|record| record.score > Some(0.0))
+            .collect();
+
+        matches.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        matches.truncate(query.limit);
+        Ok(matches)
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool, PoeError> {
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(records.remove(key).is_some())
+    }
+}
+
+/// Tool letting the model save, search and delete notes in a [`MemoryStore`]
+#[derive(Clone)]
+pub struct MemoryTool {
+    name: String,
+    store: Arc<dyn MemoryStore>,
+    embedder: Option<Arc<dyn Embedder>>,
+    default_limit: usize,
+}
+
+impl MemoryTool {
+    /// Create a `memory` tool backed by `store`
+    pub fn new(store: Arc<dyn MemoryStore>) -> Self {
+        Self {
+            name: "memory".to_string(),
+            store,
+            embedder: None,
+            default_limit: 5,
+        }
+    }
+
+    /// Function name exposed to the model
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Embed saved notes and queries with `embedder`
+    pub fn with_embedder(mut self, embedder: Arc<dyn Embedder>) -> Self {
+        self.embedder = Some(embedder);
+        self
+    }
+
+    /// Number of results returned when the model gives no limit (default 5)
+    pub fn with_default_limit(mut self, limit: usize) -> Self {
+        self.default_limit = limit;
+        self
+    }
+
+    async fn embed(&self, text: &str) -> Result<Option<Vec<f32>>, PoeError> {
+        let Some(embedder) = &self.embedder else {
+            return Ok(None);
+        };
+        Ok(embedder
+            .embed(&[text.to_string()])
+            .await?
+            .into_iter()
+            .next())
+    }
+}
+
+impl std::fmt::Debug for MemoryTool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MemoryTool")
+            .field("name", &self.name)
+            .field("has_embedder", &self.embedder.is_some())
+            .field("default_limit", &self.default_limit)
+            .finish()
+    }
+}
+
+fn required_str<'a>(arguments: &'a Value, field: &str) -> Result<&'a str, PoeError> {
+    arguments[field]
+        .as_str()
+        .ok_or_else(|| PoeError::ToolCallParseFailed(format!("missing {}", field)))
+}
+
+#[async_trait]
+impl Tool for MemoryTool {
+    fn definition(&self) -> ChatTool {
+        ChatTool::function(&self.name)
+            .description(
+                "Long-term memory: save a note under a key, search notes by relevance, or delete a note",
+            )
+            .param("action", ParamType::String, true)
+            .enum_values(["save", "search", "delete"])
+            .param("key", ParamType::String, false)
+            .description("Note key (save, delete)")
+            .param("text", ParamType::String, false)
+            .description("Note text (save) or search query (search)")
+            .param("limit", ParamType::Integer, false)
+            .description("Maximum number of results (search)")
+            .build()
+    }
+
+    async fn call(&self, arguments: Value) -> Result<String, PoeError> {
+        let output = match required_str(&arguments, "action")? {
+            "save" => {
+                let key = required_str(&arguments, "key")?;
+                let text = required_str(&arguments, "text")?;
+                self.store
+                    .upsert(MemoryRecord {
+                        key: key.to_string(),
+                        text: text.to_string(),
+                        embedding: self.embed(text).await?,
+                        score: None,
+                    })
+                    .await?;
+                json!({ "saved": key })
+            }
+            "search" => {
+                let text = required_str(&arguments, "text")?;
+                let limit = arguments["limit"]
+                    .as_u64()
+                    .map_or(self.default_limit, |limit| limit as usize);
+                let records = self
+                    .store
+                    .query(&MemoryQuery {
+                        text: text.to_string(),
+                        embedding: self.embed(text).await?,
+                        limit,
+                    })
+                    .await?;
+                let results: Vec<Value> = records
+                    .into_iter()
+                    .map(|record| json!({ "key": record.key, "text": record.text, "score": record.score }))
+                    .collect();
+                json!({ "results": results })
+            }
+            "delete" => {
+                let key = required_str(&arguments, "key")?;
+                json!({ "deleted": self.store.delete(key).await? })
+            }
+            action => {
+                return Err(PoeError::ToolCallParseFailed(format!(
+                    "unknown action: {}",
+                    action
+                )));
+            }
+        };
+
+        Ok(output.to_string())
+    }
+}
@@ -0,0 +1,101 @@
+#[cfg(feature = "tools-http")]
+pub mod http_fetch;
+pub mod memory;
+#[cfg(feature = "tools-process")]
+pub mod process;
+
+use crate::error::PoeError;
+use crate::types::{ChatTool, ChatToolCall, ChatToolResult, ROLE_TOOL};
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+/// A tool the model can call, executed locally
+#[async_trait]
+pub trait Tool: Send + Sync {
+    /// Definition sent to the bot in `ChatRequest::tools`
+    fn definition(&self) -> ChatTool;
+
+    /// Run the tool with the parsed call arguments and return its output
+    async fn call(&self, arguments: Value) -> Result<String, PoeError>;
+}
+
+/// Set of tools exposed to a bot, dispatching tool calls by function name
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: Vec<Arc<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a tool, replacing any tool with the same function name
+    pub fn register(mut self, tool: impl Tool + 'static) -> Self {
+        let name = tool.definition().function.name;
+        self.tools
+            .retain(|existing| existing.definition().function.name != name);
+        self.tools.push(Arc::new(tool));
+        self
+    }
+
+    /// Get a tool by function name
+    pub fn get(&self, name: &str) -> Option<&Arc<dyn Tool>> {
+        self.tools
+            .iter()
+            .find(|tool| tool.definition().function.name == name)
+    }
+
+    /// Definitions of every registered tool, for `ChatRequest::tools`
+    pub fn definitions(&self) -> Vec<ChatTool> {
+        self.tools.iter().map(|tool| tool.definition()).collect()
+    }
+
+    /// Execute one tool call; failures are reported to the model as an
+    /// `{"error": ...}` result rather than aborting the tool loop
+    pub async fn execute(&self, tool_call: &ChatToolCall) -> ChatToolResult {
+        let name = &tool_call.function.name;
+        let output = match self.get(name) {
+            Some(tool) => match serde_json::from_str(&tool_call.function.arguments) {
+                Ok(mut arguments) => {
+                    tool.definition().function.apply_defaults(&mut arguments);
+                    tool.call(arguments).await
+                }
+                Err(e) => Err(PoeError::ToolCallParseFailed(e.to_string())),
+            },
+            None => Err(PoeError::ToolExecutionFailed(format!(
+                "unknown tool: {}",
+                name
+            ))),
+        };
+
+        ChatToolResult {
+            role: ROLE_TOOL.to_string(),
+            tool_call_id: tool_call.id.clone(),
+            name: name.clone(),
+            content: output.unwrap_or_else(|e| json!({ "error": e.to_string() }).to_string()),
+        }
+    }
+
+    /// Execute tool calls concurrently, keeping their order
+    pub async fn execute_all(&self, tool_calls: &[ChatToolCall]) -> Vec<ChatToolResult> {
+        join_all(tool_calls.iter().map(|tool_call| self.execute(tool_call))).await
+    }
+}
+
+impl std::fmt::Debug for ToolRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ToolRegistry")
+            .field(
+                "tools",
+                &self
+                    .tools
+                    .iter()
+                    .map(|tool| tool.definition().function.name)
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
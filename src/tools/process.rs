@@ -0,0 +1,190 @@
+use super::Tool;
+use crate::error::PoeError;
+use crate::types::{ChatTool, ParamType};
+use async_trait::async_trait;
+use serde_json::{Value, json};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+
+/// Subprocess tool that only runs allowlisted programs.
+///
+/// Programs are executed directly (never through a shell), with a null
+/// stdin, a wall-clock timeout and a cap on captured stdout/stderr; the
+/// process is killed when either limit is hit.
+#[derive(Debug, Clone)]
+pub struct ProcessTool {
+    name: String,
+    allowed_commands: Vec<String>,
+    timeout: Duration,
+    max_output_bytes: usize,
+    working_dir: Option<PathBuf>,
+    clear_env: bool,
+}
+
+impl ProcessTool {
+    /// Create a `run_command` tool allowed to run only `allowed_commands`
+    pub fn new<I, S>(allowed_commands: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            name: "run_command".to_string(),
+            allowed_commands: allowed_commands.into_iter().map(Into::into).collect(),
+            timeout: Duration::from_secs(10),
+            max_output_bytes: 64 * 1024,
+            working_dir: None,
+            clear_env: true,
+        }
+    }
+
+    /// Function name exposed to the model
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Kill the process after `timeout` (default 10s)
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Cap captured stdout and stderr at `bytes` each (default 64 KiB)
+    pub fn with_max_output_bytes(mut self, bytes: usize) -> Self {
+        self.max_output_bytes = bytes;
+        self
+    }
+
+    /// Run commands in `dir` instead of the current directory
+    pub fn with_working_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.working_dir = Some(dir.into());
+        self
+    }
+
+    /// Whether to start commands with an empty environment (default `true`)
+    pub fn with_clear_env(mut self, clear_env: bool) -> Self {
+        self.clear_env = clear_env;
+        self
+    }
+
+    async fn run(&self, program: &str, args: &[String]) -> Result<Value, PoeError> {
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if self.clear_env {
+            command.env_clear();
+            if let Some(path) = std::env::var_os("PATH") {
+                command.env("PATH", path);
+            }
+        }
+        if let Some(dir) = &self.working_dir {
+            command.current_dir(dir);
+        }
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| PoeError::ToolExecutionFailed(format!("{}: {}", program, e)))?;
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+
+        let run = async {
+            let ((stdout, stdout_truncated), (stderr, stderr_truncated)) = tokio::join!(
+                read_capped(stdout, self.max_output_bytes),
+                read_capped(stderr, self.max_output_bytes),
+            );
+            let truncated = stdout_truncated || stderr_truncated;
+            if truncated {
+                let _ = child.start_kill();
+            }
+            let status = child.wait().await;
+            (status, stdout, stderr, truncated)
+        };
+
+        let (status, stdout, stderr, truncated) =
+            tokio::time::timeout(self.timeout, run).await.map_err(|_| {
+                PoeError::ToolExecutionFailed(format!(
+                    "{} timed out after {:?}",
+                    program, self.timeout
+                ))
+            })?;
+        let status = status.map_err(|e| PoeError::ToolExecutionFailed(e.to_string()))?;
+
+        Ok(json!({
+            "exit_code": status.code(),
+            "stdout": stdout,
+            "stderr": stderr,
+            "truncated": truncated,
+        }))
+    }
+}
+
+// Read at most `limit` bytes, reporting whether the stream had more
+async fn read_capped<R: AsyncRead + Unpin>(reader: Option<R>, limit: usize) -> (String, bool) {
+    let Some(reader) = reader else {
+        return (String::new(), false);
+    };
+
+    let mut buffer = Vec::new();
+    let _ = reader.take(limit as u64 + 1).read_to_end(&mut buffer).await;
+    let truncated = buffer.len() > limit;
+    buffer.truncate(limit);
+    (String::from_utf8_lossy(&buffer).into_owned(), truncated)
+}
+
+#[async_trait]
+impl Tool for ProcessTool {
+    fn definition(&self) -> ChatTool {
+        ChatTool::function(&self.name)
+            .description(&format!(
+                "Run a program without a shell and return its exit code, stdout and stderr. Allowed programs: {}",
+                self.allowed_commands.join(", ")
+            ))
+            .param("command", ParamType::String, true)
+            .enum_values(self.allowed_commands.iter().map(String::as_str))
+            .description("Program to run")
+            .param("args", ParamType::Array, false)
+            .items(ParamType::String)
+            .description("Arguments passed to the program")
+            .build()
+    }
+
+    async fn call(&self, arguments: Value) -> Result<String, PoeError> {
+        let program = arguments["command"]
+            .as_str()
+            .ok_or_else(|| PoeError::ToolCallParseFailed("missing command".to_string()))?;
+        if !self
+            .allowed_commands
+            .iter()
+            .any(|allowed| allowed == program)
+        {
+            return Err(PoeError::ToolExecutionFailed(format!(
+                "command not allowed: {}",
+                program
+            )));
+        }
+
+        let args = match &arguments["args"] {
+            Value::Null => Vec::new(),
+            Value::Array(args) => args
+                .iter()
+                .map(|arg| arg.as_str().map(str::to_string))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| PoeError::ToolCallParseFailed("args must be strings".to_string()))?,
+            _ => {
+                return Err(PoeError::ToolCallParseFailed(
+                    "args must be an array".to_string(),
+                ));
+            }
+        };
+
+        Ok(self.run(program, &args).await?.to_string())
+    }
+}
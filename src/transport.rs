@@ -0,0 +1,258 @@
+use crate::error::PoeError;
+use crate::logging::is_sensitive_header;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::stream::BoxStream;
+use futures_util::{StreamExt, TryStreamExt};
+#[cfg(feature = "reqwest")]
+use reqwest::Client;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+/// Outgoing HTTP request handed to an [`HttpTransport`]
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// HTTP response returned by an [`HttpTransport`], with a streamed body
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: BoxStream<'static, Result<Bytes, PoeError>>,
+}
+
+impl HttpResponse {
+    /// Check for a 2xx status code
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Get every value of a header, matched case-insensitively
+    pub fn header_values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.headers
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Read the whole body as UTF-8 text (lossy)
+    pub async fn text(self) -> Result<String, PoeError> {
+        let body: Vec<u8> = self
+            .body
+            .try_fold(Vec::new(), |mut body, chunk| async move {
+                body.extend_from_slice(&chunk);
+                Ok(body)
+            })
+            .await?;
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+}
+
+/// HTTP layer used by `PoeClient` for every request: chat, file uploads,
+/// model lists and GraphQL.
+///
+/// Implement this to run the crate on another runtime or over a custom
+/// channel (unix sockets, test harnesses); `ReqwestTransport` is the default
+/// with the `reqwest` feature.
+#[async_trait]
+pub trait HttpTransport: Send + Sync {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, PoeError>;
+}
+
+#[async_trait]
+impl<T: HttpTransport + ?Sized> HttpTransport for std::sync::Arc<T> {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, PoeError> {
+        (**self).send(request).await
+    }
+}
+
+/// Default transport backed by a pooled `reqwest::Client`
+#[cfg(feature = "reqwest")]
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestTransport {
+    client: Client,
+}
+
+#[cfg(feature = "reqwest")]
+impl ReqwestTransport {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+}
+
+#[cfg(feature = "reqwest")]
+#[async_trait]
+impl HttpTransport for ReqwestTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, PoeError> {
+        let method = reqwest::Method::from_bytes(request.method.as_bytes())
+            .map_err(|e| PoeError::TransportError(e.to_string()))?;
+
+        let response = request
+            .headers
+            .iter()
+            .fold(
+                self.client.request(method, &request.url),
+                |builder, (name, value)| builder.header(name, value),
+            )
+            .body(request.body)
+            .send()
+            .await?;
+
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| {
+                value
+                    .to_str()
+                    .ok()
+                    .map(|value| (name.to_string(), value.to_string()))
+            })
+            .collect();
+
+        Ok(HttpResponse {
+            status: response.status().as_u16(),
+            headers,
+            body: response.bytes_stream().map_err(PoeError::from).boxed(),
+        })
+    }
+}
+
+/// Placeholder used by `PoeClient::new` without the `reqwest` feature until
+/// a transport is set with `PoeClient::with_transport`
+#[cfg(not(feature = "reqwest"))]
+pub(crate) struct MissingTransport;
+
+#[cfg(not(feature = "reqwest"))]
+#[async_trait]
+impl HttpTransport for MissingTransport {
+    async fn send(&self, _request: HttpRequest) -> Result<HttpResponse, PoeError> {
+        Err(PoeError::TransportError(
+            "no HTTP transport configured: enable the `reqwest` feature or call PoeClient::with_transport"
+                .to_string(),
+        ))
+    }
+}
+
+/// One request/response pair captured by an [`HttpTap`]
+#[derive(Debug, Clone)]
+pub struct HttpExchange {
+    /// Request as sent, with credential headers masked
+    pub request: HttpRequest,
+    pub status: u16,
+    pub response_headers: Vec<(String, String)>,
+    /// Response body bytes read so far
+    pub response_body: Vec<u8>,
+}
+
+impl HttpExchange {
+    /// Render the exchange in a stable text form for golden-file tests.
+    ///
+    /// Only the URL path and query are kept, JSON bodies are pretty-printed
+    /// and response headers are left out, since they vary between servers.
+    pub fn to_golden(&self) -> String {
+        let target = url::Url::parse(&self.request.url)
+            .map(|url| match url.query() {
+                Some(query) => format!("{}?{}", url.path(), query),
+                None => url.path().to_string(),
+            })
+            .unwrap_or_else(|_| self.request.url.clone());
+
+        let mut output = format!("> {} {}\n", self.request.method, target);
+        for (name, value) in &self.request.headers {
+            let _ = writeln!(output, "> {}: {}", name, value);
+        }
+        output.push_str(&golden_body(&self.request.body));
+        let _ = writeln!(output, "< {}", self.status);
+        output.push_str(&golden_body(&self.response_body));
+        output
+    }
+}
+
+fn golden_body(body: &[u8]) -> String {
+    if body.is_empty() {
+        return String::new();
+    }
+    let text = serde_json::from_slice::<serde_json::Value>(body)
+        .ok()
+        .and_then(|json| serde_json::to_string_pretty(&json).ok())
+        .unwrap_or_else(|| String::from_utf8_lossy(body).into_owned());
+    format!("{}\n", text.trim_end())
+}
+
+/// [`HttpTransport`] decorator recording the exact bytes of every call, for
+/// golden-file tests that catch unintended wire-format changes.
+///
+/// Share it with the client through an `Arc` to read the capture afterwards:
+/// `client.with_transport(tap.clone())`.
+pub struct HttpTap<T> {
+    inner: T,
+    // 每筆記錄各自上鎖，讓仍在讀取的串流在 clear 之後不會寫入其他記錄
+    exchanges: Mutex<Vec<Arc<Mutex<HttpExchange>>>>,
+}
+
+impl<T: HttpTransport> HttpTap<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            exchanges: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Get the calls captured so far, in the order they were sent
+    pub fn exchanges(&self) -> Vec<HttpExchange> {
+        self.exchanges
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|exchange| exchange.lock().unwrap_or_else(|e| e.into_inner()).clone())
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.exchanges
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
+
+#[async_trait]
+impl<T: HttpTransport> HttpTransport for HttpTap<T> {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, PoeError> {
+        let mut recorded = request.clone();
+        for (name, value) in &mut recorded.headers {
+            if is_sensitive_header(name) {
+                *value = "***MASKED***".to_string();
+            }
+        }
+
+        let response = self.inner.send(request).await?;
+        let exchange = Arc::new(Mutex::new(HttpExchange {
+            request: recorded,
+            status: response.status,
+            response_headers: response.headers.clone(),
+            response_body: Vec::new(),
+        }));
+        self.exchanges
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(exchange.clone());
+
+        // 邊讀取邊記錄回應內容，串流未讀完時只保留已收到的部分
+        let body = response
+            .body
+            .inspect_ok(move |chunk| {
+                exchange
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .response_body
+                    .extend_from_slice(chunk)
+            })
+            .boxed();
+        Ok(HttpResponse { body, ..response })
+    }
+}
@@ -1,6 +1,9 @@
+use crate::error::PoeError;
+use crate::util::generate_id;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::collections::HashMap;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 
 // Bot Chat request structure
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -20,11 +23,523 @@ pub struct ChatRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub logit_bias: Option<HashMap<String, f32>>,
+    pub logit_bias: Option<LogitBias>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stop_sequences: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+    /// Client-side handling of retryable `error` events, `None` to inherit
+    /// the client's setting; not sent to Poe
+    #[serde(skip)]
+    pub retryable_error_policy: Option<RetryableErrorPolicy>,
+    /// Scheduling priority used by `ScheduledPoeClient`; not sent to Poe
+    #[serde(skip)]
+    pub priority: RequestPriority,
+}
+
+/// Priority of a request waiting for a `RequestScheduler` slot
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RequestPriority {
+    /// Batch work that should yield to everything else
+    Background,
+    #[default]
+    Normal,
+    /// A user is waiting on the response
+    Interactive,
+}
+
+/// How `PoeClient::stream_request` handles an `error` event with `allow_retry: true`.
+///
+/// Requests without a policy use `Retry` when the client has a retry policy
+/// (see `PoeClient::with_retry_policy`) and `YieldAndContinue` otherwise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RetryableErrorPolicy {
+    /// Yield the error and keep reading the stream
+    #[default]
+    YieldAndContinue,
+    /// Yield the error and end the stream
+    Terminate,
+    /// Drop the error and re-send the request up to `max_retries` times
+    /// (subject to the retry budget) with exponential backoff, continuing
+    /// with the new response. A `replace_response` with empty text resets
+    /// any text already yielded; once a file or tool call has been yielded
+    /// the error is yielded instead, as those cannot be taken back.
+    Retry { max_retries: u32 },
+}
+
+// Protocol defaults so requests can be written as `ChatRequest { query, ..Default::default() }`
+impl Default for ChatRequest {
+    fn default() -> Self {
+        Self {
+            version: "1.1".to_string(),
+            r#type: "query".to_string(),
+            query: Vec::new(),
+            user_id: String::new(),
+            conversation_id: generate_id("conv"),
+            message_id: generate_id("msg"),
+            tools: None,
+            tool_calls: None,
+            tool_results: None,
+            temperature: None,
+            logit_bias: None,
+            stop_sequences: None,
+            seed: None,
+            tool_choice: None,
+            retryable_error_policy: None,
+            priority: RequestPriority::default(),
+        }
+    }
+}
+
+impl ChatRequest {
+    /// Start building a request with the protocol defaults
+    pub fn builder() -> ChatRequestBuilder {
+        ChatRequestBuilder::default()
+    }
+
+    /// Validate the role sequence against Poe's multi-turn rules: only
+    /// `system`/`user`/`bot` roles, system messages first, user and bot turns
+    /// alternating, and the conversation starting and ending with a user turn
+    pub fn validate_roles(&self) -> Result<(), PoeError> {
+        let mut previous: Option<&str> = None;
+
+        for (index, message) in self.query.iter().enumerate() {
+            let role = message.role.as_str();
+            match role {
+                ROLE_SYSTEM => {
+                    if previous.is_some() {
+                        return Err(PoeError::InvalidRoleSequence(format!(
+                            "message {}: system message must precede user and bot turns",
+                            index
+                        )));
+                    }
+                    continue;
+                }
+                ROLE_USER | ROLE_BOT => {}
+                _ => {
+                    return Err(PoeError::InvalidRoleSequence(format!(
+                        "message {}: unknown role '{}'",
+                        index, role
+                    )));
+                }
+            }
+
+            match previous {
+                None if role != ROLE_USER => {
+                    return Err(PoeError::InvalidRoleSequence(format!(
+                        "message {}: conversation must start with a user turn",
+                        index
+                    )));
+                }
+                Some(prev) if prev == role => {
+                    return Err(PoeError::InvalidRoleSequence(format!(
+                        "message {}: consecutive '{}' turns, user and bot must alternate",
+                        index, role
+                    )));
+                }
+                _ => {}
+            }
+            previous = Some(role);
+        }
+
+        if previous != Some(ROLE_USER) {
+            return Err(PoeError::InvalidRoleSequence(
+                "conversation must end with a user turn".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Fluent builder for [`ChatRequest`], created by [`ChatRequest::builder`].
+///
+/// Unset fields keep the protocol defaults (version, type, generated IDs);
+/// `build` validates the role sequence and the tool settings.
+#[derive(Debug, Clone, Default)]
+pub struct ChatRequestBuilder {
+    request: ChatRequest,
+}
+
+impl ChatRequestBuilder {
+    /// Append a message
+    pub fn message(mut self, message: ChatMessage) -> Self {
+        self.request.query.push(message);
+        self
+    }
+
+    pub fn system_message(self, content: &str) -> Self {
+        self.text_message(ROLE_SYSTEM, content)
+    }
+
+    pub fn user_message(self, content: &str) -> Self {
+        self.text_message(ROLE_USER, content)
+    }
+
+    pub fn bot_message(self, content: &str) -> Self {
+        self.text_message(ROLE_BOT, content)
+    }
+
+    /// Attach a file to the last added message
+    pub fn attachment(mut self, attachment: Attachment) -> Self {
+        if let Some(message) = self.request.query.last_mut() {
+            message
+                .attachments
+                .get_or_insert_with(Vec::new)
+                .push(attachment);
+        }
+        self
+    }
+
+    /// Set the content type of the last added message (text messages
+    /// default to [`ContentType::Markdown`])
+    pub fn content_type(mut self, content_type: ContentType) -> Self {
+        if let Some(message) = self.request.query.last_mut() {
+            message.content_type = content_type.as_str().to_string();
+        }
+        self
+    }
+
+    pub fn user_id(mut self, user_id: &str) -> Self {
+        self.request.user_id = user_id.to_string();
+        self
+    }
+
+    pub fn conversation_id(mut self, conversation_id: &str) -> Self {
+        self.request.conversation_id = conversation_id.to_string();
+        self
+    }
+
+    pub fn message_id(mut self, message_id: &str) -> Self {
+        self.request.message_id = message_id.to_string();
+        self
+    }
+
+    /// Offer a tool, e.g. one built with [`ChatTool::function`]
+    pub fn tool(mut self, tool: impl Into<ChatTool>) -> Self {
+        self.request
+            .tools
+            .get_or_insert_with(Vec::new)
+            .push(tool.into());
+        self
+    }
+
+    pub fn tool_choice(mut self, tool_choice: ToolChoice) -> Self {
+        self.request.tool_choice = Some(tool_choice);
+        self
+    }
+
+    /// Send the results of the previous turn's tool calls
+    pub fn tool_results(
+        mut self,
+        tool_calls: Vec<ChatToolCall>,
+        tool_results: Vec<ChatToolResult>,
+    ) -> Self {
+        self.request.tool_calls = Some(tool_calls);
+        self.request.tool_results = Some(tool_results);
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.request.temperature = Some(temperature);
+        self
+    }
+
+    pub fn logit_bias(mut self, logit_bias: LogitBias) -> Self {
+        self.request.logit_bias = Some(logit_bias);
+        self
+    }
+
+    /// Add a sequence that stops generation
+    pub fn stop_sequence(mut self, stop: &str) -> Self {
+        self.request
+            .stop_sequences
+            .get_or_insert_with(Vec::new)
+            .push(stop.to_string());
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.request.seed = Some(seed);
+        self
+    }
+
+    pub fn retryable_error_policy(mut self, policy: RetryableErrorPolicy) -> Self {
+        self.request.retryable_error_policy = Some(policy);
+        self
+    }
+
+    pub fn priority(mut self, priority: RequestPriority) -> Self {
+        self.request.priority = priority;
+        self
+    }
+
+    /// Validate and return the request
+    pub fn build(self) -> Result<ChatRequest, PoeError> {
+        let request = self.request;
+        request.validate_roles()?;
+
+        if let Some(temperature) = request.temperature
+            && !(temperature.is_finite() && temperature >= 0.0)
+        {
+            return Err(PoeError::InvalidRequest(format!(
+                "temperature must be a non-negative number, got {}",
+                temperature
+            )));
+        }
+
+        let tools = request.tools.as_deref().unwrap_or_default();
+        match &request.tool_choice {
+            Some(ToolChoice::Required) if tools.is_empty() => {
+                return Err(PoeError::InvalidRequest(
+                    "tool_choice 'required' needs at least one tool".to_string(),
+                ));
+            }
+            Some(ToolChoice::Function(name))
+                if !tools.iter().any(|tool| &tool.function.name == name) =>
+            {
+                return Err(PoeError::InvalidRequest(format!(
+                    "tool_choice names unknown tool '{}'",
+                    name
+                )));
+            }
+            _ => {}
+        }
+
+        Ok(request)
+    }
+
+    fn text_message(self, role: &str, content: &str) -> Self {
+        self.message(ChatMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+            attachments: None,
+            content_type: ContentType::default().as_str().to_string(),
+        })
+    }
+}
+
+/// Reaction carried by a [`ReportFeedbackRequest`]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedbackType {
+    Like,
+    Dislike,
 }
 
+/// Poe bot protocol `report_feedback` request, telling a bot how a user
+/// rated one of its messages
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ReportFeedbackRequest {
+    pub version: String,
+    pub r#type: String,
+    /// ID of the rated bot message, e.g. from `DoneInfo::message_id`
+    pub message_id: String,
+    pub user_id: String,
+    pub conversation_id: String,
+    pub feedback_type: FeedbackType,
+}
+
+impl ReportFeedbackRequest {
+    pub fn new(message_id: &str, conversation_id: &str, feedback_type: FeedbackType) -> Self {
+        Self {
+            version: "1.1".to_string(),
+            r#type: "report_feedback".to_string(),
+            message_id: message_id.to_string(),
+            user_id: String::new(),
+            conversation_id: conversation_id.to_string(),
+            feedback_type,
+        }
+    }
+
+    pub fn with_user_id(mut self, user_id: &str) -> Self {
+        self.user_id = user_id.to_string();
+        self
+    }
+}
+
+// Tool usage control, serialized with OpenAI semantics:
+// "auto" | "none" | "required" | {"type": "function", "function": {"name": ...}}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolChoice {
+    Auto,
+    None,
+    Required,
+    Function(String),
+}
+
+impl Serialize for ToolChoice {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ToolChoice::Auto => serializer.serialize_str("auto"),
+            ToolChoice::None => serializer.serialize_str("none"),
+            ToolChoice::Required => serializer.serialize_str("required"),
+            ToolChoice::Function(name) => json!({
+                "type": "function",
+                "function": { "name": name }
+            })
+            .serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ToolChoice {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let value = Value::deserialize(deserializer)?;
+        match &value {
+            Value::String(mode) => match mode.as_str() {
+                "auto" => Ok(ToolChoice::Auto),
+                "none" => Ok(ToolChoice::None),
+                "required" => Ok(ToolChoice::Required),
+                other => Err(D::Error::custom(format!("unknown tool_choice: {}", other))),
+            },
+            _ => value
+                .get("function")
+                .and_then(|function| function.get("name"))
+                .and_then(Value::as_str)
+                .map(|name| ToolChoice::Function(name.to_string()))
+                .ok_or_else(|| D::Error::custom("tool_choice object must contain function.name")),
+        }
+    }
+}
+
+// Logit bias keyed either by tokenizer token IDs or by literal strings
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum LogitBias {
+    TokenIds(BTreeMap<u32, f32>),
+    Strings(HashMap<String, f32>),
+}
+
+/// Tokenizer used to turn words into token IDs for `LogitBias`
+pub trait Tokenizer {
+    fn encode(&self, text: &str) -> Vec<u32>;
+}
+
+impl LogitBias {
+    /// Bias value that effectively bans a token
+    pub const BAN: f32 = -100.0;
+
+    /// Create an empty token ID bias map
+    pub fn token_ids() -> Self {
+        LogitBias::TokenIds(BTreeMap::new())
+    }
+
+    /// Create an empty string-keyed bias map
+    pub fn strings() -> Self {
+        LogitBias::Strings(HashMap::new())
+    }
+
+    /// Set the bias of a single token ID, keyed by its decimal ID in string mode
+    pub fn token(mut self, token_id: u32, bias: f32) -> Self {
+        self.insert_token(token_id, bias);
+        self
+    }
+
+    /// Ban a single token ID
+    pub fn ban_token(self, token_id: u32) -> Self {
+        self.token(token_id, Self::BAN)
+    }
+
+    /// Set the bias of a literal string key, switching to string mode if needed
+    pub fn string(mut self, key: &str, bias: f32) -> Self {
+        self.string_map().insert(key.to_string(), bias);
+        self
+    }
+
+    /// Boost (or suppress with a negative value) every token of `word`
+    pub fn boost_word(mut self, tokenizer: &impl Tokenizer, word: &str, bias: f32) -> Self {
+        for token_id in tokenizer.encode(word) {
+            self.insert_token(token_id, bias);
+        }
+        self
+    }
+
+    /// Ban every token of `word`
+    pub fn ban_word(self, tokenizer: &impl Tokenizer, word: &str) -> Self {
+        self.boost_word(tokenizer, word, Self::BAN)
+    }
+
+    /// Number of biased entries
+    pub fn len(&self) -> usize {
+        match self {
+            LogitBias::TokenIds(map) => map.len(),
+            LogitBias::Strings(map) => map.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // String keys cannot be expressed as token IDs, so string mode keeps the ID as a string
+    fn insert_token(&mut self, token_id: u32, bias: f32) {
+        match self {
+            LogitBias::TokenIds(map) => {
+                map.insert(token_id, bias);
+            }
+            LogitBias::Strings(map) => {
+                map.insert(token_id.to_string(), bias);
+            }
+        }
+    }
+
+    fn string_map(&mut self) -> &mut HashMap<String, f32> {
+        if let LogitBias::TokenIds(map) = self {
+            let converted = map.iter().map(|(id, bias)| (id.to_string(), *bias)).collect();
+            *self = LogitBias::Strings(converted);
+        }
+        match self {
+            LogitBias::Strings(map) => map,
+            LogitBias::TokenIds(_) => unreachable!(),
+        }
+    }
+}
+
+// Keys are always strings on the wire; maps whose keys are all numeric are read as token IDs
+impl<'de> Deserialize<'de> for LogitBias {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let map = HashMap::<String, f32>::deserialize(deserializer)?;
+        let token_ids: Option<BTreeMap<u32, f32>> = map
+            .iter()
+            .map(|(key, bias)| key.parse::<u32>().ok().map(|id| (id, *bias)))
+            .collect();
+
+        Ok(match token_ids {
+            Some(token_ids) if !token_ids.is_empty() => LogitBias::TokenIds(token_ids),
+            _ => LogitBias::Strings(map),
+        })
+    }
+}
+
+impl From<HashMap<String, f32>> for LogitBias {
+    fn from(map: HashMap<String, f32>) -> Self {
+        LogitBias::Strings(map)
+    }
+}
+
+impl From<BTreeMap<u32, f32>> for LogitBias {
+    fn from(map: BTreeMap<u32, f32>) -> Self {
+        LogitBias::TokenIds(map)
+    }
+}
+
+/// Role of system prompts
+pub const ROLE_SYSTEM: &str = "system";
+pub const ROLE_USER: &str = "user";
+/// Role of bot turns on the Poe wire
+pub const ROLE_BOT: &str = "bot";
+/// OpenAI-style bot role, used for messages rebuilt from XML tool calls
+pub const ROLE_ASSISTANT: &str = "assistant";
+/// Role of tool results
+pub const ROLE_TOOL: &str = "tool";
+/// `type` of function tools and tool calls
+pub const TOOL_TYPE_FUNCTION: &str = "function";
+
 // Message structure
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ChatMessage {
@@ -35,12 +550,98 @@ pub struct ChatMessage {
     pub content_type: String,
 }
 
+impl ChatMessage {
+    /// Parse `content_type`, accepting common aliases such as `"markdown"`
+    pub fn parsed_content_type(&self) -> ContentType {
+        ContentType::parse(&self.content_type)
+    }
+
+    pub fn with_content_type(mut self, content_type: ContentType) -> Self {
+        self.content_type = content_type.as_str().to_string();
+        self
+    }
+}
+
+/// How Poe renders a message's `content`.
+///
+/// `ChatMessage::content_type` stays a string on the wire; this enum catches
+/// typos like `"markdown"` vs `"text/markdown"` before they silently change
+/// rendering.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, Hash)]
+pub enum ContentType {
+    #[default]
+    #[serde(rename = "text/markdown", alias = "markdown", alias = "md", alias = "text/x-markdown")]
+    Markdown,
+    #[serde(rename = "text/plain", alias = "plain", alias = "text", alias = "plaintext")]
+    PlainText,
+    /// Any other value, kept as sent
+    #[serde(untagged)]
+    Other(String),
+}
+
+impl ContentType {
+    /// Parse a content type leniently (case-insensitive, aliases accepted)
+    pub fn parse(value: &str) -> Self {
+        let normalized = value.trim().to_ascii_lowercase();
+        let deserializer: serde::de::value::StrDeserializer<'_, serde::de::value::Error> =
+            serde::de::IntoDeserializer::into_deserializer(normalized.as_str());
+        match ContentType::deserialize(deserializer) {
+            Ok(ContentType::Other(_)) | Err(_) => ContentType::Other(value.to_string()),
+            Ok(known) => known,
+        }
+    }
+
+    /// Value sent on the wire
+    pub fn as_str(&self) -> &str {
+        match self {
+            ContentType::Markdown => "text/markdown",
+            ContentType::PlainText => "text/plain",
+            ContentType::Other(value) => value,
+        }
+    }
+
+    /// Whether Poe is known to render this content type
+    pub fn is_known(&self) -> bool {
+        !matches!(self, ContentType::Other(_))
+    }
+}
+
+impl std::fmt::Display for ContentType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 // ChatMessage Attachment structure
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Attachment {
     pub url: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub content_type: Option<String>,
+    /// Unix timestamp (seconds) after which `url` is no longer valid; not sent to Poe
+    #[serde(skip)]
+    pub expires_at: Option<u64>,
+    /// Where the file came from, so `PoeClient::refresh_attachment` can re-upload it; not sent to Poe
+    #[serde(skip)]
+    pub source: Option<FileUploadRequest>,
+}
+
+impl Attachment {
+    /// Check whether the URL expires within `margin_secs` seconds of `now` (Unix seconds)
+    pub fn is_stale(&self, now: u64, margin_secs: u64) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= now.saturating_add(margin_secs))
+    }
+}
+
+impl From<&FileData> for Attachment {
+    fn from(file: &FileData) -> Self {
+        Self {
+            url: file.url.clone(),
+            content_type: (!file.content_type.is_empty()).then(|| file.content_type.clone()),
+            ..Default::default()
+        }
+    }
 }
 
 // Tool definition related structures
@@ -50,6 +651,138 @@ pub struct ChatTool {
     pub function: FunctionDefinition,
 }
 
+impl ChatTool {
+    /// Start building a function tool, e.g.
+    /// `ChatTool::function("get_weather").param("city", ParamType::String, true).build()`
+    pub fn function(name: &str) -> ChatToolBuilder {
+        ChatToolBuilder {
+            name: name.to_string(),
+            description: None,
+            properties: serde_json::Map::new(),
+            required: Vec::new(),
+            last_param: None,
+        }
+    }
+}
+
+/// JSON Schema type of a tool parameter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamType {
+    String,
+    Integer,
+    Number,
+    Boolean,
+    Array,
+    Object,
+}
+
+impl ParamType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParamType::String => "string",
+            ParamType::Integer => "integer",
+            ParamType::Number => "number",
+            ParamType::Boolean => "boolean",
+            ParamType::Array => "array",
+            ParamType::Object => "object",
+        }
+    }
+}
+
+/// Fluent builder for function tools, created by [`ChatTool::function`].
+///
+/// `description`, `enum_values`, `items` and `default_value` after a `param`
+/// call apply to that parameter.
+#[derive(Debug, Clone)]
+pub struct ChatToolBuilder {
+    name: String,
+    description: Option<String>,
+    properties: serde_json::Map<String, Value>,
+    required: Vec<String>,
+    last_param: Option<String>,
+}
+
+impl ChatToolBuilder {
+    /// Describe the tool, or the last added parameter
+    pub fn description(mut self, description: &str) -> Self {
+        match self.last_param_schema() {
+            Some(schema) => {
+                schema.insert("description".to_string(), description.into());
+            }
+            None => self.description = Some(description.to_string()),
+        }
+        self
+    }
+
+    /// Add a parameter, replacing any parameter with the same name
+    pub fn param(mut self, name: &str, param_type: ParamType, required: bool) -> Self {
+        self.properties
+            .insert(name.to_string(), json!({ "type": param_type.as_str() }));
+        self.required.retain(|existing| existing != name);
+        if required {
+            self.required.push(name.to_string());
+        }
+        self.last_param = Some(name.to_string());
+        self
+    }
+
+    /// Restrict the last added parameter to `values`
+    pub fn enum_values<I, V>(mut self, values: I) -> Self
+    where
+        I: IntoIterator<Item = V>,
+        V: Into<Value>,
+    {
+        let values: Vec<Value> = values.into_iter().map(Into::into).collect();
+        if let Some(schema) = self.last_param_schema() {
+            schema.insert("enum".to_string(), Value::Array(values));
+        }
+        self
+    }
+
+    /// Set the element type of the last added array parameter
+    pub fn items(mut self, item_type: ParamType) -> Self {
+        if let Some(schema) = self.last_param_schema() {
+            schema.insert("items".to_string(), json!({ "type": item_type.as_str() }));
+        }
+        self
+    }
+
+    /// Set the value filled in when the model omits the last added parameter
+    /// (see [`FunctionDefinition::apply_defaults`])
+    pub fn default_value(mut self, value: impl Into<Value>) -> Self {
+        if let Some(schema) = self.last_param_schema() {
+            schema.insert("default".to_string(), value.into());
+        }
+        self
+    }
+
+    pub fn build(self) -> ChatTool {
+        ChatTool {
+            r#type: TOOL_TYPE_FUNCTION.to_string(),
+            function: FunctionDefinition {
+                name: self.name,
+                description: self.description,
+                parameters: Some(FunctionParameters {
+                    r#type: "object".to_string(),
+                    properties: Value::Object(self.properties),
+                    required: self.required,
+                }),
+            },
+        }
+    }
+
+    fn last_param_schema(&mut self) -> Option<&mut serde_json::Map<String, Value>> {
+        let name = self.last_param.as_ref()?;
+        self.properties.get_mut(name)?.as_object_mut()
+    }
+}
+
+impl From<ChatToolBuilder> for ChatTool {
+    fn from(builder: ChatToolBuilder) -> Self {
+        builder.build()
+    }
+}
+
 // ChatTool FunctionDefinition structure
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FunctionDefinition {
@@ -60,6 +793,33 @@ pub struct FunctionDefinition {
     pub parameters: Option<FunctionParameters>,
 }
 
+impl FunctionDefinition {
+    /// Fill optional parameters missing from `arguments` with the `default`
+    /// declared in their schema, returning whether any was filled.
+    /// Non-object arguments are left unchanged.
+    pub fn apply_defaults(&self, arguments: &mut Value) -> bool {
+        let (Some(parameters), Some(arguments)) = (&self.parameters, arguments.as_object_mut())
+        else {
+            return false;
+        };
+        let Some(properties) = parameters.properties.as_object() else {
+            return false;
+        };
+
+        let mut filled = false;
+        for (name, schema) in properties {
+            if arguments.contains_key(name) || parameters.required.contains(name) {
+                continue;
+            }
+            if let Some(default) = schema.get("default") {
+                arguments.insert(name.clone(), default.clone());
+                filled = true;
+            }
+        }
+        filled
+    }
+}
+
 // FunctionDefinition FunctionParameters structure
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FunctionParameters {
@@ -74,6 +834,14 @@ pub struct ChatToolCall {
     pub id: String,
     pub r#type: String,
     pub function: FunctionCall,
+    /// Set when the stream decoder completed the call without
+    /// `finish_reason: "tool_calls"` (on `done`, stream end or quiescence)
+    #[serde(skip)]
+    pub inferred: bool,
+    /// Index of the choice the call was streamed in, for bots returning
+    /// several candidates (`choices[n]`)
+    #[serde(skip)]
+    pub choice_index: usize,
 }
 
 // ChatToolCall FunctionCall structure
@@ -92,9 +860,22 @@ pub struct ChatToolResult {
     pub content: String,
 }
 
+/// Fallbacks for completing streamed tool calls when the bot never sends
+/// `finish_reason: "tool_calls"`, in addition to the `done` event which
+/// always completes them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ToolCallCompletion {
+    /// Emit accumulated tool calls when the response ends without a `done` event
+    pub on_stream_end: bool,
+    /// Emit them once no data has arrived for this long
+    pub quiescence: Option<std::time::Duration>,
+}
+
 // Used for tracking partial tool calls
 #[derive(Debug, Clone, Default)]
 pub struct PartialToolCall {
+    pub choice_index: usize,
+    pub index: usize,
     pub id: String,
     pub r#type: String,
     pub function_name: String,
@@ -102,13 +883,45 @@ pub struct PartialToolCall {
 }
 
 // Event response
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Clone)]
 pub struct ChatResponse {
     pub event: ChatEventType,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub data: Option<ChatResponseData>,
 }
 
+// 資料以 untagged 形式序列化，多個變體形狀相同（Text/SuggestedReply、Done/Usage/Meta/Raw），
+// 反序列化時依 event 決定變體，確保每種事件都能原樣讀回
+impl<'de> Deserialize<'de> for ChatResponse {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Wire {
+            event: ChatEventType,
+            // 區分缺少 data（None）與 `"data": null`（Some(Null)）
+            #[serde(default, deserialize_with = "present")]
+            data: Option<Value>,
+        }
+
+        fn present<'de, D: serde::Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<Value>, D::Error> {
+            Value::deserialize(deserializer).map(Some)
+        }
+
+        let wire = Wire::deserialize(deserializer)?;
+        let data = match wire.data {
+            None => None,
+            Some(value) => Some(
+                ChatResponseData::from_event(&wire.event, value).map_err(serde::de::Error::custom)?,
+            ),
+        };
+        Ok(ChatResponse {
+            event: wire.event,
+            data,
+        })
+    }
+}
+
 // Event type
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub enum ChatEventType {
@@ -118,6 +931,18 @@ pub enum ChatEventType {
     File,
     Done,
     Error,
+    /// Keep-alive ping, surfaced only with `PoeClient::with_keep_alive_events`
+    Ping,
+    /// Rendering hints for the response, carrying [`ChatResponseData::Meta`]
+    Meta,
+    /// Follow-up the user may send next, carrying [`ChatResponseData::SuggestedReply`]
+    SuggestedReply,
+    /// Token or point accounting, carrying [`ChatResponseData::Usage`];
+    /// emitted just before the `json` or `done` event that reported it
+    Usage,
+    /// Event the crate does not know yet, by its SSE `event:` name; the
+    /// payload is passed through as [`ChatResponseData::Raw`]
+    Unknown(String),
 }
 
 // File data structure
@@ -129,33 +954,292 @@ pub struct FileData {
     pub inline_ref: String,
 }
 
+/// Payload of a `meta` event: how Poe should render the response
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct MetaData {
+    /// Content type of the response text, e.g. `"text/markdown"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// Whether URLs in the response are turned into links
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub linkify: Option<bool>,
+    /// Whether Poe shows suggested replies after the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suggested_replies: Option<bool>,
+    /// Any other fields of the payload
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, Value>,
+}
+
+impl MetaData {
+    /// Parse `content_type`, accepting common aliases such as `"markdown"`
+    pub fn parsed_content_type(&self) -> Option<ContentType> {
+        self.content_type.as_deref().map(ContentType::parse)
+    }
+}
+
+/// Payload of a `done` event, e.g. the final message ID needed for feedback calls
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct DoneInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completion_tokens: Option<u64>,
+    /// Any other fields of the payload
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, Value>,
+}
+
+impl DoneInfo {
+    /// Parse a `done` data payload; `None` when it is missing, empty or not an object
+    pub fn from_payload(data: &str) -> Option<Self> {
+        match serde_json::from_str::<Value>(data) {
+            Ok(Value::Object(fields)) if !fields.is_empty() => {
+                serde_json::from_value(Value::Object(fields)).ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Token and point accounting reported by a bot in `json` or `done` events
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Usage {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completion_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_tokens: Option<u64>,
+    /// Compute points charged for the message (`points` or `cost`)
+    #[serde(default, alias = "cost", skip_serializing_if = "Option::is_none")]
+    pub points: Option<f64>,
+}
+
+impl Usage {
+    /// Read usage from an event payload, either from its `usage` object or
+    /// from top-level fields; `None` when the payload reports none
+    pub fn from_payload(payload: &Value) -> Option<Self> {
+        let source = payload.get("usage").filter(|usage| usage.is_object()).unwrap_or(payload);
+        let usage: Usage = serde_json::from_value(source.clone()).ok()?;
+        (usage != Usage::default()).then_some(usage)
+    }
+
+    /// Total tokens, summing prompt and completion tokens when the bot does
+    /// not report a total
+    pub fn total(&self) -> Option<u64> {
+        self.total_tokens.or(match (self.prompt_tokens, self.completion_tokens) {
+            (None, None) => None,
+            (prompt, completion) => Some(prompt.unwrap_or(0) + completion.unwrap_or(0)),
+        })
+    }
+
+    /// Overwrite fields with those reported by a later event
+    pub fn merge(&mut self, later: Usage) {
+        self.prompt_tokens = later.prompt_tokens.or(self.prompt_tokens);
+        self.completion_tokens = later.completion_tokens.or(self.completion_tokens);
+        self.total_tokens = later.total_tokens.or(self.total_tokens);
+        self.points = later.points.or(self.points);
+    }
+}
+
 // Possible response data types
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(untagged)]
 pub enum ChatResponseData {
     Text { text: String },
     Error { text: String, allow_retry: bool },
     ToolCalls(Vec<ChatToolCall>),
     File(FileData),
+    Done(DoneInfo),
+    Meta(MetaData),
+    SuggestedReply { text: String },
+    Usage(Usage),
     Empty,
+    KeepAlive,
+    /// Payload of an [`ChatEventType::Unknown`] event: its JSON data, or the
+    /// data as a string when it is not JSON
+    Raw(Value),
+}
+
+impl ChatResponseData {
+    // 依事件類型解讀 untagged 資料；`null` 對應無內容的變體
+    fn from_event(event: &ChatEventType, value: Value) -> Result<Self, serde_json::Error> {
+        Ok(match (event, value) {
+            (ChatEventType::Ping, Value::Null) => ChatResponseData::KeepAlive,
+            (ChatEventType::Unknown(_), value) => ChatResponseData::Raw(value),
+            (_, Value::Null) => ChatResponseData::Empty,
+            (ChatEventType::Text | ChatEventType::ReplaceResponse, value) => {
+                ChatResponseData::Text {
+                    text: text_field(value)?,
+                }
+            }
+            (ChatEventType::SuggestedReply, value) => ChatResponseData::SuggestedReply {
+                text: text_field(value)?,
+            },
+            (ChatEventType::Error, value) => {
+                #[derive(Deserialize)]
+                struct ErrorData {
+                    text: String,
+                    allow_retry: bool,
+                }
+                let error: ErrorData = serde_json::from_value(value)?;
+                ChatResponseData::Error {
+                    text: error.text,
+                    allow_retry: error.allow_retry,
+                }
+            }
+            (ChatEventType::Json, value @ Value::Array(_)) => {
+                ChatResponseData::ToolCalls(serde_json::from_value(value)?)
+            }
+            (ChatEventType::File, value) => ChatResponseData::File(serde_json::from_value(value)?),
+            (ChatEventType::Done, value) => ChatResponseData::Done(serde_json::from_value(value)?),
+            (ChatEventType::Meta, value) => ChatResponseData::Meta(serde_json::from_value(value)?),
+            (ChatEventType::Usage, value) => ChatResponseData::Usage(serde_json::from_value(value)?),
+            (_, value) => serde_json::from_value(value)?,
+        })
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+fn text_field(value: Value) -> Result<String, serde_json::Error> {
+    #[derive(Deserialize)]
+    struct TextData {
+        text: String,
+    }
+    serde_json::from_value::<TextData>(value).map(|data| data.text)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelResponse {
     pub data: Vec<ModelInfo>,
 }
 
+impl ModelResponse {
+    /// Find a model by handle (case-insensitive)
+    pub fn find(&self, handle: &str) -> Option<&ModelInfo> {
+        self.data
+            .iter()
+            .find(|model| model.id.eq_ignore_ascii_case(handle))
+    }
+
+    /// Check whether a model handle is available (case-insensitive)
+    pub fn contains(&self, handle: &str) -> bool {
+        self.find(handle).is_some()
+    }
+
+    /// Keep only the models matching `filter`
+    pub fn filtered(mut self, filter: &ModelFilter) -> Self {
+        self.data.retain(|model| filter.matches(model));
+        self
+    }
+}
+
+/// Criteria for narrowing a model list; unset fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct ModelFilter {
+    /// Exact owner, e.g. `"Anthropic"` (case-insensitive)
+    pub owned_by: Option<String>,
+    /// Substring of the model handle (case-insensitive)
+    pub search: Option<String>,
+    /// Input or output modality the model must support, e.g. `"image"`
+    pub capability: Option<String>,
+}
+
+impl ModelFilter {
+    /// Match models owned by `owner`
+    pub fn owned_by(mut self, owner: &str) -> Self {
+        self.owned_by = Some(owner.to_string());
+        self
+    }
+
+    /// Match models whose handle contains `text`
+    pub fn search(mut self, text: &str) -> Self {
+        self.search = Some(text.to_string());
+        self
+    }
+
+    /// Match models supporting `modality` as input or output
+    pub fn capability(mut self, modality: &str) -> Self {
+        self.capability = Some(modality.to_string());
+        self
+    }
+
+    /// Check whether a model satisfies every configured criterion
+    pub fn matches(&self, model: &ModelInfo) -> bool {
+        let owner_matches = self
+            .owned_by
+            .as_ref()
+            .is_none_or(|owner| model.owned_by.eq_ignore_ascii_case(owner));
+        let search_matches = self
+            .search
+            .as_ref()
+            .is_none_or(|text| model.id.to_lowercase().contains(&text.to_lowercase()));
+        let capability_matches = self.capability.as_ref().is_none_or(|modality| {
+            model.architecture.as_ref().is_some_and(|architecture| {
+                architecture
+                    .input_modalities
+                    .iter()
+                    .chain(&architecture.output_modalities)
+                    .any(|supported| supported.eq_ignore_ascii_case(modality))
+            })
+        });
+
+        owner_matches && search_matches && capability_matches
+    }
+}
+
+// Model catalog source selection used by `PoeClient::get_models`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ModelListPolicy {
+    V1Only,
+    GraphqlOnly,
+    #[default]
+    V1ThenGraphql,
+    GraphqlThenV1,
+}
+
+// Where `PoeClient::get_model_list` looks for a fresh persisted query hash once the current one expires
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GraphqlHashSource {
+    /// Read the hash from the `POE_GQL_MODEL_HASH` environment variable
+    Env,
+    /// Fetch `{"<queryName>": "<hash>"}` (or a list of hashes per query) from a URL
+    Remote(String),
+}
+
+// Outbound chat request returned by `PoeClient::prepare_request`
+#[derive(Debug, Clone)]
+pub struct PreparedRequest {
+    pub url: String,
+    /// Request headers, with credentials masked
+    pub headers: Vec<(String, String)>,
+    pub body: Value,
+}
+
 // Model information
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelInfo {
     pub id: String,
     pub object: String,
     pub created: i64,
     pub owned_by: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub architecture: Option<ModelArchitecture>,
+}
+
+// Supported modalities reported by the v1/models API
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModelArchitecture {
+    #[serde(default)]
+    pub input_modalities: Vec<String>,
+    #[serde(default)]
+    pub output_modalities: Vec<String>,
 }
 
 // File upload request structure
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 #[serde(untagged)]
 pub enum FileUploadRequest {
     LocalFile {
@@ -168,11 +1252,145 @@ pub enum FileUploadRequest {
 }
 
 // File upload response structure
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FileUploadResponse {
     pub attachment_url: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub mime_type: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub size: Option<u64>,
+    /// Unix timestamp (seconds) when the URL expires, as reported by the
+    /// server or derived from `PoeClient::with_attachment_ttl`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// Hex SHA-256 of the uploaded bytes, computed locally for local files
+    /// (verified against the server's value when it reports one)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// The upload that produced this response
+    #[serde(skip)]
+    pub source: Option<FileUploadRequest>,
+}
+
+impl FileUploadResponse {
+    /// Build a message attachment carrying the expiry and source for refreshing
+    pub fn to_attachment(&self) -> Attachment {
+        Attachment {
+            url: self.attachment_url.clone(),
+            content_type: self.mime_type.clone(),
+            expires_at: self.expires_at,
+            source: self.source.clone(),
+        }
+    }
+}
+
+/// Reply of Poe's message attachment endpoint, see
+/// `PoeClient::post_message_attachment`
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct MessageAttachmentResponse {
+    /// Reference for showing an inline attachment in the reply's markdown,
+    /// e.g. `![chart][ref]`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inline_ref: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment_url: Option<String>,
+}
+
+/// Server-reported MIME type of an upload that differs from the local one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MimeMismatch {
+    pub file: String,
+    /// Declared MIME type, or the sniffed one when none was declared
+    pub expected: String,
+    pub reported: String,
+}
+
+// poe.com web session credentials (cookies), kept separate from the bot API access key
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PoeWebSession {
+    pub p_b: String,
+    pub p_lat: String,
+}
+
+impl PoeWebSession {
+    pub fn new(p_b: &str, p_lat: &str) -> Self {
+        Self {
+            p_b: p_b.to_string(),
+            p_lat: p_lat.to_string(),
+        }
+    }
+
+    /// Render the session as a `Cookie` header fragment
+    #[cfg(feature = "gql")]
+    pub(crate) fn cookie_value(&self) -> String {
+        if self.p_lat.is_empty() {
+            format!("p-b={}", self.p_b)
+        } else {
+            format!("p-b={}; p-lat={}", self.p_b, self.p_lat)
+        }
+    }
+}
+
+// Never expose session tokens through Debug output
+impl fmt::Debug for PoeWebSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoeWebSession")
+            .field("p_b", &"***MASKED***")
+            .field("p_lat", &"***MASKED***")
+            .finish()
+    }
+}
+
+// GraphQL error entry from a poe.com `errors` array
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GraphqlError {
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<Vec<Value>>,
+}
+
+impl GraphqlError {
+    /// Parse the `errors` array of a GraphQL response body
+    pub fn from_response(body: &Value) -> Vec<Self> {
+        let Some(errors) = body.get("errors").and_then(Value::as_array) else {
+            return Vec::new();
+        };
+
+        errors
+            .iter()
+            .map(|error| GraphqlError {
+                message: error
+                    .get("message")
+                    .and_then(Value::as_str)
+                    .unwrap_or("Unknown GraphQL error")
+                    .to_string(),
+                code: error
+                    .get("extensions")
+                    .and_then(|extensions| extensions.get("code"))
+                    .or_else(|| error.get("code"))
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+                path: error.get("path").and_then(Value::as_array).cloned(),
+            })
+            .collect()
+    }
+
+    /// Check whether the error indicates an expired persisted query hash
+    pub fn is_hash_expired(&self) -> bool {
+        let code = self.code.as_deref().unwrap_or_default();
+        code.eq_ignore_ascii_case("PERSISTED_QUERY_NOT_FOUND")
+            || self.message.contains("PersistedQueryNotFound")
+            || self.message.to_lowercase().contains("persisted query not found")
+    }
+}
+
+impl fmt::Display for GraphqlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.code {
+            Some(code) => write!(f, "[{}] {}", code, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
 }
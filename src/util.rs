@@ -0,0 +1,208 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use unicode_segmentation::UnicodeSegmentation;
+
+// Process-wide counter keeping generated IDs unique within the same nanosecond
+static ID_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Generate a unique identifier for conversations and messages, e.g. `conv-18c6f0a9d3e2b4c00001`
+pub fn generate_id(prefix: &str) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let counter = ID_COUNTER.fetch_add(1, Ordering::Relaxed) & 0xffff;
+    format!("{}-{:x}{:04x}", prefix, nanos, counter)
+}
+
+/// Guess a file's MIME type from its leading bytes, falling back to its extension
+pub fn sniff_mime(path: &Path, head: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF8", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"ID3", "audio/mpeg"),
+        (b"OggS", "audio/ogg"),
+    ];
+
+    if let Some((_, mime)) = SIGNATURES.iter().find(|(magic, _)| head.starts_with(magic)) {
+        return Some(mime);
+    }
+    if head.len() >= 12 && &head[..4] == b"RIFF" {
+        match &head[8..12] {
+            b"WEBP" => return Some("image/webp"),
+            b"WAVE" => return Some("audio/wav"),
+            _ => {}
+        }
+    }
+    if head.len() >= 8 && &head[4..8] == b"ftyp" {
+        return Some("video/mp4");
+    }
+
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    Some(match extension.as_str() {
+        "txt" | "log" => "text/plain",
+        "md" | "markdown" => "text/markdown",
+        "csv" => "text/csv",
+        "html" | "htm" => "text/html",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        _ => return None,
+    })
+}
+
+/// Splits text into chunks of at most `max_bytes` bytes without breaking
+/// UTF-8 sequences or grapheme clusters.
+///
+/// A single grapheme larger than `max_bytes` (e.g. a long emoji ZWJ sequence)
+/// is emitted as its own chunk rather than being split.
+#[derive(Debug, Clone)]
+pub struct TextChunker {
+    max_bytes: usize,
+    pending: String,
+}
+
+impl TextChunker {
+    /// Create a chunker producing chunks of at most `max_bytes` bytes
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes: max_bytes.max(1),
+            pending: String::new(),
+        }
+    }
+
+    /// Get the configured maximum chunk size in bytes
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    /// Split a complete text into grapheme-aligned chunks
+    pub fn split<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        let mut end = 0;
+
+        for (offset, grapheme) in text.grapheme_indices(true) {
+            let grapheme_end = offset + grapheme.len();
+            if grapheme_end - start > self.max_bytes && end > start {
+                chunks.push(&text[start..end]);
+                start = end;
+            }
+            end = grapheme_end;
+        }
+
+        if end > start {
+            chunks.push(&text[start..end]);
+        }
+
+        chunks
+    }
+
+    /// Feed a streamed text delta and return the chunks that are safe to emit.
+    ///
+    /// The trailing grapheme is held back because the next delta may extend it
+    /// (combining marks, ZWJ sequences); call [`TextChunker::flush`] at the end
+    /// of the stream to emit it.
+    pub fn push(&mut self, delta: &str) -> Vec<String> {
+        self.pending.push_str(delta);
+
+        let hold_from = match self.pending.grapheme_indices(true).next_back() {
+            Some((offset, _)) => offset,
+            None => return Vec::new(),
+        };
+
+        let ready: String = self.pending.drain(..hold_from).collect();
+        self.split(&ready).into_iter().map(str::to_string).collect()
+    }
+
+    /// Emit everything still buffered
+    pub fn flush(&mut self) -> Vec<String> {
+        let remaining = std::mem::take(&mut self.pending);
+        self.split(&remaining)
+            .into_iter()
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+// Escape sequences longer than this are treated as garbage and dropped
+const MAX_ESCAPE_LENGTH: usize = 256;
+
+/// Removes ANSI escape sequences and control characters (except `\n` and
+/// `\t`) from streamed text before it reaches terminals or log pipelines.
+///
+/// An escape sequence split across deltas is held back until it completes.
+#[derive(Debug, Clone, Default)]
+pub struct TextSanitizer {
+    pending: String,
+}
+
+impl TextSanitizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sanitize a complete text
+    pub fn sanitize(text: &str) -> String {
+        Self::new().push(text)
+    }
+
+    /// Feed a streamed text delta and return its sanitized form
+    pub fn push(&mut self, delta: &str) -> String {
+        self.pending.push_str(delta);
+        let input = std::mem::take(&mut self.pending);
+        let mut output = String::with_capacity(input.len());
+        let mut chars = input.char_indices().peekable();
+
+        while let Some((start, c)) = chars.next() {
+            if c != '\x1b' {
+                if !c.is_control() || c == '\n' || c == '\t' {
+                    output.push(c);
+                }
+                continue;
+            }
+
+            let complete = match chars.next() {
+                // CSI: parameters and intermediates, then a final byte in @..~
+                Some((_, '[')) => chars.any(|(_, c)| ('@'..='~').contains(&c)),
+                // OSC: terminated by BEL or ESC \
+                Some((_, ']')) => {
+                    let mut previous = ' ';
+                    chars.any(|(_, c)| {
+                        let done = c == '\x07' || (previous == '\x1b' && c == '\\');
+                        previous = c;
+                        done
+                    })
+                }
+                Some(_) => true,
+                None => false,
+            };
+
+            if !complete {
+                if input.len() - start <= MAX_ESCAPE_LENGTH {
+                    self.pending = input[start..].to_string();
+                }
+                break;
+            }
+        }
+
+        output
+    }
+
+    /// Discard a held-back incomplete escape sequence, e.g. before a `replace_response`
+    pub fn reset(&mut self) {
+        self.pending.clear();
+    }
+}
+
+/// Lowercase hex encoding of a digest
+#[cfg(feature = "client")]
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -1,18 +1,30 @@
-use crate::types::{
-    ChatMessage, ChatRequest, ChatTool, ChatToolCall, ChatToolResult, FunctionCall,
-};
+#[cfg(feature = "xml-parse")]
+use crate::error::PoeError;
+#[cfg(all(feature = "xml-parse", feature = "trace"))]
+use crate::logging::TARGET_XML;
+use crate::types::{ChatMessage, ChatTool};
+#[cfg(feature = "xml-parse")]
+use crate::types::{ChatToolCall, FunctionCall, TOOL_TYPE_FUNCTION};
+#[cfg(feature = "xml-prompt")]
+use crate::types::{ChatRequest, ChatToolResult, ROLE_USER, ToolChoice};
+#[cfg(feature = "xml-parse")]
+use serde_json::Value;
+#[cfg(feature = "xml-parse")]
 use std::collections::HashMap;
+#[cfg(feature = "xml-parse")]
 use std::sync::atomic::{AtomicU64, Ordering};
 
 // Global tool call ID counter to ensure each tool call has a unique ID
+#[cfg(feature = "xml-parse")]
 static GLOBAL_CALL_ID: AtomicU64 = AtomicU64::new(1);
 
 // Generate next unique tool call ID
+#[cfg(feature = "xml-parse")]
 fn get_next_call_id() -> u64 {
     GLOBAL_CALL_ID.fetch_add(1, Ordering::SeqCst)
 }
 
-#[cfg(feature = "trace")]
+#[cfg(all(feature = "trace", feature = "xml-parse"))]
 fn safe_string_truncate(s: &str, max_bytes: usize) -> &str {
     if s.len() <= max_bytes {
         return s;
@@ -45,10 +57,12 @@ pub struct XmlParameter {
 }
 
 // XML tool conversion trait
+#[cfg(feature = "xml-prompt")]
 pub trait ToXml {
     fn to_xml(&self) -> String;
 }
 
+#[cfg(feature = "xml-prompt")]
 impl ToXml for ChatTool {
     fn to_xml(&self) -> String {
         let mut xml = String::new();
@@ -118,6 +132,7 @@ impl ToXml for ChatTool {
     }
 }
 
+#[cfg(feature = "xml-prompt")]
 impl ToXml for Vec<ChatTool> {
     fn to_xml(&self) -> String {
         if self.is_empty() {
@@ -134,6 +149,7 @@ impl ToXml for Vec<ChatTool> {
     }
 }
 
+#[cfg(feature = "xml-prompt")]
 impl ToXml for ChatToolResult {
     fn to_xml(&self) -> String {
         let mut xml = String::new();
@@ -158,6 +174,7 @@ impl ToXml for ChatToolResult {
     }
 }
 
+#[cfg(feature = "xml-prompt")]
 impl ToXml for Vec<ChatToolResult> {
     fn to_xml(&self) -> String {
         if self.is_empty() {
@@ -175,6 +192,7 @@ impl ToXml for Vec<ChatToolResult> {
 }
 
 // XML escaping function
+#[cfg(feature = "xml-prompt")]
 fn escape_xml(text: &str) -> String {
     text.replace('&', "&amp;")
         .replace('<', "&lt;")
@@ -184,6 +202,8 @@ fn escape_xml(text: &str) -> String {
 }
 
 // Add XML tool attachment functionality to ChatMessage (internal use only)
+#[cfg(feature = "xml-prompt")]
+#[cfg_attr(not(feature = "client"), allow(dead_code))]
 impl ChatMessage {
     /// Append XML format tool definitions to message content end (internal use only)
     pub(crate) fn append_xml_tools(&mut self, tools: &[ChatTool]) {
@@ -204,17 +224,41 @@ impl ChatMessage {
     }
 }
 
+// Convert tool_choice into a prompt instruction (internal use only)
+#[cfg(feature = "xml-prompt")]
+#[cfg_attr(not(feature = "client"), allow(dead_code))]
+impl ToolChoice {
+    pub(crate) fn to_xml_instruction(&self) -> Option<String> {
+        match self {
+            ToolChoice::Auto => None,
+            ToolChoice::None => Some(
+                "\n\nTool Choice: Do NOT call any tool in this response. Answer directly in natural language.".to_string(),
+            ),
+            ToolChoice::Required => Some(
+                "\n\nTool Choice: You MUST call at least one of the tools above in this response.".to_string(),
+            ),
+            ToolChoice::Function(name) => Some(format!(
+                "\n\nTool Choice: You MUST call the \"{}\" tool in this response.",
+                escape_xml(name)
+            )),
+        }
+    }
+}
+
 // Add XML tool processing functionality to ChatRequest (internal use only)
+#[cfg(feature = "xml-prompt")]
+#[cfg_attr(not(feature = "client"), allow(dead_code))]
 impl ChatRequest {
     /// Convert tools to XML format and append to last user message (internal use only)
     pub(crate) fn append_tools_as_xml(&mut self) {
-        if let Some(ref tools) = self.tools {
-            if !tools.is_empty() {
-                // Find the last user message
-                for message in self.query.iter_mut().rev() {
-                    if message.role == "user" {
-                        // Add complete tool usage prompt
-                        let tool_usage_prompt = r#"
+        if let Some(ref tools) = self.tools
+            && !tools.is_empty()
+        {
+            // Find the last user message
+            for message in self.query.iter_mut().rev() {
+                if message.role == ROLE_USER {
+                    // Add complete tool usage prompt
+                    let tool_usage_prompt = r#"
 
 You are a powerful AI assistant. Your core mission is to accurately and efficiently answer user questions and execute tasks.
 
@@ -266,10 +310,14 @@ Explanation:
 
 Now, begin your work based on the user's next prompt. Remember, you are a problem-solver, and your tools are your most powerful weapons.
 "#;
-                        message.content.push_str(tool_usage_prompt);
-                        message.append_xml_tools(tools);
-                        break;
+                    message.content.push_str(tool_usage_prompt);
+                    message.append_xml_tools(tools);
+                    if let Some(instruction) =
+                        self.tool_choice.as_ref().and_then(ToolChoice::to_xml_instruction)
+                    {
+                        message.content.push_str(&instruction);
                     }
+                    break;
                 }
             }
         }
@@ -277,13 +325,14 @@ Now, begin your work based on the user's next prompt. Remember, you are a proble
 
     /// Append tool results in XML format to last user message (internal use only)
     pub(crate) fn append_tool_results_as_xml(&mut self) {
-        if let Some(ref tool_results) = self.tool_results {
-            if !tool_results.is_empty() {
-                // Find the last user message
-                for message in self.query.iter_mut().rev() {
-                    if message.role == "user" {
-                        // Add tool result analysis prompt
-                        let tool_results_prompt = r#"
+        if let Some(ref tool_results) = self.tool_results
+            && !tool_results.is_empty()
+        {
+            // Find the last user message
+            for message in self.query.iter_mut().rev() {
+                if message.role == ROLE_USER {
+                    // Add tool result analysis prompt
+                    let tool_results_prompt = r#"
 
 You have previously requested one or more tool calls. The results are now available. Your new task is to analyze these results and formulate a final, comprehensive answer for the user in natural language.
 
@@ -296,10 +345,9 @@ The tool results are provided to you in the following XML format:
 3.  **Formulate the Final Answer**: Your response should be the complete and final answer to the user's original query. Do not output any more `<tool_call>` blocks unless the results explicitly indicate a necessary follow-up action.
 4.  **Handle Errors Gracefully**: If a tool returned an error, politely inform the user that you were unable to retrieve that specific piece of information and, if appropriate, briefly explain the issue (e.g., "I couldn't find information for that city.").
 "#;
-                        message.content.push_str(tool_results_prompt);
-                        message.append_xml_tool_results(tool_results);
-                        break;
-                    }
+                    message.content.push_str(tool_results_prompt);
+                    message.append_xml_tool_results(tool_results);
+                    break;
                 }
             }
         }
@@ -307,8 +355,21 @@ The tool results are provided to you in the following XML format:
 }
 
 // XML tool call parsing functionality
+#[cfg(feature = "xml-parse")]
 pub struct XmlToolCallParser;
 
+/// How [`XmlToolCallParser::decode_xml_entities`] handles unknown entities
+#[cfg(feature = "xml-parse")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum XmlEntityMode {
+    /// Keep unknown entities and bare `&` as written; used by tool call parsing
+    #[default]
+    Lenient,
+    /// Reject unknown entities and bare `&`
+    Strict,
+}
+
+#[cfg(feature = "xml-parse")]
 impl XmlToolCallParser {
     /// Parse XML tool calls from text
     pub fn parse_xml_tool_calls(text: &str) -> Vec<ChatToolCall> {
@@ -317,8 +378,8 @@ impl XmlToolCallParser {
         #[cfg(feature = "trace")]
         {
             use tracing::debug;
-            debug!("Starting XML tool call parsing, text length: {}", text.len());
-            debug!("Text content preview: {}", text);
+            debug!(target: TARGET_XML, "Starting XML tool call parsing, text length: {}", text.len());
+            debug!(target: TARGET_XML, "Text content preview: {}", text);
         }
 
         // First look for <tool_call> wrapped tool calls
@@ -335,10 +396,11 @@ impl XmlToolCallParser {
                 {
                     use tracing::debug;
                     debug!(
+                        target: TARGET_XML,
                         "Found complete tool call #{}, start position: {}, end position: {}",
                         current_call_id, actual_start, actual_end
                     );
-                    debug!("Tool call content: {}", call_content);
+                    debug!(target: TARGET_XML, "Tool call content: {}", call_content);
                 }
 
                 if let Some(tool_call) = Self::parse_single_tool_call(call_content, current_call_id)
@@ -347,6 +409,7 @@ impl XmlToolCallParser {
                     {
                         use tracing::debug;
                         debug!(
+                            target: TARGET_XML,
                             "Successfully parsed tool call #{}: {}",
                             current_call_id, tool_call.function.name
                         );
@@ -356,7 +419,7 @@ impl XmlToolCallParser {
                     #[cfg(feature = "trace")]
                     {
                         use tracing::debug;
-                        debug!("Failed to parse tool call #{}", current_call_id);
+                        debug!(target: TARGET_XML, "Failed to parse tool call #{}", current_call_id);
                     }
                 }
 
@@ -365,7 +428,7 @@ impl XmlToolCallParser {
                 #[cfg(feature = "trace")]
                 {
                     use tracing::debug;
-                    debug!("Found <tool_call> but no corresponding </tool_call>, stopping parsing");
+                    debug!(target: TARGET_XML, "Found <tool_call> but no corresponding </tool_call>, stopping parsing");
                 }
                 break;
             }
@@ -386,10 +449,11 @@ impl XmlToolCallParser {
                     {
                         use tracing::debug;
                         debug!(
+                            target: TARGET_XML,
                             "Found direct invoke call #{}, start position: {}, end position: {}",
                             current_call_id, actual_start, actual_end
                         );
-                        debug!("Invoke call content: {}", invoke_content);
+                        debug!(target: TARGET_XML, "Invoke call content: {}", invoke_content);
                     }
 
                     if let Some(tool_call) =
@@ -399,6 +463,7 @@ impl XmlToolCallParser {
                         {
                             use tracing::debug;
                             debug!(
+                                target: TARGET_XML,
                                 "Successfully parsed direct invoke call #{}: {}",
                                 current_call_id, tool_call.function.name
                             );
@@ -408,7 +473,7 @@ impl XmlToolCallParser {
                         #[cfg(feature = "trace")]
                         {
                             use tracing::debug;
-                            debug!("Failed to parse direct invoke call #{}", current_call_id);
+                            debug!(target: TARGET_XML, "Failed to parse direct invoke call #{}", current_call_id);
                         }
                     }
 
@@ -417,7 +482,7 @@ impl XmlToolCallParser {
                     #[cfg(feature = "trace")]
                     {
                         use tracing::debug;
-                        debug!("Found <invoke but no corresponding </invoke>, stopping parsing");
+                        debug!(target: TARGET_XML, "Found <invoke but no corresponding </invoke>, stopping parsing");
                     }
                     break;
                 }
@@ -428,6 +493,7 @@ impl XmlToolCallParser {
         {
             use tracing::debug;
             debug!(
+                target: TARGET_XML,
                 "XML tool call parsing completed, found {} tool calls",
                 tool_calls.len()
             );
@@ -463,6 +529,21 @@ impl XmlToolCallParser {
             }
         }
 
+        // Fill parameters the model left out with their declared defaults
+        for tool_call in &mut tool_calls {
+            let Some(tool) = tools
+                .iter()
+                .find(|tool| tool.function.name == tool_call.function.name)
+            else {
+                continue;
+            };
+            if let Ok(mut arguments) = serde_json::from_str::<Value>(&tool_call.function.arguments)
+                && tool.function.apply_defaults(&mut arguments)
+            {
+                tool_call.function.arguments = arguments.to_string();
+            }
+        }
+
         tool_calls
     }
 
@@ -471,8 +552,8 @@ impl XmlToolCallParser {
         #[cfg(feature = "trace")]
         {
             use tracing::debug;
-            debug!("Attempting to parse single tool call, content length: {}", xml_content.len());
-            debug!("XML content preview: {}", safe_string_truncate(xml_content, 200));
+            debug!(target: TARGET_XML, "Attempting to parse single tool call, content length: {}", xml_content.len());
+            debug!(target: TARGET_XML, "XML content preview: {}", safe_string_truncate(xml_content, 200));
         }
 
         // First try parsing <invoke name="tool_name"> format
@@ -483,6 +564,7 @@ impl XmlToolCallParser {
             {
                 use tracing::debug;
                 debug!(
+                    target: TARGET_XML,
                     "Successfully parsed invoke format, tool name: {}, parameters: {}",
                     function_name, arguments
                 );
@@ -490,11 +572,13 @@ impl XmlToolCallParser {
 
             return Some(ChatToolCall {
                 id: format!("call_{}", call_id),
-                r#type: "function".to_string(),
+                r#type: TOOL_TYPE_FUNCTION.to_string(),
                 function: FunctionCall {
                     name: function_name,
                     arguments,
                 },
+                inferred: false,
+                choice_index: 0,
             });
         }
 
@@ -507,6 +591,7 @@ impl XmlToolCallParser {
             {
                 use tracing::debug;
                 debug!(
+                    target: TARGET_XML,
                     "Successfully parsed old format, tool name: {}, parameters: {}",
                     function_name, arguments
                 );
@@ -514,11 +599,13 @@ impl XmlToolCallParser {
 
             return Some(ChatToolCall {
                 id: format!("call_{}", call_id),
-                r#type: "function".to_string(),
+                r#type: TOOL_TYPE_FUNCTION.to_string(),
                 function: FunctionCall {
                     name: function_name,
                     arguments,
                 },
+                inferred: false,
+                choice_index: 0,
             });
         }
 
@@ -532,6 +619,7 @@ impl XmlToolCallParser {
             {
                 use tracing::debug;
                 debug!(
+                    target: TARGET_XML,
                     "Successfully parsed direct tool name format, tool name: {}, parameters: {}",
                     function_name, arguments
                 );
@@ -539,18 +627,20 @@ impl XmlToolCallParser {
 
             return Some(ChatToolCall {
                 id: format!("call_{}", call_id),
-                r#type: "function".to_string(),
+                r#type: TOOL_TYPE_FUNCTION.to_string(),
                 function: FunctionCall {
                     name: function_name,
                     arguments,
                 },
+                inferred: false,
+                choice_index: 0,
             });
         }
 
         #[cfg(feature = "trace")]
         {
             use tracing::debug;
-            debug!("Failed to parse tool call, no valid tool name found");
+            debug!(target: TARGET_XML, "Failed to parse tool call, no valid tool name found");
         }
 
         None
@@ -589,8 +679,8 @@ impl XmlToolCallParser {
                                 #[cfg(feature = "trace")]
                                 {
                                     use tracing::debug;
-                                    debug!("Extracted direct tool name: {}", tag_content);
-                                    debug!("Tool content: {}", tool_content);
+                                    debug!(target: TARGET_XML, "Extracted direct tool name: {}", tag_content);
+                                    debug!(target: TARGET_XML, "Tool content: {}", tool_content);
                                 }
 
                                 return Some((tag_content.to_string(), tool_content.to_string()));
@@ -652,11 +742,13 @@ impl XmlToolCallParser {
 
                 return Some(ChatToolCall {
                     id: format!("call_{}", call_id),
-                    r#type: "function".to_string(),
+                    r#type: TOOL_TYPE_FUNCTION.to_string(),
                     function: FunctionCall {
                         name: tool_name.to_string(),
                         arguments,
                     },
+                    inferred: false,
+                    choice_index: 0,
                 });
             }
         }
@@ -724,7 +816,8 @@ impl XmlToolCallParser {
                                 .trim();
                             if !param_value.is_empty() {
                                 // Decode XML entities
-                                let decoded_value = Self::decode_xml_entities(param_value);
+                                let decoded_value = Self::decode_xml_entities(param_value, XmlEntityMode::Lenient)
+                                    .unwrap_or_else(|_| param_value.to_string());
                                 params.insert(param_name, decoded_value);
                             }
                         }
@@ -770,7 +863,8 @@ impl XmlToolCallParser {
                         if let Some(end_pos) = xml_content[content_start..].find(&end_tag) {
                             let value = xml_content[content_start..content_start + end_pos].trim();
                             if !value.is_empty() {
-                                let decoded_value = Self::decode_xml_entities(value);
+                                let decoded_value = Self::decode_xml_entities(value, XmlEntityMode::Lenient)
+                                    .unwrap_or_else(|_| value.to_string());
                                 params.insert(tag_name.to_string(), decoded_value);
                             }
                             current_pos = content_start + end_pos + end_tag.len();
@@ -794,17 +888,75 @@ impl XmlToolCallParser {
         }
     }
 
-    /// Decode XML entities
-    fn decode_xml_entities(text: &str) -> String {
-        text.replace("&lt;", "<")
-            .replace("&gt;", ">")
-            .replace("&amp;", "&")
-            .replace("&quot;", "\"")
-            .replace("&apos;", "'")
+    /// Decode XML entities in a single pass, so `&amp;lt;` becomes `&lt;`
+    /// rather than `<`. Supports the five predefined entities and numeric
+    /// references (`&#39;`, `&#x27;`).
+    ///
+    /// Unknown entities and bare `&` are kept verbatim in lenient mode and
+    /// rejected in strict mode.
+    pub fn decode_xml_entities(text: &str, mode: XmlEntityMode) -> Result<String, PoeError> {
+        let mut decoded = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while let Some(start) = rest.find('&') {
+            decoded.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let name = rest[1..]
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '#')
+                .filter(|&end| rest[1 + end..].starts_with(';'))
+                .map(|end| &rest[1..1 + end]);
+            match name.and_then(Self::decode_entity) {
+                Some(character) => {
+                    decoded.push(character);
+                    rest = &rest[name.map_or(0, str::len) + 2..];
+                }
+                None if mode == XmlEntityMode::Strict => {
+                    let shown = name.map_or_else(|| "&".to_string(), |name| format!("&{};", name));
+                    return Err(PoeError::ToolCallParseFailed(format!(
+                        "unknown XML entity: {}",
+                        shown
+                    )));
+                }
+                None => {
+                    decoded.push('&');
+                    rest = &rest[1..];
+                }
+            }
+        }
+
+        decoded.push_str(rest);
+        Ok(decoded)
+    }
+
+    fn decode_entity(name: &str) -> Option<char> {
+        match name {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => {
+                let reference = name.strip_prefix('#')?;
+                let code = match reference.strip_prefix(['x', 'X']) {
+                    Some(hex) if !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+                        u32::from_str_radix(hex, 16).ok()?
+                    }
+                    None if !reference.is_empty()
+                        && reference.chars().all(|c| c.is_ascii_digit()) =>
+                    {
+                        reference.parse().ok()?
+                    }
+                    _ => return None,
+                };
+                char::from_u32(code)
+            }
+        }
     }
 }
 
 // Add XML tool call detection functionality to ChatMessage
+#[cfg(feature = "xml-parse")]
 impl ChatMessage {
     /// Detect if message contains XML tool calls (general format)
     pub fn contains_xml_tool_calls(&self) -> bool {